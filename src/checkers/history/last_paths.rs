@@ -4,19 +4,25 @@
 //! See the documentation for [`HoardPaths::enforce_old_and_new_piles_are_same`]
 //! for an explanation of why this is useful.
 
-use super::super::Checker;
-use crate::config::hoard::Hoard;
+use super::{super::Checker, Migration};
+use crate::{
+    config::hoard::{Hoard, Pile},
+    utils,
+};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fs, io,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use thiserror::Error;
 
 /// File name in which `last_paths` are stored
 const FILE_NAME: &str = "last_paths.json";
 
+/// Name under which the candidates of an anonymous pile are recorded.
+const ANONYMOUS_PILE: &str = "";
+
 /// Errors that may occur while working with a [`LastPaths`] or related types.
 #[derive(Debug, Error)]
 #[non_exhaustive]
@@ -59,7 +65,7 @@ impl Checker for LastPaths {
     fn new(name: &str, hoard: &Hoard, _is_backup: bool) -> Result<Self, Self::Error> {
         Ok(LastPaths({
             let mut map = HashMap::new();
-            map.insert(name.into(), HoardPaths::from(hoard.clone()));
+            map.insert(name.into(), HoardPaths::from(hoard));
             map
         }))
     }
@@ -93,12 +99,31 @@ impl Checker for LastPaths {
             fs::create_dir_all(parent)?;
         }
         tracing::trace!("writing lastpaths file");
-        fs::write(path, content)?;
+        utils::write_atomic(&path, content.as_bytes())?;
         Ok(())
     }
 }
 
 impl LastPaths {
+    /// Like [`Checker::new`], for an operation on only the piles of the named
+    /// hoard `hoard`: the other piles of the hoard keep the paths that were
+    /// recorded for them.
+    ///
+    /// # Errors
+    ///
+    /// Any I/O or `serde` error while reading the recorded paths.
+    pub fn for_piles(name: &str, hoard: &Hoard) -> Result<Self, Error> {
+        let mut paths = HoardPaths::from(hoard);
+        if let Some(old) = Self::from_default_file()?.0.remove(name) {
+            paths.keep_other_piles(old, hoard);
+        }
+        Ok(LastPaths({
+            let mut map = HashMap::new();
+            map.insert(name.into(), paths);
+            map
+        }))
+    }
+
     /// Get the entry for the given hoard, if exists.
     #[must_use]
     fn hoard(&self, hoard: &str) -> Option<&HoardPaths> {
@@ -110,6 +135,43 @@ impl LastPaths {
         self.0.insert(hoard, paths);
     }
 
+    /// Apply `migration` to the recorded hoards.
+    fn migrate(&mut self, migration: &Migration) {
+        match *migration {
+            Migration::RenameHoard { from, to } =>
+                if let Some(paths) = self.0.remove(from) {
+                    self.0.insert(to.to_owned(), paths);
+                },
+            Migration::ForgetHoard(hoard) => {
+                self.0.remove(hoard);
+            },
+            Migration::RenamePile { hoard, from, to } => {
+                if let Some(PilePaths::Named(piles)) =
+                    self.0.get_mut(hoard).map(|paths| &mut paths.piles)
+                {
+                    if let Some(path) = piles.remove(from) {
+                        piles.insert(to.to_owned(), path);
+                    }
+                }
+                if let Some(candidates) = self.0.get_mut(hoard).map(|paths| &mut paths.candidates) {
+                    if let Some(paths) = candidates.remove(from) {
+                        candidates.insert(to.to_owned(), paths);
+                    }
+                }
+            },
+            Migration::ForgetPile { hoard, pile } => {
+                if let Some(PilePaths::Named(piles)) =
+                    self.0.get_mut(hoard).map(|paths| &mut paths.piles)
+                {
+                    piles.remove(pile);
+                }
+                if let Some(paths) = self.0.get_mut(hoard) {
+                    paths.candidates.remove(pile);
+                }
+            },
+        }
+    }
+
     /// Read the last paths from the default file.
     ///
     /// # Errors
@@ -135,6 +197,27 @@ impl LastPaths {
     }
 }
 
+/// Apply `migration` to the last paths recorded in the history directory `dir`
+/// of a system, if there are any.
+///
+/// # Errors
+///
+/// Any I/O or `serde` error while reading or writing the file.
+pub fn migrate(dir: &Path, migration: &Migration) -> Result<(), Error> {
+    let path = dir.join(FILE_NAME);
+    let _span = tracing::debug_span!("migrate_last_paths", ?path, ?migration).entered();
+    let mut last_paths: LastPaths = match fs::File::open(&path) {
+        Ok(file) => serde_json::from_reader(file)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    last_paths.migrate(migration);
+    let content = serde_json::to_string_pretty(&last_paths)?;
+    utils::write_atomic(&path, content.as_bytes())?;
+    Ok(())
+}
+
 /// An entry for the last time a hoard was processed.
 ///
 /// Contains the timestamp of the last operation on this hoard and a mapping
@@ -143,9 +226,13 @@ impl LastPaths {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct HoardPaths {
     /// Timestamp of `HoardPaths`
-    timestamp: chrono::DateTime<chrono::Utc>,
+    timestamp:  chrono::DateTime<chrono::Utc>,
     /// Mapping of files to each `Pile`
-    piles:     PilePaths,
+    piles:      PilePaths,
+    /// The candidate paths each `Pile` with several of them chose from. The
+    /// anonymous pile is recorded under an empty name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    candidates: BTreeMap<String, Vec<PathBuf>>,
 }
 
 /// Internal type for [`HoardPaths`] mapping to anonymous or named piles.
@@ -180,18 +267,36 @@ impl From<HashMap<String, PathBuf>> for PilePaths {
     }
 }
 
-impl From<Hoard> for PilePaths {
+impl From<&Hoard> for HoardPaths {
     #[inline]
-    fn from(other: Hoard) -> Self {
-        match other {
-            Hoard::Anonymous(pile) => PilePaths::Anonymous(pile.path),
-            Hoard::Named(named) => PilePaths::Named(
-                named
-                    .piles
+    fn from(hoard: &Hoard) -> Self {
+        let piles: Vec<(&str, &Pile)> = match hoard {
+            Hoard::Anonymous(pile) => vec![(ANONYMOUS_PILE, pile)],
+            Hoard::Named(named) => named
+                .piles
+                .iter()
+                .map(|(name, pile)| (name.as_str(), pile))
+                .collect(),
+        };
+        let candidates = piles
+            .iter()
+            .filter(|(_, pile)| pile.candidates.len() > 1)
+            .map(|(name, pile)| ((*name).to_owned(), pile.candidates.clone()))
+            .collect();
+        let paths = match hoard {
+            Hoard::Anonymous(pile) => PilePaths::Anonymous(pile.path.clone()),
+            Hoard::Named(_) => PilePaths::Named(
+                piles
                     .into_iter()
-                    .filter_map(|(key, pile)| pile.path.map(|path| (key, path)))
+                    .filter_map(|(name, pile)| {
+                        pile.path.clone().map(|path| (name.to_owned(), path))
+                    })
                     .collect(),
             ),
+        };
+        Self {
+            candidates,
+            ..Self::from(paths)
         }
     }
 }
@@ -203,8 +308,9 @@ where
     #[inline]
     fn from(val: T) -> Self {
         Self {
-            timestamp: chrono::offset::Utc::now(),
-            piles:     val.into(),
+            timestamp:  chrono::offset::Utc::now(),
+            piles:      val.into(),
+            candidates: BTreeMap::new(),
         }
     }
 }
@@ -243,6 +349,24 @@ impl HoardPaths {
         }
     }
 
+    /// Add the paths and candidates that `old` recorded for the piles that
+    /// are not part of `hoard`, which these paths were taken from.
+    fn keep_other_piles(&mut self, old: HoardPaths, hoard: &Hoard) {
+        let named = match hoard {
+            Hoard::Named(named) => named,
+            Hoard::Anonymous(_) => return,
+        };
+        let is_other = |pile: &String| !named.piles.contains_key(pile);
+        if let (PilePaths::Named(new), PilePaths::Named(old)) = (&mut self.piles, old.piles) {
+            new.extend(old.into_iter().filter(|(pile, _)| is_other(pile)));
+        }
+        self.candidates.extend(
+            old.candidates
+                .into_iter()
+                .filter(|(pile, _)| is_other(pile)),
+        );
+    }
+
     /// Logs any inconsistencies and returns an error if any are found.
     ///
     /// This check basically returns an error if `old != new`, but does some
@@ -292,6 +416,11 @@ impl HoardPaths {
     pub fn enforce_old_and_new_piles_are_same(old: &Self, new: &Self) -> Result<(), Error> {
         tracing::debug!("comparing old and new piles' paths");
         tracing::trace!(?old, ?new);
+        let candidates = |pile: &str| {
+            new.candidates
+                .get(pile)
+                .or_else(|| old.candidates.get(pile))
+        };
         match (&old.piles, &new.piles) {
             (PilePaths::Anonymous(old), PilePaths::Anonymous(new)) => {
                 tracing::trace!("both piles are anonymous");
@@ -306,7 +435,7 @@ impl HoardPaths {
                     // If both are None, they are the same. So check only for both as Some(_).
                     // Then check if the paths match.
                     if old != new {
-                        tracing::warn!(?old, ?new, "anonymous pile path changed");
+                        warn_path_changed(candidates(ANONYMOUS_PILE), ANONYMOUS_PILE, old, new);
                         return Err(Error::HoardPathsMismatch);
                     }
                 }
@@ -348,12 +477,7 @@ impl HoardPaths {
                     let new_path = new.get(key).expect("key should exist in map");
                     if old_path != new_path {
                         mismatch = true;
-                        tracing::warn!(
-                            ?old_path,
-                            ?new_path,
-                            "pile \"{}\" has a different path",
-                            key
-                        );
+                        warn_path_changed(candidates(key), key, old_path, new_path);
                     }
                 }
 
@@ -367,9 +491,53 @@ impl HoardPaths {
     }
 }
 
+/// The positions of `old_path` and `new_path` among the `candidates` of a
+/// pile, if both are candidates of it.
+fn candidate_positions(
+    candidates: Option<&Vec<PathBuf>>,
+    old_path: &Path,
+    new_path: &Path,
+) -> Option<(usize, usize)> {
+    let candidates = candidates?;
+    let position = |path: &Path| candidates.iter().position(|candidate| candidate == path);
+    Some((position(old_path)?, position(new_path)?))
+}
+
+/// Log that `pile` has a different path and, if both paths are among its
+/// `candidates`, which candidate it switched from and to.
+fn warn_path_changed(
+    candidates: Option<&Vec<PathBuf>>,
+    pile: &str,
+    old_path: &Path,
+    new_path: &Path,
+) {
+    let name = if pile == ANONYMOUS_PILE {
+        "anonymous pile".to_owned()
+    } else {
+        format!("pile \"{}\"", pile)
+    };
+    match candidate_positions(candidates, old_path, new_path) {
+        Some((old_index, new_index)) => tracing::warn!(
+            ?old_path,
+            ?new_path,
+            "{} switched from candidate path {} to candidate path {}{}",
+            name,
+            old_index + 1,
+            new_index + 1,
+            if new_index > old_index {
+                ", the previous one may no longer exist"
+            } else {
+                ""
+            }
+        ),
+        None => tracing::warn!(?old_path, ?new_path, "{} has a different path", name),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::hoard::MultipleEntries;
     use maplit::hashmap;
 
     const NAMED_PILE_1: &str = "test1";
@@ -402,6 +570,123 @@ mod tests {
         assert_eq!(got_hoard_paths, Some(&hoard_paths));
     }
 
+    #[test]
+    fn migrations_rename_and_forget_entries() {
+        let mut last_paths = LastPaths::default();
+        last_paths.set_hoard("anon".to_owned(), anonymous_hoard_paths());
+        last_paths.set_hoard("named".to_owned(), named_hoard_paths());
+
+        last_paths.migrate(&Migration::RenameHoard {
+            from: "anon",
+            to:   "renamed",
+        });
+        assert_eq!(last_paths.hoard("anon"), None);
+        assert_eq!(
+            last_paths
+                .hoard("renamed")
+                .and_then(HoardPaths::anonymous_pile),
+            Some(&PathBuf::from("/test/path"))
+        );
+
+        last_paths.migrate(&Migration::RenamePile {
+            hoard: "named",
+            from:  NAMED_PILE_1,
+            to:    "new",
+        });
+        last_paths.migrate(&Migration::ForgetPile {
+            hoard: "named",
+            pile:  NAMED_PILE_2,
+        });
+        let named = last_paths.hoard("named").unwrap();
+        assert_eq!(named.named_pile("new"), Some(&PathBuf::from("/test/path")));
+        assert_eq!(named.named_pile(NAMED_PILE_1), None);
+        assert_eq!(named.named_pile(NAMED_PILE_2), None);
+
+        last_paths.migrate(&Migration::ForgetHoard("renamed"));
+        assert_eq!(last_paths.hoard("renamed"), None);
+    }
+
+    #[test]
+    fn paths_of_other_piles_are_kept() {
+        let mut old = named_hoard_paths();
+        old.candidates
+            .insert(NAMED_PILE_2.into(), vec![PathBuf::from("/test/other/path")]);
+        let pile = |path: Option<&str>| Pile {
+            config:     None,
+            path:       path.map(PathBuf::from),
+            candidates: Vec::new(),
+        };
+        let hoard = |path: Option<&str>| {
+            Hoard::Named(MultipleEntries {
+                piles: hashmap! { NAMED_PILE_1.into() => pile(path) },
+            })
+        };
+
+        let mut new = HoardPaths::from(&hoard(Some("/new/path")));
+        new.keep_other_piles(old.clone(), &hoard(Some("/new/path")));
+        assert_eq!(
+            new.named_pile(NAMED_PILE_1),
+            Some(&PathBuf::from("/new/path"))
+        );
+        assert_eq!(old.named_pile(NAMED_PILE_2), new.named_pile(NAMED_PILE_2));
+        assert_eq!(old.candidates, new.candidates);
+
+        let mut new = HoardPaths::from(&hoard(None));
+        new.keep_other_piles(old.clone(), &hoard(None));
+        assert_eq!(
+            new.named_pile(NAMED_PILE_1),
+            None,
+            "the pile no longer has a path"
+        );
+        assert_eq!(old.named_pile(NAMED_PILE_2), new.named_pile(NAMED_PILE_2));
+    }
+
+    #[test]
+    fn candidates_of_changed_paths_are_located() {
+        let pile = |path: &str| Pile {
+            config:     None,
+            path:       Some(PathBuf::from(path)),
+            candidates: vec![PathBuf::from("/flatpak"), PathBuf::from("/native")],
+        };
+        let old = HoardPaths::from(&Hoard::Anonymous(pile("/flatpak")));
+        let new = HoardPaths::from(&Hoard::Anonymous(pile("/native")));
+        assert_eq!(
+            new.candidates.get(ANONYMOUS_PILE).map(Vec::len),
+            Some(2),
+            "candidates are recorded"
+        );
+        assert!(matches!(
+            HoardPaths::enforce_old_and_new_piles_are_same(&old, &new),
+            Err(Error::HoardPathsMismatch)
+        ));
+        assert_eq!(
+            candidate_positions(
+                new.candidates.get(ANONYMOUS_PILE),
+                Path::new("/flatpak"),
+                Path::new("/native")
+            ),
+            Some((0, 1))
+        );
+        assert_eq!(
+            candidate_positions(
+                new.candidates.get(ANONYMOUS_PILE),
+                Path::new("/elsewhere"),
+                Path::new("/native")
+            ),
+            None
+        );
+        let single = Pile {
+            candidates: vec![PathBuf::from("/native")],
+            ..pile("/native")
+        };
+        assert!(
+            HoardPaths::from(&Hoard::Anonymous(single))
+                .candidates
+                .is_empty(),
+            "piles with a single path record no candidates"
+        );
+    }
+
     #[test]
     fn test_hoard_paths_time_returns_timestamp_reference() {
         let hoard_paths = anonymous_hoard_paths();
@@ -16,7 +16,8 @@ fn get_uuid_file() -> PathBuf {
     PROJECT_DIRS.config_dir().join(UUID_FILE_NAME)
 }
 
-fn get_history_root_dir() -> PathBuf {
+/// The directory containing the operation history of all systems.
+pub(crate) fn get_history_root_dir() -> PathBuf {
     let _span = tracing::debug_span!("get_history_root_dir").entered();
     PROJECT_DIRS.data_dir().join(HISTORY_DIR_NAME)
 }
@@ -26,6 +27,59 @@ fn get_history_dir_for_id(id: Uuid) -> PathBuf {
     get_history_root_dir().join(id.to_string())
 }
 
+/// A change to the names of hoards or piles that their history must follow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Migration<'a> {
+    /// A hoard was renamed.
+    RenameHoard {
+        /// The old name of the hoard.
+        from: &'a str,
+        /// The new name of the hoard.
+        to:   &'a str,
+    },
+    /// A named pile was renamed.
+    RenamePile {
+        /// The hoard the pile belongs to.
+        hoard: &'a str,
+        /// The old name of the pile.
+        from:  &'a str,
+        /// The new name of the pile.
+        to:    &'a str,
+    },
+    /// A hoard was removed.
+    ForgetHoard(&'a str),
+    /// A named pile was removed.
+    ForgetPile {
+        /// The hoard the pile belonged to.
+        hoard: &'a str,
+        /// The name of the pile.
+        pile:  &'a str,
+    },
+}
+
+impl Migration<'_> {
+    /// The name of the hoard whose history is changed.
+    #[must_use]
+    pub fn hoard(&self) -> &str {
+        match *self {
+            Self::RenameHoard { from, .. } | Self::ForgetHoard(from) => from,
+            Self::RenamePile { hoard, .. } | Self::ForgetPile { hoard, .. } => hoard,
+        }
+    }
+}
+
+/// The history directories of all systems, including this one.
+///
+/// # Errors
+///
+/// Any I/O error while reading the history root directory.
+pub(crate) fn get_all_history_dirs() -> Result<Vec<PathBuf>, io::Error> {
+    let id = get_or_generate_uuid()?;
+    let mut dirs = get_history_dirs_not_for_id(&id)?;
+    dirs.push(get_history_dir_for_id(id));
+    Ok(dirs)
+}
+
 fn get_history_dirs_not_for_id(id: &Uuid) -> Result<Vec<PathBuf>, io::Error> {
     let _span = tracing::debug_span!("get_history_dir_not_for_id", %id).entered();
     let root = get_history_root_dir();
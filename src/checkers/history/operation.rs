@@ -7,9 +7,14 @@
 //! It does this by parsing synchronized logs from this and other systems to
 //! determine which system was the last one to touch a file.
 
+use super::Migration;
 use crate::{
     checkers::Checker,
-    config::hoard::{Hoard as ConfigHoard, Pile as ConfigPile},
+    config::{
+        builder::glob,
+        hoard::{Hoard as ConfigHoard, Pile as ConfigPile},
+    },
+    utils,
 };
 use md5::{Digest, Md5};
 use once_cell::sync::Lazy;
@@ -139,8 +144,8 @@ impl Checker for HoardOperation {
             .join(format!("{}.log", self.timestamp.format(TIME_FORMAT_STR)));
         tracing::trace!(path=%path.display(), "ensuring parent directories for operation log file");
         path.parent().map(fs::create_dir_all).transpose()?;
-        let file = fs::File::create(path)?;
-        serde_json::to_writer(file, &self)?;
+        let content = serde_json::to_vec(&self)?;
+        utils::write_atomic(&path, &content)?;
         Ok(())
     }
 }
@@ -160,6 +165,22 @@ impl HoardOperation {
         result
     }
 
+    /// Like [`Checker::new`], for a backup of only the piles of the named
+    /// hoard `hoard`: the other piles of the hoard keep the checksums of the
+    /// latest operation on this system, as their files were not copied.
+    ///
+    /// # Errors
+    ///
+    /// Any error while hashing the files of the piles or reading the latest
+    /// operation.
+    pub fn for_piles(name: &str, hoard: &ConfigHoard) -> Result<Self, Error> {
+        let mut operation = <Self as Checker>::new(name, hoard, true)?;
+        if let Some(latest) = Self::latest_local(name)? {
+            operation.hoard.keep_other_piles(latest.hoard);
+        }
+        Ok(operation)
+    }
+
     /// Checks if files in both operations are the same.
     ///
     /// # Errors
@@ -235,6 +256,47 @@ impl HoardOperation {
         Self::get_latest_hoard_operation_from_system_dir(&self_folder, hoard, false)
     }
 
+    /// Returns the latest backup operation recorded on this machine (by UUID).
+    ///
+    /// # Errors
+    ///
+    /// - Any errors that occur while reading from the filesystem
+    /// - Any parsing errors from `serde_json` when parsing the file
+    pub fn latest_local_backup(hoard: &str) -> Result<Option<Self>, Error> {
+        let _span = tracing::debug_span!("latest_local_backup", %hoard).entered();
+        tracing::debug!("finding latest backup Operation file for this machine");
+        let uuid = super::get_or_generate_uuid()?;
+        let self_folder = super::get_history_dir_for_id(uuid);
+        Self::get_latest_hoard_operation_from_system_dir(&self_folder, hoard, true)
+    }
+
+    /// Apply `migration` to this operation.
+    fn migrate(&mut self, migration: &Migration) {
+        match (*migration, &mut self.hoard) {
+            (Migration::RenameHoard { to, .. }, _) => to.clone_into(&mut self.hoard_name),
+            (Migration::RenamePile { from, to, .. }, Hoard::Named(piles)) => {
+                if let Some(pile) = piles.remove(from) {
+                    piles.insert(to.to_owned(), pile);
+                }
+            },
+            (Migration::ForgetPile { pile, .. }, Hoard::Named(piles)) => {
+                piles.remove(pile);
+            },
+            (
+                Migration::ForgetHoard(_)
+                | Migration::RenamePile { .. }
+                | Migration::ForgetPile { .. },
+                _,
+            ) => {},
+        }
+    }
+
+    /// The time at which this operation was started.
+    #[must_use]
+    pub fn timestamp(&self) -> chrono::DateTime<chrono::Utc> {
+        self.timestamp
+    }
+
     /// Returns the latest backup operation recorded on any other machine (by
     /// UUID).
     ///
@@ -267,6 +329,50 @@ impl HoardOperation {
     }
 }
 
+/// Apply `migration` to the operation logs in the history directory `dir` of a
+/// system.
+///
+/// Logs of a renamed hoard are moved to the directory of the new name, and
+/// logs of a forgotten hoard are deleted.
+///
+/// # Errors
+///
+/// Any I/O or `serde` error while reading, writing or deleting logs.
+pub fn migrate(dir: &Path, migration: &Migration) -> Result<(), Error> {
+    let root = dir.join(migration.hoard());
+    let _span = tracing::debug_span!("migrate_operations", ?root, ?migration).entered();
+    if !root.exists() {
+        return Ok(());
+    }
+
+    let target = match *migration {
+        Migration::ForgetHoard(_) => {
+            fs::remove_dir_all(&root)?;
+            return Ok(());
+        },
+        Migration::RenameHoard { to, .. } => dir.join(to),
+        Migration::RenamePile { .. } | Migration::ForgetPile { .. } => root.clone(),
+    };
+    fs::create_dir_all(&target)?;
+
+    for entry in fs::read_dir(&root)? {
+        let path = entry?.path();
+        let file_name = match path.file_name() {
+            Some(file_name) if HoardOperation::file_is_log(&path) => file_name.to_owned(),
+            _ => continue,
+        };
+        tracing::trace!(path = %path.display(), "migrating operation log");
+        let mut operation: HoardOperation = serde_json::from_reader(fs::File::open(&path)?)?;
+        operation.migrate(migration);
+        utils::write_atomic(&target.join(file_name), &serde_json::to_vec(&operation)?)?;
+    }
+
+    if target != root {
+        fs::remove_dir_all(&root)?;
+    }
+    Ok(())
+}
+
 /// Operation log information for a single hoard.
 ///
 /// Really just a wrapper for [`Pile`] because piles may be anonymous or named.
@@ -278,6 +384,17 @@ pub enum Hoard {
     Named(HashMap<String, Pile>),
 }
 
+impl Hoard {
+    /// Add the checksums that `other` has for the piles this hoard does not.
+    fn keep_other_piles(&mut self, other: Hoard) {
+        if let (Hoard::Named(piles), Hoard::Named(other)) = (self, other) {
+            for (name, pile) in other {
+                piles.entry(name).or_insert(pile);
+            }
+        }
+    }
+}
+
 impl TryFrom<&ConfigHoard> for Hoard {
     type Error = Error;
 
@@ -337,9 +454,20 @@ impl TryFrom<&ConfigPile> for Pile {
 
     fn try_from(pile: &ConfigPile) -> Result<Self, Self::Error> {
         let _span = tracing::trace_span!("pile_to_operation", ?pile).entered();
-        pile.path.as_ref().map_or_else(
-            || Ok(Self(HashMap::new())),
-            |path| hash_path(path, path).map(Self),
-        )
+        let path = match &pile.path {
+            Some(path) => path,
+            None => return Ok(Self(HashMap::new())),
+        };
+        match glob::split(path) {
+            // The entries of a pile ending in a pattern are stored by name.
+            Ok((root, Some(_))) => {
+                let mut map = HashMap::new();
+                for entry in glob::entries(path).unwrap_or_default() {
+                    map.extend(hash_path(&entry, root)?);
+                }
+                Ok(Self(map))
+            },
+            Ok((_, None)) | Err(_) => hash_path(path, path).map(Self),
+        }
     }
 }
@@ -16,6 +16,7 @@
 //! optimizations are applied to make later evaluations easier.
 
 use core::fmt;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     convert::{TryFrom, TryInto},
@@ -24,7 +25,7 @@ use std::{
 };
 
 /// An internal container for the [`Combinator<T>`] type.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Hash, JsonSchema)]
 #[serde(untagged)]
 pub enum Inner<T: TryInto<bool>> {
     /// A single item that can be converted to a boolean.
@@ -61,6 +62,14 @@ where
             Inner::Multiple(list) => list.is_empty(),
         }
     }
+
+    /// The items in this [`Inner<T>`].
+    pub fn items(&self) -> &[T] {
+        match self {
+            Inner::Single(item) => std::slice::from_ref(item),
+            Inner::Multiple(list) => list,
+        }
+    }
 }
 
 impl<T, E> TryFrom<Inner<T>> for bool
@@ -109,10 +118,20 @@ where
 ///
 /// If at least one of the [`Inner<T>`] items evaluates to `true`, then entire
 /// `Combinator<T>` will evaluate to `true`.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Hash, JsonSchema)]
 #[serde(transparent)]
 pub struct Combinator<T: TryInto<bool>>(pub Vec<Inner<T>>);
 
+impl<T> Combinator<T>
+where
+    T: TryInto<bool>,
+{
+    /// Every item in the [`Combinator<T>`], regardless of how it is combined.
+    pub fn items(&self) -> impl Iterator<Item = &T> {
+        self.0.iter().flat_map(Inner::items)
+    }
+}
+
 impl<T> Combinator<T>
 where
     T: Serialize + TryInto<bool>,
@@ -531,6 +550,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_combinator_items() {
+        let combinator = Combinator(vec![
+            Inner::Single(Tester(true)),
+            Inner::Multiple(vec![]),
+            Inner::Multiple(vec![Tester(false), Tester(true)]),
+        ]);
+        let items: Vec<_> = combinator.items().collect();
+        assert_eq!(items, [&Tester(true), &Tester(false), &Tester(true)]);
+    }
+
     #[test]
     fn test_combinator_is_empty() {
         for case in CASES.iter() {
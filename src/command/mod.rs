@@ -29,7 +29,7 @@ pub enum Command {
     Config {
         /// Whether to convert file-type
         #[structopt(name = "convert", short = "x", long = "convert", takes_value = false)]
-        convert:       bool,
+        convert:        bool,
         /// Input file format
         #[structopt(
             short = "i", long = "input-format",
@@ -37,7 +37,7 @@ pub enum Command {
             value_name = "format",
             possible_values = &ConfigFormat::variants()
         )]
-        input_format:  Option<String>,
+        input_format:   Option<String>,
         /// Output file format
         #[structopt(
             short = "f", long = "output-format",
@@ -45,7 +45,7 @@ pub enum Command {
             value_name = "format",
             possible_values = &ConfigFormat::variants()
         )]
-        output_format: Option<String>,
+        output_format:  Option<String>,
         /// Output file path
         #[structopt(
             short = "o",
@@ -53,13 +53,30 @@ pub enum Command {
             takes_value = true,
             value_name = "file"
         )]
-        output_file:   Option<PathBuf>,
+        output_file:    Option<PathBuf>,
+        /// Convert the configuration file itself, replacing it with one in the
+        /// output format.
+        #[structopt(
+            short = "I", long = "in-place",
+            takes_value = false,
+            requires_all = &["convert", "output-format"],
+            conflicts_with = "output-file"
+        )]
+        in_place:       bool,
+        /// Print the configuration as it is used, after merging every
+        /// configuration file and applying environment variable overrides.
+        #[structopt(
+            long = "show-effective",
+            takes_value = false,
+            conflicts_with_all = &["convert", "cache_build", "cache_clear"]
+        )]
+        show_effective: bool,
         /// Theme to use for colored output
         #[structopt(short = "t", long = "theme", takes_value = true, requires = "color")]
-        theme:         Option<String>,
+        theme:          Option<String>,
         /// Whether to color output
         #[structopt(name = "color", short = "C", long = "color", takes_value = false)]
-        color:         bool,
+        color:          bool,
         /// Build cache for custom themes
         #[structopt(
             name = "cache_build",
@@ -67,7 +84,7 @@ pub enum Command {
             takes_value = false,
             conflicts_with_all = &["cache_clear", "convert"]
         )]
-        cache_build:   bool,
+        cache_build:    bool,
         /// Clear cache for custom themes
         #[structopt(
             name = "cache_clear",
@@ -75,7 +92,7 @@ pub enum Command {
             takes_value = false,
             conflicts_with_all = &["cache_build", "convert"],
         )]
-        cache_clear:   bool,
+        cache_clear:    bool,
         /// Source path to build or clear
         #[structopt(
             name = "cache_source",
@@ -84,7 +101,7 @@ pub enum Command {
             takes_value = true,
             requires = "cache_build"
         )]
-        source:        Option<String>,
+        source:         Option<String>,
         /// Destination path to build or clear
         #[structopt(
             name = "cache_dest",
@@ -93,11 +110,19 @@ pub enum Command {
             takes_value = true,
             requires = "cache_build"
         )]
-        dest:          Option<String>,
+        dest:           Option<String>,
+        /// What to do instead of converting the configuration file.
+        #[structopt(subcommand)]
+        action:         Option<ConfigCommand>,
+    },
+    /// Loads all configuration for validation and checks it for likely
+    /// mistakes. Succeeds if the configuration builds and no check finds an
+    /// error.
+    Validate {
+        /// Also fail on warnings.
+        #[structopt(long, value_name = "level", possible_values = &["warnings"])]
+        deny: Option<String>,
     },
-    /// Loads all configuration for validation.
-    /// If the configuration loads and builds, this command succeeds.
-    Validate,
     /// Back up the given hoard(s).
     Backup {
         /// The name(s) of the hoard(s) to back up. Will back up all hoards if
@@ -110,6 +135,129 @@ pub enum Command {
         /// empty.
         hoards: Vec<String>,
     },
+    /// Watch the given hoard(s) and back up piles automatically when their
+    /// files change.
+    Watch {
+        /// The name(s) of the hoard(s) to watch. Will watch all hoards if
+        /// empty.
+        hoards: Vec<String>,
+        /// Seconds to wait after the last change before backing up a pile.
+        #[structopt(short, long, default_value = "2")]
+        delay:  u64,
+    },
+    /// Run backups of hoards unattended, according to their `schedule`.
+    Schedule(ScheduleCommand),
+    /// Run git in the hoards root, e.g. `hoard git log` or
+    /// `hoard git remote add origin <url>`.
+    #[structopt(setting = clap::AppSettings::TrailingVarArg)]
+    Git {
+        /// Arguments to pass to git.
+        #[structopt(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Add a file or directory to a hoard in the configuration file.
+    Adopt {
+        /// The file or directory to adopt.
+        path:   PathBuf,
+        /// The hoard to add it to. Created if it does not exist.
+        #[structopt(long)]
+        hoard:  String,
+        /// The pile to add it to. Required if the hoard has named piles.
+        #[structopt(long)]
+        pile:   Option<String>,
+        /// The environment condition to use the path under, e.g.
+        /// `linux|laptop`. Defaults to the only environment that applies.
+        #[structopt(long)]
+        env:    Option<String>,
+        /// Back up the hoard after adding the path.
+        #[structopt(short, long)]
+        backup: bool,
+    },
+    /// Import the dotfiles managed by stow, dotbot, chezmoi or yadm as hoards,
+    /// copying the files into the hoards root as their first backup.
+    ImportConfig {
+        /// The tool that manages the dotfiles.
+        #[structopt(
+            long,
+            value_name = "tool",
+            possible_values = &["stow", "dotbot", "chezmoi", "yadm"]
+        )]
+        from:    String,
+        /// The stow directory, dotbot configuration file (or its directory),
+        /// chezmoi source directory or yadm repository.
+        path:    PathBuf,
+        /// The directory the tool installs into. Defaults to the parent of the
+        /// stow directory for stow, and the home directory otherwise.
+        #[structopt(long)]
+        target:  Option<PathBuf>,
+        /// Print the new configuration instead of writing it and copying the
+        /// files.
+        #[structopt(short = "n", long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Rename a hoard, or a named pile with `hoard:pile`, along with its
+    /// stored files and history.
+    Rename {
+        /// The hoard (`hoard`) or pile (`hoard:pile`) to rename.
+        old: String,
+        /// The new name. For a pile, either `pile` or `hoard:pile`.
+        new: String,
+    },
+    /// Remove a hoard, or a named pile with `hoard:pile`, from the
+    /// configuration, along with its stored files and history.
+    Forget {
+        /// The hoard (`hoard`) or pile (`hoard:pile`) to forget.
+        target:     String,
+        /// Keep the stored files in the hoards root.
+        #[structopt(long)]
+        keep_files: bool,
+    },
+    /// List every hoard and pile with the path it uses on this system.
+    List {
+        /// Also list every configured environment condition of each pile.
+        #[structopt(short, long)]
+        all: bool,
+    },
+    /// Show how each environment is evaluated and how the path of each pile
+    /// is chosen.
+    Explain {
+        /// The hoard (`hoard`) or pile (`hoard:pile`) to explain. Defaults to
+        /// all of them.
+        target: Option<String>,
+        /// `text` for a full explanation, or `shell` to print whether each
+        /// environment applies as `export` statements.
+        #[structopt(
+            long,
+            value_name = "format",
+            possible_values = &["text", "shell"],
+            default_value = "text"
+        )]
+        format: String,
+    },
+    /// Edit the configuration file in `$VISUAL` or `$EDITOR`, saving it only
+    /// once it is valid.
+    Edit,
+    /// Generate a starter configuration file.
+    Init {
+        /// Add a hoard for each known application found on this system.
+        #[structopt(long)]
+        detect:      bool,
+        /// The format to write the configuration in. Defaults to the format of
+        /// the output file.
+        #[structopt(
+            short = "f", long = "format",
+            value_name = "format",
+            possible_values = &ConfigFormat::variants()
+        )]
+        format:      Option<String>,
+        /// Where to write the configuration. Defaults to the configuration
+        /// file, with its extension changed to match `--format`.
+        #[structopt(short = "o", long = "output-file", parse(from_os_str))]
+        output_file: Option<PathBuf>,
+        /// Print the configuration instead of writing it.
+        #[structopt(short = "n", long = "dry-run")]
+        dry_run:     bool,
+    },
     /// Add item to configuration file
     Add {
         /// Add an environment to file
@@ -121,6 +269,38 @@ pub enum Command {
     },
 }
 
+/// The possible actions of `hoard config`.
+#[derive(Clone, PartialEq, Debug, StructOpt)]
+pub enum ConfigCommand {
+    /// Print the JSON Schema of the configuration file, for editors to
+    /// complete and validate configuration files with.
+    Schema,
+}
+
+/// The possible actions of `hoard schedule`.
+#[derive(Clone, PartialEq, Debug, StructOpt)]
+pub enum ScheduleCommand {
+    /// Install systemd user units (or a crontab block) that back up every
+    /// hoard with a `schedule`.
+    Install {
+        /// Install into the user's crontab instead of systemd user units.
+        #[structopt(long)]
+        cron:    bool,
+        /// Print what would be installed without installing it.
+        #[structopt(short = "n", long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Remove all units (or the crontab block) installed by `install`.
+    Uninstall {
+        /// Remove the crontab block instead of systemd user units.
+        #[structopt(long)]
+        cron: bool,
+    },
+    /// Show the schedule, last backup and next backup of each scheduled
+    /// hoard.
+    Status,
+}
+
 // #[allow(non_camel_case_types)]
 // #[derive(Debug, StructOpt)]
 // pub enum CacheCommands {
@@ -128,9 +308,27 @@ pub enum Command {
 //     clear
 // }
 
+impl Command {
+    /// Whether the command needs a valid configuration file to run, as
+    /// opposed to commands that create or fix one.
+    #[must_use]
+    pub fn requires_valid_config(&self) -> bool {
+        !matches!(
+            self,
+            Self::Edit
+                | Self::Init { .. }
+                | Self::ImportConfig { .. }
+                | Self::Config {
+                    action: Some(ConfigCommand::Schema),
+                    ..
+                }
+        )
+    }
+}
+
 impl Default for Command {
     fn default() -> Self {
-        Self::Validate
+        Self::Validate { deny: None }
     }
 }
 
@@ -141,6 +339,6 @@ mod tests {
     #[test]
     fn default_command_is_help() {
         // The default command is validate if one is not given
-        assert_eq!(Command::Validate, Command::default());
+        assert_eq!(Command::Validate { deny: None }, Command::default());
     }
 }
@@ -0,0 +1,447 @@
+//! Add files and directories to hoards from the command line.
+//!
+//! `hoard adopt <path> --hoard <name>` adds `path` to the configuration file as
+//! a new hoard, a new pile of an existing hoard, or a new environment condition
+//! of an existing pile. The file is written back in its original format.
+//!
+//! See [`run_adopt`].
+
+use super::{
+    builder::glob,
+    directories::PROJECT_DIRS,
+    edit::{self, ConfigDocument},
+    hoard::Hoard,
+    Config,
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Errors that can occur while adopting a path.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The path to adopt does not exist.
+    #[error("cannot adopt {0}: no such file or directory")]
+    NotFound(PathBuf),
+    /// The path is already backed up by a pile, or contains one.
+    #[error("{path} overlaps with {pile_path}, which is already in {hoard}")]
+    AlreadyCovered {
+        /// The path to adopt.
+        path:      PathBuf,
+        /// The hoard (and pile) that covers the path.
+        hoard:     String,
+        /// The path of that pile.
+        pile_path: PathBuf,
+    },
+    /// A hoard or pile name cannot be used as a directory name.
+    #[error("invalid hoard or pile name: {0:?}")]
+    InvalidName(String),
+    /// The condition names an environment that is not configured.
+    #[error("no such environment is configured: {0}")]
+    UnknownEnv(String),
+    /// The condition does not apply to this system, so the path would not be
+    /// used here.
+    #[error("condition {0} does not apply to this system")]
+    ConditionDoesNotApply(String),
+    /// No condition was given and there is no single obvious one.
+    #[error(
+        "cannot choose an environment condition, pass one with --env (matching environments: \
+         {})",
+        if .0.is_empty() { "none".to_owned() } else { .0.join(", ") }
+    )]
+    AmbiguousEnv(Vec<String>),
+    /// The hoard has named piles, but no pile name was given.
+    #[error("hoard {0} has named piles, pass the pile to add with --pile")]
+    PileRequired(String),
+    /// A pile name was given for a hoard with a single anonymous pile.
+    #[error("hoard {0} has a single anonymous pile, it cannot have named piles")]
+    AnonymousHoard(String),
+    /// The pile already has a path for the condition.
+    #[error("{hoard} already has a path for condition {condition}")]
+    ConditionExists {
+        /// The hoard (and pile) that has the condition.
+        hoard:     String,
+        /// The condition.
+        condition: String,
+    },
+    /// Error while editing the configuration file.
+    #[error("failed to update configuration file: {0}")]
+    Edit(#[from] edit::Error),
+    /// Error while printing the result.
+    #[error("failed to print result: {0}")]
+    IO(#[from] io::Error),
+}
+
+/// How to add the path to the configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Adoption<'a> {
+    /// The hoard to add the path to.
+    hoard:     &'a str,
+    /// The pile to add the path to, if the hoard has named piles.
+    pile:      Option<&'a str>,
+    /// The environment condition to use the path under.
+    condition: &'a str,
+    /// The path as it is written to the configuration file.
+    path:      &'a str,
+}
+
+/// `hoard` and, if given, `pile` as they are shown to the user.
+fn display_name(hoard: &str, pile: Option<&str>) -> String {
+    pile.map_or_else(|| hoard.to_owned(), |pile| [hoard, ":", pile].concat())
+}
+
+/// Whether `name` can be used as the name of a hoard (or, if `is_pile`, a
+/// pile), i.e. as the name of a directory in the hoards root.
+pub(super) fn is_valid_name(name: &str, is_pile: bool) -> bool {
+    let reserved = is_pile && matches!(name, "config" | "schedule");
+    !(reserved
+        || name.is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\', ':'].as_ref()))
+}
+
+/// Fail if `name` cannot be used as the name of a hoard or pile.
+fn check_name(name: &str, is_pile: bool) -> Result<(), Error> {
+    if is_valid_name(name, is_pile) {
+        Ok(())
+    } else {
+        Err(Error::InvalidName(name.to_owned()))
+    }
+}
+
+/// `path` as an absolute path, relative to the current directory.
+fn absolute(path: &Path) -> Result<PathBuf, Error> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+    // Drop `.` components and trailing slashes
+    let path = path.components().collect::<PathBuf>();
+    if path.symlink_metadata().is_ok() {
+        Ok(path)
+    } else {
+        Err(Error::NotFound(path))
+    }
+}
+
+/// `path` as it should be written to the configuration file, with the home
+/// directory replaced by `${HOME}`.
+pub(super) fn stored_path(path: &Path, home: &Path) -> String {
+    match path.strip_prefix(home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "${HOME}".to_owned(),
+        Ok(rest) => format!("${{HOME}}/{}", rest.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+/// Fail if `path` is inside, or contains, any candidate path of a configured
+/// pile.
+pub(super) fn check_not_covered(path: &Path, hoards: &HashMap<String, Hoard>) -> Result<(), Error> {
+    for (name, hoard) in hoards {
+        let piles: Vec<(Option<&str>, &[PathBuf])> = match hoard {
+            Hoard::Anonymous(pile) => vec![(None, &pile.candidates)],
+            Hoard::Named(named) => named
+                .piles
+                .iter()
+                .map(|(pile_name, pile)| (Some(pile_name.as_str()), pile.candidates.as_slice()))
+                .collect(),
+        };
+
+        for (pile, candidates) in piles {
+            for pile_path in candidates {
+                if glob::contains(pile_path, path) || pile_path.starts_with(path) {
+                    return Err(Error::AlreadyCovered {
+                        path:      path.to_path_buf(),
+                        hoard:     display_name(name, pile),
+                        pile_path: pile_path.clone(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The parts of `condition`, sorted so that equivalent conditions compare
+/// equal.
+fn condition_parts(condition: &str) -> Vec<&str> {
+    let mut parts = condition.split('|').map(str::trim).collect::<Vec<_>>();
+    parts.sort_unstable();
+    parts
+}
+
+/// The condition to use: `condition` if given and it applies to this system,
+/// otherwise the only environment that applies.
+fn resolve_condition(
+    condition: Option<&str>,
+    envs: &HashMap<String, bool>,
+) -> Result<String, Error> {
+    if let Some(condition) = condition {
+        let parts = condition_parts(condition);
+        for part in &parts {
+            match envs.get(*part) {
+                Some(true) => {},
+                Some(false) => return Err(Error::ConditionDoesNotApply(condition.to_owned())),
+                None => return Err(Error::UnknownEnv((*part).to_owned())),
+            }
+        }
+        return Ok(parts.join("|"));
+    }
+
+    let mut matching = envs
+        .iter()
+        .filter(|(_, applies)| **applies)
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    matching.sort_unstable();
+    match matching.as_slice() {
+        [only] => Ok(only.clone()),
+        _ => Err(Error::AmbiguousEnv(matching)),
+    }
+}
+
+/// Add `condition = path` to `pile`, unless it already has that condition.
+fn add_condition(pile: &mut Map<String, Value>, adoption: &Adoption) -> Result<(), Error> {
+    let wanted = condition_parts(adoption.condition);
+    if pile
+        .keys()
+        .any(|condition| condition_parts(condition) == wanted)
+    {
+        return Err(Error::ConditionExists {
+            hoard:     display_name(adoption.hoard, adoption.pile),
+            condition: adoption.condition.to_owned(),
+        });
+    }
+    pile.insert(
+        adoption.condition.to_owned(),
+        Value::String(adoption.path.to_owned()),
+    );
+    Ok(())
+}
+
+/// Add the adopted path to the `hoards` table of a configuration file.
+///
+/// `existing` is the processed hoard of the same name, if there is one. It
+/// tells whether the hoard has an anonymous pile or named ones.
+fn add_to_hoards(
+    hoards: &mut Map<String, Value>,
+    existing: Option<&Hoard>,
+    adoption: &Adoption,
+) -> Result<(), Error> {
+    let not_a_table = || edit::Error::NotATable(format!("hoards.{}", adoption.hoard));
+    let hoard = hoards
+        .entry(adoption.hoard)
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(not_a_table)?;
+
+    match (existing, adoption.pile) {
+        (Some(Hoard::Anonymous(_)), Some(_)) =>
+            Err(Error::AnonymousHoard(adoption.hoard.to_owned())),
+        (Some(Hoard::Named(_)), None) => Err(Error::PileRequired(adoption.hoard.to_owned())),
+        (Some(Hoard::Anonymous(_)) | None, None) => add_condition(hoard, adoption),
+        (Some(Hoard::Named(_)) | None, Some(pile)) => {
+            let pile = hoard
+                .entry(pile)
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .ok_or_else(|| {
+                    edit::Error::NotATable(format!("hoards.{}.{}", adoption.hoard, pile))
+                })?;
+            add_condition(pile, adoption)
+        },
+    }
+}
+
+/// Add `path` to the configuration file and, if `backup` is `true`, back up the
+/// hoard it was added to.
+///
+/// # Errors
+///
+/// Any [`Error`] while checking the path or editing the configuration file, or
+/// any [`super::Error`] from the first backup.
+pub(super) fn run_adopt(
+    config: &Config,
+    path: &Path,
+    hoard: &str,
+    pile: Option<&str>,
+    condition: Option<&str>,
+    backup: bool,
+) -> Result<(), super::Error> {
+    let _span = tracing::debug_span!("adopt", ?path, %hoard, ?pile).entered();
+    check_name(hoard, false)?;
+    if let Some(pile) = pile {
+        check_name(pile, true)?;
+    }
+
+    let path = absolute(path)?;
+    check_not_covered(&path, &config.hoards)?;
+    let condition = resolve_condition(condition, &config.environments)?;
+    let stored = stored_path(&path, PROJECT_DIRS.home_dir());
+    let adoption = Adoption {
+        hoard,
+        pile,
+        condition: &condition,
+        path: &stored,
+    };
+
+    config.check_hoard_file(hoard).map_err(Error::from)?;
+    let mut doc = ConfigDocument::load(&config.config_file).map_err(Error::from)?;
+    add_to_hoards(
+        doc.hoards_mut().map_err(Error::from)?,
+        config.hoards.get(hoard),
+        &adoption,
+    )?;
+
+    // Make sure the new configuration builds before replacing the old one
+    let adopted = config.rebuild(&doc)?;
+    doc.save().map_err(Error::from)?;
+    tracing::info!(
+        "adopted {} into {} as {} = {}",
+        path.display(),
+        display_name(hoard, pile),
+        condition,
+        stored
+    );
+
+    if backup {
+        adopted.run_operation(&[hoard.to_owned()], true)?;
+    } else if !config.output.is_json() {
+        let stdout = io::stdout();
+        writeln!(
+            stdout.lock(),
+            "Run `hoard backup {}` to back up {}",
+            hoard,
+            path.display()
+        )
+        .map_err(Error::from)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::hoard::{MultipleEntries, Pile};
+    use maplit::hashmap;
+    use serde_json::json;
+
+    fn adoption<'a>(pile: Option<&'a str>, condition: &'a str) -> Adoption<'a> {
+        Adoption {
+            hoard: "nvim",
+            pile,
+            condition,
+            path: "${HOME}/.config/nvim",
+        }
+    }
+
+    fn pile(path: &str) -> Pile {
+        Pile {
+            config:     None,
+            path:       Some(PathBuf::from(path)),
+            candidates: vec![PathBuf::from(path)],
+        }
+    }
+
+    #[test]
+    fn home_is_replaced_in_stored_paths() {
+        let home = Path::new("/home/user");
+        assert_eq!(
+            stored_path(Path::new("/home/user/.config/nvim"), home),
+            "${HOME}/.config/nvim"
+        );
+        assert_eq!(stored_path(Path::new("/etc/hosts"), home), "/etc/hosts");
+        assert_eq!(
+            stored_path(Path::new("/home/username"), home),
+            "/home/username"
+        );
+    }
+
+    #[test]
+    fn covered_paths_are_refused() {
+        let hoards = hashmap! {
+            "zsh".to_owned() => Hoard::Anonymous(pile("/home/user/.zshrc")),
+            "config".to_owned() => Hoard::Named(MultipleEntries {
+                piles: hashmap! { "git".to_owned() => pile("/home/user/.config/git") },
+            }),
+        };
+
+        check_not_covered(Path::new("/home/user/.config/nvim"), &hoards).unwrap();
+        let err = check_not_covered(Path::new("/home/user/.config/git/config"), &hoards)
+            .expect_err("path inside a pile should be refused");
+        assert!(matches!(err, Error::AlreadyCovered { hoard, .. } if hoard == "config:git"));
+        check_not_covered(Path::new("/home/user"), &hoards)
+            .expect_err("path containing a pile should be refused");
+    }
+
+    #[test]
+    fn condition_defaults_to_the_only_matching_env() {
+        let envs = hashmap! {
+            "linux".to_owned() => true,
+            "macos".to_owned() => false,
+        };
+        assert_eq!(resolve_condition(None, &envs).unwrap(), "linux");
+        assert!(matches!(
+            resolve_condition(Some("macos"), &envs),
+            Err(Error::ConditionDoesNotApply(_))
+        ));
+        assert!(matches!(
+            resolve_condition(Some("linux|laptop"), &envs),
+            Err(Error::UnknownEnv(env)) if env == "laptop"
+        ));
+
+        let envs = hashmap! {
+            "linux".to_owned() => true,
+            "laptop".to_owned() => true,
+        };
+        assert!(matches!(
+            resolve_condition(None, &envs),
+            Err(Error::AmbiguousEnv(envs)) if envs.len() == 2
+        ));
+        assert_eq!(
+            resolve_condition(Some("linux|laptop"), &envs).unwrap(),
+            "laptop|linux"
+        );
+    }
+
+    #[test]
+    fn adds_hoards_piles_and_conditions() {
+        let mut hoards = Map::new();
+        add_to_hoards(&mut hoards, None, &adoption(None, "linux")).unwrap();
+        let anonymous = Hoard::Anonymous(pile("/home/user/.config/nvim"));
+        add_to_hoards(&mut hoards, Some(&anonymous), &adoption(None, "macos")).unwrap();
+        assert!(matches!(
+            add_to_hoards(&mut hoards, Some(&anonymous), &adoption(None, "linux")),
+            Err(Error::ConditionExists { .. })
+        ));
+        assert_eq!(
+            Value::Object(hoards),
+            json!({
+                "nvim": {
+                    "linux": "${HOME}/.config/nvim",
+                    "macos": "${HOME}/.config/nvim",
+                },
+            })
+        );
+
+        let mut hoards = Map::new();
+        let named = Hoard::Named(MultipleEntries {
+            piles: HashMap::new(),
+        });
+        assert!(matches!(
+            add_to_hoards(&mut hoards, Some(&named), &adoption(None, "linux")),
+            Err(Error::PileRequired(_))
+        ));
+        add_to_hoards(&mut hoards, Some(&named), &adoption(Some("init"), "linux")).unwrap();
+        assert_eq!(
+            Value::Object(hoards),
+            json!({ "nvim": { "init": { "linux": "${HOME}/.config/nvim" } } })
+        );
+    }
+}
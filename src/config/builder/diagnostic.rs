@@ -0,0 +1,171 @@
+//! Where in a configuration file an error occurred.
+//!
+//! See [`locate`].
+
+use super::Error;
+use std::path::Path;
+
+/// `message` without the ` at line N column M` that parsers append, since
+/// the location is reported separately.
+fn strip_location(message: &str) -> &str {
+    match message.rsplit_once(" at line ") {
+        Some((message, location))
+            if location.split(" column ").all(|number| {
+                !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit())
+            }) =>
+            message,
+        Some(_) | None => message,
+    }
+}
+
+/// The position of the table `key` (e.g. `hoards.nvim`) in the TOML `text`,
+/// or of the closest table above it.
+fn find_toml_table(text: &str, key: &str) -> Option<(usize, usize)> {
+    let mut key = key;
+    loop {
+        let header = ["[", key].concat();
+        let found = text.lines().enumerate().find_map(|(index, line)| {
+            let trimmed = line.trim_start();
+            let rest = trimmed.strip_prefix(&header)?;
+            (rest.starts_with(']') || rest.starts_with('.'))
+                .then_some((index + 1, line.len() - trimmed.len() + 1))
+        });
+        if found.is_some() {
+            return found;
+        }
+        key = key.rsplit_once('.')?.0;
+    }
+}
+
+/// Where the TOML parser error `error` occurred in `text`, starting at 1.
+///
+/// Errors raised while deserializing a value, e.g. an invalid hoard, often
+/// have no position, only the key they occurred at. Those are attributed to
+/// the table of that key.
+pub(super) fn toml_position(error: &toml::de::Error, text: &str) -> Option<(usize, usize)> {
+    if let Some((line, column)) = error.line_col() {
+        return Some((line + 1, column + 1));
+    }
+    let message = error.to_string();
+    let key = message.rsplit_once(" for key `")?.1.strip_suffix('`')?;
+    find_toml_table(text, key)
+}
+
+/// The lines of `text` around `line`, with a marker under `column`. Both
+/// start at 1.
+fn snippet(text: &str, line: usize, column: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let width = line.to_string().len();
+    let mut snippet = vec![format!("{:width$} |", "", width = width)];
+    if let Some(previous) = line.checked_sub(2).and_then(|index| lines.get(index)) {
+        snippet.push(format!(
+            "{:>width$} | {}",
+            line - 1,
+            previous,
+            width = width
+        ));
+    }
+    if let Some(current) = line.checked_sub(1).and_then(|index| lines.get(index)) {
+        snippet.push(format!("{:>width$} | {}", line, current, width = width));
+        snippet.push(format!(
+            "{:width$} | {:>column$}",
+            "",
+            "^",
+            width = width,
+            column = column.max(1)
+        ));
+    }
+    snippet.join("\n")
+}
+
+/// `error`, which occurred while parsing `text` from the file at `path`, with
+/// its `position` (line and column, starting at 1) and a snippet of the
+/// file. Errors without a position are returned as they are.
+pub(super) fn locate(
+    path: &Path,
+    text: &str,
+    position: Option<(usize, usize)>,
+    error: Error,
+) -> Error {
+    match position {
+        Some((line, column)) if line > 0 => Error::Parse {
+            path: path.to_path_buf(),
+            line,
+            column,
+            message: strip_location(&error.to_string()).to_owned(),
+            snippet: snippet(text, line, column),
+            error: Box::new(error),
+        },
+        Some(_) | None => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::builder::Builder;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn snippet_marks_column() {
+        let text = "[envs.linux]\nos = [\"linux\"]\n[hoards.nvim]\nlinux = 3\n";
+        assert_eq!(
+            snippet(text, 4, 9),
+            "  |\n3 | [hoards.nvim]\n4 | linux = 3\n  |         ^"
+        );
+        assert_eq!(
+            strip_location("invalid type: integer `3` at line 4 column 9"),
+            "invalid type: integer `3`"
+        );
+        assert_eq!(strip_location("expected a table"), "expected a table");
+    }
+
+    #[test]
+    fn toml_tables_are_found_by_key() {
+        let text = "[envs.linux]\nos = [\"linux\"]\n\n  [hoards.nvim.init]\nlinux = 3\n";
+        assert_eq!(find_toml_table(text, "hoards.nvim"), Some((4, 3)));
+        assert_eq!(find_toml_table(text, "envs.linux.os"), Some((1, 1)));
+        assert_eq!(find_toml_table(text, "git"), None);
+    }
+
+    #[test]
+    fn parse_errors_report_file_line_and_column() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let cases = [
+            ("config.toml", "[hoards.nvim]\nlinux = 3\n"),
+            ("config.yaml", "hoards:\n  nvim:\n    linux: 3\n"),
+            (
+                "config.json",
+                "{\n  \"hoards\": {\n    \"nvim\": { \"linux\": 3 }\n  }\n}\n",
+            ),
+        ];
+        for &(name, text) in &cases {
+            let path = dir.path().join(name);
+            fs::write(&path, text).unwrap();
+            match Builder::from_file(&path).expect_err("the path is not a string") {
+                Error::Parse {
+                    path: error_path,
+                    line,
+                    message,
+                    snippet,
+                    ..
+                } => {
+                    assert_eq!(error_path, path);
+                    assert!(line > 0, "{}", name);
+                    assert!(
+                        message.contains(
+                            "`linux` should be a path, a list of paths or a named pile, found a \
+                             number"
+                        ),
+                        "{}: {}",
+                        name,
+                        message
+                    );
+                    assert!(snippet.contains('^'), "{}: {}", name, snippet);
+                },
+                err => panic!("{}: unexpected error: {}", name, err),
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! See [`EnvVariable`].
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     convert::{Infallible, TryInto},
@@ -9,7 +10,7 @@ use std::{
 
 /// A conditional structure that checks if the given environment variable exists
 /// and optionally if it is set to a specific value.
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash, JsonSchema)]
 pub struct EnvVariable {
     /// The variable to check.
     pub var:      String,
@@ -1,5 +1,6 @@
 //! See [`ExeExists`].
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{convert::TryInto, fmt, fmt::Formatter};
 
@@ -8,7 +9,7 @@ use std::{convert::TryInto, fmt, fmt::Formatter};
 ///
 /// See the documentation for [`which`] for more information on how detection
 /// works.
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash, JsonSchema)]
 #[serde(transparent)]
 pub struct ExeExists(pub String);
 
@@ -23,9 +24,7 @@ impl TryInto<bool> for ExeExists {
             Err(err) => match err {
                 // AFAICT, this error is the "exe not found" one.
                 which::Error::CannotFindBinaryPath => Ok(false),
-                err
-                @
-                (which::Error::BadAbsolutePath
+                err @ (which::Error::BadAbsolutePath
                 | which::Error::BadRelativePath
                 | which::Error::CannotGetCurrentDir
                 | which::Error::CannotCanonicalize) => Err(err),
@@ -1,11 +1,12 @@
 //! See [`Hostname`].
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{convert::TryInto, fmt, fmt::Formatter};
 
 /// A conditional structure that compares the system's hostname to the given
 /// string.
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash, JsonSchema)]
 #[serde(transparent)]
 pub struct Hostname(pub String);
 
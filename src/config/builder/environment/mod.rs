@@ -7,6 +7,7 @@ pub mod os;
 pub mod path;
 
 use crate::combinator::Combinator;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -14,7 +15,8 @@ pub use self::{
     envvar::EnvVariable, exe::ExeExists, hostname::Hostname, os::OperatingSystem, path::PathExists,
 };
 use std::{
-    convert::{Infallible, TryInto},
+    collections::BTreeMap,
+    convert::{Infallible, TryFrom, TryInto},
     fmt,
 };
 
@@ -68,14 +70,22 @@ impl From<Infallible> for Error {
 /// - [`Hostname`]
 /// - [`OperatingSystem`]
 /// - [`PathExists`]
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Hash, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Hash, Default, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Environment {
+    /// Hostnames of the system.
     hostname:    Option<Combinator<Hostname>>,
+    /// Operating systems, as named by Rust's `std::env::consts::OS`.
     os:          Option<Combinator<OperatingSystem>>,
+    /// Environment variables that are set, optionally to a given value.
     env:         Option<Combinator<EnvVariable>>,
+    /// Executables found in `$PATH`.
     exe_exists:  Option<Combinator<ExeExists>>,
+    /// Paths that exist.
     path_exists: Option<Combinator<PathExists>>,
+    /// Variables for pile paths, set while this environment applies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vars:        Option<BTreeMap<String, String>>,
 }
 
 impl fmt::Display for Environment {
@@ -133,6 +143,7 @@ impl TryInto<bool> for Environment {
             env,
             exe_exists,
             path_exists,
+            ..
         } = self;
 
         let hostname_cond: bool = hostname.map_or(Ok(true), TryInto::try_into)?;
@@ -145,7 +156,180 @@ impl TryInto<bool> for Environment {
     }
 }
 
+/// The result of one kind of condition of an [`Environment`] on this system.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConditionResult {
+    /// The kind of condition: `hostname`, `os`, `env`, `exe_exists` or
+    /// `path_exists`.
+    pub kind:      &'static str,
+    /// The condition as a boolean expression, e.g. `OPERATING SYSTEM == linux`.
+    pub condition: String,
+    /// Whether the condition holds on this system.
+    pub passed:    bool,
+    /// What was found on this system, e.g. `operating system is macos`.
+    pub reason:    String,
+}
+
+/// A condition that can describe what it finds on this system.
+trait Observe {
+    /// What was found on this system while checking the condition.
+    fn observe(&self) -> Result<String, Error>;
+}
+
+impl Observe for Hostname {
+    fn observe(&self) -> Result<String, Error> {
+        let host = ::hostname::get().map_err(Error::Hostname)?;
+        Ok(format!("hostname is {}", host.to_string_lossy()))
+    }
+}
+
+impl Observe for OperatingSystem {
+    fn observe(&self) -> Result<String, Error> {
+        Ok(format!("operating system is {}", std::env::consts::OS))
+    }
+}
+
+impl Observe for EnvVariable {
+    fn observe(&self) -> Result<String, Error> {
+        // Only show the value if it is compared against, as it may be a secret
+        Ok(match (std::env::var_os(&self.var), &self.expected) {
+            (None, _) => format!("${{{}}} is not set", self.var),
+            (Some(_), None) => format!("${{{}}} is set", self.var),
+            (Some(value), Some(_)) => {
+                format!("${{{}}} is \"{}\"", self.var, value.to_string_lossy())
+            },
+        })
+    }
+}
+
+impl Observe for ExeExists {
+    fn observe(&self) -> Result<String, Error> {
+        let ExeExists(exe) = self;
+        match which::which(exe) {
+            Ok(path) => Ok(format!("{} is {}", exe, path.display())),
+            Err(which::Error::CannotFindBinaryPath) => Ok(format!("{} is not in $PATH", exe)),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl Observe for PathExists {
+    fn observe(&self) -> Result<String, Error> {
+        let PathExists(path) = self;
+        Ok(if path.exists() {
+            format!("{} exists", path.display())
+        } else {
+            format!("{} does not exist", path.display())
+        })
+    }
+}
+
+/// Evaluate `combinator` on its own and describe what its items found.
+fn explain_condition<T, E>(
+    kind: &'static str,
+    combinator: &Combinator<T>,
+) -> Result<ConditionResult, Error>
+where
+    T: Clone + fmt::Display + Observe + TryInto<bool, Error = E>,
+    E: std::error::Error,
+    Error: From<E>,
+{
+    let mut observed: Vec<String> = Vec::new();
+    for item in combinator.items() {
+        let observation = item.observe()?;
+        if !observed.contains(&observation) {
+            observed.push(observation);
+        }
+    }
+    let reason = if observed.is_empty() {
+        String::from("an empty condition always passes")
+    } else {
+        observed.join("; ")
+    };
+
+    Ok(ConditionResult {
+        kind,
+        condition: combinator.to_string(),
+        passed: bool::try_from(combinator.clone())?,
+        reason,
+    })
+}
+
 impl Environment {
+    /// Evaluate each kind of condition of this `Environment` on its own, along
+    /// with what was found on this system. The `Environment` applies if all of
+    /// them pass.
+    ///
+    /// # Errors
+    ///
+    /// Any [`enum@Error`] that occurs while evaluating a condition.
+    pub fn explain(&self) -> Result<Vec<ConditionResult>, Error> {
+        let Environment {
+            hostname,
+            os,
+            env,
+            exe_exists,
+            path_exists,
+            ..
+        } = self;
+
+        let mut results = Vec::new();
+        if let Some(hostname) = hostname {
+            results.push(explain_condition("hostname", hostname)?);
+        }
+        if let Some(os) = os {
+            results.push(explain_condition("os", os)?);
+        }
+        if let Some(env) = env {
+            results.push(explain_condition("env", env)?);
+        }
+        if let Some(exe_exists) = exe_exists {
+            results.push(explain_condition("exe_exists", exe_exists)?);
+        }
+        if let Some(path_exists) = path_exists {
+            results.push(explain_condition("path_exists", path_exists)?);
+        }
+        Ok(results)
+    }
+
+    /// The variables this `Environment` sets for pile paths while it applies.
+    #[must_use]
+    pub fn vars(&self) -> Option<&BTreeMap<String, String>> {
+        self.vars.as_ref()
+    }
+
+    /// Why this `Environment` and `other` can never apply at the same time,
+    /// e.g. `operating systems` if they require different ones, or `None` if
+    /// they can.
+    #[must_use]
+    pub fn conflict(&self, other: &Self) -> Option<&'static str> {
+        /// Whether `left` and `right` have no item in common. An empty or
+        /// missing condition allows anything.
+        fn disjoint<T: PartialEq + TryInto<bool>>(
+            left: Option<&Combinator<T>>,
+            right: Option<&Combinator<T>>,
+        ) -> bool {
+            match (left, right) {
+                (Some(left), Some(right)) => {
+                    let mut left = left.items().peekable();
+                    let right: Vec<&T> = right.items().collect();
+                    left.peek().is_some()
+                        && !right.is_empty()
+                        && left.all(|item| !right.contains(&item))
+                },
+                (None, _) | (_, None) => false,
+            }
+        }
+
+        if disjoint(self.os.as_ref(), other.os.as_ref()) {
+            Some("operating systems")
+        } else if disjoint(self.hostname.as_ref(), other.hostname.as_ref()) {
+            Some("hostnames")
+        } else {
+            None
+        }
+    }
+
     /// Checks that there are no invalid or impossible conditions set.
     ///
     /// # Errors
@@ -207,6 +391,7 @@ mod tests {
                 env:         Some(Combinator(vec![Inner::Single(env_var.clone())])),
                 exe_exists:  Some(Combinator(vec![Inner::Single(exe_exists.clone())])),
                 path_exists: Some(Combinator(vec![Inner::Single(path_exists.clone())])),
+                vars:        None,
             };
 
             let expected = vec![
@@ -354,4 +539,38 @@ mod tests {
                 .expect("expecting one of two operating systems should succeed");
         }
     }
+
+    mod explain {
+        use super::*;
+        use std::path::PathBuf;
+
+        #[test]
+        fn test_explain_reports_each_condition() {
+            let env = Environment {
+                os: Some(Combinator(vec![
+                    Inner::Single(OperatingSystem(std::env::consts::OS.into())),
+                    Inner::Single(OperatingSystem("not an os".into())),
+                ])),
+                path_exists: Some(Combinator(vec![Inner::Single(PathExists(PathBuf::from(
+                    "/this/path/does/not/exist",
+                )))])),
+                ..Environment::default()
+            };
+
+            let results = env.explain().expect("failed to explain environment");
+            assert_eq!(results.len(), 2);
+            assert_eq!(results[0].kind, "os");
+            assert!(results[0].passed);
+            assert_eq!(
+                results[0].reason,
+                format!("operating system is {}", std::env::consts::OS)
+            );
+            assert_eq!(results[1].kind, "path_exists");
+            assert!(!results[1].passed);
+            assert_eq!(
+                results[1].reason,
+                "/this/path/does/not/exist does not exist"
+            );
+        }
+    }
 }
@@ -1,5 +1,6 @@
 //! See [`OperatingSystem`].
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     convert::{Infallible, TryInto},
@@ -16,7 +17,7 @@ use std::{
 /// Linuxulator will detect the system as Linux.
 ///
 /// For possible values to check against, see [`std::env::consts::OS`].
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash, JsonSchema)]
 #[serde(transparent)]
 pub struct OperatingSystem(pub String);
 
@@ -1,5 +1,6 @@
 //! See [`PathExists`].
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
     convert::{Infallible, TryInto},
@@ -12,7 +13,7 @@ use std::{
 ///
 /// The path can be anything from a file, directory, symbolic link, or
 /// otherwise, so long as *something* with that name exists.
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash, JsonSchema)]
 #[serde(transparent)]
 #[repr(transparent)]
 pub struct PathExists(pub PathBuf);
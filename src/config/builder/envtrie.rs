@@ -256,10 +256,14 @@ impl Node {
         Ok(eval)
     }
 
-    fn get_highest_path(&self, envs: &HashMap<String, bool>) -> Result<Option<&str>, Error> {
+    fn get_highest_match(
+        &self,
+        envs: &HashMap<String, bool>,
+    ) -> Result<Option<(String, &str)>, Error> {
         tracing::trace!("evaluating envtrie for best matching path");
-        let Evaluation { path, .. } = self.get_evaluation(envs)?;
-        Ok(path)
+        let Evaluation { name, path, .. } = self.get_evaluation(envs)?;
+        // The root node has no name, leaving a leading separator
+        Ok(path.map(|path| (name.trim_start_matches(ENV_SEPARATOR).to_owned(), path)))
     }
 }
 
@@ -297,6 +301,24 @@ fn validate_environments(environments: &HashMap<String, String>) -> Result<(), E
     Ok(())
 }
 
+/// The weight of an environment that is not in any exclusivity list.
+pub const DEFAULT_WEIGHT: usize = 1;
+
+/// The weight of each environment in `exclusive_list`, used to choose between
+/// matching conditions with the same number of environments. Environments
+/// earlier in a list weigh more. Environments not in any list weigh
+/// [`DEFAULT_WEIGHT`].
+///
+/// # Errors
+///
+/// [`Error::WeightCycle`] if the lists prefer an environment both to and not to
+/// another one.
+pub fn environment_weights(
+    exclusive_list: &[Vec<String>],
+) -> Result<HashMap<String, usize>, Error> {
+    get_weighted_map(exclusive_list)
+}
+
 fn get_weighted_map(exclusive_list: &[Vec<String>]) -> Result<HashMap<String, usize>, Error> {
     let _span = tracing::trace_span!(
         "get_weighted_map",
@@ -447,7 +469,10 @@ impl EnvTrie {
                 for segment in envs.into_iter().rev() {
                     let segment = segment.to_owned();
 
-                    prev_node.score = weighted_map.get(&segment).copied().unwrap_or(1);
+                    prev_node.score = weighted_map
+                        .get(&segment)
+                        .copied()
+                        .unwrap_or(DEFAULT_WEIGHT);
                     prev_node.name = segment.clone();
                     let tree = {
                         let mut tree = HashMap::new();
@@ -494,8 +519,25 @@ impl EnvTrie {
             ?environments,
             "getting best matching path with given environments"
         );
+        self.get_match(environments)
+            .map(|found| found.map(|(_, path)| path))
+    }
+
+    /// Get the best-matched environment condition and its path in the
+    /// `EnvTrie`.
+    ///
+    /// The environments in the returned condition are sorted by name, so it
+    /// may not be written the same way as in the configuration file.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`EnvTrie::get_path`].
+    pub fn get_match(
+        &self,
+        environments: &HashMap<String, bool>,
+    ) -> Result<Option<(String, &str)>, Error> {
         let EnvTrie(node) = self;
-        node.get_highest_path(environments)
+        node.get_highest_match(environments)
     }
 }
 
@@ -577,7 +619,7 @@ mod tests {
                         if !node_eq_ignore_score(&node1, &node2) {
                             panic!(
                                 "received trie did not match expected\nReceived: {:#?}\nExpected: \
-                     {:#?}",
+                                 {:#?}",
                                 node1, node2
                             )
                         },
@@ -764,4 +806,26 @@ mod tests {
         exclusivity: vec![],
         expected: Err(Error::NoEnvironments)
     }
+
+    #[test]
+    fn test_match_names_winning_condition() {
+        let environments = hashmap! {
+            format!("{}|{}", LABEL_B_1, LABEL_A_1) => PATH_1.into(),
+            LABEL_A_1.into() => PATH_2.into(),
+        };
+        let trie = EnvTrie::new(&environments, &[]).expect("failed to create trie");
+
+        let envs = hashmap! { LABEL_A_1.into() => true, LABEL_B_1.into() => true };
+        let expected = format!("{}|{}", LABEL_A_1, LABEL_B_1);
+        assert_eq!(trie.get_match(&envs).unwrap(), Some((expected, PATH_1)));
+
+        let envs = hashmap! { LABEL_A_1.into() => true, LABEL_B_1.into() => false };
+        assert_eq!(
+            trie.get_match(&envs).unwrap(),
+            Some((LABEL_A_1.to_owned(), PATH_2))
+        );
+
+        let envs = hashmap! { LABEL_A_1.into() => false, LABEL_B_1.into() => false };
+        assert_eq!(trie.get_match(&envs).unwrap(), None);
+    }
 }
@@ -0,0 +1,348 @@
+//! Glob patterns in pile paths.
+//!
+//! After variables are expanded, pile paths can use glob patterns, e.g. for
+//! directories whose names differ between systems, like
+//! `~/.mozilla/firefox/*.default-release/user.js`:
+//!
+//! - A pattern in a directory must resolve to a single directory on this
+//!   system; see [`resolve`].
+//! - A pattern in the last component is kept in the pile path: the pile then
+//!   consists of the entries of its directory whose names match, each stored in
+//!   the hoard under its own name; see [`split`].
+//!
+//! Names with pattern characters are matched literally by escaping each of
+//! these characters as a pattern of itself, e.g. `[[]GOG] Foo`; see [`escape`].
+
+use globset::{GlobBuilder, GlobMatcher};
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use thiserror::Error;
+
+/// The characters that make a path component a glob pattern.
+const PATTERN_CHARS: &[char] = &['*', '?', '[', '{'];
+
+/// Errors that can occur while resolving glob patterns in pile paths.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A component of a path is not a valid glob pattern.
+    #[error("invalid glob pattern {pattern}: {error}")]
+    Pattern {
+        /// The invalid pattern.
+        pattern: String,
+        /// The error that occurred while parsing it.
+        #[source]
+        error:   globset::Error,
+    },
+    /// The patterns in a path match several directories.
+    #[error(
+        "{} matches several paths, make the pattern more specific: {}",
+        .pattern.display(),
+        .matches.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    Ambiguous {
+        /// The pile path with the patterns.
+        pattern: PathBuf,
+        /// The paths that it matches.
+        matches: Vec<PathBuf>,
+    },
+}
+
+/// Whether the path component `component` is a glob pattern.
+#[must_use]
+pub fn is_pattern(component: &OsStr) -> bool {
+    component.to_string_lossy().contains(PATTERN_CHARS)
+}
+
+/// The name that the pattern `component` matches if it only escapes pattern
+/// characters, like `[[]GOG] Foo` matches `[GOG] Foo`.
+fn literal(component: &OsStr) -> Option<OsString> {
+    let pattern = component.to_string_lossy();
+    let mut name = String::new();
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '[' => match (chars.next(), chars.next()) {
+                (Some(escaped), Some(']')) if escaped != '!' && escaped != '^' =>
+                    name.push(escaped),
+                _ => return None,
+            },
+            '*' | '?' | '{' | '}' => return None,
+            ch => name.push(ch),
+        }
+    }
+    Some(name.into())
+}
+
+/// `name` as a pattern that only matches itself, e.g. as one of the
+/// alternatives in `{a,b}`.
+#[must_use]
+pub fn escape(name: &str) -> String {
+    name.chars()
+        .map(|ch| match ch {
+            '*' | '?' | '[' | ']' | '{' | '}' | ',' => ['[', ch, ']'].iter().collect(),
+            ch => ch.to_string(),
+        })
+        .collect()
+}
+
+/// Compile the pattern `component`, which matches a single path component.
+///
+/// # Errors
+///
+/// [`Error::Pattern`] if the pattern is invalid.
+pub fn matcher(component: &OsStr) -> Result<GlobMatcher, Error> {
+    let pattern = component.to_string_lossy();
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|error| Error::Pattern {
+            pattern: pattern.into_owned(),
+            error,
+        })
+}
+
+/// Split the pile path `path` into the directory its entries are in and, if
+/// its last component is a pattern, the matcher of their names. Otherwise,
+/// the pile is `path` itself.
+///
+/// # Errors
+///
+/// [`Error::Pattern`] if the last component is an invalid pattern.
+pub fn split(path: &Path) -> Result<(&Path, Option<GlobMatcher>), Error> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if is_pattern(name) => Ok((parent, Some(matcher(name)?))),
+        (None, _) | (_, None) | (Some(_), Some(_)) => Ok((path, None)),
+    }
+}
+
+/// The entries of the directory `dir` whose names match `matcher`, sorted.
+fn matching_entries(dir: &Path, matcher: &GlobMatcher) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| matcher.is_match(entry.file_name()))
+        .map(|entry| entry.path())
+        .collect();
+    entries.sort_unstable();
+    entries
+}
+
+/// The paths of the files and directories of the pile with path `path`.
+///
+/// # Errors
+///
+/// [`Error::Pattern`] if the last component is an invalid pattern.
+pub fn entries(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    Ok(match split(path)? {
+        (dir, Some(matcher)) => matching_entries(dir, &matcher),
+        (path, None) if path.exists() => vec![path.to_path_buf()],
+        (_, None) => Vec::new(),
+    })
+}
+
+/// Whether the pile with path `path` has anything to back up.
+#[must_use]
+pub fn exists(path: &Path) -> bool {
+    entries(path).map_or(false, |entries| !entries.is_empty())
+}
+
+/// Whether `path` is, or is inside of, an entry of the pile with path
+/// `pile_path`.
+#[must_use]
+pub fn contains(pile_path: &Path, path: &Path) -> bool {
+    match split(pile_path) {
+        Ok((dir, Some(matcher))) => path
+            .strip_prefix(dir)
+            .ok()
+            .and_then(|rest| rest.iter().next())
+            .map_or(false, |name| matcher.is_match(name)),
+        Ok((_, None)) | Err(_) => path.starts_with(pile_path),
+    }
+}
+
+/// Resolve the patterns in the directories of the pile path `path` on this
+/// system. The last component is kept as is.
+///
+/// Of the directories that match, the ones that contain the last component
+/// are preferred, to pick one of several profiles. Without any, a single
+/// matching directory is used, e.g. to restore into. Returns `None` if no
+/// directory matches.
+///
+/// # Errors
+///
+/// [`Error::Pattern`] for invalid patterns, and [`Error::Ambiguous`] if the
+/// path matches several directories that cannot be told apart.
+pub fn resolve(path: &Path) -> Result<Option<PathBuf>, Error> {
+    let mut components: Vec<Component<'_>> = path.components().collect();
+    let last = match components.pop() {
+        Some(last) => last,
+        None => return Ok(Some(path.to_path_buf())),
+    };
+    if path.exists()
+        && path
+            .components()
+            .any(|component| is_pattern(component.as_os_str()))
+    {
+        tracing::warn!(
+            ?path,
+            "the path exists but is used as a glob pattern, escape its pattern characters like \
+             `[[]` to use it as is"
+        );
+    }
+    if !components
+        .iter()
+        .any(|component| is_pattern(component.as_os_str()))
+    {
+        return Ok(Some(path.to_path_buf()));
+    }
+
+    let mut dirs = vec![PathBuf::new()];
+    for component in components {
+        let name = component.as_os_str();
+        // Escaped names are used as they are, even if they do not exist yet
+        let literal = if is_pattern(name) {
+            literal(name)
+        } else {
+            Some(name.to_owned())
+        };
+        if let Some(name) = literal {
+            for dir in &mut dirs {
+                dir.push(&name);
+            }
+        } else {
+            let matcher = matcher(name)?;
+            dirs = dirs
+                .iter()
+                .flat_map(|dir| matching_entries(dir, &matcher))
+                .filter(|dir| dir.is_dir())
+                .collect();
+        }
+    }
+
+    let matches: Vec<PathBuf> = dirs.into_iter().map(|dir| dir.join(last)).collect();
+    let existing: Vec<PathBuf> = matches
+        .iter()
+        .filter(|path| exists(path))
+        .cloned()
+        .collect();
+    match (existing.as_slice(), matches.as_slice()) {
+        ([single], _) | ([], [single]) => Ok(Some(single.clone())),
+        ([], []) => Ok(None),
+        ([], several) | (several, _) => Err(Error::Ambiguous {
+            pattern: path.to_path_buf(),
+            matches: several.to_vec(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn profiles() -> TempDir {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        for profile in &["abc.default-release", "def.default-release", "ghi.dev"] {
+            fs::create_dir(dir.path().join(profile)).unwrap();
+        }
+        fs::write(dir.path().join("abc.default-release/user.js"), b"").unwrap();
+        fs::write(dir.path().join("ghi.dev/a.conf"), b"").unwrap();
+        fs::write(dir.path().join("ghi.dev/b.conf"), b"").unwrap();
+        fs::write(dir.path().join("ghi.dev/other"), b"").unwrap();
+        dir
+    }
+
+    #[test]
+    fn directories_resolve_to_the_single_match() {
+        let dir = profiles();
+        let root = dir.path();
+        assert_eq!(
+            resolve(&root.join("*.default-release/user.js")).unwrap(),
+            Some(root.join("abc.default-release/user.js")),
+            "the directory that has the file is preferred"
+        );
+        assert_eq!(
+            resolve(&root.join("*.dev/prefs.js")).unwrap(),
+            Some(root.join("ghi.dev/prefs.js")),
+            "a single directory is restored into"
+        );
+        assert_eq!(resolve(&root.join("*.beta/prefs.js")).unwrap(), None);
+        let error = resolve(&root.join("*.default-release/prefs.js")).unwrap_err();
+        assert!(
+            matches!(&error, Error::Ambiguous { matches, .. } if matches.len() == 2),
+            "{}",
+            error
+        );
+        assert_eq!(
+            resolve(&root.join("ghi.dev/*.conf")).unwrap(),
+            Some(root.join("ghi.dev/*.conf")),
+            "the last component is kept"
+        );
+    }
+
+    #[test]
+    fn last_component_selects_entries() {
+        let dir = profiles();
+        let pile = dir.path().join("ghi.dev/*.conf");
+        assert_eq!(entries(&pile).unwrap(), vec![
+            dir.path().join("ghi.dev/a.conf"),
+            dir.path().join("ghi.dev/b.conf"),
+        ]);
+        assert!(exists(&pile));
+        assert!(!exists(&dir.path().join("ghi.dev/*.toml")));
+        assert!(contains(&pile, &dir.path().join("ghi.dev/a.conf")));
+        assert!(!contains(&pile, &dir.path().join("ghi.dev/other")));
+        assert!(matches!(
+            split(Path::new("/a/[b")),
+            Err(Error::Pattern { .. })
+        ));
+    }
+
+    #[test]
+    fn escaped_directories_are_used_as_they_are() {
+        let dir = profiles();
+        let root = dir.path();
+        fs::create_dir(root.join("[GOG] Foo")).unwrap();
+        fs::create_dir(root.join("G Foo")).unwrap();
+        let escaped = ["[[]GOG] Foo", &escape("[GOG] Foo")];
+        for name in &escaped {
+            assert_eq!(
+                resolve(&root.join(name).join("saves")).unwrap(),
+                Some(root.join("[GOG] Foo/saves"))
+            );
+            assert_eq!(
+                resolve(&root.join(name).join("[[]{]a.conf")).unwrap(),
+                Some(root.join("[GOG] Foo/[[]{]a.conf")),
+                "the last component is kept"
+            );
+            assert_eq!(entries(&root.join(name)).unwrap(), vec![
+                root.join("[GOG] Foo")
+            ]);
+        }
+        assert_eq!(
+            resolve(&root.join("[[]GOG] Bar/saves")).unwrap(),
+            Some(root.join("[GOG] Bar/saves")),
+            "escaped directories are restored into even if they do not exist"
+        );
+        assert_eq!(
+            resolve(&root.join("[GOG] Foo/saves")).unwrap(),
+            Some(root.join("G Foo/saves")),
+            "unescaped brackets are a pattern"
+        );
+    }
+
+    #[test]
+    fn escaped_names_only_match_themselves() {
+        let pattern = ["{", &escape("[GOG] a,b"), ",", &escape("*.js"), "}"].concat();
+        let matcher = matcher(OsStr::new(&pattern)).unwrap();
+        assert!(matcher.is_match("[GOG] a,b"));
+        assert!(matcher.is_match("*.js"));
+        assert!(!matcher.is_match("G"));
+        assert!(!matcher.is_match("user.js"));
+    }
+}
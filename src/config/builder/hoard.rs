@@ -10,12 +10,19 @@
 //! configuration file. All environments in the condition must match the current
 //! system for its matching path to be used.
 
+use super::{
+    glob::{self, Error as GlobError},
+    vars::PathVars,
+    GlobalConfig,
+};
 use crate::{
-    config::builder::envtrie::{EnvTrie, Error as TrieError},
-    env_vars::{expand_env_in_path, Error as EnvError},
+    config::builder::envtrie::{environment_weights, EnvTrie, Error as TrieError, DEFAULT_WEIGHT},
+    env_vars::Error as EnvError,
 };
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, convert::TryFrom, fmt, time::Duration};
 use thiserror::Error;
 
 type ConfigMultiple = crate::config::hoard::MultipleEntries;
@@ -32,6 +39,16 @@ pub enum Error {
     /// Error while expanding environment variables in a path.
     #[error("error while expanding environment variables in path: {0}")]
     ExpandEnv(#[from] EnvError),
+    /// Error while resolving the glob patterns in a path.
+    #[error("error while resolving glob patterns in path: {0}")]
+    Glob(#[from] GlobError),
+    /// Encryption settings are given, but no level says which encryption to
+    /// use.
+    #[error("encryption settings are given but `encrypt` is not set")]
+    MissingEncryptionKind,
+    /// Symmetric encryption is used without a password.
+    #[error("symmetric encryption needs `encrypt_pass` or `encrypt_pass_cmd`")]
+    MissingPassword,
 }
 
 /// Configuration for symmetric (password) encryption.
@@ -127,26 +144,500 @@ impl Default for Walker {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
 pub struct Config {
     /// Encryption configuration options
-    pub encryption: Option<Encryption>,
+    pub encryption:        Option<Encryption>,
     /// WalkBuilder configuration options
     #[serde(flatten)]
     #[serde(default)]
-    pub walker:     Walker,
+    pub walker:            Walker,
+    /// Which candidate path to restore to.
+    #[serde(default)]
+    pub restore_candidate: RestoreCandidate,
+}
+
+/// Which of the candidate paths of a condition a restore uses. Backups always
+/// use the first candidate that exists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreCandidate {
+    /// The first candidate that exists, or the first one if none do, like a
+    /// backup.
+    Existing,
+    /// Always the first candidate, e.g. to move the files of an older
+    /// location to the preferred one.
+    First,
+}
+
+impl Default for RestoreCandidate {
+    fn default() -> Self {
+        Self::Existing
+    }
+}
+
+/// Which encryption an [`EncryptionLayer`] selects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptionKind {
+    /// No encryption, e.g. to turn off encryption set by the hoard.
+    None,
+    /// Symmetric encryption.
+    Symmetric,
+    /// Asymmetric encryption.
+    Asymmetric,
+}
+
+/// Encryption settings as written at one level of the configuration.
+///
+/// Every setting is optional, so a pile can change a single setting and
+/// inherit the rest from its hoard and `global_config`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, JsonSchema)]
+pub struct EncryptionLayer {
+    /// Which encryption to use.
+    #[serde(rename = "encrypt", default, skip_serializing_if = "Option::is_none")]
+    pub kind:         Option<EncryptionKind>,
+    /// Raw password for symmetric encryption.
+    #[serde(
+        rename = "encrypt_pass",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub password:     Option<String>,
+    /// Command printing the password for symmetric encryption. Takes
+    /// precedence over [`password`](Self::password).
+    #[serde(
+        rename = "encrypt_pass_cmd",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub password_cmd: Option<Vec<String>>,
+    /// GPG public key for asymmetric encryption.
+    #[serde(
+        rename = "encrypt_pub_key",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub public_key:   Option<String>,
+    /// Armored output for asymmetric encryption.
+    #[serde(
+        rename = "encrypt_armor",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub armor:        Option<bool>,
+}
+
+impl EncryptionLayer {
+    /// The settings of `self`, overridden by those set in `other`.
+    #[must_use]
+    pub fn layer(self, other: Self) -> Self {
+        Self {
+            kind:         other.kind.or(self.kind),
+            password:     other.password.or(self.password),
+            password_cmd: other.password_cmd.or(self.password_cmd),
+            public_key:   other.public_key.or(self.public_key),
+            armor:        other.armor.or(self.armor),
+        }
+    }
+
+    /// The names of the settings that are set, as written in the
+    /// configuration.
+    #[must_use]
+    pub fn set_fields(&self) -> Vec<&'static str> {
+        let fields = [
+            ("encrypt", self.kind.is_some()),
+            ("encrypt_pass", self.password.is_some()),
+            ("encrypt_pass_cmd", self.password_cmd.is_some()),
+            ("encrypt_pub_key", self.public_key.is_some()),
+            ("encrypt_armor", self.armor.is_some()),
+        ];
+        fields
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// The [`Encryption`] these settings describe. `public_key` is used for
+    /// asymmetric encryption if no key is set.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::MissingEncryptionKind`] if settings are given without
+    /// `encrypt`, or [`Error::MissingPassword`] for symmetric encryption
+    /// without a password.
+    pub fn resolve(self, public_key: Option<&String>) -> Result<Option<Encryption>, Error> {
+        match self.kind {
+            None if self.set_fields().is_empty() => Ok(None),
+            None => Err(Error::MissingEncryptionKind),
+            Some(EncryptionKind::None) => Ok(None),
+            Some(EncryptionKind::Symmetric) => match (self.password_cmd, self.password) {
+                (Some(cmd), _) => Ok(Some(Encryption::Symmetric(
+                    SymmetricEncryption::PasswordCmd(cmd),
+                ))),
+                (None, Some(password)) => Ok(Some(Encryption::Symmetric(
+                    SymmetricEncryption::Password(password),
+                ))),
+                (None, None) => Err(Error::MissingPassword),
+            },
+            Some(EncryptionKind::Asymmetric) => {
+                let default = AsymmetricEncryption::default();
+                Ok(Some(Encryption::Asymmetric(AsymmetricEncryption {
+                    public_key: self.public_key.or_else(|| public_key.cloned()),
+                    armor:      self.armor.unwrap_or(default.armor),
+                })))
+            },
+        }
+    }
+}
+
+/// Walker settings as written at one level of the configuration. Settings
+/// left out are inherited.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct WalkerLayer {
+    /// Follow symlinks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follow_links:   Option<bool>,
+    /// Collect hidden files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hidden:         Option<bool>,
+    /// Max depth to go.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth:      Option<usize>,
+    /// File patterns to ignore. A list set at a lower level replaces the one
+    /// above it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude:        Option<Vec<String>>,
+    /// File patterns to include.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern:        Option<String>,
+    /// Whether the pattern is to be parsed as a regex instead of a glob.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex:          Option<bool>,
+    /// To be case sensitive or not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_sensitive: Option<bool>,
+}
+
+impl WalkerLayer {
+    /// The settings of `self`, overridden by those set in `other`.
+    #[must_use]
+    pub fn layer(self, other: Self) -> Self {
+        Self {
+            follow_links:   other.follow_links.or(self.follow_links),
+            hidden:         other.hidden.or(self.hidden),
+            max_depth:      other.max_depth.or(self.max_depth),
+            exclude:        other.exclude.or(self.exclude),
+            pattern:        other.pattern.or(self.pattern),
+            regex:          other.regex.or(self.regex),
+            case_sensitive: other.case_sensitive.or(self.case_sensitive),
+        }
+    }
+
+    /// The names of the settings that are set.
+    #[must_use]
+    pub fn set_fields(&self) -> Vec<&'static str> {
+        let fields = [
+            ("follow_links", self.follow_links.is_some()),
+            ("hidden", self.hidden.is_some()),
+            ("max_depth", self.max_depth.is_some()),
+            ("exclude", self.exclude.is_some()),
+            ("pattern", self.pattern.is_some()),
+            ("regex", self.regex.is_some()),
+            ("case_sensitive", self.case_sensitive.is_some()),
+        ];
+        fields
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// The [`Walker`] with these settings and defaults for the rest.
+    #[must_use]
+    pub fn resolve(self) -> Walker {
+        let default = Walker::default();
+        Walker {
+            follow_links:   self.follow_links.unwrap_or(default.follow_links),
+            hidden:         self.hidden.unwrap_or(default.hidden),
+            max_depth:      self.max_depth.or(default.max_depth),
+            exclude:        self.exclude.unwrap_or(default.exclude),
+            pattern:        self.pattern.unwrap_or(default.pattern),
+            regex:          self.regex.unwrap_or(default.regex),
+            case_sensitive: self.case_sensitive.unwrap_or(default.case_sensitive),
+        }
+    }
+}
+
+/// Hoard/Pile configuration as written in the configuration file, and in
+/// `global_config`.
+///
+/// Settings are merged one by one: a pile inherits every setting it leaves
+/// out from its hoard, which inherits from `global_config`. The merged
+/// settings are [`resolve`](Self::resolve)d into a [`Config`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, JsonSchema)]
+pub struct ConfigLayer {
+    /// Encryption configuration options.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption:        Option<EncryptionLayer>,
+    /// `WalkBuilder` configuration options.
+    #[serde(flatten)]
+    pub walker:            WalkerLayer,
+    /// Which candidate path to restore to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restore_candidate: Option<RestoreCandidate>,
+}
+
+impl ConfigLayer {
+    /// The settings of `self`, overridden by those set in `other`.
+    #[must_use]
+    pub fn layer(self, other: Self) -> Self {
+        let encryption = match (self.encryption, other.encryption) {
+            (Some(bottom), Some(top)) => Some(bottom.layer(top)),
+            (bottom, top) => top.or(bottom),
+        };
+        Self {
+            encryption,
+            walker: self.walker.layer(other.walker),
+            restore_candidate: other.restore_candidate.or(self.restore_candidate),
+        }
+    }
+
+    /// The names of the settings that are set, as written in the
+    /// configuration.
+    #[must_use]
+    pub fn set_fields(&self) -> Vec<&'static str> {
+        let mut fields = self.walker.set_fields();
+        fields.extend(self.encryption.iter().flat_map(EncryptionLayer::set_fields));
+        if self.restore_candidate.is_some() {
+            fields.push("restore_candidate");
+        }
+        fields
+    }
+
+    /// Whether no setting is set.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.set_fields().is_empty()
+    }
+
+    /// The [`Config`] with these settings and defaults for the rest.
+    /// `public_key` is used for asymmetric encryption if no key is set.
+    ///
+    /// # Errors
+    ///
+    /// Any [`enum@Error`] from [`EncryptionLayer::resolve`].
+    pub fn resolve(self, public_key: Option<&String>) -> Result<Config, Error> {
+        Ok(Config {
+            encryption:        self.encryption.unwrap_or_default().resolve(public_key)?,
+            walker:            self.walker.resolve(),
+            restore_candidate: self.restore_candidate.unwrap_or_default(),
+        })
+    }
+}
+
+/// When a hoard should be backed up when running unattended.
+///
+/// See `hoard schedule install`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    /// Back up each time this much time has passed, e.g. `6h` or `1day 12h`.
+    Interval(String),
+    /// Back up at the times matched by a calendar expression. This is a
+    /// systemd `OnCalendar` expression (e.g. `daily` or `Mon *-*-* 09:00`),
+    /// or a cron expression when installing into a crontab.
+    Calendar(String),
+}
+
+impl Schedule {
+    /// The parsed duration of an [`Interval`](Schedule::Interval) schedule,
+    /// or `None` for a calendar schedule.
+    ///
+    /// # Errors
+    ///
+    /// The interval is not a valid duration.
+    pub fn interval(&self) -> Result<Option<Duration>, humantime::DurationError> {
+        match self {
+            Self::Interval(interval) => humantime::parse_duration(interval).map(Some),
+            Self::Calendar(_) => Ok(None),
+        }
+    }
+}
+
+/// One environment condition of a [`Pile`], as evaluated on this system.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Candidate {
+    /// The condition as written in the configuration, e.g. `linux|laptop`.
+    pub condition: String,
+    /// The path(s) used if the condition is chosen, before expanding
+    /// variables.
+    pub path:      ConditionPaths,
+    /// The environments in the condition that do not apply to this system.
+    /// The condition can only be chosen if this is empty.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing:   Vec<String>,
+    /// The weight of each environment in the condition, largest first.
+    /// Conditions with more environments win, ties are broken by comparing
+    /// these.
+    pub scores:    Vec<usize>,
+}
+
+/// The path of a [`Pile`] for one environment condition: a single path, or
+/// candidate paths in order of preference.
+///
+/// Backups use the first candidate that exists, restores the one chosen by
+/// the [`RestoreCandidate`] setting.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ConditionPaths {
+    /// A single path.
+    Single(String),
+    /// Candidate paths, most preferred first.
+    Candidates(Vec<String>),
+}
+
+impl ConditionPaths {
+    /// The candidate paths, most preferred first. A single path is the only
+    /// candidate.
+    #[must_use]
+    pub fn candidates(&self) -> &[String] {
+        match self {
+            Self::Single(path) => std::slice::from_ref(path),
+            Self::Candidates(paths) => paths,
+        }
+    }
+}
+
+impl fmt::Display for ConditionPaths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Single(path) => write!(f, "{}", path),
+            Self::Candidates(paths) => write!(f, "[{}]", paths.join(", ")),
+        }
+    }
+}
+
+impl From<&str> for ConditionPaths {
+    fn from(path: &str) -> Self {
+        Self::Single(path.to_owned())
+    }
 }
 
 /// A single pile in the hoard.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Pile {
-    config: Option<Config>,
+    /// Settings of the pile.
+    config:   Option<ConfigLayer>,
+    /// Only used for anonymous piles. Named piles use the hoard's schedule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<Schedule>,
+    /// The path of the pile for each environment condition.
     #[serde(flatten)]
-    items:  HashMap<String, String>,
+    items:    HashMap<String, ConditionPaths>,
 }
 
 impl Pile {
+    /// The settings set on this pile, if any.
+    #[must_use]
+    pub fn config(&self) -> Option<&ConfigLayer> {
+        self.config.as_ref()
+    }
+
+    /// The configured environment conditions and the path used for each.
+    #[must_use]
+    pub fn conditions(&self) -> &HashMap<String, ConditionPaths> {
+        &self.items
+    }
+
+    /// The [`EnvTrie`] of the conditions of this pile.
+    fn trie(&self, exclusivity: &[Vec<String>]) -> Result<EnvTrie, Error> {
+        let items: HashMap<String, String> = self
+            .items
+            .iter()
+            .map(|(condition, paths)| (condition.clone(), paths.to_string()))
+            .collect();
+        EnvTrie::new(&items, exclusivity).map_err(Error::from)
+    }
+
+    /// The environment condition, as written in the configuration, whose path
+    /// is used with the given environments.
+    ///
+    /// # Errors
+    ///
+    /// Any [`enum@Error`] that occurs while evaluating the conditions.
+    pub fn matching_condition(
+        &self,
+        envs: &HashMap<String, bool>,
+        exclusivity: &[Vec<String>],
+    ) -> Result<Option<&str>, Error> {
+        let trie = self.trie(exclusivity)?;
+        let condition = match trie.get_match(envs)? {
+            None => return Ok(None),
+            Some((condition, _)) => condition,
+        };
+
+        // The trie sorts the environments of a condition, so compare them as sets
+        let sorted = |condition: &str| {
+            let mut envs: Vec<String> = condition.split('|').map(str::to_owned).collect();
+            envs.sort_unstable();
+            envs
+        };
+        let matched = sorted(&condition);
+        Ok(self
+            .items
+            .keys()
+            .find(|key| sorted(key) == matched)
+            .map(String::as_str))
+    }
+
+    /// Every environment condition of this pile, sorted, with whether it
+    /// applies and how it would be scored against the others.
+    ///
+    /// # Errors
+    ///
+    /// [`TrieError::WeightCycle`] if the exclusivity lists are inconsistent.
+    pub fn candidates(
+        &self,
+        envs: &HashMap<String, bool>,
+        exclusivity: &[Vec<String>],
+    ) -> Result<Vec<Candidate>, Error> {
+        let weights = environment_weights(exclusivity)?;
+        let mut candidates: Vec<Candidate> = self
+            .items
+            .iter()
+            .map(|(condition, path)| {
+                let names = condition.split('|');
+                let mut scores: Vec<usize> = names
+                    .clone()
+                    .map(|env| weights.get(env).copied().unwrap_or(DEFAULT_WEIGHT))
+                    .collect();
+                scores.sort_unstable_by(|left, right| right.cmp(left));
+                Candidate {
+                    condition: condition.clone(),
+                    path: path.clone(),
+                    missing: names
+                        .filter(|env| !envs.get(*env).copied().unwrap_or(false))
+                        .map(str::to_owned)
+                        .collect(),
+                    scores,
+                }
+            })
+            .collect();
+        candidates.sort_unstable_by(|left, right| left.condition.cmp(&right.condition));
+        Ok(candidates)
+    }
+
+    /// Resolve the candidate paths of the pile with `vars` and their glob
+    /// patterns, using the first that exists, and its settings on top of the
+    /// `inherited` ones. Without any settings at any level, the pile has no
+    /// [`Config`].
     fn process_with(
         self,
         envs: &HashMap<String, bool>,
         exclusivity: &[Vec<String>],
+        inherited: &ConfigLayer,
+        public_key: Option<&String>,
+        vars: &PathVars,
     ) -> Result<ConfigSingle, Error> {
         let _span = tracing::debug_span!(
             "process_pile",
@@ -154,20 +645,58 @@ impl Pile {
         )
         .entered();
 
-        let Pile { config, items } = self;
-        let trie = EnvTrie::new(&items, exclusivity)?;
-        let path = trie.get_path(envs)?.map(expand_env_in_path).transpose()?;
+        let paths = self
+            .matching_condition(envs, exclusivity)?
+            .and_then(|condition| self.items.get(condition))
+            .map_or(&[][..], ConditionPaths::candidates);
+        let mut candidates = Vec::new();
+        for path in paths {
+            let path = vars.expand(path)?;
+            match glob::resolve(&path) {
+                Ok(Some(resolved)) => candidates.push(resolved),
+                Ok(None) => tracing::warn!(?path, "no directory matches the pile path"),
+                // Only this system is ambiguous, so skip the path instead of
+                // failing to load every other hoard
+                Err(error @ glob::Error::Ambiguous { .. }) =>
+                    tracing::error!(%error, "skipping the pile path"),
+                Err(error) => return Err(error.into()),
+            }
+        }
+        let path = candidates
+            .iter()
+            .find(|path| glob::exists(path))
+            .or_else(|| candidates.first())
+            .cloned();
+        tracing::trace!(?path, ?candidates, "chosen pile path");
+        let config = self.config;
+
+        let layer = inherited.clone().layer(config.unwrap_or_default());
+        let config = if layer.is_empty() {
+            None
+        } else {
+            Some(layer.resolve(public_key)?)
+        };
+        tracing::trace!(?config, "effective pile configuration");
 
-        Ok(ConfigSingle { config, path })
+        Ok(ConfigSingle {
+            config,
+            path,
+            candidates,
+        })
     }
 }
 
 /// A set of multiple related piles (i.e. in a single hoard).
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct MultipleEntries {
-    config: Option<Config>,
+    /// Settings inherited by every pile.
+    config:   Option<ConfigLayer>,
+    /// When the hoard is backed up unattended.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<Schedule>,
+    /// The piles, by name.
     #[serde(flatten)]
-    items:  HashMap<String, Pile>,
+    items:    HashMap<String, Pile>,
 }
 
 impl MultipleEntries {
@@ -175,14 +704,25 @@ impl MultipleEntries {
         self,
         envs: &HashMap<String, bool>,
         exclusivity: &[Vec<String>],
+        global: &GlobalConfig,
+        vars: &PathVars,
     ) -> Result<ConfigMultiple, Error> {
-        let MultipleEntries { config, items } = self;
+        let MultipleEntries { config, items, .. } = self;
+        let inherited = global.config.clone().layer(config.unwrap_or_default());
         let items = items
             .into_iter()
             .map(|(pile, entry)| {
                 tracing::debug!(%pile, "processing pile");
-                let mut entry = entry.process_with(envs, exclusivity)?;
-                entry.config = entry.config.or_else(|| config.clone());
+                if entry.schedule.is_some() {
+                    tracing::warn!(%pile, "schedules can only be set per hoard, ignoring");
+                }
+                let entry = entry.process_with(
+                    envs,
+                    exclusivity,
+                    &inherited,
+                    global.public_key.as_ref(),
+                    vars,
+                )?;
                 Ok((pile, entry))
             })
             .collect::<Result<_, Error>>()?;
@@ -192,8 +732,12 @@ impl MultipleEntries {
 }
 
 /// A definition of a Hoard.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-#[serde(untagged)]
+///
+/// Deserialized through [`TryFrom<Value>`] rather than as an untagged enum, to
+/// say what is wrong with an invalid hoard instead of that it matches no
+/// variant.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged, try_from = "Value")]
 pub enum Hoard {
     /// A single anonymous [`Pile`].
     Single(Pile),
@@ -201,12 +745,215 @@ pub enum Hoard {
     Multiple(MultipleEntries),
 }
 
+/// What kind of value `value` is, for error messages.
+fn value_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "nothing",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a path",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "a table",
+    }
+}
+
+/// Remove the `config` and `schedule` of a hoard or pile from `table`.
+/// `context` is prepended to keys in error messages.
+fn take_settings(
+    table: &mut Map<String, Value>,
+    context: &str,
+) -> Result<(Option<ConfigLayer>, Option<Schedule>), String> {
+    Ok((
+        take_setting(table, "config", context)?,
+        take_setting(table, "schedule", context)?,
+    ))
+}
+
+/// Remove the setting `key` from `table`. Its name is reserved, so a path or
+/// a table that is not the setting is reported as a misnamed condition or
+/// pile.
+fn take_setting<T: DeserializeOwned>(
+    table: &mut Map<String, Value>,
+    key: &str,
+    context: &str,
+) -> Result<Option<T>, String> {
+    match table.remove(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(_) | Value::Array(_)) => Err(format!(
+            "`{}{}` is a reserved name for settings and cannot be an environment condition, \
+             rename the environment",
+            context, key
+        )),
+        Some(value) => serde_json::from_value(value).map(Some).map_err(|err| {
+            format!(
+                "invalid `{0}{1}`: {2} (`{1}` is a reserved name for settings and cannot name a \
+                 pile)",
+                context, key, err
+            )
+        }),
+    }
+}
+
+/// The candidate paths in the list `paths` of `condition`.
+fn take_candidates(paths: Vec<Value>, condition: &str) -> Result<ConditionPaths, String> {
+    if paths.is_empty() {
+        return Err(format!("`{}` should list at least one path", condition));
+    }
+    paths
+        .into_iter()
+        .map(|path| match path {
+            Value::String(path) => Ok(path),
+            Value::Null
+            | Value::Bool(_)
+            | Value::Number(_)
+            | Value::Array(_)
+            | Value::Object(_) => Err(format!(
+                "`{}` should list paths, found {}",
+                condition,
+                value_kind(&path)
+            )),
+        })
+        .collect::<Result<_, _>>()
+        .map(ConditionPaths::Candidates)
+}
+
+/// The environment conditions and paths in `table`.
+fn take_paths(
+    table: Map<String, Value>,
+    context: &str,
+) -> Result<HashMap<String, ConditionPaths>, String> {
+    table
+        .into_iter()
+        .map(|(condition, value)| match value {
+            Value::String(path) => Ok((condition, ConditionPaths::Single(path))),
+            Value::Array(paths) => {
+                let paths = take_candidates(paths, &[context, &condition].concat())?;
+                Ok((condition, paths))
+            },
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::Object(_) => Err(format!(
+                "`{}{}` should be a path, found {}",
+                context,
+                condition,
+                value_kind(&value)
+            )),
+        })
+        .collect()
+}
+
+impl TryFrom<Value> for Hoard {
+    type Error = String;
+
+    /// Parse a hoard from its definition in the configuration.
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let mut table = match value {
+            Value::Object(table) => table,
+            Value::Null
+            | Value::Bool(_)
+            | Value::Number(_)
+            | Value::String(_)
+            | Value::Array(_) =>
+                return Err(format!(
+                    "a hoard should be a table of environment conditions and paths, or of named \
+                     piles, found {}",
+                    value_kind(&value)
+                )),
+        };
+        let (config, schedule) = take_settings(&mut table, "")?;
+
+        if let Some((key, value)) = table
+            .iter()
+            .find(|(_, value)| !value.is_string() && !value.is_array() && !value.is_object())
+        {
+            return Err(format!(
+                "`{}` should be a path, a list of paths or a named pile, found {}",
+                key,
+                value_kind(value)
+            ));
+        }
+        let path = table
+            .iter()
+            .find(|(_, value)| value.is_string() || value.is_array());
+        let pile = table.iter().find(|(_, value)| value.is_object());
+        match (path, pile) {
+            (Some((path, _)), Some((pile, _))) => Err(format!(
+                "the hoard has both paths (`{}`) and named piles (`{}`), move the paths into a \
+                 named pile",
+                path, pile
+            )),
+            (_, Some(_)) => {
+                let mut items = HashMap::new();
+                for (name, value) in table {
+                    let mut pile = match value {
+                        Value::Object(pile) => pile,
+                        Value::Null
+                        | Value::Bool(_)
+                        | Value::Number(_)
+                        | Value::String(_)
+                        | Value::Array(_) => continue,
+                    };
+                    let context = [name.as_str(), "."].concat();
+                    let (config, schedule) = take_settings(&mut pile, &context)?;
+                    let items_of_pile = take_paths(pile, &context)?;
+                    items.insert(name, Pile {
+                        config,
+                        schedule,
+                        items: items_of_pile,
+                    });
+                }
+                Ok(Hoard::Multiple(MultipleEntries {
+                    config,
+                    schedule,
+                    items,
+                }))
+            },
+            (_, None) => Ok(Hoard::Single(Pile {
+                config,
+                schedule,
+                items: take_paths(table, "")?,
+            })),
+        }
+    }
+}
+
 impl Hoard {
+    /// The configured [`Schedule`] for this `Hoard`, if any.
+    #[must_use]
+    pub fn schedule(&self) -> Option<&Schedule> {
+        match self {
+            Hoard::Single(single) => single.schedule.as_ref(),
+            Hoard::Multiple(multiple) => multiple.schedule.as_ref(),
+        }
+    }
+
+    /// The settings set on the hoard itself. For a single anonymous pile,
+    /// these are the settings of the pile.
+    #[must_use]
+    pub fn config(&self) -> Option<&ConfigLayer> {
+        match self {
+            Hoard::Single(single) => single.config.as_ref(),
+            Hoard::Multiple(multiple) => multiple.config.as_ref(),
+        }
+    }
+
+    /// The [`Pile`]s of this `Hoard`, with the name of each named pile.
+    #[must_use]
+    pub fn piles(&self) -> Vec<(Option<&str>, &Pile)> {
+        match self {
+            Hoard::Single(single) => vec![(None, single)],
+            Hoard::Multiple(multiple) => multiple
+                .items
+                .iter()
+                .map(|(name, pile)| (Some(name.as_str()), pile))
+                .collect(),
+        }
+    }
+
     /// Resolve with path(s) to use for the `Hoard`.
     ///
     /// Uses the provided information to determine which environment combination
     /// is the best match for each [`Pile`] and thus which path to use for
-    /// each one.
+    /// each one, expanded with `vars`. The settings of each pile are merged
+    /// with those of the hoard and `global`.
     ///
     /// # Errors
     ///
@@ -215,19 +962,28 @@ impl Hoard {
         self,
         envs: &HashMap<String, bool>,
         exclusivity: &[Vec<String>],
+        global: &GlobalConfig,
+        vars: &PathVars,
     ) -> Result<crate::config::hoard::Hoard, Error> {
         match self {
             Hoard::Single(single) => {
                 tracing::debug!("processing anonymous pile");
-                Ok(ConfigHoard::Anonymous(
-                    single.process_with(envs, exclusivity)?,
-                ))
+                Ok(ConfigHoard::Anonymous(single.process_with(
+                    envs,
+                    exclusivity,
+                    &global.config,
+                    global.public_key.as_ref(),
+                    vars,
+                )?))
             },
             Hoard::Multiple(multiple) => {
                 tracing::debug!("processing named pile(s)");
-                Ok(ConfigHoard::Named(
-                    multiple.process_with(envs, exclusivity)?,
-                ))
+                Ok(ConfigHoard::Named(multiple.process_with(
+                    envs,
+                    exclusivity,
+                    global,
+                    vars,
+                )?))
             },
         }
     }
@@ -246,25 +1002,253 @@ mod tests {
         #[test]
         fn env_vars_are_expanded() {
             let pile = Pile {
-                config: None,
-                items:  hashmap! {
+                config:   None,
+                schedule: None,
+                items:    hashmap! {
                     "foo".into() => "${HOME}/something".into()
                 },
             };
 
             let home = std::env::var("HOME").expect("failed to read $HOME");
+            let path = PathBuf::from(format!("{}/something", home));
             let expected = ConfigPile {
-                config: None,
-                path:   Some(PathBuf::from(format!("{}/something", home))),
+                config:     None,
+                path:       Some(path.clone()),
+                candidates: vec![path],
             };
 
             let envs = hashmap! { "foo".into() =>  true };
             let result = pile
-                .process_with(&envs, &[])
+                .process_with(
+                    &envs,
+                    &[],
+                    &ConfigLayer::default(),
+                    None,
+                    &PathVars::default(),
+                )
                 .expect("pile should process without issues");
 
             assert_eq!(result, expected);
         }
+
+        #[test]
+        fn candidates_and_matching_condition() {
+            let pile = Pile {
+                config:   None,
+                schedule: None,
+                items:    hashmap! {
+                    "linux|laptop".into() => "/laptop".into(),
+                    "linux".into() => "/linux".into(),
+                    "macos".into() => "/macos".into(),
+                },
+            };
+            let envs = hashmap! {
+                "linux".into() => true,
+                "laptop".into() => true,
+                "macos".into() => false,
+            };
+            let exclusivity = vec![vec!["linux".into(), "macos".into()]];
+
+            let candidates = pile
+                .candidates(&envs, &exclusivity)
+                .expect("failed to evaluate candidates");
+            let summary: Vec<_> = candidates
+                .iter()
+                .map(|cand| {
+                    (
+                        cand.condition.as_str(),
+                        cand.missing.clone(),
+                        cand.scores.clone(),
+                    )
+                })
+                .collect();
+            assert_eq!(summary, vec![
+                ("linux", vec![], vec![1]),
+                ("linux|laptop", vec![], vec![1, 1]),
+                ("macos", vec!["macos".to_owned()], vec![0]),
+            ]);
+            assert_eq!(
+                pile.matching_condition(&envs, &exclusivity).unwrap(),
+                Some("linux|laptop")
+            );
+        }
+
+        #[test]
+        fn first_existing_candidate_is_backed_up() {
+            let dir = tempfile::tempdir().expect("failed to create temp dir");
+            let root = dir.path().display().to_string();
+            std::fs::create_dir(dir.path().join("native")).unwrap();
+            let hoard: Hoard = toml::from_str(&format!(
+                "foo = [\"{0}/flatpak\", \"{0}/native\"]\n[config]\nrestore_candidate = \
+                 \"first\"\n",
+                root
+            ))
+            .unwrap();
+            let envs = hashmap! { "foo".into() => true };
+            let hoard = hoard
+                .process_with(&envs, &[], &GlobalConfig::default(), &PathVars::default())
+                .unwrap();
+
+            let path = |hoard: &ConfigHoard| match hoard {
+                ConfigHoard::Anonymous(pile) => pile.path.clone(),
+                ConfigHoard::Named(_) => None,
+            };
+            assert_eq!(path(&hoard), Some(dir.path().join("native")));
+            assert_eq!(
+                path(&hoard.for_operation(true)),
+                Some(dir.path().join("native"))
+            );
+            assert_eq!(
+                path(&hoard.for_operation(false)),
+                Some(dir.path().join("flatpak"))
+            );
+        }
+
+        #[test]
+        fn ambiguous_candidates_are_skipped() {
+            let dir = tempfile::tempdir().expect("failed to create temp dir");
+            for profile in &["a.default", "b.default", "native"] {
+                std::fs::create_dir(dir.path().join(profile)).unwrap();
+            }
+            let hoard: Hoard = toml::from_str(&format!(
+                "foo = [\"{0}/*.default/user.js\", \"{0}/native\"]\n",
+                dir.path().display()
+            ))
+            .unwrap();
+            let envs = hashmap! { "foo".into() => true };
+            let hoard = hoard
+                .process_with(&envs, &[], &GlobalConfig::default(), &PathVars::default())
+                .expect("an ambiguous path should not fail the configuration");
+            assert!(
+                matches!(&hoard, ConfigHoard::Anonymous(pile) if pile.candidates == [dir.path().join("native")]),
+                "{:?}",
+                hoard
+            );
+        }
+
+        #[test]
+        fn piles_inherit_settings_field_by_field() {
+            let global = GlobalConfig {
+                public_key: Some("GLOBAL".into()),
+                config: ConfigLayer {
+                    walker: WalkerLayer {
+                        hidden: Some(true),
+                        pattern: Some("*.conf".into()),
+                        ..WalkerLayer::default()
+                    },
+                    ..ConfigLayer::default()
+                },
+                ..GlobalConfig::default()
+            };
+            let pile = |config| Pile {
+                config,
+                schedule: None,
+                items: hashmap! { "foo".into() => "/some/path".into() },
+            };
+            let hoard = Hoard::Multiple(MultipleEntries {
+                config:   Some(ConfigLayer {
+                    encryption:        Some(EncryptionLayer {
+                        kind: Some(EncryptionKind::Asymmetric),
+                        ..EncryptionLayer::default()
+                    }),
+                    walker:            WalkerLayer {
+                        max_depth: Some(3),
+                        ..WalkerLayer::default()
+                    },
+                    restore_candidate: None,
+                }),
+                schedule: None,
+                items:    hashmap! {
+                    "depth".into() => pile(Some(ConfigLayer {
+                        encryption: Some(EncryptionLayer {
+                            armor: Some(false),
+                            ..EncryptionLayer::default()
+                        }),
+                        walker: WalkerLayer {
+                            max_depth: Some(1),
+                            ..WalkerLayer::default()
+                        },
+                        restore_candidate: None,
+                    })),
+                    "plain".into() => pile(Some(ConfigLayer {
+                        encryption: Some(EncryptionLayer {
+                            kind: Some(EncryptionKind::None),
+                            ..EncryptionLayer::default()
+                        }),
+                        ..ConfigLayer::default()
+                    })),
+                },
+            });
+
+            let envs = hashmap! { "foo".into() => true };
+            let piles = match hoard
+                .process_with(&envs, &[], &global, &PathVars::default())
+                .unwrap()
+            {
+                ConfigHoard::Named(multiple) => multiple.piles,
+                ConfigHoard::Anonymous(_) => panic!("expected named piles"),
+            };
+
+            let depth = piles["depth"].config.clone().unwrap();
+            assert_eq!(
+                depth.encryption,
+                Some(Encryption::Asymmetric(AsymmetricEncryption {
+                    public_key: Some("GLOBAL".into()),
+                    armor:      false,
+                }))
+            );
+            assert_eq!(depth.walker, Walker {
+                hidden: true,
+                max_depth: Some(1),
+                pattern: "*.conf".into(),
+                ..Walker::default()
+            });
+
+            let plain = piles["plain"].config.clone().unwrap();
+            assert_eq!(plain.encryption, None);
+            assert_eq!(plain.walker.max_depth, Some(3));
+        }
+
+        #[test]
+        fn incomplete_encryption_is_an_error() {
+            let symmetric = EncryptionLayer {
+                kind: Some(EncryptionKind::Symmetric),
+                ..EncryptionLayer::default()
+            };
+            assert!(matches!(
+                symmetric.resolve(None),
+                Err(Error::MissingPassword)
+            ));
+            let armor = EncryptionLayer {
+                armor: Some(true),
+                ..EncryptionLayer::default()
+            };
+            assert!(matches!(
+                armor.resolve(None),
+                Err(Error::MissingEncryptionKind)
+            ));
+        }
+
+        #[test]
+        fn invalid_hoards_are_explained() {
+            let error = |text: &str| toml::from_str::<Hoard>(text).unwrap_err().to_string();
+            assert!(error("linux = \"/a\"\n[init]\nlinux = \"/b\"\n")
+                .contains("both paths (`linux`) and named piles (`init`)"));
+            assert!(error("[init]\nlinux = 1\n")
+                .contains("`init.linux` should be a path, found a number"));
+            assert!(error("[init]\nlinux = [\"/b\", 2]\n")
+                .contains("`init.linux` should list paths, found a number"));
+            assert!(error("linux = []\n").contains("`linux` should list at least one path"));
+            assert!(error("[config]\nmax_depth = \"deep\"\n").contains("invalid `config`"));
+            assert!(error("schedule = \"/a\"\n").contains("`schedule` is a reserved name"));
+            assert!(
+                error("[init]\nconfig = [\"/a\"]\n").contains("`init.config` is a reserved name")
+            );
+            assert!(
+                error("[init]\nlinux = \"/a\"\n[schedule]\nlinux = \"/b\"\n")
+                    .contains("`schedule` is a reserved name for settings and cannot name a pile")
+            );
+        }
     }
 
     mod serde {
@@ -275,9 +1259,10 @@ mod tests {
         #[test]
         fn single_entry_no_config() {
             let hoard = Hoard::Single(Pile {
-                config: None,
-                items:  hashmap! {
-                    "bar_env|foo_env".to_owned() => "/some/path".to_owned()
+                config:   None,
+                schedule: None,
+                items:    hashmap! {
+                    "bar_env|foo_env".to_owned() => "/some/path".into()
                 },
             });
 
@@ -294,15 +1279,18 @@ mod tests {
         #[test]
         fn single_entry_with_config() {
             let hoard = Hoard::Single(Pile {
-                config: Some(Config {
-                    encryption: Some(Encryption::Asymmetric(AsymmetricEncryption {
+                config:   Some(ConfigLayer {
+                    encryption:        Some(EncryptionLayer {
+                        kind: Some(EncryptionKind::Asymmetric),
                         public_key: Some("public key".to_owned()),
-                        armor:      true,
-                    })),
-                    walker:     Walker::default(),
+                        ..EncryptionLayer::default()
+                    }),
+                    walker:            WalkerLayer::default(),
+                    restore_candidate: None,
                 }),
-                items:  hashmap! {
-                    "bar_env|foo_env".to_owned() => "/some/path".to_owned()
+                schedule: None,
+                items:    hashmap! {
+                    "bar_env|foo_env".to_owned() => "/some/path".into()
                 },
             });
 
@@ -311,10 +1299,22 @@ mod tests {
                 Token::Str("config"),
                 Token::Some,
                 Token::Map { len: None },
+                Token::Str("encryption"),
+                Token::Some,
+                Token::Struct {
+                    name: "EncryptionLayer",
+                    len:  2,
+                },
                 Token::Str("encrypt"),
-                Token::Str("asymmetric"),
+                Token::Some,
+                Token::UnitVariant {
+                    name:    "EncryptionKind",
+                    variant: "asymmetric",
+                },
                 Token::Str("encrypt_pub_key"),
+                Token::Some,
                 Token::Str("public key"),
+                Token::StructEnd,
                 Token::MapEnd,
                 Token::Str("bar_env|foo_env"),
                 Token::Str("/some/path"),
@@ -322,15 +1322,42 @@ mod tests {
             ]);
         }
 
+        #[test]
+        fn single_entry_with_schedule() {
+            let hoard = Hoard::Single(Pile {
+                config:   None,
+                schedule: Some(Schedule::Interval("6h".to_owned())),
+                items:    hashmap! {
+                    "bar_env|foo_env".to_owned() => "/some/path".into()
+                },
+            });
+
+            assert_tokens(&hoard, &[
+                Token::Map { len: None },
+                Token::Str("config"),
+                Token::None,
+                Token::Str("schedule"),
+                Token::Some,
+                Token::Enum { name: "Schedule" },
+                Token::Str("interval"),
+                Token::Str("6h"),
+                Token::Str("bar_env|foo_env"),
+                Token::Str("/some/path"),
+                Token::MapEnd,
+            ]);
+        }
+
         #[test]
         fn multiple_entry_no_config() {
             let hoard = Hoard::Multiple(MultipleEntries {
-                config: None,
-                items:  hashmap! {
+                config:   None,
+                schedule: None,
+                items:    hashmap! {
                     "item1".to_owned() => Pile {
                         config: None,
+                        schedule: None,
                         items: hashmap! {
-                            "bar_env|foo_env".to_owned() => "/some/path".to_owned()
+                            "bar_env|foo_env".to_owned() => "/some/path".into()
                         }
                     },
                 },
@@ -354,17 +1381,25 @@ mod tests {
         #[test]
         fn multiple_entry_with_config() {
             let hoard = Hoard::Multiple(MultipleEntries {
-                config: Some(Config {
-                    encryption: Some(Encryption::Symmetric(SymmetricEncryption::Password(
-                        "correcthorsebatterystaple".into(),
-                    ))),
-                    walker:     Walker::default(),
+                config:   Some(ConfigLayer {
+                    encryption:        Some(EncryptionLayer {
+                        kind: Some(EncryptionKind::Symmetric),
+                        password: Some("correcthorsebatterystaple".into()),
+                        ..EncryptionLayer::default()
+                    }),
+                    walker:            WalkerLayer {
+                        hidden: Some(true),
+                        ..WalkerLayer::default()
+                    },
+                    restore_candidate: None,
                 }),
-                items:  hashmap! {
+                schedule: None,
+                items:    hashmap! {
                     "item1".to_owned() => Pile {
                         config: None,
+                        schedule: None,
                         items: hashmap! {
-                            "bar_env|foo_env".to_owned() => "/some/path".to_owned()
+                            "bar_env|foo_env".to_owned() => "/some/path".into()
                         }
                     },
                 },
@@ -378,30 +1413,18 @@ mod tests {
                     Token::Map { len: None },
                         Token::Str("encryption"),
                         Token::Some,
-                        Token::Map { len: Some(2) },
+                        Token::Struct { name: "EncryptionLayer", len: 2 },
                             Token::Str("encrypt"),
-                            Token::Str("symmetric"),
+                            Token::Some,
+                            Token::UnitVariant { name: "EncryptionKind", variant: "symmetric" },
                             Token::Str("encrypt_pass"),
+                            Token::Some,
                             Token::Str("correcthorsebatterystaple"),
-                        Token::MapEnd,
-
-                        Token::Str("walker"),
-                        Token::Str("Walker"),
-                            Token::Str("follow_links"),
-                            Token::Bool(false),
-                            Token::Str("hidden"),
-                            Token::Bool(true),
-                            Token::Str("max_depth"),
-                            Token::None,
-                            Token::Str("exclude"),
-                            Token::Seq { len: Some(0) },
-                            Token::SeqEnd,
-                            Token::Str("pattern"),
-                            Token::String("*"),
-                            Token::Str("regex"),
-                            Token::Bool(false),
-                            Token::Str("case_sensitive"),
-                            Token::Bool(false),
+                        Token::StructEnd,
+
+                        Token::Str("hidden"),
+                        Token::Some,
+                        Token::Bool(true),
                     Token::MapEnd,
 
                     Token::Str("item1"),
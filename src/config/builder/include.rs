@@ -0,0 +1,383 @@
+//! Configuration split across several files.
+//!
+//! A configuration file can `include` other files or globs, relative to
+//! itself, and every file in `config.d` next to the main configuration file is
+//! loaded as well. See [`Builder::with_includes`].
+
+use super::{Builder, Error, GlobalConfig};
+use globset::GlobBuilder;
+use ignore::WalkBuilder;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The directory next to the main configuration file whose files are loaded
+/// automatically.
+const CONFIG_DIR: &str = "config.d";
+
+/// Extensions of the files loaded from [`CONFIG_DIR`].
+const EXTENSIONS: [&str; 4] = ["toml", "yml", "yaml", "json"];
+
+/// Whether `pattern` uses glob syntax.
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'].as_ref())
+}
+
+/// The directory of the configuration file at `path`.
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+}
+
+/// The files matched by `pattern` from the `include` list of `file`, sorted.
+///
+/// A plain path must exist, while a glob may match nothing.
+fn expand(file: &Path, pattern: &str) -> Result<Vec<PathBuf>, Error> {
+    let full = parent_dir(file).join(pattern);
+    if !is_glob(pattern) {
+        return if full.exists() {
+            Ok(vec![full])
+        } else {
+            Err(Error::IncludeNotFound {
+                file:    file.to_path_buf(),
+                pattern: pattern.to_owned(),
+            })
+        };
+    }
+
+    let matcher = GlobBuilder::new(&full.to_string_lossy())
+        .literal_separator(true)
+        .build()
+        .map_err(|error| Error::InvalidInclude {
+            file: file.to_path_buf(),
+            pattern: pattern.to_owned(),
+            error,
+        })?
+        .compile_matcher();
+    // Only walk the directories the glob can match in
+    let base: PathBuf = full
+        .components()
+        .take_while(|component| !is_glob(&component.as_os_str().to_string_lossy()))
+        .collect();
+
+    let mut found: Vec<PathBuf> = WalkBuilder::new(&base)
+        .standard_filters(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().map_or(false, |kind| kind.is_file()))
+        .map(ignore::DirEntry::into_path)
+        .filter(|path| matcher.is_match(path))
+        .collect();
+    found.sort();
+    Ok(found)
+}
+
+/// The configuration files in [`CONFIG_DIR`] next to `config_file`, sorted.
+fn config_dir_files(config_file: &Path) -> Result<Vec<PathBuf>, Error> {
+    let dir = parent_dir(config_file).join(CONFIG_DIR);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) =>
+            return Err(Error::Included {
+                path:  dir,
+                error: Box::new(Error::ReadConfig(err)),
+            }),
+    };
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|err| Error::Included {
+                path:  dir.clone(),
+                error: Box::new(Error::ReadConfig(err)),
+            })?
+            .path();
+        let is_config = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map_or(false, |ext| EXTENSIONS.contains(&ext));
+        if is_config && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Loads configuration files, skipping any that were already loaded.
+struct Loader {
+    /// Canonical paths of the files loaded so far.
+    visited: Vec<PathBuf>,
+    /// Each loaded file with its contents, in the order they were loaded.
+    loaded:  Vec<(PathBuf, Builder)>,
+}
+
+impl Loader {
+    /// Mark `path` as loaded, returning `false` if it already was.
+    fn visit(&mut self, path: &Path) -> bool {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.visited.contains(&canonical) {
+            false
+        } else {
+            self.visited.push(canonical);
+            true
+        }
+    }
+
+    /// Add `builder`, read from `path`, and every file it includes.
+    fn add(&mut self, path: &Path, builder: Builder) -> Result<(), Error> {
+        let includes = builder.include.clone().unwrap_or_default();
+        self.loaded.push((path.to_path_buf(), builder));
+        for pattern in &includes {
+            for file in expand(path, pattern)? {
+                self.load(&file)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the file at `path` and every file it includes, unless it was
+    /// already loaded.
+    fn load(&mut self, path: &Path) -> Result<(), Error> {
+        if !self.visit(path) {
+            tracing::debug!(path = %path.display(), "skipping already included file");
+            return Ok(());
+        }
+        tracing::debug!(path = %path.display(), "loading included configuration file");
+        let builder = Builder::from_file(path).map_err(|error| Error::Included {
+            path:  path.to_path_buf(),
+            error: Box::new(error),
+        })?;
+        self.add(path, builder)
+    }
+}
+
+/// Move the entries of `other`, defined in `path`, into `target`, failing
+/// with `duplicate(name, first, second)` if an entry was already defined.
+fn merge_named<T>(
+    target: &mut Option<HashMap<String, T>>,
+    owners: &mut HashMap<String, PathBuf>,
+    other: Option<HashMap<String, T>>,
+    path: &Path,
+    duplicate: fn(String, PathBuf, PathBuf) -> Error,
+) -> Result<(), Error> {
+    let target = target.get_or_insert_with(HashMap::new);
+    for (name, value) in other.unwrap_or_default() {
+        if let Some(first) = owners.get(&name) {
+            return Err(duplicate(name, first.clone(), path.to_path_buf()));
+        }
+        owners.insert(name.clone(), path.to_path_buf());
+        target.insert(name, value);
+    }
+    Ok(())
+}
+
+/// Merge the global configuration `other` into `target`. Ignore patterns are
+/// combined, other settings are only taken from `other` if not already set.
+fn merge_global(target: &mut Option<GlobalConfig>, other: Option<GlobalConfig>) {
+    let other = match other {
+        Some(other) => other,
+        None => return,
+    };
+    let target = target.get_or_insert_with(GlobalConfig::default);
+    if let Some(ignores) = other.ignores {
+        target.ignores.get_or_insert_with(Vec::new).extend(ignores);
+    }
+    if target.public_key.is_none() {
+        target.public_key = other.public_key;
+    }
+    target.config = other.config.layer(target.config.clone());
+}
+
+/// Each name in `names` mapped to `path`, the file it is defined in.
+fn owners<T>(names: Option<&HashMap<String, T>>, path: &Path) -> HashMap<String, PathBuf> {
+    names
+        .into_iter()
+        .flat_map(HashMap::keys)
+        .map(|name| (name.clone(), path.to_path_buf()))
+        .collect()
+}
+
+/// Merge every loaded file into the first one.
+fn merge(loaded: Vec<(PathBuf, Builder)>) -> Result<Builder, Error> {
+    let mut files = loaded.into_iter();
+    let (root_path, mut merged) = files
+        .next()
+        .unwrap_or_else(|| (PathBuf::new(), Builder::new()));
+    let mut env_owners = owners(merged.environments.as_ref(), &root_path);
+    let mut hoard_owners = owners(merged.hoards.as_ref(), &root_path);
+
+    for (path, builder) in files {
+        merge_named(
+            &mut merged.environments,
+            &mut env_owners,
+            builder.environments,
+            &path,
+            |name, first, second| Error::DuplicateEnvironment {
+                name,
+                first,
+                second,
+            },
+        )?;
+        merge_named(
+            &mut merged.hoards,
+            &mut hoard_owners,
+            builder.hoards,
+            &path,
+            |name, first, second| Error::DuplicateHoard {
+                name,
+                first,
+                second,
+            },
+        )?;
+        if let Some(exclusivity) = builder.exclusivity {
+            merged
+                .exclusivity
+                .get_or_insert_with(Vec::new)
+                .extend(exclusivity);
+        }
+        if let Some(vars) = builder.vars {
+            let merged_vars = merged.vars.get_or_insert_with(BTreeMap::new);
+            for (name, value) in vars {
+                merged_vars.entry(name).or_insert(value);
+            }
+        }
+        merge_global(&mut merged.global_config, builder.global_config);
+        if merged.hoards_root.is_none() {
+            merged.hoards_root = builder.hoards_root;
+        }
+        if merged.git.is_none() {
+            merged.git = builder.git;
+        }
+    }
+    merged.hoard_files = hoard_owners;
+    Ok(merged)
+}
+
+/// Load the files included by `builder`, read from `config_file`, and the
+/// files in [`CONFIG_DIR`] next to it, and merge them all into one.
+pub(super) fn merge_includes(builder: Builder, config_file: &Path) -> Result<Builder, Error> {
+    let mut loader = Loader {
+        visited: Vec::new(),
+        loaded:  Vec::new(),
+    };
+    loader.visit(config_file);
+    loader.add(config_file, builder)?;
+    for file in config_dir_files(config_file)? {
+        loader.load(&file)?;
+    }
+    merge(loader.loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, text: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn load(path: &Path) -> Result<Builder, Error> {
+        merge_includes(Builder::from_file(path)?, path)
+    }
+
+    #[test]
+    fn includes_and_config_dir_are_merged() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let main = write(
+            &dir,
+            "config.toml",
+            "include = [\"hoards/*.toml\", \"*.toml\"]\nexclusivity = [[\"a\"]]\n[envs.linux]\nos \
+             = [\"linux\"]\n[global_config]\nignores = [\"*.log\"]\n",
+        );
+        write(
+            &dir,
+            "hoards/nvim.toml",
+            "[hoards.nvim]\n\"linux\" = \"/nvim\"\n",
+        );
+        write(
+            &dir,
+            "hoards/git.toml",
+            "exclusivity = [[\"b\"]]\n[hoards.git]\n\"linux\" = \"/git\"\n",
+        );
+        write(
+            &dir,
+            "config.d/envs.yaml",
+            "envs:\n  macos:\n    os: [macos]\nglobal_config:\n  ignores: ['*.tmp']\n",
+        );
+        write(&dir, "config.d/notes.txt", "not configuration");
+
+        let builder = load(&main).unwrap();
+        let mut hoards: Vec<&String> = builder.hoards.as_ref().unwrap().keys().collect();
+        hoards.sort_unstable();
+        assert_eq!(hoards, ["git", "nvim"]);
+        assert_eq!(
+            builder.hoard_files["nvim"],
+            dir.path().join("hoards/nvim.toml")
+        );
+        assert_eq!(
+            builder.hoard_files["git"],
+            dir.path().join("hoards/git.toml")
+        );
+        let mut envs: Vec<&String> = builder.environments.as_ref().unwrap().keys().collect();
+        envs.sort_unstable();
+        assert_eq!(envs, ["linux", "macos"]);
+        assert_eq!(builder.exclusivity.unwrap(), vec![
+            vec!["a".to_owned()],
+            vec!["b".to_owned()]
+        ]);
+        assert_eq!(builder.global_config.unwrap().ignores.unwrap(), [
+            "*.log", "*.tmp"
+        ]);
+    }
+
+    #[test]
+    fn duplicate_names_name_both_files() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let main = write(
+            &dir,
+            "config.toml",
+            "include = [\"other.json\"]\n[hoards.nvim]\n\"linux\" = \"/nvim\"\n",
+        );
+        let other = write(
+            &dir,
+            "other.json",
+            r#"{ "hoards": { "nvim": { "linux": "/other" } } }"#,
+        );
+        match load(&main).expect_err("nvim is defined twice") {
+            Error::DuplicateHoard {
+                name,
+                first,
+                second,
+            } => {
+                assert_eq!(name, "nvim");
+                assert_eq!(first, main);
+                assert_eq!(second, other);
+            },
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn missing_plain_include_is_an_error() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let main = write(
+            &dir,
+            "config.toml",
+            "include = [\"missing.toml\", \"none/*.toml\"]\n",
+        );
+        assert!(matches!(
+            load(&main),
+            Err(Error::IncludeNotFound { pattern, .. }) if pattern == "missing.toml"
+        ));
+    }
+}
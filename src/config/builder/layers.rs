@@ -0,0 +1,96 @@
+//! Configuration layered from several files.
+//!
+//! From lowest to highest precedence: the system-wide configuration file, the
+//! user's configuration file and a project configuration file found in the
+//! current directory or one of its parents. Command-line arguments are layered
+//! on top of all of them. See [`Builder::with_layers`].
+
+use super::{Builder, Error};
+use crate::{config::directories::system_config_file, PROJECT_CONFIG_FILE_NAME};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// The project configuration file in `start` or the closest of its parents.
+fn find_project_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Whether `left` and `right` are the same file.
+fn same_file(left: &Path, right: &Path) -> bool {
+    match (left.canonicalize(), right.canonicalize()) {
+        (Ok(left), Ok(right)) => left == right,
+        (Err(_), _) | (_, Err(_)) => left == right,
+    }
+}
+
+/// The system-wide and project configuration files that exist, other than
+/// `config_file`.
+fn layer_files(config_file: &Path) -> (Option<PathBuf>, Option<PathBuf>) {
+    let system =
+        system_config_file().filter(|path| path.is_file() && !same_file(path, config_file));
+    let project = env::current_dir()
+        .ok()
+        .and_then(|dir| find_project_file(&dir))
+        .filter(|path| !same_file(path, config_file));
+    (system, project)
+}
+
+/// Whether a system-wide or project configuration file exists besides
+/// `config_file`.
+pub(super) fn has_layers(config_file: &Path) -> bool {
+    let (system, project) = layer_files(config_file);
+    system.is_some() || project.is_some()
+}
+
+/// Read the configuration file at `path` with the files it includes.
+fn load_layer(path: &Path) -> Result<Builder, Error> {
+    tracing::debug!(path = %path.display(), "loading configuration layer");
+    Builder::from_file(path)
+        .and_then(|builder| builder.with_includes(path))
+        .map_err(|error| Error::Layer {
+            path:  path.to_path_buf(),
+            error: Box::new(error),
+        })
+}
+
+/// Layer `user`, read from `config_file`, over the system-wide configuration
+/// and under the project configuration, if they exist.
+pub(super) fn with_layers(user: Builder, config_file: &Path) -> Result<Builder, Error> {
+    let (system, project) = layer_files(config_file);
+    let mut builder = match system {
+        Some(path) => load_layer(&path)?.layer(user),
+        None => user,
+    };
+    if let Some(path) = project {
+        builder = builder.layer(load_layer(&path)?);
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn project_file_is_found_in_parents() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let nested = dir.path().join("project/src/module");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_file(&nested), None);
+
+        let project_file = dir.path().join("project").join(PROJECT_CONFIG_FILE_NAME);
+        fs::write(&project_file, "").unwrap();
+        assert_eq!(find_project_file(&nested), Some(project_file.clone()));
+        assert_eq!(
+            find_project_file(&dir.path().join("project")),
+            Some(project_file)
+        );
+    }
+}
@@ -1,30 +1,47 @@
 //! The [`Builder`] struct serves as an intermediate step between raw
 //! configuration and the [`Config`] type that is used by `hoard`.
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     convert::TryInto,
+    env,
     ffi::OsStr,
     io,
     path::{Path, PathBuf},
 };
 
 // use normpath::PathExt;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 use thiserror::Error;
 
-use self::hoard::Hoard;
+use self::{
+    hoard::{ConfigLayer, Hoard, Schedule},
+    vars::PathVars,
+};
 use environment::Environment;
 
 use crate::{
-    command::Command, config::directories::PROJECT_DIRS, CONFIG_FILE_NAME, HOARDS_DIR_SLUG,
+    command::Command, config::directories::PROJECT_DIRS, CONFIG_FILE_NAME, CONFIG_FILE_NAMES,
+    HOARDS_DIR_SLUG,
 };
 
-use super::Config;
+use super::{
+    git::GitConfig,
+    output::OutputFormat,
+    storage::{self, Location},
+    Config,
+};
 
+mod diagnostic;
 pub mod environment;
 pub mod envtrie;
+pub mod glob;
 pub mod hoard;
+mod include;
+mod layers;
+mod overrides;
+pub mod vars;
 
 /// Errors that can happen when using a [`Builder`].
 #[derive(Debug, Error)]
@@ -41,37 +58,193 @@ pub enum Error {
     /// Error while reading from a configuration file.
     #[error("failed to read configuration file: {0}")]
     ReadConfig(io::Error),
+    /// A configuration file could not be parsed, with where in the file.
+    #[error("{}:{line}:{column}: {message}\n{snippet}", .path.display())]
+    Parse {
+        /// The configuration file.
+        path:    PathBuf,
+        /// The line of the error, starting at 1.
+        line:    usize,
+        /// The column of the error, starting at 1.
+        column:  usize,
+        /// What went wrong.
+        message: String,
+        /// The lines of the file around the error.
+        snippet: String,
+        /// The error from the parser.
+        #[source]
+        error:   Box<Error>,
+    },
     /// Error while determining whether configured environments apply.
     #[error("failed to determine current environment: {0}")]
     Environment(#[from] environment::Error),
     /// Error while determining which paths to use for configured hoards.
     #[error("failed to process hoard configuration: {0}")]
     ProcessHoard(#[from] hoard::Error),
+    /// A hoard's schedule interval is not a valid duration.
+    #[error("invalid schedule interval for hoard {hoard}: {error}")]
+    InvalidSchedule {
+        /// The name of the hoard with the invalid schedule.
+        hoard: String,
+        /// The error that occurred while parsing the interval.
+        #[source]
+        error: humantime::DurationError,
+    },
+    /// The hoards root is not a valid path or storage URL.
+    #[error("invalid hoards root: {0}")]
+    Storage(#[from] storage::Error),
+    /// More than one default configuration file exists.
+    #[error(
+        "found more than one configuration file ({}), remove all but one or convert it with \
+         `hoard config -x -I`",
+        .0.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    AmbiguousConfigFile(Vec<PathBuf>),
+    /// A path in `include` does not exist.
+    #[error("{file} includes {pattern}, which does not exist")]
+    IncludeNotFound {
+        /// The file with the `include` list.
+        file:    PathBuf,
+        /// The path that does not exist.
+        pattern: String,
+    },
+    /// A glob in `include` is invalid.
+    #[error("invalid include pattern {pattern} in {file}: {error}")]
+    InvalidInclude {
+        /// The file with the `include` list.
+        file:    PathBuf,
+        /// The invalid glob.
+        pattern: String,
+        /// The error that occurred while parsing the glob.
+        #[source]
+        error:   globset::Error,
+    },
+    /// An included configuration file could not be loaded.
+    #[error("failed to load included file {path}: {error}")]
+    Included {
+        /// The included file.
+        path:  PathBuf,
+        /// The error that occurred while loading it.
+        #[source]
+        error: Box<Error>,
+    },
+    /// A system-wide or project configuration file could not be loaded.
+    #[error("failed to load configuration file {path}: {error}")]
+    Layer {
+        /// The configuration file.
+        path:  PathBuf,
+        /// The error that occurred while loading it.
+        #[source]
+        error: Box<Error>,
+    },
+    /// An environment variable overrides a key below a value that is not a
+    /// table.
+    #[error("{var} cannot be applied: `{key}` is not a table")]
+    EnvOverrideNotATable {
+        /// The name of the environment variable.
+        var: String,
+        /// The key that is not a table.
+        key: String,
+    },
+    /// The configuration is invalid after applying environment variables.
+    #[error("invalid configuration after applying {}: {error}", .vars.join(", "))]
+    InvalidEnvOverride {
+        /// The names of the environment variables that were applied.
+        vars:  Vec<String>,
+        /// The error that occurred.
+        #[source]
+        error: serde_json::Error,
+    },
+    /// The variables for pile paths could not be determined.
+    #[error("invalid variables: {0}")]
+    Vars(#[from] vars::Error),
+    /// The hoards root contains a variable that cannot be expanded.
+    #[error("failed to expand the hoards root: {0}")]
+    ExpandHoardsRoot(#[source] crate::env_vars::Error),
+    /// A hoard is defined in more than one configuration file.
+    #[error("hoard {name} is defined in both {first} and {second}")]
+    DuplicateHoard {
+        /// The name of the hoard.
+        name:   String,
+        /// The file that defined it first.
+        first:  PathBuf,
+        /// The file that defined it again.
+        second: PathBuf,
+    },
+    /// An environment is defined in more than one configuration file.
+    #[error("environment {name} is defined in both {first} and {second}")]
+    DuplicateEnvironment {
+        /// The name of the environment.
+        name:   String,
+        /// The file that defined it first.
+        first:  PathBuf,
+        /// The file that defined it again.
+        second: PathBuf,
+    },
 }
 
-/// Global configuration that applies to each of the hoards
-/// Ingores is the only option as of now, but the individual hoard
-/// configuration options will be available for the global as well.
-#[allow(clippy::struct_excessive_bools)]
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+/// Global configuration that applies to each of the hoards.
+///
+/// Besides the global ignore patterns and public key, every setting of a
+/// hoard or pile can be given here, to be inherited by the hoards that do
+/// not set it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, JsonSchema)]
 #[serde(rename_all = "snake_case", default)]
 pub struct GlobalConfig {
     /// Global ignore patterns that mimic git's ignore patterns
     pub ignores:    Option<Vec<String>>,
     /// Public GPG key
     pub public_key: Option<String>,
+    /// Settings inherited by every hoard and pile.
+    #[serde(flatten)]
+    pub config:     ConfigLayer,
+}
+
+impl GlobalConfig {
+    /// Applies the settings in `other` over those in `self`. Ignore patterns
+    /// are combined.
+    #[must_use]
+    pub fn layer(mut self, other: Self) -> Self {
+        self.config = self.config.layer(other.config);
+        if let Some(ignores) = other.ignores {
+            let combined = self.ignores.get_or_insert_with(Vec::new);
+            for ignore in ignores {
+                if !combined.contains(&ignore) {
+                    combined.push(ignore);
+                }
+            }
+        }
+        if other.public_key.is_some() {
+            self.public_key = other.public_key;
+        }
+        self
+    }
 }
 
 /// Intermediate data structure to build a [`Config`](crate::config::Config).
-#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, StructOpt)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, StructOpt, JsonSchema)]
 #[structopt(rename_all = "kebab")]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub struct Builder {
+    /// Files or globs, relative to this file, to load more configuration
+    /// from.
+    #[structopt(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    include:       Option<Vec<String>>,
+    /// Named environments that hoards choose their paths by.
     #[structopt(skip)]
     #[serde(rename = "envs")]
     environments:  Option<HashMap<String, Environment>>,
+    /// Lists of environments, most preferred first, that break ties between
+    /// environment conditions.
     #[structopt(skip)]
     exclusivity:   Option<Vec<Vec<String>>>,
+    /// Variables for pile paths, used as `${vars.<name>}`. Environments can
+    /// set their own.
+    #[structopt(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vars:          Option<BTreeMap<String, String>>,
+    /// Directory or storage URL to store hoards in.
     #[structopt(short, long)]
     hoards_root:   Option<PathBuf>,
     #[structopt(short, long)]
@@ -83,10 +256,28 @@ pub struct Builder {
     #[serde(skip)]
     #[structopt(short, long)]
     force:         bool,
+    /// Wait for other `hoard` processes to finish instead of failing.
+    #[serde(skip)]
+    #[structopt(short, long)]
+    wait:          bool,
+    /// Report progress and results as text or as one JSON event per line.
+    #[serde(skip)]
+    #[structopt(long, possible_values = &OutputFormat::variants())]
+    output:        Option<OutputFormat>,
+    /// The hoards to back up and restore, by name.
     #[structopt(skip)]
     hoards:        Option<HashMap<String, Hoard>>,
+    /// The configuration file each hoard is defined in, if known.
+    #[structopt(skip)]
+    #[serde(skip)]
+    hoard_files:   HashMap<String, PathBuf>,
+    /// Settings that apply to every hoard.
     #[structopt(skip)]
     global_config: Option<GlobalConfig>,
+    /// Versioning of the hoards root with git.
+    #[structopt(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git:           Option<GitConfig>,
 }
 
 impl Default for Builder {
@@ -96,10 +287,37 @@ impl Default for Builder {
 }
 
 impl Builder {
-    /// Returns the default path for the configuration file.
+    /// The configuration files in `dir` named one of [`CONFIG_FILE_NAMES`],
+    /// by precedence.
+    fn existing_config_files(dir: &Path) -> Vec<PathBuf> {
+        CONFIG_FILE_NAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    /// Returns the default path for the configuration file: the existing
+    /// configuration file with the highest precedence in the configuration
+    /// directory, or `config.toml` if there is none.
     fn default_config_file() -> PathBuf {
         tracing::debug!("getting default configuration file");
-        PROJECT_DIRS.config_dir().join(CONFIG_FILE_NAME)
+        let dir = PROJECT_DIRS.config_dir();
+        Self::existing_config_files(dir)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| dir.join(CONFIG_FILE_NAME))
+    }
+
+    /// Like [`Builder::default_config_file`], but fails if more than one
+    /// configuration file exists, as it is unclear which one to use.
+    fn discover_config_file() -> Result<PathBuf, Error> {
+        let dir = PROJECT_DIRS.config_dir();
+        let mut files = Self::existing_config_files(dir);
+        if files.len() > 1 {
+            return Err(Error::AmbiguousConfigFile(files));
+        }
+        Ok(files.pop().unwrap_or_else(|| dir.join(CONFIG_FILE_NAME)))
     }
 
     /// Returns the default location for storing hoards.
@@ -116,17 +334,34 @@ impl Builder {
     pub fn new() -> Self {
         tracing::trace!("creating new config builder");
         Self {
+            include:       None,
             hoards:        None,
+            hoard_files:   HashMap::new(),
             hoards_root:   None,
             config_file:   None,
             command:       None,
             environments:  None,
             exclusivity:   None,
+            vars:          None,
             force:         false,
+            wait:          false,
+            output:        None,
             global_config: None,
+            git:           None,
         }
     }
 
+    /// The JSON Schema of the configuration file, for editors to complete and
+    /// validate configuration files with.
+    #[must_use]
+    pub fn schema() -> RootSchema {
+        let mut schema = schema_for!(Self);
+        let metadata = schema.schema.metadata();
+        metadata.title = Some("hoard configuration".to_owned());
+        metadata.description = Some("The configuration file of hoard.".to_owned());
+        schema
+    }
+
     /// Create a new [`Builder`] pre-populated with the contents of the given
     /// TOML file.
     ///
@@ -136,7 +371,10 @@ impl Builder {
     pub fn from_file_toml(path: &Path) -> Result<Self, Error> {
         tracing::debug!("reading configuration from \"{}\"", path.to_string_lossy());
         let s = std::fs::read_to_string(path).map_err(Error::ReadConfig)?;
-        toml::from_str(&s).map_err(Error::DeserializeTomlConfig)
+        toml::from_str(&s).map_err(|err| {
+            let position = diagnostic::toml_position(&err, &s);
+            diagnostic::locate(path, &s, position, Error::DeserializeTomlConfig(err))
+        })
     }
 
     /// Create a new [`Builder`] pre-populated with the contents of the given
@@ -148,7 +386,12 @@ impl Builder {
     pub fn from_file_yaml(path: &Path) -> Result<Self, Error> {
         tracing::debug!("reading configuration from \"{}\"", path.to_string_lossy());
         let s = std::fs::read_to_string(path).map_err(Error::ReadConfig)?;
-        serde_yaml::from_str(&s).map_err(Error::DeserializeYamlConfig)
+        serde_yaml::from_str(&s).map_err(|err| {
+            let position = err
+                .location()
+                .map(|location| (location.line(), location.column()));
+            diagnostic::locate(path, &s, position, Error::DeserializeYamlConfig(err))
+        })
     }
 
     /// Create a new [`Builder`] pre-populated with the contents of the given
@@ -160,7 +403,91 @@ impl Builder {
     pub fn from_file_json(path: &Path) -> Result<Self, Error> {
         tracing::debug!("reading configuration from \"{}\"", path.to_string_lossy());
         let s = std::fs::read_to_string(path).map_err(Error::ReadConfig)?;
-        serde_json::from_str(&s).map_err(Error::DeserializeJsonConfig)
+        serde_json::from_str(&s).map_err(|err| {
+            let position = Some((err.line(), err.column()));
+            diagnostic::locate(path, &s, position, Error::DeserializeJsonConfig(err))
+        })
+    }
+
+    /// Create a new [`Builder`] pre-populated with the contents of the given
+    /// file, in the format given by its extension (TOML by default).
+    ///
+    /// Files it includes are not loaded, see [`Builder::with_includes`].
+    ///
+    /// # Errors
+    ///
+    /// Variants of [`enum@Error`] related to reading and parsing the file.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("yaml" | "yml") => Self::from_file_yaml(path),
+            Some("json") => Self::from_file_json(path),
+            _ => Self::from_file_toml(path),
+        }
+    }
+
+    /// Merge in the configuration files listed in `include`, relative to
+    /// `config_file` (the file this was read from), and the files in the
+    /// `config.d` directory next to `config_file`.
+    ///
+    /// Environments and hoards are combined, failing if a name is defined in
+    /// more than one file. Exclusivity lists and global ignore patterns are
+    /// combined, other settings are taken from `config_file` first.
+    ///
+    /// # Errors
+    ///
+    /// Any [`enum@Error`] while reading an included file, or if a name is
+    /// defined twice.
+    pub fn with_includes(self, config_file: &Path) -> Result<Self, Error> {
+        include::merge_includes(self, config_file)
+    }
+
+    /// Layer this configuration, read from `config_file`, over the
+    /// system-wide configuration file and under the project configuration
+    /// file (`.hoard.toml` in the current directory or one of its parents),
+    /// if they exist.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Layer`] if one of the files cannot be loaded.
+    pub fn with_layers(self, config_file: &Path) -> Result<Self, Error> {
+        layers::with_layers(self, config_file)
+    }
+
+    /// Override keys of this configuration with `HOARD_<KEY>` environment
+    /// variables, with `__` between nested keys, e.g.
+    /// `HOARD_GLOBAL_CONFIG__IGNORES`. Values are parsed as JSON if they can
+    /// be, and used as text otherwise.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::EnvOverrideNotATable`] if a variable overrides a key below a
+    /// value, or [`Error::InvalidEnvOverride`] if the result is not a valid
+    /// configuration.
+    pub fn with_env_overrides(self) -> Result<Self, Error> {
+        overrides::apply(self, env::vars())
+    }
+
+    /// Load the configuration file at `config_file` with everything layered
+    /// around it: included files, the system-wide and project configuration
+    /// files, and environment variable overrides.
+    ///
+    /// # Errors
+    ///
+    /// Any [`enum@Error`] while loading one of the files or applying the
+    /// overrides.
+    pub fn from_config_file(config_file: &Path) -> Result<Self, Error> {
+        let from_file = match Self::from_file(config_file) {
+            Ok(from_file) => from_file.with_includes(config_file)?,
+            // The user's configuration file is optional if other layers exist
+            Err(Error::ReadConfig(err))
+                if err.kind() == io::ErrorKind::NotFound && layers::has_layers(config_file) =>
+            {
+                tracing::debug!("user configuration file does not exist");
+                Self::new()
+            },
+            Err(err) => return Err(err),
+        };
+        from_file.with_layers(config_file)?.with_env_overrides()
     }
 
     /// Helper method to process command-line arguments and the config file
@@ -174,10 +501,10 @@ impl Builder {
         let from_args = Self::from_args();
 
         tracing::trace!("attempting to get configuration file from cli arguments or use default");
-        let config_file = from_args
-            .config_file
-            .clone()
-            .unwrap_or_else(Self::default_config_file);
+        let config_file = match from_args.config_file.clone() {
+            Some(config_file) => config_file,
+            None => Self::discover_config_file()?,
+        };
 
         // .map_or_else(Self::default_config_file, |p| {
         //     p.normalize()
@@ -197,18 +524,17 @@ impl Builder {
             config_file.to_string_lossy()
         );
 
-        let from_file = match config_file.extension().and_then(OsStr::to_str) {
-            Some("yaml" | "yml") => Self::from_file_yaml(&config_file)?,
-            Some("json") => Self::from_file_json(&config_file)?,
-            _ => Self::from_file_toml(&config_file)?,
-        };
+        let from_file = Self::from_config_file(&config_file)?;
 
         tracing::debug!("merging configuration file and cli arguments");
-        Ok(from_file.layer(from_args))
+        Ok(from_file.layer(from_args).set_config_file(config_file))
     }
 
     /// Applies all configured values in `other` over those in *this*
     /// `ConfigBuilder`.
+    ///
+    /// Hoards and environments in `other` replace those with the same name,
+    /// exclusivity lists and global ignore patterns are combined.
     #[must_use]
     pub fn layer(mut self, other: Self) -> Self {
         let _span = tracing::trace_span!(
@@ -230,7 +556,47 @@ impl Builder {
             self = self.set_command(path);
         }
 
+        if let Some(output) = other.output {
+            self = self.set_output(output);
+        }
+
+        if let Some(environments) = other.environments {
+            self.environments
+                .get_or_insert_with(HashMap::new)
+                .extend(environments);
+        }
+
+        if let Some(hoards) = other.hoards {
+            self.hoards.get_or_insert_with(HashMap::new).extend(hoards);
+        }
+        self.hoard_files.extend(other.hoard_files);
+
+        if let Some(exclusivity) = other.exclusivity {
+            let combined = self.exclusivity.get_or_insert_with(Vec::new);
+            for list in exclusivity {
+                if !combined.contains(&list) {
+                    combined.push(list);
+                }
+            }
+        }
+
+        if let Some(vars) = other.vars {
+            self.vars.get_or_insert_with(BTreeMap::new).extend(vars);
+        }
+
+        if let Some(global_config) = other.global_config {
+            self.global_config = Some(match self.global_config.take() {
+                Some(bottom) => bottom.layer(global_config),
+                None => global_config,
+            });
+        }
+
+        if let Some(git) = other.git {
+            self.git = Some(git);
+        }
+
         self.force = self.force || other.force;
+        self.wait = self.wait || other.wait;
 
         self
     }
@@ -277,6 +643,12 @@ impl Builder {
         self
     }
 
+    /// The command that will be run, if set.
+    #[must_use]
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
     /// Set whether to force the command to run despite possible failed checks.
     #[must_use]
     pub fn set_force(mut self, force: bool) -> Self {
@@ -285,6 +657,23 @@ impl Builder {
         self
     }
 
+    /// Set the format to report progress and results in.
+    #[must_use]
+    pub fn set_output(mut self, output: OutputFormat) -> Self {
+        tracing::trace!(%output, "setting output format");
+        self.output = Some(output);
+        self
+    }
+
+    /// Set whether to wait for other `hoard` processes to finish instead of
+    /// failing.
+    #[must_use]
+    pub fn set_wait(mut self, wait: bool) -> Self {
+        tracing::trace!(?wait, "setting wait");
+        self.wait = wait;
+        self
+    }
+
     /// Unset the hoards map
     #[must_use]
     pub fn unset_hoards(mut self) -> Self {
@@ -351,6 +740,35 @@ impl Builder {
         )
     }
 
+    /// Where hoards are stored, with the variables in a local `hoards_root`
+    /// expanded.
+    fn storage(hoards_root: Option<PathBuf>, vars: &PathVars) -> Result<Location, Error> {
+        match Location::parse(&hoards_root.unwrap_or_else(Self::default_hoard_root))? {
+            Location::Local(path) => Ok(Location::Local(
+                vars.expand_vars(path).map_err(Error::ExpandHoardsRoot)?,
+            )),
+            remote @ Location::S3(_) => Ok(remote),
+        }
+    }
+
+    /// Collects the schedules of `hoards`, validating their intervals.
+    fn schedules(hoards: &HashMap<String, Hoard>) -> Result<HashMap<String, Schedule>, Error> {
+        hoards
+            .iter()
+            .filter_map(|(name, hoard)| {
+                hoard.schedule().map(|schedule| {
+                    schedule
+                        .interval()
+                        .map(|_| (name.clone(), schedule.clone()))
+                        .map_err(|error| Error::InvalidSchedule {
+                            hoard: name.clone(),
+                            error,
+                        })
+                })
+            })
+            .collect()
+    }
+
     /// Build this [`Builder`] into a [`Config`].
     ///
     /// # Errors
@@ -361,27 +779,45 @@ impl Builder {
         tracing::debug!("building configuration from builder");
         let environments = self.evaluated_environments()?;
         tracing::debug!(?environments);
+        let env_sources = self.environments.clone().unwrap_or_default();
         let exclusivity = self.exclusivity.unwrap_or_else(Vec::new);
         tracing::debug!(?exclusivity);
-        let hoards_root = self.hoards_root.unwrap_or_else(Self::default_hoard_root);
-        tracing::debug!(?hoards_root);
         let config_file = self.config_file.unwrap_or_else(Self::default_config_file);
         tracing::debug!(?config_file);
+        let vars = PathVars::new(
+            &config_file,
+            self.vars.as_ref(),
+            &env_sources,
+            &environments,
+            &exclusivity,
+        )?;
+        tracing::debug!(?vars);
+        let storage = Self::storage(self.hoards_root, &vars)?;
+        let hoards_root = storage.local_dir();
+        tracing::debug!(?storage, ?hoards_root);
         let command = self.command.unwrap_or_default();
         tracing::debug!(?command);
         let force = self.force;
-        tracing::debug!(?force);
+        let wait = self.wait;
+        let output = self.output.unwrap_or_default();
+        tracing::debug!(?force, ?wait, %output);
         let global_config = self.global_config.unwrap_or_default();
         tracing::debug!(?global_config);
 
+        let hoards = self.hoards.unwrap_or_else(HashMap::new);
+        let schedules = Self::schedules(&hoards)?;
+        tracing::debug!(?schedules);
+
         tracing::debug!("processing hoards...");
-        let hoards = self
-            .hoards
-            .unwrap_or_else(HashMap::new)
+        let sources = hoards.clone();
+        let hoards = hoards
             .into_iter()
             .map(|(name, hoard)| {
                 let _span = tracing::debug_span!("processing_hoard", %name).entered();
-                Ok((name, hoard.process_with(&environments, &exclusivity)?))
+                Ok((
+                    name,
+                    hoard.process_with(&environments, &exclusivity, &global_config, &vars)?,
+                ))
             })
             .collect::<Result<_, Error>>()?;
         tracing::debug!("processed hoards");
@@ -389,10 +825,21 @@ impl Builder {
         Ok(Config {
             command,
             hoards_root,
+            storage,
             config_file,
             global_config,
             hoards,
+            sources,
+            env_sources,
+            environments,
+            exclusivity,
+            vars,
+            schedules,
             force,
+            wait,
+            output,
+            git: self.git,
+            hoard_files: self.hoard_files,
         })
     }
 }
@@ -406,19 +853,26 @@ mod tests {
 
         fn get_default_populated_builder() -> Builder {
             Builder {
+                include:       None,
                 hoards_root:   Some(Builder::default_hoard_root()),
                 config_file:   Some(Builder::default_config_file()),
-                command:       Some(Command::Validate),
+                command:       Some(Command::Validate { deny: None }),
                 environments:  None,
                 exclusivity:   None,
+                vars:          None,
                 hoards:        None,
+                hoard_files:   HashMap::new(),
                 force:         false,
+                wait:          false,
+                output:        None,
+                git:           None,
                 global_config: None,
             }
         }
 
         fn get_non_default_populated_builder() -> Builder {
             Builder {
+                include:       None,
                 hoards_root:   Some(PathBuf::from("/testing/saves")),
                 config_file:   Some(PathBuf::from("/testing/config.toml")),
                 command:       Some(Command::Restore {
@@ -426,8 +880,13 @@ mod tests {
                 }),
                 environments:  None,
                 exclusivity:   None,
+                vars:          None,
                 hoards:        None,
+                hoard_files:   HashMap::new(),
                 force:         false,
+                wait:          false,
+                output:        None,
+                git:           None,
                 global_config: None,
             }
         }
@@ -440,13 +899,19 @@ mod tests {
         #[test]
         fn new_builder_is_all_none() {
             let expected = Builder {
+                include:       None,
                 hoards_root:   None,
                 config_file:   None,
                 command:       None,
                 environments:  None,
                 hoards:        None,
+                hoard_files:   HashMap::new(),
                 exclusivity:   None,
+                vars:          None,
                 force:         false,
+                wait:          false,
+                output:        None,
+                git:           None,
                 global_config: None,
             };
 
@@ -493,6 +958,76 @@ mod tests {
             );
         }
 
+        #[test]
+        fn schema_describes_top_level_keys() {
+            let schema = serde_json::to_value(Builder::schema()).unwrap();
+            let properties = schema["properties"].as_object().unwrap();
+            for key in &["envs", "exclusivity", "hoards_root", "hoards", "git"] {
+                assert!(properties.contains_key(*key), "missing {}", key);
+            }
+            assert_eq!(schema["title"], "hoard configuration");
+        }
+
+        #[test]
+        fn config_files_are_found_by_precedence() {
+            let dir = tempfile::TempDir::new().expect("failed to create temp dir");
+            assert!(Builder::existing_config_files(dir.path()).is_empty());
+            for name in &["config.json", "config.yml", "config.toml"] {
+                std::fs::write(dir.path().join(name), "").unwrap();
+            }
+            std::fs::write(dir.path().join("config.txt"), "").unwrap();
+            assert_eq!(
+                Builder::existing_config_files(dir.path()),
+                ["config.toml", "config.yml", "config.json"]
+                    .iter()
+                    .map(|name| dir.path().join(name))
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn layered_builder_merges_hoards_envs_and_global_config() {
+            let system: Builder = toml::from_str(
+                r#"
+                exclusivity = [["work", "home"]]
+                [envs.work]
+                hostname = ["work-laptop"]
+                [hoards.team]
+                "work" = "/team"
+                [hoards.shared]
+                "work" = "/shared"
+                [global_config]
+                ignores = ["*.log"]
+                public_key = "TEAM"
+                "#,
+            )
+            .unwrap();
+            let user: Builder = toml::from_str(
+                r#"
+                exclusivity = [["work", "home"]]
+                [hoards.shared]
+                "home" = "/mine"
+                [global_config]
+                ignores = ["*.log", "*.swp"]
+                "#,
+            )
+            .unwrap();
+
+            let layered = system.layer(user);
+            let hoards = layered.hoards.as_ref().unwrap();
+            assert_eq!(hoards.len(), 2);
+            assert_eq!(
+                hoards["shared"],
+                toml::from_str::<Hoard>(r#""home" = "/mine""#).unwrap(),
+                "hoards on top replace those below"
+            );
+            assert!(layered.environments.unwrap().contains_key("work"));
+            assert_eq!(layered.exclusivity.unwrap().len(), 1);
+            let global_config = layered.global_config.unwrap();
+            assert_eq!(global_config.ignores.unwrap(), ["*.log", "*.swp"]);
+            assert_eq!(global_config.public_key.unwrap(), "TEAM");
+        }
+
         #[test]
         fn builder_saves_root_sets_correctly() {
             let mut builder = Builder::new();
@@ -526,7 +1061,7 @@ mod tests {
         fn builder_command_sets_correctly() {
             let mut builder = Builder::new();
             assert_eq!(None, builder.command, "command should start as None");
-            let cmd = Command::Validate;
+            let cmd = Command::Validate { deny: None };
             builder = builder.set_command(cmd.clone());
             assert_eq!(Some(cmd), builder.command, "command should now be set");
         }
@@ -562,7 +1097,7 @@ mod tests {
         #[test]
         fn builder_command_unsets_correctly() {
             let mut builder = Builder::new();
-            let cmd = Command::Validate;
+            let cmd = Command::Validate { deny: None };
             builder = builder.set_command(cmd.clone());
             assert_eq!(Some(cmd), builder.command, "command should start as set");
             builder = builder.unset_command();
@@ -0,0 +1,214 @@
+//! Configuration overrides from environment variables.
+//!
+//! Every variable named `HOARD_<KEY>` with a key of the configuration file
+//! overrides that key, with `__` separating nested keys, e.g.
+//! `HOARD_GLOBAL_CONFIG__IGNORES` or
+//! `HOARD_HOARDS__NVIM__CONFIG__ENCRYPTION__ENCRYPT`. See
+//! [`Builder::with_env_overrides`].
+
+use super::{Builder, Error};
+use serde_json::{Map, Value};
+
+/// The prefix of every override variable.
+const PREFIX: &str = "HOARD_";
+
+/// Separates nested keys in the name of a variable.
+const SEPARATOR: &str = "__";
+
+/// Top-level keys that can be set by a variable of their own.
+const VALUE_KEYS: [&str; 2] = ["hoards_root", "exclusivity"];
+
+/// Top-level tables whose keys can be set with [`SEPARATOR`]. The tables
+/// themselves cannot be set, so that e.g. `HOARD_ENVS` from `hoard explain
+/// --format shell` is not mistaken for an override.
+const TABLE_KEYS: [&str; 5] = ["envs", "vars", "hoards", "global_config", "git"];
+
+/// A key as compared against the name of a variable.
+fn normalize(key: &str) -> String {
+    key.to_lowercase().replace('-', "_")
+}
+
+/// The keys named by the environment variable `name`, lowercased, or `None`
+/// if it is not an override.
+fn override_path(name: &str) -> Option<Vec<String>> {
+    let path: Vec<String> = name
+        .strip_prefix(PREFIX)?
+        .split(SEPARATOR)
+        .map(normalize)
+        .collect();
+    let top = path.first()?.as_str();
+    let is_override = match path.len() {
+        1 => VALUE_KEYS.contains(&top),
+        _ => TABLE_KEYS.contains(&top) || VALUE_KEYS.contains(&top),
+    };
+    (is_override && path.iter().all(|key| !key.is_empty())).then_some(path)
+}
+
+/// The value of a variable: JSON if it parses as such (e.g. `true`, `3` or
+/// `["*.log"]`), otherwise the text itself.
+fn parse_value(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_owned()))
+}
+
+/// Set the key at `path` in `root` to `value`, reusing existing keys that only
+/// differ in case or in `-` and `_`, and creating missing tables.
+fn set(
+    root: &mut Map<String, Value>,
+    var: &str,
+    path: &[String],
+    value: Value,
+) -> Result<(), Error> {
+    let (last, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    let existing_key = |table: &Map<String, Value>, key: &str| -> String {
+        table
+            .keys()
+            .find(|existing| normalize(existing) == key)
+            .cloned()
+            .unwrap_or_else(|| key.to_owned())
+    };
+
+    let mut table = root;
+    for key in parents {
+        let key = existing_key(table, key);
+        let entry = table.entry(key.clone()).or_insert(Value::Null);
+        if entry.is_null() {
+            *entry = Value::Object(Map::new());
+        }
+        table = entry
+            .as_object_mut()
+            .ok_or_else(|| Error::EnvOverrideNotATable {
+                var: var.to_owned(),
+                key,
+            })?;
+    }
+    let key = existing_key(table, last);
+    table.insert(key, value);
+    Ok(())
+}
+
+/// Apply every override in `vars` to `builder`, in order of name so shorter
+/// paths are set before the keys below them.
+pub(super) fn apply(
+    builder: Builder,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<Builder, Error> {
+    let mut overrides: Vec<(String, Vec<String>, String)> = vars
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.clone(), override_path(&name)?, value)))
+        .collect();
+    if overrides.is_empty() {
+        return Ok(builder);
+    }
+    overrides.sort();
+
+    let names: Vec<String> = overrides.iter().map(|(name, ..)| name.clone()).collect();
+    let invalid = |error| Error::InvalidEnvOverride {
+        vars: names.clone(),
+        error,
+    };
+    let mut root = match serde_json::to_value(&builder).map_err(invalid)? {
+        Value::Object(root) => root,
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) | Value::Array(_) =>
+            Map::new(),
+    };
+    for (name, path, value) in &overrides {
+        tracing::debug!(var = %name, key = %path.join("."), "overriding configuration");
+        set(&mut root, name, path, parse_value(value))?;
+    }
+    // Keys that are not set are serialized as `null`
+    let root: Map<String, Value> = root
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .collect();
+    serde_json::from_value(Value::Object(root)).map_err(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::builder::hoard::{EncryptionKind, Hoard};
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn only_configuration_keys_are_overrides() {
+        assert_eq!(
+            override_path("HOARD_HOARDS__NVIM__CONFIG__MAX_DEPTH"),
+            Some(vec![
+                "hoards".to_owned(),
+                "nvim".to_owned(),
+                "config".to_owned(),
+                "max_depth".to_owned()
+            ])
+        );
+        assert_eq!(
+            override_path("HOARD_HOARDS_ROOT"),
+            Some(vec!["hoards_root".to_owned()])
+        );
+        for name in &[
+            "HOARD_ENVS",
+            "HOARD_ENV_LINUX",
+            "HOARD_LOG",
+            "HOARD_CONFIG_DIR",
+            "HOARD_HOARDS",
+            "HOARD_HOARDS____X",
+            "HOME",
+        ] {
+            assert_eq!(override_path(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn overrides_change_nested_keys() {
+        let builder: Builder = toml::from_str(
+            "[envs.linux]\nos = [\"linux\"]\n[hoards.Nvim.config.encryption]\nencrypt = \
+             \"asymmetric\"\n[hoards.Nvim.init]\n\"linux\" = \"/nvim\"\n",
+        )
+        .unwrap();
+        let builder = apply(
+            builder,
+            vars(&[
+                ("HOARD_HOARDS__NVIM__CONFIG__ENCRYPTION__ENCRYPT", "none"),
+                ("HOARD_HOARDS__NVIM__CONFIG__MAX_DEPTH", "2"),
+                ("HOARD_GLOBAL_CONFIG__IGNORES", "[\"*.log\"]"),
+                ("HOARD_HOARDS_ROOT", "/backups"),
+                ("HOARD_LOG", "debug"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(builder.hoards_root, Some("/backups".into()));
+        assert_eq!(builder.global_config.unwrap().ignores.unwrap(), ["*.log"]);
+        let hoards = builder.hoards.unwrap();
+        let config = hoards.get("Nvim").and_then(Hoard::config).unwrap();
+        assert_eq!(config.walker.max_depth, Some(2));
+        assert_eq!(
+            config.encryption.as_ref().unwrap().kind,
+            Some(EncryptionKind::None)
+        );
+    }
+
+    #[test]
+    fn overriding_below_a_value_is_an_error() {
+        let result = apply(
+            Builder::new(),
+            vars(&[
+                ("HOARD_HOARDS_ROOT", "/backups"),
+                ("HOARD_HOARDS_ROOT__X", "1"),
+            ]),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::EnvOverrideNotATable { var, key })
+                if var == "HOARD_HOARDS_ROOT__X" && key == "hoards_root"
+        ));
+    }
+}
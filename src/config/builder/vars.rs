@@ -0,0 +1,264 @@
+//! Variables for pile paths.
+//!
+//! Besides environment variables, pile paths can use:
+//!
+//! - `${vars.<name>}`: a variable from the top-level `vars` table, or from the
+//!   `vars` table of an environment that applies to this system.
+//! - `${hoard.config_dir}`: the directory of the configuration file.
+//! - `${hoard.hostname}`: the hostname of this system.
+//! - `${hoard.os}`: the operating system, as used by the `os` condition.
+//!
+//! Relative pile paths are relative to the directory of the configuration
+//! file. See [`PathVars`].
+
+use super::{
+    environment::Environment,
+    envtrie::{environment_weights, Error as TrieError, DEFAULT_WEIGHT},
+};
+use crate::env_vars::{expand_vars_in_path, Error as EnvError};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// The prefix of variables defined in the configuration.
+const USER_PREFIX: &str = "vars.";
+
+/// The prefix of variables provided by `hoard`.
+const BUILTIN_PREFIX: &str = "hoard.";
+
+/// Errors that can occur while collecting the variables for pile paths.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The hostname of this system could not be determined.
+    #[error("failed to detect hostname: {0}")]
+    Hostname(#[source] std::io::Error),
+    /// Environments with the same weight set a variable to different values.
+    #[error(
+        "variable {var} is set differently by {}, which apply equally. Add them to an \
+         exclusivity list to prefer one",
+        .envs.join(" and ")
+    )]
+    Ambiguous {
+        /// The name of the variable.
+        var:  String,
+        /// The environments that set it.
+        envs: Vec<String>,
+    },
+    /// The value of a variable could not be expanded.
+    #[error("failed to expand variable {var}: {error}")]
+    Expand {
+        /// The name of the variable.
+        var:   String,
+        /// The error that occurred.
+        #[source]
+        error: EnvError,
+    },
+    /// The exclusivity lists are inconsistent.
+    #[error("{0}")]
+    Exclusivity(#[from] TrieError),
+}
+
+/// The variables available to pile paths on this system, and the directory
+/// relative paths are resolved against.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathVars {
+    vars:       HashMap<String, String>,
+    config_dir: PathBuf,
+}
+
+impl PathVars {
+    /// Collect the variables for the configuration file at `config_file`:
+    /// the built-in ones, `global` and those of the `envs` that apply
+    /// according to `applies`.
+    ///
+    /// A variable set by several applying environments takes the value of the
+    /// one weighing the most in `exclusivity`. Environment variables override
+    /// those in `global`. Values can use environment variables and built-in
+    /// variables, and environment values can use `global` ones too.
+    ///
+    /// # Errors
+    ///
+    /// Any [`enum@Error`] while detecting the built-in values or expanding
+    /// variables, or [`Error::Ambiguous`] if the environments that set a
+    /// variable cannot be told apart.
+    pub fn new(
+        config_file: &Path,
+        global: Option<&BTreeMap<String, String>>,
+        envs: &HashMap<String, Environment>,
+        applies: &HashMap<String, bool>,
+        exclusivity: &[Vec<String>],
+    ) -> Result<Self, Error> {
+        let config_dir = config_file.parent().unwrap_or_else(|| Path::new(""));
+        let config_dir = env::current_dir()
+            .map_or_else(|_| config_dir.to_path_buf(), |cwd| cwd.join(config_dir));
+        let hostname = ::hostname::get().map_err(Error::Hostname)?;
+
+        let mut vars = HashMap::new();
+        vars.insert(
+            [BUILTIN_PREFIX, "config_dir"].concat(),
+            config_dir.display().to_string(),
+        );
+        vars.insert(
+            [BUILTIN_PREFIX, "hostname"].concat(),
+            hostname.to_string_lossy().into_owned(),
+        );
+        vars.insert([BUILTIN_PREFIX, "os"].concat(), env::consts::OS.to_owned());
+
+        let expand = |vars: &HashMap<String, String>, name: &str, value: &str| {
+            expand_vars_in_path(value, vars)
+                .map(|value| (name.to_owned(), value.display().to_string()))
+                .map_err(|error| Error::Expand {
+                    var: name.to_owned(),
+                    error,
+                })
+        };
+
+        let global: Vec<(String, String)> = global
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| expand(&vars, &[USER_PREFIX, name].concat(), value))
+            .collect::<Result<_, _>>()?;
+        vars.extend(global);
+
+        let weights = environment_weights(exclusivity)?;
+        let weight = |env: &str| weights.get(env).copied().unwrap_or(DEFAULT_WEIGHT);
+        // Each variable and the applying environments that set it, heaviest first
+        let mut candidates: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+        let mut applying: Vec<(&String, &Environment)> = envs
+            .iter()
+            .filter(|(name, _)| applies.get(*name).copied().unwrap_or(false))
+            .collect();
+        applying.sort_unstable_by(|(left, _), (right, _)| {
+            weight(right)
+                .cmp(&weight(left))
+                .then_with(|| left.cmp(right))
+        });
+        for (env, environment) in applying {
+            for (name, value) in environment.vars().into_iter().flatten() {
+                candidates
+                    .entry(name.as_str())
+                    .or_default()
+                    .push((env.as_str(), value.as_str()));
+            }
+        }
+
+        let mut from_envs = Vec::new();
+        for (name, setters) in candidates {
+            let (first_env, first_value) = setters[0];
+            let tied: Vec<String> = setters
+                .iter()
+                .filter(|(env, value)| weight(env) == weight(first_env) && *value != first_value)
+                .map(|(env, _)| (*env).to_owned())
+                .collect();
+            if !tied.is_empty() {
+                return Err(Error::Ambiguous {
+                    var:  [USER_PREFIX, name].concat(),
+                    envs: std::iter::once(first_env.to_owned()).chain(tied).collect(),
+                });
+            }
+            from_envs.push(expand(&vars, &[USER_PREFIX, name].concat(), first_value)?);
+        }
+        vars.extend(from_envs);
+
+        Ok(Self { vars, config_dir })
+    }
+
+    /// Expand the variables in `path`, without resolving relative paths.
+    ///
+    /// # Errors
+    ///
+    /// Any [`EnvError`] while expanding the variables.
+    pub fn expand_vars<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, EnvError> {
+        expand_vars_in_path(path, &self.vars)
+    }
+
+    /// Expand the variables in the pile path `path` and resolve it against
+    /// the directory of the configuration file if it is relative.
+    ///
+    /// # Errors
+    ///
+    /// Any [`EnvError`] while expanding the variables.
+    pub fn expand<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, EnvError> {
+        let path = self.expand_vars(path)?;
+        Ok(if path.is_relative() {
+            self.config_dir.join(path)
+        } else {
+            path
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envs() -> HashMap<String, Environment> {
+        toml::from_str(
+            "[work]\nvars = { dotfiles = \"/work/dots\" }\n[home]\nvars = { dotfiles = \
+             \"/home/dots\", editor = \"${vars.base}/nvim\" }\n[other]\nvars = { dotfiles = \
+             \"/other\" }\n",
+        )
+        .unwrap()
+    }
+
+    fn applies(names: &[&str]) -> HashMap<String, bool> {
+        ["work", "home", "other"]
+            .iter()
+            .map(|name| ((*name).to_owned(), names.contains(name)))
+            .collect()
+    }
+
+    #[test]
+    fn paths_use_global_environment_and_builtin_variables() {
+        let mut global = BTreeMap::new();
+        global.insert("base".to_owned(), "${hoard.config_dir}/base".to_owned());
+        global.insert("dotfiles".to_owned(), "/global/dots".to_owned());
+        let vars = PathVars::new(
+            Path::new("/etc/hoard/config.toml"),
+            Some(&global),
+            &envs(),
+            &applies(&["home"]),
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            vars.expand("${vars.dotfiles}/vimrc").unwrap(),
+            PathBuf::from("/home/dots/vimrc")
+        );
+        assert_eq!(
+            vars.expand("${vars.editor}").unwrap(),
+            PathBuf::from("/etc/hoard/base/nvim")
+        );
+        assert_eq!(
+            vars.expand("dots/${hoard.os}").unwrap(),
+            Path::new("/etc/hoard/dots").join(env::consts::OS)
+        );
+        let error = vars.expand("${vars.missing}").unwrap_err();
+        assert_eq!(error.to_string(), "variable is not defined: vars.missing");
+    }
+
+    #[test]
+    fn exclusivity_decides_between_environments() {
+        let config = Path::new("/config.toml");
+        let mut global = BTreeMap::new();
+        global.insert("base".to_owned(), "/base".to_owned());
+        let both = applies(&["work", "home"]);
+        let error = PathVars::new(config, Some(&global), &envs(), &both, &[]).unwrap_err();
+        assert!(
+            matches!(&error, Error::Ambiguous { var, envs } if var == "vars.dotfiles" && envs == &["home", "work"]),
+            "{}",
+            error
+        );
+
+        let exclusivity = [vec!["work".to_owned(), "home".to_owned()]];
+        let vars = PathVars::new(config, Some(&global), &envs(), &both, &exclusivity).unwrap();
+        assert_eq!(
+            vars.expand("${vars.dotfiles}").unwrap(),
+            PathBuf::from("/work/dots")
+        );
+    }
+}
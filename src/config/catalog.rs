@@ -0,0 +1,199 @@
+//! A catalog of known applications and where they keep their configuration.
+//!
+//! The catalog is embedded from `catalog.toml` and used by `hoard init
+//! --detect` to find applications on this system.
+
+use crate::env_vars::expand_env_in_path;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// The embedded catalog.
+const CATALOG: &str = include_str!("catalog.toml");
+
+/// Paths to a pile on each operating system, keyed by the name used in
+/// [`std::env::consts::OS`].
+pub(super) type OsPaths = BTreeMap<String, String>;
+
+/// A known application.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(super) struct App {
+    /// The executable that must be in `$PATH` for the application to be
+    /// detected.
+    exe:   String,
+    /// Paths of a hoard with a single anonymous pile.
+    #[serde(default)]
+    paths: OsPaths,
+    /// Paths of each pile of a hoard with named piles.
+    #[serde(default)]
+    piles: BTreeMap<String, OsPaths>,
+}
+
+/// A pile of an [`App`] that exists on this system.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct DetectedPile<'a> {
+    /// The name of the pile, if the hoard has named piles.
+    name:  Option<&'a str>,
+    /// The paths to the pile on every operating system.
+    paths: &'a OsPaths,
+    /// The expanded path on this system.
+    found: PathBuf,
+}
+
+impl DetectedPile<'_> {
+    /// The expanded path on this system.
+    pub(super) fn found(&self) -> &Path {
+        &self.found
+    }
+
+    /// The operating systems the pile has a path on.
+    pub(super) fn operating_systems(&self) -> impl Iterator<Item = &str> {
+        self.paths.keys().map(String::as_str)
+    }
+}
+
+impl App {
+    /// Every pile of the application, with its name if it is named.
+    fn piles(&self) -> Vec<(Option<&str>, &OsPaths)> {
+        if self.piles.is_empty() {
+            vec![(None, &self.paths)]
+        } else {
+            self.piles
+                .iter()
+                .map(|(name, paths)| (Some(name.as_str()), paths))
+                .collect()
+        }
+    }
+
+    /// The piles of the application that exist on `os`, or an empty list if
+    /// its executable is not installed.
+    pub(super) fn detect(&self, os: &str) -> Vec<DetectedPile<'_>> {
+        if which::which(&self.exe).is_err() {
+            return Vec::new();
+        }
+        self.piles()
+            .into_iter()
+            .filter_map(|(name, paths)| {
+                let found = expand_env_in_path(paths.get(os)?).ok()?;
+                found
+                    .exists()
+                    .then_some(DetectedPile { name, paths, found })
+            })
+            .collect()
+    }
+
+    /// The definition of the environment that applies when the application
+    /// is installed.
+    pub(super) fn environment(&self) -> Value {
+        json!({ "exe_exists": [self.exe] })
+    }
+}
+
+/// The definition of the environment that applies on `os`.
+pub(super) fn os_environment(os: &str) -> Value {
+    json!({ "os": [os] })
+}
+
+/// The configuration of a hoard named `name` holding `piles`, with each path
+/// under the condition `<os>|<name>`.
+pub(super) fn hoard_config(name: &str, piles: &[DetectedPile<'_>]) -> Value {
+    let conditions = |paths: &OsPaths| -> Map<String, Value> {
+        paths
+            .iter()
+            .map(|(os, path)| ([os, "|", name].concat(), Value::String(path.clone())))
+            .collect()
+    };
+    match piles {
+        [DetectedPile {
+            name: None, paths, ..
+        }] => Value::Object(conditions(paths)),
+        piles => Value::Object(
+            piles
+                .iter()
+                .filter_map(|pile| {
+                    pile.name
+                        .map(|name| (name.to_owned(), Value::Object(conditions(pile.paths))))
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// Parse the embedded catalog, keyed by application name.
+///
+/// # Errors
+///
+/// Only if the embedded catalog is malformed, which its tests rule out.
+pub(super) fn load() -> Result<BTreeMap<String, App>, ::toml::de::Error> {
+    ::toml::from_str(CATALOG)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::adopt::is_valid_name;
+
+    #[test]
+    fn catalog_parses_with_valid_names() {
+        let catalog = load().expect("embedded catalog should parse");
+        assert!(!catalog.is_empty());
+        for (name, app) in &catalog {
+            assert!(is_valid_name(name, false), "invalid hoard name {}", name);
+            assert!(
+                !["linux", "macos", "windows"].contains(&name.as_str()),
+                "{} clashes with an OS environment",
+                name
+            );
+            assert!(
+                app.paths.is_empty() != app.piles.is_empty(),
+                "{} needs either paths or piles",
+                name
+            );
+            for (pile, paths) in app.piles() {
+                if let Some(pile) = pile {
+                    assert!(is_valid_name(pile, true), "invalid pile name {}", pile);
+                }
+                assert!(!paths.is_empty(), "{} has a pile without paths", name);
+            }
+        }
+    }
+
+    #[test]
+    fn hoard_config_uses_os_and_app_conditions() {
+        let paths: OsPaths = vec![
+            ("linux".to_owned(), "${HOME}/.config/nvim".to_owned()),
+            ("windows".to_owned(), "${LOCALAPPDATA}/nvim".to_owned()),
+        ]
+        .into_iter()
+        .collect();
+        let anonymous = [DetectedPile {
+            name:  None,
+            paths: &paths,
+            found: PathBuf::from("/home/user/.config/nvim"),
+        }];
+        assert_eq!(
+            hoard_config("neovim", &anonymous),
+            json!({
+                "linux|neovim": "${HOME}/.config/nvim",
+                "windows|neovim": "${LOCALAPPDATA}/nvim",
+            })
+        );
+
+        let named = [DetectedPile {
+            name:  Some("init"),
+            paths: &paths,
+            found: PathBuf::from("/home/user/.config/nvim"),
+        }];
+        assert_eq!(
+            hoard_config("neovim", &named),
+            json!({ "init": {
+                "linux|neovim": "${HOME}/.config/nvim",
+                "windows|neovim": "${LOCALAPPDATA}/nvim",
+            } })
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Convert the configuration file to another format in place.
+//!
+//! See [`run_convert_in_place`].
+
+use super::{edit::ConfigDocument, filetypes::infer_format, Config};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+use thiserror::Error;
+
+/// Errors that can occur while converting the configuration file in place.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A file with the new extension already exists.
+    #[error("{0} already exists, remove it or use --force to overwrite it")]
+    AlreadyExists(PathBuf),
+    /// The user declined the conversion.
+    #[error("conversion cancelled, the configuration was not changed")]
+    Cancelled,
+    /// The answer to the confirmation could not be read.
+    #[error("failed to read answer: {0}")]
+    Prompt(#[source] io::Error),
+    /// The original configuration file could not be removed.
+    #[error("failed to remove {path}: {error}")]
+    Remove {
+        /// The original configuration file.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+}
+
+/// Ask `question` on stderr and whether the answer is yes.
+///
+/// Without a terminal to ask on, the answer is yes.
+fn confirm(question: &str, input: &mut impl BufRead) -> io::Result<bool> {
+    if !atty::is(atty::Stream::Stdin) {
+        return Ok(true);
+    }
+    let mut stderr = io::stderr();
+    write!(stderr, "{} [y/N] ", question)?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Convert the configuration file to `format`, replacing it with a file of
+/// the same name and the extension of `format`.
+///
+/// The converted configuration is validated before it is written, and the
+/// original file is only removed once the new one is written. Unless
+/// `--force` is given, the user is asked to confirm first.
+///
+/// # Errors
+///
+/// Any [`enum@Error`], or any error while reading, validating or writing the
+/// configuration.
+pub(super) fn run_convert_in_place(config: &Config, format: &str) -> Result<(), super::Error> {
+    let format = infer_format(None, Some(&format.to_owned()))?;
+    let original = ConfigDocument::load(&config.config_file)?;
+    if original.format() == format {
+        tracing::info!("configuration is already {}", format.name());
+        return Ok(());
+    }
+
+    let old_path = original.path().to_path_buf();
+    let new_path = old_path.with_extension(format.preferred_extension());
+    if !config.force {
+        if new_path.exists() {
+            return Err(Error::AlreadyExists(new_path).into());
+        }
+        let question = format!("Convert {} to {}?", old_path.display(), new_path.display());
+        if !confirm(&question, &mut io::stdin().lock()).map_err(Error::Prompt)? {
+            return Err(Error::Cancelled.into());
+        }
+    }
+
+    let converted = original.converted_to(new_path, format);
+    config.rebuild(&converted)?;
+    converted.save()?;
+    fs::remove_file(&old_path).map_err(|error| Error::Remove {
+        path: old_path.clone(),
+        error,
+    })?;
+    tracing::info!(
+        "converted {} to {}",
+        old_path.display(),
+        converted.path().display()
+    );
+    Ok(())
+}
@@ -159,6 +159,26 @@ impl HoardProjectDirs {
     }
 }
 
+/// The system-wide configuration file, layered below the user's.
+///
+/// `$HOARD_SYSTEM_CONFIG` overrides the default of `/etc/hoard/config.toml`
+/// (`%PROGRAMDATA%\hoard\config.toml` on Windows). Setting it to an empty
+/// value disables the system-wide configuration.
+#[must_use]
+pub fn system_config_file() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("HOARD_SYSTEM_CONFIG") {
+        return (!path.is_empty()).then_some(PathBuf::from(path));
+    }
+
+    #[cfg(windows)]
+    let dir = env::var_os("PROGRAMDATA").map(PathBuf::from)?;
+
+    #[cfg(not(windows))]
+    let dir = PathBuf::from("/etc");
+
+    Some(dir.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+}
+
 /// Get all user directories (not for macOS)
 pub(crate) fn get_dirs() -> ProjectDirs {
     tracing::trace!("determining project default folders");
@@ -0,0 +1,363 @@
+//! Programmatic edits of the configuration file.
+//!
+//! Commands that change the configuration (e.g. `hoard adopt`) load the file
+//! into a [`ConfigDocument`], change it, and write it back in the format it was
+//! read in. Only the lines of what changed are rewritten, so comments, key
+//! order and formatting are kept.
+
+use super::{
+    builder::Builder,
+    filetypes::{self, format::ConfigFormat, infer_format, json, layout, toml, yaml},
+};
+use crate::utils::write_atomic;
+use serde_json::{Map, Value};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Errors that can occur while editing the configuration file.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The configuration file could not be read.
+    #[error("failed to read {path}: {error}")]
+    Read {
+        /// The path of the configuration file.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+    /// The configuration file could not be written.
+    #[error("failed to write {path}: {error}")]
+    Write {
+        /// The path of the configuration file.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+    /// The configuration file could not be parsed or serialized.
+    #[error("{0}")]
+    Format(#[from] filetypes::Error),
+    /// A section that should be a table is something else.
+    #[error("expected `{0}` to be a table")]
+    NotATable(String),
+    /// A hoard to edit is defined in another configuration file, e.g. an
+    /// included one.
+    #[error("hoard {hoard} is defined in {}, edit that file instead", path.display())]
+    DefinedElsewhere {
+        /// The name of the hoard.
+        hoard: String,
+        /// The file the hoard is defined in.
+        path:  PathBuf,
+    },
+    /// A setting that should be an array is something else.
+    #[error("expected `{0}` to be an array")]
+    NotAnArray(String),
+    /// The edited configuration is not a valid configuration.
+    #[error("the edited configuration is invalid: {0}")]
+    Invalid(#[source] serde_json::Error),
+    /// A configuration could not be turned into a document.
+    #[error("failed to serialize configuration: {0}")]
+    Serialize(#[source] serde_json::Error),
+}
+
+/// `value` without any `null`s in objects, which not every format can
+/// represent.
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => value,
+    }
+}
+
+/// The file a [`ConfigDocument`] was read from.
+#[derive(Clone, Debug)]
+struct Original {
+    /// The text of the file.
+    text:   String,
+    /// The format of the file.
+    format: ConfigFormat,
+    /// The contents of the file, before any edits.
+    root:   Map<String, Value>,
+}
+
+/// A configuration file, loaded for editing.
+#[derive(Clone, Debug)]
+pub struct ConfigDocument {
+    /// Where the file is read from and written to.
+    path:     PathBuf,
+    /// The format of the file.
+    format:   ConfigFormat,
+    /// The contents of the file.
+    root:     Map<String, Value>,
+    /// The file the document was read from, whose comments and formatting are
+    /// kept.
+    original: Option<Original>,
+}
+
+impl PartialEq for ConfigDocument {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.format == other.format && self.root == other.root
+    }
+}
+
+impl ConfigDocument {
+    /// Load the configuration file at `path`, inferring its format from the
+    /// extension.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Read`] if the file cannot be read, or [`Error::Format`] if it
+    /// cannot be parsed.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let format = infer_format(Some(&path.to_path_buf()), None)?;
+        let text = fs::read_to_string(path).map_err(|error| Error::Read {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        Self::parse(path.to_path_buf(), format, &text)
+    }
+
+    /// An empty document to be written to `path` in `format`.
+    #[must_use]
+    pub fn new(path: PathBuf, format: ConfigFormat) -> Self {
+        Self {
+            path,
+            format,
+            root: Map::new(),
+            original: None,
+        }
+    }
+
+    /// A document with the contents of `builder`, to be written to `path` in
+    /// `format`. Settings that are not set are left out, and keys are sorted.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Serialize`] if `builder` cannot be represented as a document.
+    pub fn from_builder(
+        builder: &Builder,
+        path: PathBuf,
+        format: ConfigFormat,
+    ) -> Result<Self, Error> {
+        let value = serde_json::to_value(builder).map_err(Error::Serialize)?;
+        let root = match layout::sorted(without_nulls(value)) {
+            Value::Object(root) => root,
+            Value::Null
+            | Value::Bool(_)
+            | Value::Number(_)
+            | Value::String(_)
+            | Value::Array(_) => Map::new(),
+        };
+        Ok(Self {
+            path,
+            format,
+            root,
+            original: None,
+        })
+    }
+
+    /// The same document, to be written to `path` in `format` instead.
+    #[must_use]
+    pub fn converted_to(&self, path: PathBuf, format: ConfigFormat) -> Self {
+        Self {
+            path,
+            format,
+            root: self.root.clone(),
+            original: self.original.clone(),
+        }
+    }
+
+    /// Parse `text` in `format` as the contents of the file at `path`.
+    fn parse(path: PathBuf, format: ConfigFormat, text: &str) -> Result<Self, Error> {
+        let root = match format {
+            ConfigFormat::Json => json::deserialize(text)?,
+            ConfigFormat::Toml => toml::deserialize(text)?,
+            ConfigFormat::Yaml if text.trim().is_empty() => Map::new(),
+            ConfigFormat::Yaml => yaml::deserialize(text)?,
+        };
+        let original = Some(Original {
+            text: text.to_owned(),
+            format,
+            root: root.clone(),
+        });
+        Ok(Self {
+            path,
+            format,
+            root,
+            original,
+        })
+    }
+
+    /// The path of the configuration file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The format the configuration file is written in.
+    #[must_use]
+    pub fn format(&self) -> ConfigFormat {
+        self.format
+    }
+
+    /// The table at the top-level `key`, created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NotATable`] if `key` exists but is not a table.
+    pub fn table_mut(&mut self, key: &str) -> Result<&mut Map<String, Value>, Error> {
+        self.root
+            .entry(key)
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .ok_or_else(|| Error::NotATable(key.to_owned()))
+    }
+
+    /// The array at `key` in the top-level table `table`, both created if they
+    /// do not exist.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NotATable`] if `table` is not a table, or [`Error::NotAnArray`]
+    /// if `key` is not an array.
+    pub fn array_mut(&mut self, table: &str, key: &str) -> Result<&mut Vec<Value>, Error> {
+        self.table_mut(table)?
+            .entry(key)
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .ok_or_else(|| Error::NotAnArray(format!("{}.{}", table, key)))
+    }
+
+    /// The `hoards` table, created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NotATable`] if `hoards` is not a table.
+    pub fn hoards_mut(&mut self) -> Result<&mut Map<String, Value>, Error> {
+        self.table_mut("hoards")
+    }
+
+    /// A [`Builder`] with the contents of the document, to validate changes
+    /// before saving them.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Invalid`] if the document is not a valid configuration.
+    pub fn to_builder(&self) -> Result<Builder, Error> {
+        serde_json::from_value(Value::Object(self.root.clone())).map_err(Error::Invalid)
+    }
+
+    /// The contents of the document in its format. Edits of a file keep its
+    /// comments and formatting, and a file converted to another format keeps
+    /// the comments that the format can carry.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Format`] if the contents cannot be represented in the format,
+    /// e.g. a `null` in TOML.
+    pub fn to_text(&self) -> Result<String, Error> {
+        Ok(match &self.original {
+            Some(original) if original.format == self.format =>
+                filetypes::update(&original.text, self.format, &original.root, &self.root)?,
+            Some(original) => filetypes::convert(
+                &original.text,
+                original.format,
+                &layout::reorder(&self.root, &original.root),
+                self.format,
+            )?,
+            None => filetypes::render(self.format, &self.root)?,
+        })
+    }
+
+    /// Write the document back to its file.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Format`] if it cannot be serialized, or [`Error::Write`] if the
+    /// file cannot be written.
+    pub fn save(&self) -> Result<(), Error> {
+        let text = self.to_text()?;
+        tracing::debug!(path = %self.path.display(), "writing configuration file");
+        write_atomic(&self.path, text.as_bytes()).map_err(|error| Error::Write {
+            path: self.path.clone(),
+            error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn add_hoard(format: ConfigFormat, text: &str) -> ConfigDocument {
+        let mut doc = ConfigDocument::parse(PathBuf::from("config"), format, text)
+            .expect("failed to parse document");
+        doc.hoards_mut()
+            .unwrap()
+            .insert("nvim".into(), json!({ "linux": "${HOME}/.config/nvim" }));
+        doc
+    }
+
+    #[test]
+    fn edits_round_trip_in_every_format() {
+        let cases = [
+            (
+                ConfigFormat::Toml,
+                "exclusivity = [[\"a\"]]\n\n[envs.linux]\nos = [\"linux\"]\n",
+            ),
+            (
+                ConfigFormat::Yaml,
+                "exclusivity:\n  - [a]\nenvs:\n  linux:\n    os: [linux]\n",
+            ),
+            (
+                ConfigFormat::Json,
+                r#"{"exclusivity": [["a"]], "envs": {"linux": {"os": ["linux"]}}}"#,
+            ),
+        ];
+
+        for &(format, text) in &cases {
+            let doc = add_hoard(format, text);
+            let written = doc.to_text().expect("failed to serialize document");
+            let reread = ConfigDocument::parse(PathBuf::from("config"), format, &written)
+                .expect("failed to parse written document");
+            assert_eq!(reread, doc, "{}", written);
+            doc.to_builder().expect("edited document should be valid");
+        }
+    }
+
+    #[test]
+    fn edits_keep_comments_and_conversions_carry_them() {
+        let text = "# Linux machines\n[envs.linux]\nos = [\"linux\"] # not BSD\n";
+        let doc = add_hoard(ConfigFormat::Toml, text);
+        assert_eq!(
+            doc.to_text().unwrap(),
+            [text, "\n[hoards.nvim]\nlinux = \"${HOME}/.config/nvim\"\n"].concat()
+        );
+
+        let yaml = doc.converted_to(PathBuf::from("config.yaml"), ConfigFormat::Yaml);
+        assert_eq!(
+            yaml.to_text().unwrap(),
+            "---\nenvs:\n  # Linux machines\n  linux:\n    # not BSD\n    os:\n      - \
+             linux\nhoards:\n  nvim:\n    linux: \"${HOME}/.config/nvim\"\n"
+        );
+    }
+
+    #[test]
+    fn non_table_sections_are_rejected() {
+        let mut doc =
+            ConfigDocument::parse(PathBuf::from("config"), ConfigFormat::Toml, "hoards = 1\n")
+                .unwrap();
+        assert!(matches!(doc.hoards_mut(), Err(Error::NotATable(key)) if key == "hoards"));
+    }
+}
@@ -0,0 +1,229 @@
+//! Edit the configuration file in the user's editor.
+//!
+//! See [`run_edit`].
+
+use super::{edit::ConfigDocument, Config};
+use crate::utils::write_atomic;
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+/// The editor to use if neither `$VISUAL` nor `$EDITOR` is set.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Errors that can occur while editing the configuration file.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The editor could not be started.
+    #[error("failed to run editor `{editor}`: {error}")]
+    Spawn {
+        /// The editor command.
+        editor: String,
+        /// The error that occurred.
+        #[source]
+        error:  io::Error,
+    },
+    /// The editor exited unsuccessfully.
+    #[error("editor `{0}` exited unsuccessfully, the configuration was not changed")]
+    EditorFailed(String),
+    /// The user chose not to fix an invalid configuration.
+    #[error("editing aborted, the configuration was not changed")]
+    Aborted,
+    /// A file could not be read or written.
+    #[error("failed to access {path}: {error}")]
+    IO {
+        /// The path of the file.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+}
+
+/// The editor command from `$VISUAL` or `$EDITOR`, split into words.
+fn editor_command() -> Vec<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .map(|editor| {
+            editor
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        })
+        .find(|words| !words.is_empty())
+        .unwrap_or_else(|| vec![DEFAULT_EDITOR.to_owned()])
+}
+
+/// Open `path` in the user's editor and wait for it to exit.
+fn open_editor(path: &Path) -> Result<(), Error> {
+    let editor = editor_command();
+    let command = editor.join(" ");
+    tracing::debug!(editor = %command, path = %path.display(), "opening editor");
+    let status = Command::new(&editor[0])
+        .args(&editor[1..])
+        .arg(path)
+        .status()
+        .map_err(|error| Error::Spawn {
+            editor: command.clone(),
+            error,
+        })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::EditorFailed(command))
+    }
+}
+
+/// Ask whether to edit the file again after it failed to validate.
+///
+/// Anything but an explicit "no" edits again. Without a terminal to ask on,
+/// editing is aborted.
+fn ask_edit_again(input: &mut impl BufRead) -> io::Result<bool> {
+    if !atty::is(atty::Stream::Stdin) {
+        return Ok(false);
+    }
+    let mut stderr = io::stderr();
+    write!(stderr, "Edit again? [Y/n] ")?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        return Ok(false);
+    }
+    Ok(!matches!(answer.trim().to_lowercase().as_str(), "n" | "no"))
+}
+
+/// A temporary file with the same extension as `config_file`, so the edited
+/// file is parsed in the same format. Only the user can read it, since the
+/// configuration can hold passwords.
+fn temp_file(config_file: &Path) -> io::Result<NamedTempFile> {
+    let suffix = config_file
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    tempfile::Builder::new()
+        .prefix(concat!(env!("CARGO_PKG_NAME"), "-"))
+        .suffix(&suffix)
+        .tempfile()
+}
+
+/// Edit a copy of the configuration file in `$VISUAL` or `$EDITOR` and
+/// replace the original with it once it is a valid configuration.
+///
+/// If the edited configuration is invalid, the error is shown and the user can
+/// edit it again or abort, leaving the original untouched.
+///
+/// # Errors
+///
+/// Any [`enum@Error`] while editing, including [`Error::Aborted`] when the
+/// user gives up on an invalid configuration.
+pub(super) fn run_edit(config: &Config) -> Result<(), super::Error> {
+    config.emit_started("edit");
+    let original_path = &config.config_file;
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| Error::IO { path, error }
+    };
+    let original = match fs::read_to_string(original_path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(io_error(original_path)(err).into()),
+    };
+
+    let mut temp = temp_file(original_path).map_err(io_error(&env::temp_dir()))?;
+    let temp_path = temp.path().to_path_buf();
+    temp.write_all(original.as_bytes())
+        .and_then(|_| temp.flush())
+        .map_err(io_error(&temp_path))?;
+    let result = edit_until_valid(config, &temp_path);
+    if let Err(err) = temp.close() {
+        tracing::warn!(error = %err, path = %temp_path.display(), "failed to remove temporary file");
+    }
+
+    let edited = result?;
+    if edited == original {
+        tracing::info!("configuration was not changed");
+    } else {
+        write_atomic(original_path, edited.as_bytes()).map_err(io_error(original_path))?;
+        tracing::info!(path = %original_path.display(), "saved configuration");
+    }
+    config.emit_finished("edit");
+    Ok(())
+}
+
+/// Open `temp` in the editor until it holds a valid configuration, and return
+/// its contents.
+fn edit_until_valid(config: &Config, temp: &Path) -> Result<String, super::Error> {
+    let stdin = io::stdin();
+    loop {
+        open_editor(temp)?;
+        let error = match ConfigDocument::load(temp)
+            .map_err(super::Error::from)
+            .and_then(|doc| config.rebuild(&doc))
+        {
+            Ok(_) => {
+                return fs::read_to_string(temp).map_err(|error| {
+                    Error::IO {
+                        path: temp.to_path_buf(),
+                        error,
+                    }
+                    .into()
+                });
+            },
+            Err(err) => err,
+        };
+
+        tracing::error!("the edited configuration is invalid: {}", error);
+        let again = ask_edit_again(&mut stdin.lock()).map_err(|error| Error::IO {
+            path: PathBuf::from("<stdin>"),
+            error,
+        })?;
+        if !again {
+            return Err(Error::Aborted.into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[serial_test::serial]
+    fn editor_is_taken_from_visual_then_editor() {
+        let visual = env::var_os("VISUAL");
+        let editor = env::var_os("EDITOR");
+
+        env::set_var("VISUAL", "code --wait");
+        env::set_var("EDITOR", "nano");
+        assert_eq!(editor_command(), ["code", "--wait"]);
+        env::remove_var("VISUAL");
+        assert_eq!(editor_command(), ["nano"]);
+        env::set_var("EDITOR", "  ");
+        assert_eq!(editor_command(), [DEFAULT_EDITOR]);
+
+        for (var, value) in &[("VISUAL", visual), ("EDITOR", editor)] {
+            match value {
+                Some(value) => env::set_var(var, value),
+                None => env::remove_var(var),
+            }
+        }
+    }
+
+    #[test]
+    fn temp_file_keeps_extension_and_is_private() {
+        let temp = temp_file(Path::new("/home/user/.config/hoard/config.yaml")).unwrap();
+        assert_eq!(temp.path().extension().unwrap(), "yaml");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = temp.as_file().metadata().unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Print the configuration as `hoard` uses it, and the schema it follows.
+//!
+//! See [`run_show_effective`] and [`run_schema`].
+
+use super::{
+    builder::Builder,
+    edit::ConfigDocument,
+    filetypes::infer_format,
+    output::{print_document, OutputFormat},
+    Config,
+};
+use std::io;
+use thiserror::Error;
+
+/// Errors that can occur while printing the effective configuration.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The configuration could not be printed.
+    #[error("failed to print configuration: {0}")]
+    Print(#[source] io::Error),
+    /// The schema could not be serialized.
+    #[error("failed to serialize the configuration schema: {0}")]
+    Schema(#[source] serde_json::Error),
+}
+
+/// Print the JSON Schema of the configuration file.
+///
+/// # Errors
+///
+/// [`Error::Schema`] or [`Error::Print`] if the schema cannot be printed.
+pub(super) fn run_schema(output: OutputFormat) -> Result<(), Error> {
+    let schema = serde_json::to_string_pretty(&Builder::schema()).map_err(Error::Schema)?;
+    print_document(output, "json", &(schema + "\n")).map_err(Error::Print)
+}
+
+/// Print the configuration after merging every configuration file and
+/// applying environment variable overrides and command-line options, in
+/// `format` or the format of the configuration file.
+///
+/// # Errors
+///
+/// Any error while loading the configuration again or printing it.
+pub(super) fn run_show_effective(
+    config: &Config,
+    format: Option<&String>,
+) -> Result<(), super::Error> {
+    let format = infer_format(Some(&config.config_file), format)?;
+    let builder =
+        Builder::from_config_file(&config.config_file)?.set_hoards_root(config.storage.root());
+    let doc = ConfigDocument::from_builder(&builder, config.config_file.clone(), format)?;
+    let text = doc.to_text()?;
+    print_document(config.output, format.name(), &text).map_err(Error::Print)?;
+    Ok(())
+}
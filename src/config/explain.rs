@@ -0,0 +1,370 @@
+//! Explain how environments are evaluated and pile paths are chosen.
+//!
+//! See [`run_explain`].
+
+use super::{
+    builder::{
+        environment::{ConditionResult, Error as EnvironmentError},
+        envtrie::{environment_weights, DEFAULT_WEIGHT},
+        hoard::{Candidate, Error as HoardError},
+    },
+    migrate::{check_pile, display_name, parse_target},
+    output::{emit, Event},
+    Config,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{self, Write},
+};
+use thiserror::Error;
+
+/// Errors that can occur while explaining the configuration.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// An environment could not be evaluated.
+    #[error("failed to evaluate environment {name}: {error}")]
+    Environment {
+        /// The name of the environment.
+        name:  String,
+        /// The error that occurred.
+        #[source]
+        error: EnvironmentError,
+    },
+    /// The conditions of a pile could not be evaluated.
+    #[error("{0}")]
+    Hoard(#[from] HoardError),
+    /// The explanation could not be printed.
+    #[error("failed to print explanation: {0}")]
+    Print(#[source] io::Error),
+}
+
+/// How an environment was evaluated.
+struct EnvironmentExplanation<'a> {
+    name:       &'a str,
+    applies:    bool,
+    conditions: Vec<ConditionResult>,
+}
+
+/// How the path of a pile was chosen.
+struct PileExplanation<'a> {
+    hoard:      &'a str,
+    pile:       Option<&'a str>,
+    candidates: Vec<Candidate>,
+    chosen:     Option<&'a str>,
+}
+
+/// The name of the shell variable that holds whether environment `name`
+/// applies.
+fn shell_variable(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|chr| {
+            if chr.is_ascii_alphanumeric() {
+                chr.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    ["HOARD_ENV_", &name].concat()
+}
+
+/// Quote `value` for a POSIX shell.
+fn shell_quote(value: &str) -> String {
+    ["'", &value.replace('\'', r"'\''"), "'"].concat()
+}
+
+/// Write whether each environment applies as `export` statements.
+fn write_shell(out: &mut impl Write, envs: &[EnvironmentExplanation<'_>]) -> io::Result<()> {
+    for env in envs {
+        writeln!(
+            out,
+            "export {}={}",
+            shell_variable(env.name),
+            u8::from(env.applies)
+        )?;
+    }
+    let applying: Vec<&str> = envs
+        .iter()
+        .filter(|env| env.applies)
+        .map(|env| env.name)
+        .collect();
+    writeln!(
+        out,
+        "export HOARD_ENVS={}",
+        shell_quote(&applying.join(" "))
+    )
+}
+
+/// Write the evaluation of each environment.
+fn write_environments(out: &mut impl Write, envs: &[EnvironmentExplanation<'_>]) -> io::Result<()> {
+    writeln!(out, "Environments:")?;
+    for env in envs {
+        let applies = if env.applies {
+            "applies"
+        } else {
+            "does not apply"
+        };
+        if env.conditions.is_empty() {
+            writeln!(out, "  {}: {} (no conditions)", env.name, applies)?;
+            continue;
+        }
+        writeln!(out, "  {}: {}", env.name, applies)?;
+        for result in &env.conditions {
+            writeln!(
+                out,
+                "    {} {}: {} ({})",
+                if result.passed { "passed" } else { "failed" },
+                result.kind,
+                result.condition,
+                result.reason
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the weight of each environment in the exclusivity lists.
+fn write_weights(
+    out: &mut impl Write,
+    exclusivity: &[Vec<String>],
+    weights: &HashMap<String, usize>,
+) -> io::Result<()> {
+    writeln!(out, "Exclusivity weights:")?;
+    for list in exclusivity {
+        let weighted: Vec<String> = list
+            .iter()
+            .map(|env| {
+                let weight = weights.get(env).copied().unwrap_or(DEFAULT_WEIGHT);
+                format!("{} = {}", env, weight)
+            })
+            .collect();
+        writeln!(out, "  {}", weighted.join(", "))?;
+    }
+    writeln!(
+        out,
+        "  Other environments weigh {}. Conditions with more environments win, ties are broken by \
+         comparing weights.",
+        DEFAULT_WEIGHT
+    )
+}
+
+/// Write the candidate conditions of a pile and which one was chosen.
+fn write_pile(out: &mut impl Write, pile: &PileExplanation<'_>) -> io::Result<()> {
+    writeln!(out, "{}", display_name(pile.hoard, pile.pile))?;
+    let width = pile
+        .candidates
+        .iter()
+        .map(|candidate| candidate.condition.len())
+        .max()
+        .unwrap_or(0);
+    for candidate in &pile.candidates {
+        let marker = if Some(candidate.condition.as_str()) == pile.chosen {
+            '*'
+        } else {
+            ' '
+        };
+        let status = if candidate.missing.is_empty() {
+            format!("scores {:?}", candidate.scores)
+        } else {
+            format!("does not apply: {}", candidate.missing.join(", "))
+        };
+        writeln!(
+            out,
+            "  {} {:width$}  {:<24} {}",
+            marker,
+            candidate.condition,
+            status,
+            candidate.path,
+            width = width
+        )?;
+    }
+    match pile.chosen {
+        Some(chosen) => writeln!(out, "  chosen: {}", chosen),
+        None => writeln!(out, "  chosen: none, the pile is skipped"),
+    }
+}
+
+/// Explain the piles in `target` (`hoard` or `hoard:pile`), or all piles.
+fn explain_piles<'a>(
+    config: &'a Config,
+    target: Option<&'a str>,
+) -> Result<Vec<PileExplanation<'a>>, super::Error> {
+    let (hoards, only_pile): (Vec<&str>, Option<&str>) = if let Some(target) = target {
+        let (hoard, pile) = parse_target(target)?;
+        if let Some(pile) = pile {
+            check_pile(config, hoard, pile)?;
+        } else {
+            config.get_hoard(hoard)?;
+        }
+        (vec![hoard], pile)
+    } else {
+        let mut hoards: Vec<&str> = config.sources.keys().map(String::as_str).collect();
+        hoards.sort_unstable();
+        (hoards, None)
+    };
+
+    let mut piles = Vec::new();
+    let sources = hoards
+        .into_iter()
+        .filter_map(|hoard| config.sources.get(hoard).map(|source| (hoard, source)));
+    for (hoard, source) in sources {
+        let mut sources = source.piles();
+        sources.sort_unstable_by_key(|(pile, _)| *pile);
+        for (pile, pile_source) in sources {
+            if only_pile.is_some() && pile != only_pile {
+                continue;
+            }
+            piles.push(PileExplanation {
+                hoard,
+                pile,
+                candidates: pile_source
+                    .candidates(&config.environments, &config.exclusivity)
+                    .map_err(Error::from)?,
+                chosen: pile_source
+                    .matching_condition(&config.environments, &config.exclusivity)
+                    .map_err(Error::from)?,
+            });
+        }
+    }
+    Ok(piles)
+}
+
+/// Explain `envs`, or every environment if `None`.
+fn explain_environments<'a>(
+    config: &'a Config,
+    envs: Option<&BTreeSet<&str>>,
+) -> Result<Vec<EnvironmentExplanation<'a>>, Error> {
+    let mut names: Vec<&String> = config
+        .env_sources
+        .keys()
+        .filter(|name| envs.map_or(true, |envs| envs.contains(name.as_str())))
+        .collect();
+    names.sort_unstable();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let conditions =
+                config.env_sources[name]
+                    .explain()
+                    .map_err(|error| Error::Environment {
+                        name: name.clone(),
+                        error,
+                    })?;
+            Ok(EnvironmentExplanation {
+                name,
+                applies: config.environments.get(name).copied().unwrap_or(false),
+                conditions,
+            })
+        })
+        .collect()
+}
+
+/// Print how each environment was evaluated and how the path of each pile in
+/// `target` (`hoard` or `hoard:pile`, or all hoards if `None`) was chosen.
+///
+/// With `format` `shell`, only whether each environment applies is printed,
+/// as `export` statements for use in other scripts.
+///
+/// # Errors
+///
+/// An invalid or unknown `target`, or any [`enum@Error`] while evaluating the
+/// environments and piles.
+pub(super) fn run_explain(
+    config: &Config,
+    target: Option<&str>,
+    format: &str,
+) -> Result<(), super::Error> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    if format == "shell" {
+        let envs = explain_environments(config, None)?;
+        return write_shell(&mut out, &envs).map_err(|err| Error::Print(err).into());
+    }
+
+    config.emit_started("explain");
+    let piles = explain_piles(config, target)?;
+    // When explaining some piles, only show the environments they use
+    let used: Option<BTreeSet<&str>> = target.map(|_| {
+        piles
+            .iter()
+            .flat_map(|pile| &pile.candidates)
+            .flat_map(|candidate| candidate.condition.split('|'))
+            .collect()
+    });
+    let envs = explain_environments(config, used.as_ref())?;
+
+    if config.output.is_json() {
+        for env in &envs {
+            emit(&Event::EnvironmentExplained {
+                name:       env.name,
+                applies:    env.applies,
+                conditions: &env.conditions,
+            });
+        }
+        for pile in &piles {
+            emit(&Event::PileExplained {
+                hoard:      pile.hoard,
+                pile:       pile.pile,
+                candidates: &pile.candidates,
+                chosen:     pile.chosen,
+            });
+        }
+        config.emit_finished("explain");
+        return Ok(());
+    }
+
+    let weights = environment_weights(&config.exclusivity)
+        .map_err(|err| Error::from(HoardError::from(err)))?;
+    write_environments(&mut out, &envs)
+        .and_then(|()| writeln!(out))
+        .and_then(|()| write_weights(&mut out, &config.exclusivity, &weights))
+        .map_err(Error::Print)?;
+    for pile in &piles {
+        writeln!(out)
+            .and_then(|()| write_pile(&mut out, pile))
+            .map_err(Error::Print)?;
+    }
+    config.emit_finished("explain");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environments_are_exported_as_shell_variables() {
+        let envs = [
+            EnvironmentExplanation {
+                name:       "linux",
+                applies:    true,
+                conditions: Vec::new(),
+            },
+            EnvironmentExplanation {
+                name:       "work-laptop",
+                applies:    true,
+                conditions: Vec::new(),
+            },
+            EnvironmentExplanation {
+                name:       "macos",
+                applies:    false,
+                conditions: Vec::new(),
+            },
+        ];
+        let mut out = Vec::new();
+        write_shell(&mut out, &envs).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "export HOARD_ENV_LINUX=1\nexport HOARD_ENV_WORK_LAPTOP=1\nexport \
+             HOARD_ENV_MACOS=0\nexport HOARD_ENVS='linux work-laptop'\n"
+        );
+    }
+
+    #[test]
+    fn shell_values_are_quoted() {
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
@@ -1,5 +1,6 @@
 //! Format options for convertion between [`json`](serde_json),
 //! [`yaml`](serde_yaml), and [`toml`](toml)
+use serde_json::{Map, Value};
 use std::str::FromStr;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -92,35 +93,19 @@ impl Formatted {
         Formatted { format, text }
     }
 
-    /// Creates a new instance of `Formatted` with the newly formatted text
+    /// Creates a new instance of `Formatted` with the newly formatted text.
+    /// The comments of the text are carried over where the format allows, and
+    /// keys keep their order.
     ///
     /// # Errors
     /// Returns error if conversion is unsucessful
     pub fn convert_to(&self, format: ConfigFormat) -> Result<Formatted, Error> {
-        match format {
-            ConfigFormat::Json => self.to_json(),
-            ConfigFormat::Toml => self.to_toml(),
-            ConfigFormat::Yaml => self.to_yaml(),
+        if format == self.format {
+            return Ok(Formatted::new(format, self.text.clone()));
         }
-        .map(|text| Formatted { format, text })
-    }
-
-    /// Convert type to JSON
-    fn to_json(&self) -> Result<String, Error> {
-        let value = self.deserialize::<super::json::SValue>(&self.text)?;
-        super::json::serialize(&value)
-    }
-
-    /// Convert type to TOML
-    fn to_toml(&self) -> Result<String, Error> {
-        let value = self.deserialize::<super::toml::SValue>(&self.text)?;
-        super::toml::serialize(&value)
-    }
-
-    /// Convert type to YAML
-    fn to_yaml(&self) -> Result<String, Error> {
-        let value = self.deserialize::<super::yaml::SValue>(&self.text)?;
-        super::yaml::serialize(&value)
+        let root: Map<String, Value> = self.deserialize(&self.text)?;
+        super::convert(&self.text, self.format, &root, format)
+            .map(|text| Formatted { format, text })
     }
 
     /// Deserialze all types with their own deserialization function
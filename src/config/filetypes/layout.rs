@@ -0,0 +1,372 @@
+//! The layout of TOML and YAML configuration files: which line each key is
+//! on, and the comments around it.
+//!
+//! A [`Layout`] lets edits rewrite only the lines of what changed, keeping the
+//! comments, order and formatting of everything else, and lets conversions
+//! carry comments over to another format.
+
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+/// The keys leading to a value, with array indices as numbers.
+pub type KeyPath = Vec<String>;
+
+/// What the line of an [`Entry`] holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A key with its value, e.g. `key = "value"` or `key: value`.
+    Value,
+    /// A YAML key whose value is a block mapping on the following lines.
+    Mapping,
+    /// A TOML `[table]` header.
+    Table,
+    /// A TOML `[[array]]` header, starting the element at the end of `path`.
+    ArrayTable,
+}
+
+/// A key in a configuration file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// What the line of the key holds.
+    pub kind:     EntryKind,
+    /// The full path of the key.
+    pub path:     KeyPath,
+    /// The key as it is spelled on its line: relative to the table it is in
+    /// for values, the full path without array indices for headers.
+    pub key:      KeyPath,
+    /// The first line of the comments and blank lines before the key.
+    pub start:    usize,
+    /// The line of the key.
+    pub line:     usize,
+    /// The last line of the entry. For TOML headers, the last line of the
+    /// values in their table.
+    pub end:      usize,
+    /// The indentation of the key.
+    pub indent:   usize,
+    /// The comments before the key, without their `#`.
+    pub comments: Vec<String>,
+    /// The comment after the key on its line, without its `#`.
+    pub trailing: Option<String>,
+    /// How many comments are inside the value, e.g. between array elements.
+    pub inner:    usize,
+}
+
+impl Entry {
+    /// The lines of the entry itself, without the comments before it.
+    #[must_use]
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.line..=self.end
+    }
+}
+
+/// The keys of a configuration file, in the order they appear.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    /// Every key, in order.
+    pub entries: Vec<Entry>,
+    /// The comments after the last key.
+    pub tail:    Vec<String>,
+}
+
+impl Layout {
+    /// The first entry with the full path `path`.
+    #[must_use]
+    pub fn find(&self, path: &[String]) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+
+    /// The comments of the file by the path of the key they belong to, with
+    /// comments after a key on its line moved before it, and how many
+    /// comments belong to no key.
+    #[must_use]
+    pub fn comments(&self) -> (Vec<(KeyPath, Vec<String>)>, usize) {
+        let comments = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut lines = entry.comments.clone();
+                lines.extend(entry.trailing.clone());
+                (entry.path.clone(), lines)
+            })
+            .filter(|(_, lines)| !lines.is_empty())
+            .collect();
+        let inner = self.entries.iter().map(|entry| entry.inner).sum();
+        (comments, inner)
+    }
+
+    /// How many comments the file has.
+    #[must_use]
+    pub fn comment_count(&self) -> usize {
+        let (comments, inner) = self.comments();
+        comments.iter().map(|(_, lines)| lines.len()).sum::<usize>() + inner + self.tail.len()
+    }
+
+    /// Add `comments` to `text`, which has this layout, before the line of
+    /// the key each belongs to, and `tail` at the end.
+    ///
+    /// Returns the new text and how many comments have no key to go to.
+    #[must_use]
+    pub fn with_comments(
+        &self,
+        text: &str,
+        comments: &[(KeyPath, Vec<String>)],
+        tail: &[String],
+    ) -> (String, usize) {
+        let mut edits = Edits::default();
+        let mut missing = 0;
+        for (path, lines) in comments {
+            match self.find(path) {
+                Some(entry) => {
+                    let indent = " ".repeat(entry.indent);
+                    for line in lines {
+                        edits.insert(entry.line, [&indent, "#", line].concat());
+                    }
+                },
+                None => missing += lines.len(),
+            }
+        }
+        let lines: Vec<&str> = text.lines().collect();
+        for line in tail {
+            edits.insert(lines.len(), ["#", line].concat());
+        }
+        (edits.apply(&lines), missing)
+    }
+}
+
+/// Line-based changes to a text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Edits {
+    /// The new text of each changed line, or `None` if it is deleted.
+    replaced: BTreeMap<usize, Option<String>>,
+    /// The lines to insert before each line, in order.
+    inserted: BTreeMap<usize, Vec<String>>,
+}
+
+impl Edits {
+    /// Delete `lines`.
+    pub fn delete(&mut self, lines: RangeInclusive<usize>) {
+        for line in lines {
+            self.replaced.insert(line, None);
+        }
+    }
+
+    /// Replace `line` with `text`, unless it is deleted.
+    pub fn replace(&mut self, line: usize, text: String) {
+        self.replaced.entry(line).or_insert(Some(text));
+    }
+
+    /// Insert `text` before `line`, after anything inserted there before.
+    pub fn insert(&mut self, line: usize, text: String) {
+        self.inserted.entry(line).or_default().push(text);
+    }
+
+    /// Whether `line` is deleted.
+    #[must_use]
+    pub fn is_deleted(&self, line: usize) -> bool {
+        matches!(self.replaced.get(&line), Some(None))
+    }
+
+    /// `lines` with the changes applied, as a text ending with a newline.
+    #[must_use]
+    pub fn apply(&self, lines: &[&str]) -> String {
+        let mut text = String::new();
+        for index in 0..=lines.len() {
+            for inserted in self.inserted.get(&index).into_iter().flatten() {
+                text.push_str(inserted);
+                text.push('\n');
+            }
+            let line = match self.replaced.get(&index) {
+                Some(Some(replaced)) => replaced.as_str(),
+                Some(None) => continue,
+                None => match lines.get(index) {
+                    Some(line) => line,
+                    None => continue,
+                },
+            };
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// The value at `path` in `root`, following array indices.
+#[must_use]
+pub fn lookup<'a>(root: &'a Map<String, Value>, path: &[String]) -> Option<&'a Value> {
+    let (first, rest) = path.split_first()?;
+    let mut value = root.get(first)?;
+    for key in rest {
+        value = match value {
+            Value::Object(map) => map.get(key)?,
+            Value::Array(values) => values.get(key.parse::<usize>().ok()?)?,
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => return None,
+        };
+    }
+    Some(value)
+}
+
+/// The keys of `old` that are in `new` under another name: each removed key
+/// whose value was added under exactly one new key, as `(old, new)` pairs.
+fn table_renames(old: &Map<String, Value>, new: &Map<String, Value>) -> Vec<(String, String)> {
+    let removed: Vec<(&String, &Value)> = old
+        .iter()
+        .filter(|(key, _)| !new.contains_key(key.as_str()))
+        .collect();
+    let added: Vec<(&String, &Value)> = new
+        .iter()
+        .filter(|(key, _)| !old.contains_key(key.as_str()))
+        .collect();
+    removed
+        .iter()
+        .filter_map(|(from, value)| {
+            let same_value = |(_, other): &&(&String, &Value)| other == value;
+            let mut targets = added.iter().filter(same_value);
+            let sources = removed.iter().filter(same_value).count();
+            match (targets.next(), targets.next(), sources) {
+                (Some((to, _)), None, 1) => Some(((*from).clone(), (*to).clone())),
+                (Some(_) | None, Some(_) | None, _) => None,
+            }
+        })
+        .collect()
+}
+
+/// Collect the renamed keys below `path` into `renames`.
+fn collect_renames(
+    path: &mut KeyPath,
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+    renames: &mut Vec<(KeyPath, String)>,
+) {
+    for (from, to) in table_renames(old, new) {
+        let mut old_path = path.clone();
+        old_path.push(from);
+        renames.push((old_path, to));
+    }
+    for (key, value) in old {
+        if let (Value::Object(old), Some(Value::Object(new))) = (value, new.get(key)) {
+            path.push(key.clone());
+            collect_renames(path, old, new, renames);
+            path.pop();
+        }
+    }
+}
+
+/// The keys renamed between `old` and `new`, as the old path of each and its
+/// new name.
+///
+/// A key counts as renamed if it was removed from a table and its value was
+/// added to the same table under exactly one other key.
+#[must_use]
+pub fn renames(old: &Map<String, Value>, new: &Map<String, Value>) -> Vec<(KeyPath, String)> {
+    let mut renames = Vec::new();
+    collect_renames(&mut Vec::new(), old, new, &mut renames);
+    renames
+}
+
+/// `path` after `renames`, and the index of the renamed key in it if one of
+/// its keys was renamed.
+#[must_use]
+pub fn renamed(path: &[String], renames: &[(KeyPath, String)]) -> (KeyPath, Option<usize>) {
+    for (from, to) in renames {
+        if path.starts_with(from) {
+            let mut path = path.to_vec();
+            let index = from.len() - 1;
+            path[index].clone_from(to);
+            return (path, Some(index));
+        }
+    }
+    (path.to_vec(), None)
+}
+
+/// `new` with the keys of each table in the order they have in `old`, with
+/// renamed keys in the place of their old name, followed by the keys that
+/// were added.
+#[must_use]
+pub fn reorder(new: &Map<String, Value>, old: &Map<String, Value>) -> Map<String, Value> {
+    let renames: BTreeMap<String, String> = table_renames(old, new).into_iter().collect();
+    let mut ordered = Map::new();
+    for (key, old_value) in old {
+        let key = renames.get(key).unwrap_or(key);
+        if let Some(value) = new.get(key) {
+            ordered.insert(key.clone(), reorder_value(value, old_value));
+        }
+    }
+    for (key, value) in new {
+        if !ordered.contains_key(key) {
+            ordered.insert(key.clone(), value.clone());
+        }
+    }
+    ordered
+}
+
+/// `new` with the keys of its tables in the order they have in `old`.
+fn reorder_value(new: &Value, old: &Value) -> Value {
+    match (new, old) {
+        (Value::Object(new), Value::Object(old)) => Value::Object(reorder(new, old)),
+        (Value::Array(new), Value::Array(old)) => Value::Array(
+            new.iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    old.get(index)
+                        .map_or_else(|| value.clone(), |old| reorder_value(value, old))
+                })
+                .collect(),
+        ),
+        (new, _) => new.clone(),
+    }
+}
+
+/// `value` with the keys of every table sorted, for output that does not
+/// depend on the order of hash maps.
+#[must_use]
+pub fn sorted(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|(left, _), (right, _)| left.cmp(right));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sorted(value)))
+                    .collect(),
+            )
+        },
+        Value::Array(values) => Value::Array(values.into_iter().map(sorted).collect()),
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn map(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            Value::Null
+            | Value::Bool(_)
+            | Value::Number(_)
+            | Value::String(_)
+            | Value::Array(_) => panic!("not a map"),
+        }
+    }
+
+    #[test]
+    fn renamed_keys_keep_their_place() {
+        let old =
+            map(json!({ "b": { "vim": { "linux": "~/.vimrc" }, "zsh": "~/.zshrc" }, "a": "/" }));
+        let new =
+            map(json!({ "a": "/", "b": { "zsh": "~/.zshrc", "neovim": { "linux": "~/.vimrc" } } }));
+        assert_eq!(renames(&old, &new), vec![(
+            vec!["b".to_owned(), "vim".to_owned()],
+            "neovim".to_owned()
+        )]);
+
+        let ordered = reorder(&new, &old);
+        let keys: Vec<&String> = ordered.keys().collect();
+        assert_eq!(keys, ["b", "a"]);
+        let keys: Vec<&String> = ordered["b"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["neovim", "zsh"]);
+    }
+}
@@ -8,6 +8,7 @@
 pub mod assets;
 pub mod format;
 pub mod json;
+pub mod layout;
 pub mod printer;
 pub mod toml;
 pub mod yaml;
@@ -24,10 +25,12 @@ use std::{
 use self::{
     assets::HighlightAssets,
     format::{ConfigFormat, Formatted},
+    layout::Layout,
     printer::{HighlightTextPrinter, PlainTextPrinter, Printer},
 };
 use crate::config::filetypes::assets::assets_from_cache_or_binary;
 
+use serde_json::{Map, Value};
 use syntect::{dumps::from_binary, highlighting::ThemeSet};
 use thiserror::Error;
 
@@ -203,14 +206,20 @@ impl ConfigConversion {
                 .collect::<Vec<_>>()
         );
 
-        let input_text = self.read_from_input()?;
-        let output_text = input_text
-            .convert_to(self.output_format)
-            .map_err(|err| Error::ConversionError(err.to_string()))?;
-
+        let output_text = self.convert()?;
         self.write_to_output(&output_text, &assets, &self.theme)
     }
 
+    /// The input file converted to the output format, without writing it.
+    ///
+    /// # Errors
+    /// Returns errors from reading or converting the input file.
+    pub fn convert(&self) -> Result<Formatted> {
+        self.read_from_input()?
+            .convert_to(self.output_format)
+            .map_err(|err| Error::ConversionError(err.to_string()))
+    }
+
     /// Read file type that is specified by the '--config' option
     ///
     /// # Errors
@@ -266,3 +275,142 @@ pub fn infer_format(file: Option<&PathBuf>, format_name: Option<&String>) -> Res
         Err(Error::InferFormat)
     }
 }
+
+/// `root` as a document in `format`, ending with a newline.
+///
+/// # Errors
+///
+/// [`Error::Serialization`] if `root` cannot be represented in `format`, e.g.
+/// a `null` in TOML.
+pub fn render(format: ConfigFormat, root: &Map<String, Value>) -> Result<String> {
+    let text = match format {
+        ConfigFormat::Json => json::serialize(root)?,
+        ConfigFormat::Toml => {
+            // Going through `toml::Value` writes plain values before tables,
+            // which TOML requires
+            let value = toml::SValue::try_from(root)
+                .map_err(|err| Error::Serialization(err.to_string()))?;
+            toml::serialize(&value)?
+        },
+        ConfigFormat::Yaml => yaml::serialize(root)?,
+    };
+    Ok(if text.ends_with('\n') {
+        text
+    } else {
+        text + "\n"
+    })
+}
+
+/// The layout of `text` in `format`, if the format has comments and the
+/// layout is understood.
+fn layout_of(text: &str, format: ConfigFormat) -> Option<Layout> {
+    match format {
+        ConfigFormat::Json => None,
+        ConfigFormat::Toml => toml::layout(text),
+        ConfigFormat::Yaml => yaml::layout(text),
+    }
+}
+
+/// `edited` as a document in `format`, keeping the comments, key order and
+/// formatting of `text`, the document `original` was read from: only the
+/// lines of what changed are rewritten.
+///
+/// If `text` cannot be edited in place, the document is written anew with the
+/// keys in the order of `text` and the comments that can be carried over.
+///
+/// # Errors
+///
+/// [`Error::Serialization`] if `edited` cannot be represented in `format`.
+pub fn update(
+    text: &str,
+    format: ConfigFormat,
+    original: &Map<String, Value>,
+    edited: &Map<String, Value>,
+) -> Result<String> {
+    if original == edited {
+        return Ok(text.to_owned());
+    }
+    let updated = match format {
+        ConfigFormat::Json => None,
+        ConfigFormat::Toml => toml::update(text, original, edited),
+        ConfigFormat::Yaml => yaml::update(text, original, edited),
+    };
+    match (updated, format) {
+        (Some(updated), _) => Ok(updated),
+        (None, ConfigFormat::Json) => render(format, &layout::reorder(edited, original)),
+        (None, ConfigFormat::Toml | ConfigFormat::Yaml) => {
+            if !original.is_empty() {
+                tracing::warn!("could not edit the configuration file in place, writing it anew");
+            }
+            convert(text, format, &layout::reorder(edited, original), format)
+        },
+    }
+}
+
+/// `root`, read from `text` in the format `from`, as a document in `to`, with
+/// the comments of `text` before the keys they belong to.
+///
+/// Warns about comments that cannot be carried over: all of them for JSON,
+/// and comments inside of values or of keys that are gone otherwise.
+///
+/// # Errors
+///
+/// [`Error::Serialization`] if `root` cannot be represented in `to`.
+pub fn convert(
+    text: &str,
+    from: ConfigFormat,
+    root: &Map<String, Value>,
+    to: ConfigFormat,
+) -> Result<String> {
+    let rendered = render(to, root)?;
+    let (converted, lost) = match (layout_of(text, from), layout_of(&rendered, to)) {
+        (Some(source), Some(target)) => {
+            let (comments, inner) = source.comments();
+            let (converted, missing) = target.with_comments(&rendered, &comments, &source.tail);
+            (converted, inner + missing)
+        },
+        (Some(source), None) => (rendered, source.comment_count()),
+        (None, _) => {
+            let comments = match from {
+                ConfigFormat::Json => 0,
+                ConfigFormat::Toml | ConfigFormat::Yaml => text
+                    .lines()
+                    .filter(|line| line.trim_start().starts_with('#'))
+                    .count(),
+            };
+            (rendered, comments)
+        },
+    };
+    if lost > 0 {
+        tracing::warn!(
+            "{} comment(s) cannot be carried over to {}",
+            lost,
+            to.name()
+        );
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_carry_comments_over() {
+        let toml = "# Editors\n[hoards.vim]\n# Linux only\nlinux = \"~/.vimrc\" # rc\n";
+        let root: Map<String, Value> = toml::deserialize(toml).unwrap();
+        assert_eq!(
+            convert(toml, ConfigFormat::Toml, &root, ConfigFormat::Yaml).unwrap(),
+            "---\nhoards:\n  # Editors\n  vim:\n    # Linux only\n    # rc\n    linux: ~/.vimrc\n"
+        );
+
+        let yaml = "b: 1\n# the first\na: 2\n";
+        let root: Map<String, Value> = yaml::deserialize(yaml).unwrap();
+        let keys: Vec<&String> = root.keys().collect();
+        assert_eq!(keys, ["b", "a"], "keys keep their order");
+        assert_eq!(
+            convert(yaml, ConfigFormat::Yaml, &root, ConfigFormat::Toml).unwrap(),
+            "b = 1\n# the first\na = 2\n"
+        );
+    }
+}
@@ -1,5 +1,10 @@
-//! Convert type to TOML
-use super::Error;
+//! Convert type to TOML, and edit TOML documents in place.
+use super::{
+    layout::{self, Edits, Entry, EntryKind, KeyPath, Layout},
+    Error,
+};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
 
 pub use toml::Value as SValue;
 
@@ -26,3 +31,872 @@ where
     let deserialized = toml::from_str(s).map_err(|e| Error::Deserialization(e.to_string()))?;
     Ok(deserialized)
 }
+
+/// Whether `key` can be written without quotes.
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')
+}
+
+/// `key` as it is written in a TOML document.
+fn key_text(key: &str) -> String {
+    if is_bare_key(key) {
+        key.to_owned()
+    } else {
+        SValue::String(key.to_owned()).to_string()
+    }
+}
+
+/// The dotted key of `path`.
+fn dotted(path: &[String]) -> String {
+    path.iter()
+        .map(|key| key_text(key))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// `value` as an inline TOML value.
+fn inline_toml(value: &SValue) -> String {
+    match value {
+        SValue::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(inline_toml)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        SValue::Table(table) if table.is_empty() => "{}".to_owned(),
+        SValue::Table(table) => format!(
+            "{{ {} }}",
+            table
+                .iter()
+                .map(|(key, value)| format!("{} = {}", key_text(key), inline_toml(value)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        SValue::String(_)
+        | SValue::Integer(_)
+        | SValue::Float(_)
+        | SValue::Boolean(_)
+        | SValue::Datetime(_) => value.to_string(),
+    }
+}
+
+/// `value` as an inline TOML value, or `None` if TOML cannot represent it.
+fn inline(value: &Value) -> Option<String> {
+    SValue::try_from(value)
+        .ok()
+        .map(|value| inline_toml(&value))
+}
+
+/// Whether `value` is written as `[[array]]` tables.
+fn is_array_of_tables(value: &Value) -> bool {
+    match value {
+        Value::Array(values) => !values.is_empty() && values.iter().all(Value::is_object),
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) | Value::Object(_) =>
+            false,
+    }
+}
+
+/// The byte offset of the `#` that starts the comment on `line`, if any.
+fn comment_start(line: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (at, ch) in line.char_indices() {
+        match (quote, ch) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            },
+            (Some(open), close) if close == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(ch),
+            (None, '#') => return Some(at),
+            (Some(_) | None, _) => {},
+        }
+        escaped = false;
+    }
+    None
+}
+
+/// The comment on `line`, without its `#`.
+fn comment(line: &str) -> Option<String> {
+    line.get(comment_start(line)? + 1..).map(str::to_owned)
+}
+
+/// The byte offset of the quote that closes the basic string at the start of
+/// `text`.
+fn closing_quote(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (at, ch) in text.char_indices().skip(1) {
+        match ch {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(at),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// The dotted key at the start of `text`, and the rest of `text` after it.
+fn parse_key(text: &str) -> Option<(KeyPath, &str)> {
+    let mut key = KeyPath::new();
+    let mut rest = text.trim_start();
+    loop {
+        let (segment, after) = if rest.starts_with('"') {
+            let end = closing_quote(rest)?;
+            let quoted = rest.get(..=end)?;
+            let table: BTreeMap<String, String> =
+                toml::from_str(&["k = ", quoted].concat()).ok()?;
+            (table.into_iter().next()?.1, rest.get(end + 1..)?)
+        } else if let Some(literal) = rest.strip_prefix('\'') {
+            let end = literal.find('\'')?;
+            (literal.get(..end)?.to_owned(), literal.get(end + 1..)?)
+        } else {
+            let end = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '-'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return None;
+            }
+            (rest.get(..end)?.to_owned(), rest.get(end..)?)
+        };
+        key.push(segment);
+        rest = after.trim_start();
+        match rest.strip_prefix('.') {
+            Some(after) => rest = after.trim_start(),
+            None => return Some((key, rest)),
+        }
+    }
+}
+
+/// The kind of string a value is in while [`value_end`] scans it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Quote {
+    /// A `"basic"` string.
+    Basic,
+    /// A `'literal'` string.
+    Literal,
+    /// A `"""multi-line basic"""` string.
+    MultiBasic,
+    /// A `'''multi-line literal'''` string.
+    MultiLiteral,
+}
+
+/// The last line of the value that starts at byte `at` of `lines[line]`, and
+/// the number of comments on the lines after the first, found by tracking
+/// brackets and strings. `None` if the value never ends.
+fn value_end(lines: &[&str], line: usize, at: usize) -> Option<(usize, usize)> {
+    let mut depth: usize = 0;
+    let mut quote = None;
+    let mut comments = 0;
+    for (end, text) in lines.iter().enumerate().skip(line) {
+        let text = if end == line { text.get(at..)? } else { text };
+        let mut pos = 0;
+        while let Some(rest) = text.get(pos..).filter(|rest| !rest.is_empty()) {
+            let ch = rest.chars().next()?;
+            let mut step = ch.len_utf8();
+            // Up to two quotes can end a multi-line string's contents
+            let run = rest.chars().take_while(|next| *next == ch).count();
+            match (quote, ch) {
+                (None, '#') => {
+                    if end != line {
+                        comments += 1;
+                    }
+                    break;
+                },
+                (None, '[' | '{') => depth += 1,
+                (None, ']' | '}') => depth = depth.checked_sub(1)?,
+                (None, '"' | '\'') if run >= 3 => {
+                    quote = Some(if ch == '"' {
+                        Quote::MultiBasic
+                    } else {
+                        Quote::MultiLiteral
+                    });
+                    step = 3;
+                },
+                (None, '"') => quote = Some(Quote::Basic),
+                (None, '\'') => quote = Some(Quote::Literal),
+                (Some(Quote::Basic | Quote::MultiBasic), '\\') =>
+                    step += rest.chars().nth(1).map_or(0, char::len_utf8),
+                (Some(Quote::Basic), '"') | (Some(Quote::Literal), '\'') => quote = None,
+                (Some(Quote::MultiBasic), '"') | (Some(Quote::MultiLiteral), '\'') if run >= 3 => {
+                    quote = None;
+                    step = run;
+                },
+                (Some(_) | None, _) => {},
+            }
+            pos += step;
+        }
+        match quote {
+            // Only multi-line strings continue on the next line
+            Some(Quote::Basic | Quote::Literal) => return None,
+            None if depth == 0 => return Some((end, comments)),
+            Some(Quote::MultiBasic | Quote::MultiLiteral) | None => {},
+        }
+    }
+    None
+}
+
+/// The number of spaces and tabs `line` starts with.
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Builds the [`Layout`] of a TOML document line by line.
+#[derive(Debug, Default)]
+struct LayoutBuilder {
+    layout:   Layout,
+    /// The first of the comment and blank lines before the next key.
+    pending:  Option<usize>,
+    /// The comments on those lines.
+    comments: Vec<String>,
+    /// The path of the current table.
+    table:    KeyPath,
+    /// The index of the header of the current table, if it has one.
+    header:   Option<usize>,
+    /// The index of the last element of each array of tables so far.
+    arrays:   HashMap<KeyPath, usize>,
+}
+
+impl LayoutBuilder {
+    /// Add a key on `line` to the layout.
+    fn push(&mut self, kind: EntryKind, path: KeyPath, key: KeyPath, lines: (&str, usize, usize)) {
+        let (text, line, end) = lines;
+        self.layout.entries.push(Entry {
+            kind,
+            path,
+            key,
+            start: self.pending.take().unwrap_or(line),
+            line,
+            end,
+            indent: indentation(text),
+            comments: std::mem::take(&mut self.comments),
+            trailing: if line == end { comment(text) } else { None },
+            inner: 0,
+        });
+    }
+
+    /// `key` as a full path, with the index of the current element of each
+    /// array of tables in it. The last key of an `[[array]]` header gets no
+    /// index.
+    fn resolve(&self, key: &[String], is_array: bool) -> KeyPath {
+        let mut path = KeyPath::new();
+        for (position, segment) in key.iter().enumerate() {
+            path.push(segment.clone());
+            if is_array && position + 1 == key.len() {
+                break;
+            }
+            if let Some(index) = self.arrays.get(&path) {
+                path.push(index.to_string());
+            }
+        }
+        path
+    }
+
+    /// Add the `[table]` or `[[array]]` header on `line`.
+    fn header(&mut self, text: &str, line: usize) -> Option<()> {
+        let trimmed = text.trim();
+        let (is_array, rest) = match trimmed.strip_prefix("[[") {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('[')?),
+        };
+        let (key, rest) = parse_key(rest)?;
+        if !rest.starts_with(if is_array { "]]" } else { "]" }) {
+            return None;
+        }
+
+        let mut path = self.resolve(&key, is_array);
+        let kind = if is_array {
+            let index = self
+                .arrays
+                .entry(path.clone())
+                .and_modify(|index| *index += 1)
+                .or_insert(0);
+            path.push(index.to_string());
+            EntryKind::ArrayTable
+        } else {
+            EntryKind::Table
+        };
+        self.table.clone_from(&path);
+        self.header = Some(self.layout.entries.len());
+        self.push(kind, path, key, (text, line, line));
+        Some(())
+    }
+
+    /// Add the value starting on `line`, and return its last line.
+    fn value(&mut self, lines: &[&str], line: usize) -> Option<usize> {
+        let text = lines.get(line)?;
+        let (key, rest) = parse_key(text)?;
+        let value = rest.strip_prefix('=')?;
+        let (end, inner) = value_end(lines, line, text.len() - value.len())?;
+
+        let mut path = self.table.clone();
+        path.extend(key.iter().cloned());
+        self.push(EntryKind::Value, path, key, (lines.get(line)?, line, end));
+        if let Some(entry) = self.layout.entries.last_mut() {
+            entry.inner = inner;
+        }
+        if let Some(header) = self
+            .header
+            .and_then(|index| self.layout.entries.get_mut(index))
+        {
+            header.end = end;
+        }
+        Some(end)
+    }
+}
+
+/// The layout of the TOML document `text`, or `None` if it uses syntax that
+/// is not understood.
+#[must_use]
+pub fn layout(text: &str) -> Option<Layout> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut builder = LayoutBuilder::default();
+    let mut line = 0;
+    while let Some(text) = lines.get(line) {
+        let trimmed = text.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            builder.pending.get_or_insert(line);
+            builder
+                .comments
+                .extend(trimmed.strip_prefix('#').map(str::to_owned));
+        } else if trimmed.starts_with('[') {
+            builder.header(text, line)?;
+        } else {
+            line = builder.value(&lines, line)?;
+        }
+        line += 1;
+    }
+    builder.layout.tail = builder.comments;
+    Some(builder.layout)
+}
+
+/// The lines of `table` as `[path]` (or, with `is_array`, `[[path]]`) with
+/// its values, followed by its tables.
+///
+/// Like `toml` does, a table with nothing but tables gets no header.
+fn table_lines(
+    path: &[String],
+    table: &Map<String, Value>,
+    is_array: bool,
+    lines: &mut Vec<String>,
+) -> Option<()> {
+    let (tables, values): (Vec<_>, Vec<_>) = table
+        .iter()
+        .partition(|(_, value)| value.is_object() || is_array_of_tables(value));
+    if is_array || !values.is_empty() || tables.is_empty() {
+        lines.push(String::new());
+        lines.push(if is_array {
+            format!("[[{}]]", dotted(path))
+        } else {
+            format!("[{}]", dotted(path))
+        });
+        for (key, value) in values {
+            lines.push(format!("{} = {}", key_text(key), inline(value)?));
+        }
+    }
+    for (key, value) in tables {
+        let mut child = path.to_vec();
+        child.push(key.clone());
+        match value {
+            Value::Object(table) => table_lines(&child, table, false, lines)?,
+            Value::Array(elements) =>
+                for element in elements {
+                    table_lines(&child, element.as_object()?, true, lines)?;
+                },
+            Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => return None,
+        }
+    }
+    Some(())
+}
+
+/// Rewrites the lines of a TOML document that changed.
+#[derive(Debug)]
+struct Editor<'a> {
+    layout:    &'a Layout,
+    lines:     &'a [&'a str],
+    old:       &'a Map<String, Value>,
+    new:       &'a Map<String, Value>,
+    renames:   Vec<(KeyPath, String)>,
+    /// The path of each entry after renames, and whether it is kept.
+    paths:     Vec<(KeyPath, bool)>,
+    /// The (old) paths of the arrays of tables that are written anew.
+    rewritten: Vec<KeyPath>,
+    edits:     Edits,
+}
+
+impl<'a> Editor<'a> {
+    /// The key of `entry` on `line`, with `renamed` as the new name of its
+    /// key at `renamed_at`, and the rest of the line after the key.
+    fn renamed_key(entry: &Entry, line: &'a str, path: &[String], at: usize) -> (String, &'a str) {
+        let rest = parse_key(line).map_or("", |(_, rest)| rest);
+        let mut key = entry.key.clone();
+        let offset = path.len().saturating_sub(key.len());
+        if let (Some(segment), Some(renamed)) = (
+            at.checked_sub(offset).and_then(|at| key.get_mut(at)),
+            path.get(at),
+        ) {
+            segment.clone_from(renamed);
+        }
+        (dotted(&key), rest)
+    }
+
+    /// Whether the entry at `path` is in an array of tables that is written
+    /// anew.
+    fn in_rewritten(&self, path: &[String]) -> bool {
+        self.rewritten.iter().any(|array| path.starts_with(array))
+    }
+
+    /// Keep, rewrite or delete the value `entry`, whose path is now `path`.
+    fn update_value(
+        &mut self,
+        entry: &Entry,
+        path: &[String],
+        renamed_at: Option<usize>,
+    ) -> Option<bool> {
+        if self.in_rewritten(&entry.path) {
+            self.edits.delete(entry.start..=entry.end);
+            return Some(false);
+        }
+        let line = self.lines.get(entry.line)?;
+        let indent = line.get(..entry.indent)?;
+        let own_rename = renamed_at.filter(|at| *at + entry.key.len() >= path.len());
+        let value = match layout::lookup(self.new, path) {
+            None => {
+                self.edits.delete(entry.start..=entry.end);
+                return Some(false);
+            },
+            Some(value) if Some(value) != layout::lookup(self.old, &entry.path) => value,
+            Some(_) => {
+                if let Some(at) = own_rename {
+                    let (key, rest) = Self::renamed_key(entry, line, path, at);
+                    self.edits
+                        .replace(entry.line, [indent, &key, " ", rest].concat());
+                }
+                return Some(true);
+            },
+        };
+
+        let key = if let Some(at) = own_rename {
+            Self::renamed_key(entry, line, path, at).0
+        } else {
+            let rest = parse_key(line)?.1;
+            line.get(entry.indent..line.len() - rest.len())?
+                .trim_end()
+                .to_owned()
+        };
+        let mut text = format!("{}{} = {}", indent, key, inline(value)?);
+        if let Some(comment) = &entry.trailing {
+            text.push_str(" #");
+            text.push_str(comment);
+        }
+        self.edits.replace(entry.line, text);
+        self.edits.delete(entry.line + 1..=entry.end);
+        Some(true)
+    }
+
+    /// Rewrite the header of `entry` with the new name of its key at `at`.
+    fn rename_header(&mut self, entry: &Entry, path: &[String], at: usize) -> Option<()> {
+        let line = self.lines.get(entry.line)?;
+        let (open, close) = match entry.kind {
+            EntryKind::ArrayTable => ("[[", "]]"),
+            EntryKind::Table | EntryKind::Value | EntryKind::Mapping => ("[", "]"),
+        };
+        let mut key = entry.key.clone();
+        key.get_mut(at)?.clone_from(path.get(at)?);
+        let mut text = [line.get(..entry.indent)?, open, &dotted(&key), close].concat();
+        if let Some(comment) = &entry.trailing {
+            text.push_str(" #");
+            text.push_str(comment);
+        }
+        self.edits.replace(entry.line, text);
+        Some(())
+    }
+
+    /// Keep, rename or delete the `[table]` header `entry`.
+    fn update_table(
+        &mut self,
+        entry: &Entry,
+        path: &[String],
+        renamed_at: Option<usize>,
+    ) -> Option<bool> {
+        if let Some(Value::Object(_)) = layout::lookup(self.new, path) {
+            if let Some(at) = renamed_at {
+                self.rename_header(entry, path, at)?;
+            }
+            Some(true)
+        } else {
+            self.edits.delete(entry.start..=entry.line);
+            Some(false)
+        }
+    }
+
+    /// Keep or rename the `[[array]]` header `entry`, or write the whole
+    /// array anew if it changed.
+    fn update_array_table(&mut self, entry: &Entry) -> Option<bool> {
+        let array = entry.path.get(..entry.path.len() - 1)?;
+        let (new_array, renamed_at) = layout::renamed(array, &self.renames);
+        let value = layout::lookup(self.new, &new_array);
+        if value == layout::lookup(self.old, array) {
+            if let Some(at) = renamed_at {
+                self.rename_header(entry, &new_array, at)?;
+            }
+            return Some(true);
+        }
+
+        self.edits.delete(entry.start..=entry.line);
+        if !self.in_rewritten(array) {
+            self.rewritten.push(array.to_vec());
+            if let Some(Value::Array(elements)) = value.filter(|value| is_array_of_tables(value)) {
+                let mut lines = Vec::new();
+                for element in elements {
+                    table_lines(&entry.key, element.as_object()?, true, &mut lines)?;
+                }
+                for line in lines {
+                    self.edits.insert(entry.start, line);
+                }
+            }
+        }
+        Some(false)
+    }
+
+    /// Keep, rewrite or delete each entry of the document.
+    fn update_entries(&mut self) -> Option<()> {
+        let layout = self.layout;
+        for entry in &layout.entries {
+            let (path, renamed_at) = layout::renamed(&entry.path, &self.renames);
+            let kept = match entry.kind {
+                EntryKind::Value => self.update_value(entry, &path, renamed_at)?,
+                EntryKind::Table => self.update_table(entry, &path, renamed_at)?,
+                EntryKind::ArrayTable => self.update_array_table(entry)?,
+                EntryKind::Mapping => return None,
+            };
+            self.paths.push((path, kept));
+        }
+        Some(())
+    }
+
+    /// The kept entries, with their new paths.
+    fn kept(&self) -> impl Iterator<Item = (&'a Entry, &KeyPath)> + '_ {
+        self.layout
+            .entries
+            .iter()
+            .zip(&self.paths)
+            .filter(|(_, (_, kept))| *kept)
+            .map(|(entry, (path, _))| (entry, path))
+    }
+
+    /// Whether `path` is written by a kept value or array of tables.
+    fn covers(&self, path: &[String]) -> bool {
+        let rewritten = self.rewritten.iter().any(|array| {
+            path.starts_with(array)
+                && layout::lookup(self.new, array).map_or(false, is_array_of_tables)
+        });
+        rewritten
+            || self.kept().any(|(entry, entry_path)| match entry.kind {
+                EntryKind::Value => path.starts_with(entry_path),
+                EntryKind::ArrayTable => path.starts_with(&entry_path[..entry_path.len() - 1]),
+                EntryKind::Table | EntryKind::Mapping => false,
+            })
+    }
+
+    /// Whether kept entries are below `path`.
+    fn has_entries_below(&self, path: &[String]) -> bool {
+        self.kept()
+            .any(|(_, entry_path)| entry_path.starts_with(path))
+    }
+
+    /// The line after the last entry below `path`, or below its closest
+    /// ancestor with entries.
+    fn family_end(&self, path: &[String]) -> usize {
+        let end = self
+            .layout
+            .entries
+            .iter()
+            .zip(&self.paths)
+            .filter(|(_, (entry_path, _))| entry_path.starts_with(path))
+            .map(|(entry, _)| entry.end + 1)
+            .max();
+        match (end, path.split_last()) {
+            (Some(end), _) => end,
+            (None, Some((_, parent))) => self.family_end(parent),
+            (None, None) => self.lines.len(),
+        }
+    }
+
+    /// The line to add top-level values before.
+    fn root_end(&self) -> usize {
+        let entries = &self.layout.entries;
+        let first_header = entries
+            .iter()
+            .position(|entry| entry.kind != EntryKind::Value)
+            .unwrap_or(entries.len());
+        match (
+            entries.iter().take(first_header).next_back(),
+            entries.get(first_header),
+        ) {
+            (Some(last), _) => last.end + 1,
+            (None, Some(header)) => header.start,
+            (None, None) => self.lines.len(),
+        }
+    }
+
+    /// Add `values` to the table at `path`.
+    fn insert_values(&mut self, path: &[String], values: &[(&String, &Value)]) -> Option<()> {
+        let header = self
+            .kept()
+            .find(|(entry, entry_path)| {
+                entry.kind == EntryKind::Table && entry_path.as_slice() == path
+            })
+            .map(|(entry, _)| entry);
+        // A table defined by dotted keys gets more dotted keys
+        let dotted_in = self
+            .kept()
+            .filter(|(entry, entry_path)| {
+                entry.kind == EntryKind::Value
+                    && entry_path.len() > path.len()
+                    && entry_path.starts_with(path)
+                    && entry_path.len() - entry.key.len() < path.len()
+            })
+            .map(|(entry, entry_path)| (entry, entry_path.len() - entry.key.len()))
+            .last();
+
+        let (position, prefix, mut lines) = match (path.is_empty(), header, dotted_in) {
+            (true, _, _) => (self.root_end(), &path[..0], Vec::new()),
+            (false, Some(header), _) => (header.end + 1, path, Vec::new()),
+            (false, None, Some((entry, table_len))) =>
+                (entry.end + 1, path.get(..table_len)?, Vec::new()),
+            (false, None, None) => (self.family_end(path), path, vec![
+                String::new(),
+                format!("[{}]", dotted(path)),
+            ]),
+        };
+        for (key, value) in values {
+            let mut key_path = path.get(prefix.len()..)?.to_vec();
+            key_path.push((*key).clone());
+            lines.push(format!("{} = {}", dotted(&key_path), inline(value)?));
+        }
+        for line in lines {
+            self.edits.insert(position, line);
+        }
+        Some(())
+    }
+
+    /// Add the keys of `table`, at `path`, that the document does not have.
+    fn add_new(&mut self, path: &[String], table: &Map<String, Value>) -> Option<()> {
+        let mut values = Vec::new();
+        for (key, value) in table {
+            let mut child = path.to_vec();
+            child.push(key.clone());
+            if self.covers(&child) {
+                continue;
+            }
+            if self.has_entries_below(&child) {
+                self.add_new(&child, value.as_object()?)?;
+            } else if value.is_object() || is_array_of_tables(value) {
+                let mut lines = Vec::new();
+                match value {
+                    Value::Object(table) => table_lines(&child, table, false, &mut lines)?,
+                    Value::Array(elements) =>
+                        for element in elements {
+                            table_lines(&child, element.as_object()?, true, &mut lines)?;
+                        },
+                    Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) =>
+                        return None,
+                }
+                let position = self.family_end(path);
+                for line in lines {
+                    self.edits.insert(position, line);
+                }
+            } else {
+                values.push((key, value));
+            }
+        }
+        if !values.is_empty() {
+            self.insert_values(path, &values)?;
+        }
+        Some(())
+    }
+}
+
+/// `new` as a TOML document that keeps the comments, key order and
+/// formatting of `text`, from which `old` was read: only the lines of values
+/// that changed are rewritten, and new keys are added after the last key of
+/// their table.
+///
+/// Returns `None` if `text` cannot be edited in place, e.g. because it uses
+/// syntax that is not understood.
+#[must_use]
+pub fn update(text: &str, old: &Map<String, Value>, new: &Map<String, Value>) -> Option<String> {
+    let layout = layout(text)?;
+    let lines: Vec<&str> = text.lines().collect();
+    let mut editor = Editor {
+        layout: &layout,
+        lines: &lines,
+        old,
+        new,
+        renames: layout::renames(old, new),
+        paths: Vec::new(),
+        rewritten: Vec::new(),
+        edits: Edits::default(),
+    };
+    editor.update_entries()?;
+    editor.add_new(&[], new)?;
+
+    let updated = editor
+        .edits
+        .apply(&lines)
+        .trim_start_matches('\n')
+        .to_owned();
+    // Fall back to rewriting the document if the edits went wrong
+    let reread: Map<String, Value> = deserialize(&updated).ok()?;
+    (reread == *new).then_some(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn edit(text: &str, change: impl FnOnce(&mut Map<String, Value>)) -> String {
+        let old: Map<String, Value> = deserialize(text).unwrap();
+        let mut new = old.clone();
+        change(&mut new);
+        update(text, &old, &new).expect("document should be editable in place")
+    }
+
+    fn hoards(root: &mut Map<String, Value>) -> &mut Map<String, Value> {
+        root.get_mut("hoards").unwrap().as_object_mut().unwrap()
+    }
+
+    const CONFIG: &str = r#"# My configuration
+exclusivity = [["laptop", "desktop"]] # laptop first
+
+[envs.linux]
+os = ["linux"]
+
+# Editors
+[hoards.vim]
+linux = "~/.vimrc"   # the classic
+
+[hoards.zsh.rc]
+linux = "~/.zshrc"
+"#;
+
+    #[test]
+    fn unchanged_lines_are_kept() {
+        let text = edit(CONFIG, |root| {
+            hoards(root).insert("nvim".into(), json!({ "linux": "~/.config/nvim" }));
+            hoards(root)["zsh"]
+                .as_object_mut()
+                .unwrap()
+                .insert("env".into(), json!({ "linux": "~/.zshenv" }));
+            root.insert("hoards_root".into(), json!("/backups"));
+        });
+        assert_eq!(
+            text,
+            r#"# My configuration
+exclusivity = [["laptop", "desktop"]] # laptop first
+hoards_root = "/backups"
+
+[envs.linux]
+os = ["linux"]
+
+# Editors
+[hoards.vim]
+linux = "~/.vimrc"   # the classic
+
+[hoards.zsh.rc]
+linux = "~/.zshrc"
+
+[hoards.zsh.env]
+linux = "~/.zshenv"
+
+[hoards.nvim]
+linux = "~/.config/nvim"
+"#
+        );
+    }
+
+    #[test]
+    fn changes_rewrite_only_their_lines() {
+        let text = edit(CONFIG, |root| {
+            hoards(root)["vim"]["linux"] = json!("~/.vim/vimrc");
+            let zsh = hoards(root).remove("zsh").unwrap();
+            hoards(root).insert("shell".into(), zsh);
+            root.remove("exclusivity");
+        });
+        assert_eq!(
+            text,
+            r#"[envs.linux]
+os = ["linux"]
+
+# Editors
+[hoards.vim]
+linux = "~/.vim/vimrc" # the classic
+
+[hoards.shell.rc]
+linux = "~/.zshrc"
+"#
+        );
+    }
+
+    #[test]
+    fn layout_finds_keys_and_comments() {
+        let config = layout(CONFIG).unwrap();
+        let paths: Vec<String> = config
+            .entries
+            .iter()
+            .map(|entry| entry.path.join("."))
+            .collect();
+        assert_eq!(paths, [
+            "exclusivity",
+            "envs.linux",
+            "envs.linux.os",
+            "hoards.vim",
+            "hoards.vim.linux",
+            "hoards.zsh.rc",
+            "hoards.zsh.rc.linux",
+        ]);
+        let vim = config.find(&["hoards".into(), "vim".into()]).unwrap();
+        assert_eq!(vim.comments, [" Editors"]);
+        assert_eq!((vim.start, vim.line, vim.end), (5, 7, 8));
+        let arrays = layout("[[a]]\nb = 1\n[[a]]\nb = [\n  2, # two\n]\n").unwrap();
+        let last = arrays.entries.last().unwrap();
+        assert_eq!(last.path, ["a", "1", "b"]);
+        assert_eq!((last.line, last.end, last.inner), (3, 5, 1));
+    }
+
+    #[test]
+    fn values_end_where_their_brackets_and_strings_do() {
+        let text = r#"a = [
+  "]", # a bracket
+  { b = '[' },
+]
+c = """
+# not a comment ]
+""""
+d = '''
+[not.a.table]'''
+e = "\"]" # done
+"#;
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(value_end(&lines, 0, 3), Some((3, 1)));
+        assert_eq!(value_end(&lines, 4, 3), Some((6, 0)));
+        assert_eq!(value_end(&lines, 7, 3), Some((8, 0)));
+        assert_eq!(value_end(&lines, 9, 3), Some((9, 0)));
+        assert_eq!(value_end(&["a = [1,", "2"], 0, 3), None);
+        assert_eq!(value_end(&["a = \"open"], 0, 3), None);
+
+        let config = layout(text).unwrap();
+        let ends: Vec<(usize, usize)> = config
+            .entries
+            .iter()
+            .map(|entry| (entry.line, entry.end))
+            .collect();
+        assert_eq!(ends, [(0, 3), (4, 6), (7, 8), (9, 9)]);
+    }
+}
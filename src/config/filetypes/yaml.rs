@@ -1,5 +1,9 @@
-//! Convert type to YAML
-use super::Error;
+//! Convert type to YAML, and edit YAML documents in place.
+use super::{
+    layout::{self, Edits, Entry, EntryKind, KeyPath, Layout},
+    Error,
+};
+use serde_json::{Map, Value};
 
 pub use serde_yaml::Value as SValue;
 
@@ -28,3 +32,370 @@ where
 {
     serde_yaml::from_str(s).map_err(|e| Error::Deserialization(e.to_string()))
 }
+
+/// `value` as YAML, without the start of the document.
+fn to_yaml(value: &Value) -> Option<String> {
+    let text = serde_yaml::to_string(value).ok()?;
+    Some(
+        text.strip_prefix("---\n")
+            .unwrap_or(&text)
+            .trim_end()
+            .to_owned(),
+    )
+}
+
+/// `key` as it is written in a YAML document.
+fn key_text(key: &str) -> Option<String> {
+    to_yaml(&Value::String(key.to_owned()))
+}
+
+/// The lines of `key: value`, indented by `indent`.
+fn entry_lines(
+    indent: usize,
+    key: &str,
+    value: &Value,
+    trailing: Option<&String>,
+) -> Option<String> {
+    let pad = " ".repeat(indent);
+    let rendered = to_yaml(value)?;
+    let is_block = match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => false,
+    };
+    if is_block {
+        let mut lines = vec![format!("{}{}:", pad, key)];
+        lines.extend(rendered.lines().map(|line| format!("{}  {}", pad, line)));
+        Some(lines.join("\n"))
+    } else {
+        let mut line = format!("{}{}: {}", pad, key, rendered);
+        if let Some(comment) = trailing {
+            line.push_str(" #");
+            line.push_str(comment);
+        }
+        Some(line)
+    }
+}
+
+/// The byte offset of the `#` that starts the comment on `line`, if any.
+fn comment_start(line: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut previous = ' ';
+    for (at, ch) in line.char_indices() {
+        match (quote, ch) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                previous = ch;
+                continue;
+            },
+            (Some(open), close) if close == open && !escaped => quote = None,
+            (None, '"' | '\'') if previous.is_whitespace() || "[{,:".contains(previous) =>
+                quote = Some(ch),
+            (None, '#') if previous.is_whitespace() => return Some(at),
+            (Some(_) | None, _) => {},
+        }
+        escaped = false;
+        previous = ch;
+    }
+    None
+}
+
+/// The number of spaces `line` starts with.
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Whether `line` has nothing but a comment, or nothing at all.
+fn is_trivia(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// The mapping key at the start of `text`, and the rest of `text` after its
+/// colon.
+fn parse_key(text: &str) -> Option<(String, &str)> {
+    let (key, rest) = match text.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let mut escaped = false;
+            let end = text.char_indices().skip(1).find_map(|(at, ch)| {
+                let closes = ch == quote && !escaped;
+                escaped = quote == '"' && ch == '\\' && !escaped;
+                closes.then_some(at)
+            })?;
+            let key: String = serde_yaml::from_str(text.get(..=end)?).ok()?;
+            (key, text.get(end + 1..)?.trim_start().strip_prefix(':')?)
+        },
+        '-' | '?' | '[' | '{' | '&' | '*' | '!' | '|' | '>' | '%' | '@' | '`' | '#' => return None,
+        _ => {
+            let colon = text.char_indices().find_map(|(at, ch)| {
+                let next = text.get(at + 1..)?.chars().next();
+                (ch == ':' && next.map_or(true, char::is_whitespace)).then_some(at)
+            })?;
+            (
+                text.get(..colon)?.trim_end().to_owned(),
+                text.get(colon + 1..)?,
+            )
+        },
+    };
+    if rest.starts_with(|ch: char| !ch.is_whitespace()) {
+        return None;
+    }
+    Some((key, rest))
+}
+
+/// The last line of the value of the key on line `line`, indented by
+/// `indent`. A key with nothing after its colon can have a sequence at its
+/// own indentation.
+fn value_end(lines: &[&str], line: usize, indent: usize, is_empty: bool) -> usize {
+    let mut end = line;
+    let mut is_sequence = false;
+    for (index, text) in lines.iter().enumerate().skip(line + 1) {
+        if is_trivia(text) {
+            continue;
+        }
+        let trimmed = text.trim();
+        let is_item = trimmed == "-" || trimmed.starts_with("- ");
+        let line_indent = indentation(text);
+        if line_indent > indent {
+            end = index;
+        } else if is_empty && line_indent == indent && is_item && (end == line || is_sequence) {
+            is_sequence = true;
+            end = index;
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+/// The layout of the YAML document `text`, or `None` if it uses syntax that
+/// is not understood.
+#[must_use]
+pub fn layout(text: &str) -> Option<Layout> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut layout = Layout::default();
+    let mut pending = None;
+    let mut comments = Vec::new();
+    // The indentation and path of the mappings the current line is in
+    let mut mappings: Vec<(usize, KeyPath)> = Vec::new();
+    let mut line = 0;
+    while let Some(text) = lines.get(line) {
+        let trimmed = text.trim();
+        if is_trivia(text) || (trimmed == "---" && layout.entries.is_empty()) {
+            pending.get_or_insert(line);
+            comments.extend(trimmed.strip_prefix('#').map(str::to_owned));
+            line += 1;
+            continue;
+        }
+        let indent = indentation(text);
+        let (key, rest) = parse_key(text.get(indent..)?)?;
+        while mappings.last().map_or(false, |(outer, _)| *outer >= indent) {
+            mappings.pop();
+        }
+        let mut path = mappings
+            .last()
+            .map(|(_, path)| path.clone())
+            .unwrap_or_default();
+        path.push(key.clone());
+
+        let value = comment_start(rest).map_or(rest, |at| rest.get(..at).unwrap_or(rest));
+        let end = value_end(&lines, line, indent, value.trim().is_empty());
+        let is_mapping = value.trim().is_empty()
+            && lines
+                .get(line + 1..=end)?
+                .iter()
+                .find(|text| !is_trivia(text))
+                .map_or(false, |child| !child.trim_start().starts_with('-'));
+        layout.entries.push(Entry {
+            kind: if is_mapping {
+                EntryKind::Mapping
+            } else {
+                EntryKind::Value
+            },
+            path: path.clone(),
+            key: vec![key],
+            start: pending.take().unwrap_or(line),
+            line,
+            end,
+            indent,
+            comments: std::mem::take(&mut comments),
+            trailing: comment_start(rest).and_then(|at| rest.get(at + 1..).map(str::to_owned)),
+            inner: if is_mapping {
+                0
+            } else {
+                lines
+                    .get(line + 1..=end)?
+                    .iter()
+                    .filter(|text| comment_start(text).is_some())
+                    .count()
+            },
+        });
+        if is_mapping {
+            mappings.push((indent, path));
+            line += 1;
+        } else {
+            line = end + 1;
+        }
+    }
+    layout.tail = comments;
+    Some(layout)
+}
+
+/// Add the keys of `table`, at `path`, that the document does not have.
+fn add_new(
+    path: &[String],
+    table: &Map<String, Value>,
+    layout: &Layout,
+    kept: &[(KeyPath, &Entry)],
+    edits: &mut Edits,
+) -> Option<()> {
+    let children = || {
+        layout
+            .entries
+            .iter()
+            .filter(|entry| entry.path.len() == path.len() + 1 && entry.path.starts_with(path))
+    };
+    let (position, indent) = match kept.iter().find(|(kept, _)| kept.as_slice() == path) {
+        _ if path.is_empty() => (children().next_back().map_or(0, |entry| entry.end + 1), 0),
+        Some((_, entry)) => (
+            entry.end + 1,
+            children()
+                .next()
+                .map_or(entry.indent + 2, |child| child.indent),
+        ),
+        None => return None,
+    };
+    for (key, value) in table {
+        let mut child = path.to_vec();
+        child.push(key.clone());
+        match kept.iter().find(|(kept, _)| *kept == child) {
+            Some((_, entry)) if entry.kind == EntryKind::Mapping =>
+                add_new(&child, value.as_object()?, layout, kept, edits)?,
+            Some(_) => {},
+            None => edits.insert(position, entry_lines(indent, &key_text(key)?, value, None)?),
+        }
+    }
+    Some(())
+}
+
+/// `new` as a YAML document that keeps the comments, key order and
+/// formatting of `text`, from which `old` was read: only the lines of values
+/// that changed are rewritten, and new keys are added after the last key of
+/// their mapping.
+///
+/// Returns `None` if `text` cannot be edited in place, e.g. because it uses
+/// syntax that is not understood.
+#[must_use]
+pub fn update(text: &str, old: &Map<String, Value>, new: &Map<String, Value>) -> Option<String> {
+    let layout = layout(text)?;
+    if layout.entries.is_empty() {
+        return None;
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let renames = layout::renames(old, new);
+    let mut edits = Edits::default();
+    let mut kept = Vec::new();
+    let mut skipped = None;
+    for entry in &layout.entries {
+        if skipped.map_or(false, |end| entry.line <= end) {
+            continue;
+        }
+        let (path, renamed_at) = layout::renamed(&entry.path, &renames);
+        let own_rename = renamed_at == Some(path.len() - 1);
+        let key = if let Some(renamed) = path.last().filter(|_| own_rename) {
+            key_text(renamed)?
+        } else {
+            let line = lines.get(entry.line)?.get(entry.indent..)?;
+            let rest = parse_key(line)?.1;
+            line.get(..line.len() - rest.len() - 1)?.to_owned()
+        };
+        match (entry.kind, layout::lookup(new, &path)) {
+            (_, None) => {
+                edits.delete(entry.start..=entry.end);
+                skipped = Some(entry.end);
+            },
+            (EntryKind::Mapping, Some(Value::Object(_))) => kept.push((path, entry)),
+            (EntryKind::Value, Some(value)) if layout::lookup(old, &entry.path) == Some(value) =>
+                kept.push((path, entry)),
+            (
+                EntryKind::Value | EntryKind::Mapping | EntryKind::Table | EntryKind::ArrayTable,
+                Some(value),
+            ) => {
+                let trailing = entry.trailing.as_ref().filter(|_| entry.line == entry.end);
+                edits.replace(
+                    entry.line,
+                    entry_lines(entry.indent, &key, value, trailing)?,
+                );
+                edits.delete(entry.line + 1..=entry.end);
+                skipped = Some(entry.end);
+                kept.push((path, entry));
+                continue;
+            },
+        }
+        if own_rename {
+            let line = lines.get(entry.line)?;
+            let rest = parse_key(line.get(entry.indent..)?)?.1;
+            edits.replace(
+                entry.line,
+                [line.get(..entry.indent)?, &key, ":", rest].concat(),
+            );
+        }
+    }
+    add_new(&[], new, &layout, &kept, &mut edits)?;
+
+    let updated = edits.apply(&lines);
+    // Fall back to rewriting the document if the edits went wrong
+    let reread: Map<String, Value> = deserialize(&updated).ok()?;
+    (reread == *new).then_some(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CONFIG: &str = "\
+# My configuration
+exclusivity:
+  - - laptop
+    - desktop
+hoards:
+  # Editors
+  vim:
+    linux: ~/.vimrc  # the classic
+  zsh:
+    linux: ~/.zshrc
+";
+
+    #[test]
+    fn edits_keep_comments_and_order() {
+        let old: Map<String, Value> = deserialize(CONFIG).unwrap();
+        let mut new = old.clone();
+        let hoards = new["hoards"].as_object_mut().unwrap();
+        hoards["vim"]["linux"] = json!("~/.vim/vimrc");
+        let zsh = hoards.remove("zsh").unwrap();
+        hoards.insert("shell".into(), zsh);
+        hoards.insert("git".into(), json!({ "linux": "~/.gitconfig" }));
+        new.insert("hoards_root".into(), json!("/backups"));
+
+        assert_eq!(
+            update(CONFIG, &old, &new).unwrap(),
+            "\
+# My configuration
+exclusivity:
+  - - laptop
+    - desktop
+hoards:
+  # Editors
+  vim:
+    linux: ~/.vim/vimrc # the classic
+  shell:
+    linux: ~/.zshrc
+  git:
+    linux: ~/.gitconfig
+hoards_root: /backups
+"
+        );
+    }
+}
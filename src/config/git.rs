@@ -0,0 +1,337 @@
+//! Optional git integration for the hoards root.
+//!
+//! With a `git` section in the configuration file, `hoard` commits the changes
+//! of every successful backup to a git repository in the hoards root, and can
+//! refuse to restore from a working tree with uncommitted changes. Commits are
+//! only pushed if `push` is enabled, so a purely local repository works too.
+//!
+//! ```toml
+//! [git]
+//!     commit = true
+//!     require_clean = true
+//!     push = true
+//!     remote = "origin"
+//! ```
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::Path,
+    process::{Command, Stdio},
+};
+use thiserror::Error;
+
+/// Errors that can occur while using git in the hoards root.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// `git` could not be run.
+    #[error("failed to run git: {0}")]
+    IO(#[from] io::Error),
+    /// A git command failed.
+    #[error("`git {command}` failed: {stderr}")]
+    CommandFailed {
+        /// The arguments git was run with.
+        command: String,
+        /// What git printed to stderr.
+        stderr:  String,
+    },
+    /// A passed-through git command exited unsuccessfully.
+    #[error("git exited with status {0}")]
+    Exited(i32),
+    /// The working tree has uncommitted changes to the hoards being restored.
+    #[error(
+        "the hoards root has uncommitted changes, commit or discard them first:\n{}",
+        .0.join("\n")
+    )]
+    DirtyTree(Vec<String>),
+}
+
+/// The `git` section of the configuration file.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case", default, deny_unknown_fields)]
+pub struct GitConfig {
+    /// Commit the changes of every successful backup.
+    pub commit:        bool,
+    /// Refuse to restore hoards with uncommitted changes.
+    pub require_clean: bool,
+    /// Push after committing.
+    pub push:          bool,
+    /// The remote to push to. Uses git's default if not set, or `origin` if
+    /// `branch` is set.
+    pub remote:        Option<String>,
+    /// The branch to push. Uses git's default if not set.
+    pub branch:        Option<String>,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            commit:        true,
+            require_clean: false,
+            push:          false,
+            remote:        None,
+            branch:        None,
+        }
+    }
+}
+
+/// Run git in `root` and return its stdout.
+fn git(root: &Path, args: &[&str]) -> Result<String, Error> {
+    let command = args.join(" ");
+    tracing::debug!(%command, "running git");
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(Error::CommandFailed {
+            command,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        })
+    }
+}
+
+/// Create a repository in `root` unless it already is in one.
+fn ensure_repository(root: &Path) -> Result<(), Error> {
+    if git(root, &["rev-parse", "--git-dir"]).is_err() {
+        tracing::info!(root = %root.display(), "initializing git repository in hoards root");
+        std::fs::create_dir_all(root)?;
+        git(root, &["init", "--quiet"])?;
+    }
+    Ok(())
+}
+
+/// The `--` separated pathspecs of `hoards`.
+fn pathspecs<'a>(args: &[&'a str], hoards: &[&'a str]) -> Vec<&'a str> {
+    let mut all = args.to_vec();
+    all.push("--");
+    all.extend(hoards);
+    all
+}
+
+/// The commit message with `summary` as its first line, listing the `changes`
+/// printed by `git diff --name-status`.
+fn commit_message(summary: &str, changes: &str) -> String {
+    let mut message = [summary, "\n\n"].concat();
+    for line in changes.lines() {
+        let mut fields = line.split('\t');
+        let status = match fields.next() {
+            Some(status) if status.starts_with('A') => "added",
+            Some(status) if status.starts_with('D') => "deleted",
+            Some(status) if status.starts_with('R') => "renamed",
+            Some(_) => "modified",
+            None => continue,
+        };
+        let paths = fields.collect::<Vec<_>>().join(" -> ");
+        message.extend([status, ": ", &paths, "\n"]);
+    }
+    message
+}
+
+/// Whether git knows about any files in `path`.
+fn is_tracked(root: &Path, path: &str) -> bool {
+    git(root, &["ls-files", "--", path]).map_or(false, |files| !files.trim().is_empty())
+}
+
+/// Commit all changes to `paths` (relative to `root`) with `summary` as the
+/// first line of the message, and push them if configured.
+///
+/// Does nothing if there are no changes.
+///
+/// # Errors
+///
+/// Any [`Error`] from running git.
+pub(crate) fn commit_changes(
+    root: &Path,
+    config: &GitConfig,
+    paths: &[&str],
+    summary: &str,
+) -> Result<(), Error> {
+    if !config.commit {
+        return Ok(());
+    }
+    let _span = tracing::info_span!("git_commit").entered();
+    ensure_repository(root)?;
+
+    // Pathspecs that match nothing make git fail
+    let paths = paths
+        .iter()
+        .copied()
+        .filter(|path| root.join(path).exists() || is_tracked(root, path))
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let paths = paths.as_slice();
+
+    git(root, &pathspecs(&["add", "--all"], paths))?;
+    let changes = git(
+        root,
+        &pathspecs(&["diff", "--cached", "--name-status"], paths),
+    )?;
+    if changes.trim().is_empty() {
+        tracing::info!("no changes to commit");
+        return Ok(());
+    }
+
+    let message = commit_message(summary, &changes);
+    git(
+        root,
+        &pathspecs(&["commit", "--quiet", "--message", &message], paths),
+    )?;
+    tracing::info!("committed changes");
+
+    if config.push {
+        git(root, &push_args(config))?;
+        tracing::info!("pushed changes");
+    }
+    Ok(())
+}
+
+/// The arguments to push with. git takes the branch only after the remote, so
+/// a branch without a remote is pushed to `origin`.
+fn push_args(config: &GitConfig) -> Vec<&str> {
+    let mut args = vec!["push", "--quiet"];
+    match (config.remote.as_deref(), config.branch.as_deref()) {
+        (remote, Some(branch)) => args.extend(&[remote.unwrap_or("origin"), branch]),
+        (remote, None) => args.extend(remote),
+    }
+    args
+}
+
+/// Commit the changes to `hoards` after a backup and push them if configured.
+///
+/// # Errors
+///
+/// Any [`Error`] from running git.
+pub(crate) fn commit_backup(root: &Path, config: &GitConfig, hoards: &[&str]) -> Result<(), Error> {
+    let mut sorted = hoards.to_vec();
+    sorted.sort_unstable();
+    commit_changes(
+        root,
+        config,
+        hoards,
+        &["Back up ", &sorted.join(", ")].concat(),
+    )
+}
+
+/// Fail if `hoards` have uncommitted changes and a clean tree is required.
+///
+/// # Errors
+///
+/// [`Error::DirtyTree`] with the changed files, or any [`Error`] from running
+/// git.
+pub(crate) fn check_clean(root: &Path, config: &GitConfig, hoards: &[&str]) -> Result<(), Error> {
+    if !config.require_clean || git(root, &["rev-parse", "--git-dir"]).is_err() {
+        return Ok(());
+    }
+
+    let status = git(root, &pathspecs(&["status", "--porcelain"], hoards))?;
+    let dirty = status.lines().map(str::to_owned).collect::<Vec<_>>();
+    if dirty.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::DirtyTree(dirty))
+    }
+}
+
+/// Run git with `args` in `root`, attached to the terminal.
+///
+/// # Errors
+///
+/// [`Error::Exited`] with git's exit code if it fails, or [`Error::IO`] if it
+/// cannot be run.
+pub(crate) fn passthrough(root: &Path, args: &[String]) -> Result<(), Error> {
+    tracing::debug!(?args, "running git in hoards root");
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Exited(status.code().unwrap_or(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn commit_message_lists_hoards_and_files() {
+        let message = commit_message(
+            "Back up nvim, zsh",
+            "A\tnvim/init.vim\nM\tzsh/.zshrc\nD\tzsh/.zprofile\n",
+        );
+        assert_eq!(
+            message,
+            "Back up nvim, zsh\n\nadded: nvim/init.vim\nmodified: zsh/.zshrc\ndeleted: \
+             zsh/.zprofile\n"
+        );
+    }
+
+    #[test]
+    fn branches_are_pushed_to_a_remote() {
+        let config = |remote: Option<&str>, branch: Option<&str>| GitConfig {
+            push: true,
+            remote: remote.map(str::to_owned),
+            branch: branch.map(str::to_owned),
+            ..GitConfig::default()
+        };
+        assert_eq!(push_args(&config(None, None)), ["push", "--quiet"]);
+        assert_eq!(push_args(&config(Some("backup"), None)), [
+            "push", "--quiet", "backup"
+        ]);
+        assert_eq!(push_args(&config(None, Some("main"))), [
+            "push", "--quiet", "origin", "main"
+        ]);
+        assert_eq!(push_args(&config(Some("backup"), Some("main"))), [
+            "push", "--quiet", "backup", "main"
+        ]);
+    }
+
+    #[test]
+    fn commits_backups_and_checks_for_a_clean_tree() {
+        if git(Path::new("."), &["--version"]).is_err() {
+            return;
+        }
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let root = dir.path();
+        let config = GitConfig {
+            require_clean: true,
+            ..GitConfig::default()
+        };
+        fs::create_dir_all(root.join("nvim")).unwrap();
+        fs::write(root.join("nvim").join("init.vim"), "set nu").unwrap();
+
+        ensure_repository(root).unwrap();
+        git(root, &["config", "user.name", "hoard"]).unwrap();
+        git(root, &["config", "user.email", "hoard@localhost"]).unwrap();
+        commit_backup(root, &config, &["nvim"]).unwrap();
+        let log = git(root, &["log", "--format=%B"]).unwrap();
+        assert!(log.starts_with("Back up nvim"), "{}", log);
+        check_clean(root, &config, &["nvim"]).unwrap();
+
+        // Deleted directories are still committed
+        fs::rename(root.join("nvim"), root.join("neovim")).unwrap();
+        commit_changes(root, &config, &["nvim", "neovim"], "Rename nvim to neovim").unwrap();
+        check_clean(root, &config, &["nvim", "neovim"]).unwrap();
+
+        fs::write(root.join("neovim").join("init.vim"), "set nonu").unwrap();
+        assert!(matches!(
+            check_clean(root, &config, &["neovim"]),
+            Err(Error::DirtyTree(files)) if files.len() == 1
+        ));
+    }
+}
@@ -7,7 +7,8 @@ use crate::{
     checkers::history::last_paths::HoardPaths,
     config::{
         builder::{
-            hoard::{Config, Encryption, SymmetricEncryption},
+            glob,
+            hoard::{Config, Encryption, RestoreCandidate, SymmetricEncryption, Walker},
             GlobalConfig,
         },
         encrypt::{
@@ -20,6 +21,7 @@ use crate::{
             utils::context,
             Context, Recipients,
         },
+        report::{FileOutcome, Progress, Report},
     },
     hoard_error, hoard_warn,
     utils::{
@@ -30,26 +32,61 @@ use crate::{
 
 use colored::Colorize;
 use crossbeam_channel as channel;
-use ignore::{overrides::OverrideBuilder, WalkBuilder, WalkState};
+use globset::GlobMatcher;
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    WalkBuilder, WalkState,
+};
 use once_cell::sync::{Lazy, OnceCell};
 use rayon::prelude::*;
-use regex::bytes::RegexBuilder;
+use regex::bytes::{Regex, RegexBuilder};
+use serde::{ser::SerializeMap, Serialize, Serializer};
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     ffi::OsStr,
     fs, io,
     path::{Path, PathBuf},
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
+    sync::Arc,
     thread,
+    time::Instant,
 };
 use thiserror::Error;
 
 static FORTRESS_INITIALIZATION: OnceCell<Result<(Fortress, Recipients), Error>> = OnceCell::new();
 
+/// Reason reported for files that do not match the walker's pattern.
+const SKIP_PATTERN: &str = "does not match the walker pattern";
+/// Reason reported for encryption metadata that is not restored.
+const SKIP_SPECIAL: &str = "encryption metadata is not restored";
+/// Reason reported for files encrypted with a password command.
+const SKIP_PASSWORD_CMD: &str = "password command encryption is not supported yet";
+
+/// An item found by the walker in [`Pile::copy`].
+enum WalkItem {
+    /// An entry to copy.
+    Entry(ignore::DirEntry),
+    /// A file that does not match the walker's pattern.
+    Skipped(PathBuf),
+    /// An entry that could not be read.
+    Failed(ignore::Error),
+}
+
+/// The path an error from the walker is about, if any.
+fn walk_error_path(error: &ignore::Error) -> Option<&Path> {
+    match error {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } =>
+            walk_error_path(err),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::Partial(_)
+        | ignore::Error::Io(_)
+        | ignore::Error::Glob { .. }
+        | ignore::Error::UnrecognizedFileType(_)
+        | ignore::Error::InvalidDefinition => None,
+    }
+}
+
 /// Errors that can happen while backing up or restoring a hoard.
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
@@ -136,23 +173,176 @@ pub enum Error {
     Write(#[source] std::io::Error),
 }
 
+impl Error {
+    /// A stable, machine-readable name of the kind of error.
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::CopyFile { .. } => "copy_file",
+            Self::CreateDir { .. } => "create_dir",
+            Self::ReadItem { .. } => "read_item",
+            Self::TypeMismatch { .. } => "type_mismatch",
+            Self::ExcludeError(_) => "exclude_pattern",
+            Self::OverrideBuildError(_) => "override_build",
+            Self::GlobError(_) => "glob_pattern",
+            Self::RegexError(_) => "regex_pattern",
+            Self::IgnorePattern(_) => "ignore_pattern",
+            Self::Context(_) => "crypto_context",
+            Self::OnceCellAccess => "once_cell_access",
+            Self::DeconstructingFortress => "deconstructing_fortress",
+            Self::AppendingSuffix(_) => "appending_suffix",
+            Self::RemovingSuffix(_) => "removing_suffix",
+            Self::Encrypt(_) => "encrypt",
+            Self::Decrypt(_) => "decrypt",
+            Self::Write(_) => "write",
+        }
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::CopyFile { src, dest, .. } | Self::TypeMismatch { src, dest } => {
+                map.serialize_entry("src", src)?;
+                map.serialize_entry("dest", dest)?;
+            },
+            Self::CreateDir { path, .. } | Self::ReadItem { path, .. } => {
+                map.serialize_entry("path", path)?;
+            },
+            Self::ExcludeError(_)
+            | Self::OverrideBuildError(_)
+            | Self::GlobError(_)
+            | Self::RegexError(_)
+            | Self::IgnorePattern(_)
+            | Self::Context(_)
+            | Self::OnceCellAccess
+            | Self::DeconstructingFortress
+            | Self::AppendingSuffix(_)
+            | Self::RemovingSuffix(_)
+            | Self::Encrypt(_)
+            | Self::Decrypt(_)
+            | Self::Write(_) => {},
+        }
+        map.end()
+    }
+}
+
+/// Splits a pile path into the directory to copy and the matcher of the
+/// names of its entries that belong to the pile, if its last component is a
+/// glob pattern. See [`glob::split`].
+///
+/// # Errors
+///
+/// [`Error::GlobError`] if the pattern is invalid.
+fn split_pile_path(path: &Path) -> Result<(&Path, Option<GlobMatcher>), Error> {
+    glob::split(path).map_err(|err| Error::GlobError(err.to_string()))
+}
+
+/// Compiles the file name pattern of a [`Walker`] into a regular expression.
+///
+/// # Errors
+///
+/// [`Error::GlobError`] or [`Error::RegexError`] if the pattern is invalid.
+pub(crate) fn walker_pattern(walker: &Walker) -> Result<Regex, Error> {
+    let pattern = if walker.regex {
+        walker.pattern.clone()
+    } else {
+        globset::GlobBuilder::new(&walker.pattern)
+            .build()
+            .map_err(|e| Error::GlobError(e.to_string()))?
+            .regex()
+            .to_owned()
+    };
+
+    let sensitive = walker.case_sensitive || contains_upperchar(&pattern);
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!sensitive)
+        .build()
+        .map_err(|e| Error::RegexError(e.to_string()))
+}
+
+/// Builds the [`Override`] that excludes the [`Walker`]'s `exclude` patterns
+/// below `root`.
+///
+/// # Errors
+///
+/// [`Error::ExcludeError`] or [`Error::OverrideBuildError`] if a pattern is
+/// invalid.
+pub(crate) fn walker_excludes(root: &Path, walker: &Walker) -> Result<Override, Error> {
+    let mut override_builder = OverrideBuilder::new(root);
+    for ext in walker
+        .exclude
+        .iter()
+        .map(|v| String::from("!") + v.as_str())
+    {
+        override_builder
+            .add(ext.as_str())
+            .map_err(|e| Error::ExcludeError(e.to_string()))?;
+    }
+
+    override_builder
+        .build()
+        .map_err(|e| Error::OverrideBuildError(e.to_string()))
+}
+
+/// Whether the walker entry is a regular file.
+fn is_file_entry(entry: &ignore::DirEntry) -> bool {
+    entry
+        .file_type()
+        .map_or(false, |file_type| file_type.is_file())
+}
+
+/// The size of the walker entry, or `0` if it cannot be determined.
+///
+/// The metadata of the entry itself is that of the symlink if the entry is the
+/// symlinked root of the walk, so the path is followed instead.
+fn file_size(entry: &ignore::DirEntry) -> u64 {
+    fs::metadata(entry.path()).map_or(0, |meta| meta.len())
+}
+
 /// A single path to hoard, with configuration.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Pile {
     /// Optional configuration for this path.
-    pub config: Option<Config>,
+    pub config:     Option<Config>,
     /// The path to hoard.
     ///
     /// The path is optional because it will almost always be set by processing
     /// a configuration file and it is possible that none of the environment
     /// combinations match.
-    pub path:   Option<PathBuf>,
+    pub path:       Option<PathBuf>,
+    /// The candidate paths of the matching environment condition, most
+    /// preferred first. `path` is one of them.
+    pub candidates: Vec<PathBuf>,
 }
 
 impl Pile {
+    /// This pile with the candidate chosen by its
+    /// [`RestoreCandidate`] setting as its `path`.
+    fn for_restore(&self) -> Self {
+        let choice = self
+            .config
+            .as_ref()
+            .map(|config| config.restore_candidate)
+            .unwrap_or_default();
+        let path = match choice {
+            RestoreCandidate::Existing => self.path.clone(),
+            RestoreCandidate::First => self.candidates.first().cloned(),
+        };
+        Self {
+            path,
+            ..self.clone()
+        }
+    }
+
     /// Helper function for copying files and directories.
     ///
-    /// The returned [`PilePaths`] has items inserted as (src, dest).
+    /// Returns a [`Report`] of what happened to the files below `src`. With
+    /// `entries`, only the entries of `src` whose names match are copied.
     ///
     /// # Errors
     ///
@@ -162,9 +352,11 @@ impl Pile {
         &self,
         src: &Path,
         dest: &Path,
+        entries: Option<GlobMatcher>,
         global: &GlobalConfig,
         restore: bool,
-    ) -> Result<(), Error> {
+        progress: &mut Progress,
+    ) -> Result<Report, Error> {
         let _span = tracing::trace_span!(
             "copy",
             source = ?src,
@@ -172,42 +364,12 @@ impl Pile {
         )
         .entered();
 
+        let started = Instant::now();
         let threads = num_cpus::get();
         let config = self.config.clone().unwrap_or_default();
         // tracing::trace!("Walker Config: {:#?}", config.walker.clone());
 
-        let pattern = if config.walker.regex {
-            config.walker.pattern
-        } else {
-            let builder = globset::GlobBuilder::new(&config.walker.pattern);
-            builder
-                .build()
-                .map_err(|e| Error::GlobError(e.to_string()))?
-                .regex()
-                .to_owned()
-        };
-
-        let sensitive = config.walker.case_sensitive || contains_upperchar(&pattern);
-
-        let compiled_patt = RegexBuilder::new(&pattern)
-            .case_insensitive(!sensitive)
-            .build()
-            .map_err(|e| Error::RegexError(e.to_string()))?;
-
-        let pattern = Arc::new(compiled_patt);
-
-        let mut override_builder = OverrideBuilder::new(src);
-        for ext in config
-            .walker
-            .exclude
-            .iter()
-            .map(|v| String::from("!") + v.as_str())
-            .collect::<Vec<String>>()
-        {
-            override_builder
-                .add(ext.as_str())
-                .map_err(|e| Error::ExcludeError(e.to_string()))?;
-        }
+        let pattern = Arc::new(walker_pattern(&config.walker)?);
 
         let mut builder = WalkBuilder::new(src);
         builder
@@ -220,11 +382,12 @@ impl Pile {
             .git_ignore(false)
             .git_exclude(false)
             .parents(false)
-            .overrides(
-                override_builder
-                    .build()
-                    .map_err(|e| Error::OverrideBuildError(e.to_string()))?,
-            );
+            .overrides(walker_excludes(src, &config.walker)?);
+        if let Some(entries) = entries {
+            builder.filter_entry(move |entry| {
+                entry.depth() != 1 || entries.is_match(entry.file_name())
+            });
+        }
 
         if let Some(ref ignore) = global.ignores {
             let tmp =
@@ -240,7 +403,7 @@ impl Pile {
         }
 
         let walker = builder.build_parallel();
-        let (tx, rx) = channel::unbounded::<ignore::DirEntry>();
+        let (tx, rx) = channel::unbounded::<WalkItem>();
 
         thread::spawn(|| {
             walker.run(move || {
@@ -252,7 +415,10 @@ impl Pile {
                         Ok(d) => d,
                         Err(e) => {
                             hoard_warn!("{}", &e);
-                            return WalkState::Continue;
+                            return match tx.send(WalkItem::Failed(e)) {
+                                Ok(()) => WalkState::Continue,
+                                Err(_) => WalkState::Quit,
+                            };
                         },
                     };
 
@@ -265,11 +431,15 @@ impl Pile {
                     };
 
                     // Filter out patterns that don't match
-                    if !pattern.is_match(&osstr_to_bytes(entry_fname.as_ref())) {
+                    let item = if pattern.is_match(&osstr_to_bytes(entry_fname.as_ref())) {
+                        WalkItem::Entry(entry)
+                    } else if is_file_entry(&entry) {
+                        WalkItem::Skipped(entry.into_path())
+                    } else {
                         return WalkState::Continue;
-                    }
+                    };
 
-                    if tx.send(entry).is_err() {
+                    if tx.send(item).is_err() {
                         tracing::trace!("WalkBuilder sent quit");
                         return WalkState::Quit;
                     }
@@ -278,7 +448,27 @@ impl Pile {
             });
         });
 
-        while let Ok(mod_src) = rx.recv() {
+        // Collect everything first so the progress display knows the totals
+        let mut report = Report::default();
+        let mut entries = Vec::new();
+        for item in rx {
+            match item {
+                WalkItem::Entry(entry) => entries.push(entry),
+                WalkItem::Skipped(path) => {
+                    report.record(FileOutcome::Skipped(SKIP_PATTERN), 0);
+                    progress.file_skipped(&path, SKIP_PATTERN);
+                },
+                WalkItem::Failed(error) => {
+                    report.errors += 1;
+                    progress.file_failed(walk_error_path(&error), "walk", error.to_string());
+                },
+            }
+        }
+
+        let files = entries.iter().filter(|entry| is_file_entry(entry));
+        progress.start_pile(files.clone().count(), files.map(file_size).sum());
+
+        for mod_src in &entries {
             tracing::trace!("Walker source: {:?}", mod_src);
             let src_path = mod_src.path();
 
@@ -300,132 +490,15 @@ impl Pile {
                     let _span = tracing::trace_span!("is_directory").entered();
                 } else if file_type.is_file() {
                     let _span = tracing::trace_span!("is_file").entered();
-                    if let Some(parent) = mod_dest.parent() {
-                        if is_special_file(&mod_src) && restore {
-                            continue;
-                        }
-                        tracing::trace!(
-                            destination = src_path.to_string_lossy().as_ref(),
-                            "ensuring parent directories for destination",
-                        );
-                        fs::create_dir_all(parent).map_err(|err| Error::CreateDir {
-                            path:  mod_dest.clone(),
-                            error: err,
+                    let bytes = file_size(mod_src);
+                    let outcome = self
+                        .copy_file(mod_src, mod_dest, src, dest, global, restore)
+                        .map_err(|err| {
+                            progress.file_failed(Some(src_path), err.kind(), err.to_string());
+                            err
                         })?;
-                    }
-
-                    if let Some(enc) = self.config.clone().and_then(|conf| conf.encryption) {
-                        FORTRESS_INITIALIZATION.get_or_init(|| {
-                            tracing::trace!("running fortress initialization");
-                            build_fortress(
-                                if restore { src } else { dest },
-                                &self.config.clone().unwrap_or_default(),
-                                global,
-                            )
-                            .map_err(|err| Error::Context(err.into()))
-                        });
-
-                        // TODO: use or remove fortress
-                        let (_fortress, recipients) = FORTRESS_INITIALIZATION
-                            .get()
-                            .ok_or(Error::OnceCellAccess)?
-                            .as_ref()
-                            .map_err(|e| Error::Context(e.into()))?;
-
-                        // If file is '.gpg-id' or the directory '.public-keys', do
-                        // not do anything extra
-                        if is_special_file(&mod_src) {
-                            if !restore {
-                                fs::copy(src_path.to_owned(), &mod_dest).map_err(|err| {
-                                    Error::CopyFile {
-                                        src:   src_path.to_owned(),
-                                        dest:  mod_dest.clone(),
-                                        error: err,
-                                    }
-                                })?;
-                            }
-                            continue;
-                        }
-
-                        if restore {
-                            let norm = rm_sec_suffix(&mod_dest).map_err(Error::RemovingSuffix)?;
-                            println!("norm :: {:#?}", norm);
-
-                            match enc {
-                                Encryption::Symmetric(e) => match e {
-                                    SymmetricEncryption::Password(pass) => {
-                                        println!("pass: {:#?}", pass);
-                                        let plaintext =
-                                            context(&self.config.clone().unwrap_or_default())
-                                                .map_err(|err| Error::Context(err.into()))?
-                                                .decrypt_file(src_path)
-                                                .map_err(Error::Decrypt)?;
-                                        fs::write(&norm, &plaintext.unsecure_ref())
-                                            .map_err(Error::Write)?;
-                                    },
-                                    SymmetricEncryption::PasswordCmd(pass_cmd) => {
-                                        println!("pass cmd: {:#?}", pass_cmd);
-                                    },
-                                },
-                                Encryption::Asymmetric(e) => {
-                                    println!("asym: {:#?}", e.public_key);
-                                    let plaintext =
-                                        context(&self.config.clone().unwrap_or_default())
-                                            .map_err(|err| Error::Context(err.into()))?
-                                            .decrypt_file(src_path)
-                                            .map_err(Error::Decrypt)?;
-                                    fs::write(&norm, &plaintext.unsecure_ref())
-                                        .map_err(Error::Write)?;
-                                },
-                            }
-                        } else {
-                            let norm =
-                                append_sec_suffix(&mod_dest).map_err(Error::AppendingSuffix)?;
-                            let plaintext =
-                                Plaintext::from(fs::read(&src_path).map_err(|err| {
-                                    Error::ReadItem {
-                                        path:  src_path.to_path_buf(),
-                                        error: err,
-                                    }
-                                })?);
-
-                            match enc {
-                                Encryption::Symmetric(e) => match e {
-                                    SymmetricEncryption::Password(pass) => {
-                                        println!("pass: {:#?}", pass);
-                                        context(&self.config.clone().unwrap_or_default())
-                                            .map_err(|err| Error::Context(err.into()))?
-                                            .encrypt_file_symmetric(plaintext, &norm)
-                                            .map_err(Error::Encrypt)?;
-                                    },
-                                    SymmetricEncryption::PasswordCmd(pass_cmd) => {
-                                        println!("pass cmd: {:#?}", pass_cmd);
-                                    },
-                                },
-                                Encryption::Asymmetric(e) => {
-                                    println!("asym: {:#?}", e.public_key);
-                                    context(&self.config.clone().unwrap_or_default())
-                                        .map_err(|err| Error::Context(err.into()))?
-                                        .encrypt_file(recipients, plaintext, &norm)
-                                        .map_err(Error::Encrypt)?;
-                                },
-                            }
-                        }
-                    } else {
-                        // Copy all files as is
-                        fs::copy(src_path.to_owned(), &mod_dest).map_err(|err| {
-                            Error::CopyFile {
-                                src:   src_path.to_owned(),
-                                dest:  mod_dest.clone(),
-                                error: err,
-                            }
-                        })?;
-                        tracing::debug!(
-                            source = src_path.to_string_lossy().as_ref(),
-                            destination = mod_dest.to_string_lossy().as_ref(),
-                            "copying",
-                        );
-                    }
+                    report.record(outcome, bytes);
+                    progress.file_done(src_path, mod_dest, outcome, bytes);
                 } else {
                     tracing::warn!(
                         source = src_path.to_string_lossy().as_ref(),
@@ -439,7 +512,147 @@ impl Pile {
                 );
             }
         }
-        Ok(())
+
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    /// Copies (and encrypts or decrypts) a single file found by the walker in
+    /// [`Pile::copy`] to `mod_dest`.
+    ///
+    /// # Errors
+    ///
+    /// Various sorts of I/O errors as the different [`Error`] variants.
+    #[allow(clippy::too_many_lines)]
+    fn copy_file(
+        &self,
+        mod_src: &ignore::DirEntry,
+        mod_dest: &Path,
+        src: &Path,
+        dest: &Path,
+        global: &GlobalConfig,
+        restore: bool,
+    ) -> Result<FileOutcome, Error> {
+        let src_path = mod_src.path();
+        if let Some(parent) = mod_dest.parent() {
+            if is_special_file(mod_src) && restore {
+                return Ok(FileOutcome::Skipped(SKIP_SPECIAL));
+            }
+            tracing::trace!(
+                destination = src_path.to_string_lossy().as_ref(),
+                "ensuring parent directories for destination",
+            );
+            fs::create_dir_all(parent).map_err(|err| Error::CreateDir {
+                path:  mod_dest.to_path_buf(),
+                error: err,
+            })?;
+        }
+
+        if let Some(enc) = self.config.clone().and_then(|conf| conf.encryption) {
+            FORTRESS_INITIALIZATION.get_or_init(|| {
+                tracing::trace!("running fortress initialization");
+                build_fortress(
+                    if restore { src } else { dest },
+                    &self.config.clone().unwrap_or_default(),
+                    global,
+                )
+                .map_err(|err| Error::Context(err.into()))
+            });
+
+            // TODO: use or remove fortress
+            let (_fortress, recipients) = FORTRESS_INITIALIZATION
+                .get()
+                .ok_or(Error::OnceCellAccess)?
+                .as_ref()
+                .map_err(|e| Error::Context(e.into()))?;
+
+            // If file is '.gpg-id' or the directory '.public-keys', do
+            // not do anything extra
+            if is_special_file(mod_src) {
+                if restore {
+                    return Ok(FileOutcome::Skipped(SKIP_SPECIAL));
+                }
+                fs::copy(src_path, mod_dest).map_err(|err| Error::CopyFile {
+                    src:   src_path.to_owned(),
+                    dest:  mod_dest.to_path_buf(),
+                    error: err,
+                })?;
+                return Ok(FileOutcome::Copied);
+            }
+
+            if restore {
+                let norm = rm_sec_suffix(mod_dest).map_err(Error::RemovingSuffix)?;
+                tracing::trace!(path = %norm.display(), "decrypting file");
+
+                match enc {
+                    Encryption::Symmetric(e) => match e {
+                        SymmetricEncryption::Password(_) => {
+                            let plaintext = context(&self.config.clone().unwrap_or_default())
+                                .map_err(|err| Error::Context(err.into()))?
+                                .decrypt_file(src_path)
+                                .map_err(Error::Decrypt)?;
+                            fs::write(&norm, &plaintext.unsecure_ref()).map_err(Error::Write)?;
+                        },
+                        SymmetricEncryption::PasswordCmd(pass_cmd) => {
+                            tracing::trace!(command = ?pass_cmd, "skipping file encrypted with a password command");
+                            return Ok(FileOutcome::Skipped(SKIP_PASSWORD_CMD));
+                        },
+                    },
+                    Encryption::Asymmetric(e) => {
+                        tracing::trace!(public_key = ?e.public_key, "using asymmetric encryption");
+                        let plaintext = context(&self.config.clone().unwrap_or_default())
+                            .map_err(|err| Error::Context(err.into()))?
+                            .decrypt_file(src_path)
+                            .map_err(Error::Decrypt)?;
+                        fs::write(&norm, &plaintext.unsecure_ref()).map_err(Error::Write)?;
+                    },
+                }
+                Ok(FileOutcome::Decrypted)
+            } else {
+                let norm = append_sec_suffix(mod_dest).map_err(Error::AppendingSuffix)?;
+                let plaintext =
+                    Plaintext::from(fs::read(&src_path).map_err(|err| Error::ReadItem {
+                        path:  src_path.to_path_buf(),
+                        error: err,
+                    })?);
+
+                match enc {
+                    Encryption::Symmetric(e) => match e {
+                        SymmetricEncryption::Password(_) => {
+                            context(&self.config.clone().unwrap_or_default())
+                                .map_err(|err| Error::Context(err.into()))?
+                                .encrypt_file_symmetric(plaintext, &norm)
+                                .map_err(Error::Encrypt)?;
+                        },
+                        SymmetricEncryption::PasswordCmd(pass_cmd) => {
+                            tracing::trace!(command = ?pass_cmd, "skipping file encrypted with a password command");
+                            return Ok(FileOutcome::Skipped(SKIP_PASSWORD_CMD));
+                        },
+                    },
+                    Encryption::Asymmetric(e) => {
+                        tracing::trace!(public_key = ?e.public_key, "using asymmetric encryption");
+                        context(&self.config.clone().unwrap_or_default())
+                            .map_err(|err| Error::Context(err.into()))?
+                            .encrypt_file(recipients, plaintext, &norm)
+                            .map_err(Error::Encrypt)?;
+                    },
+                }
+                Ok(FileOutcome::Encrypted)
+            }
+        } else {
+            // Copy all files as is
+            fs::copy(src_path, mod_dest).map_err(|err| Error::CopyFile {
+                src:   src_path.to_owned(),
+                dest:  mod_dest.to_path_buf(),
+                error: err,
+            })?;
+            tracing::debug!(
+                source = src_path.to_string_lossy().as_ref(),
+                destination = mod_dest.to_string_lossy().as_ref(),
+                "copying",
+            );
+            Ok(FileOutcome::Copied)
+        }
     }
 
     /// Backs up files to the pile directory.
@@ -450,7 +663,12 @@ impl Pile {
     /// # Errors
     ///
     /// Various sorts of I/O errors as the different [`enum@Error`] variants.
-    pub fn backup(&self, prefix: &Path, global: &GlobalConfig) -> Result<(), Error> {
+    pub fn backup(
+        &self,
+        prefix: &Path,
+        global: &GlobalConfig,
+        progress: &mut Progress,
+    ) -> Result<Report, Error> {
         if let Some(path) = &self.path {
             let _span = tracing::debug_span!(
                 "backup_pile",
@@ -465,12 +683,12 @@ impl Pile {
             //     }
             // }
 
-            Self::copy(self, path, prefix, global, false)?;
+            let (root, entries) = split_pile_path(path)?;
+            Self::copy(self, root, prefix, entries, global, false, progress)
         } else {
             tracing::warn!("pile has no associated path -- perhaps no environment matched?");
+            Ok(Report::default())
         }
-
-        Ok(())
     }
 
     /// Restores files from the hoard into the filesystem.
@@ -478,7 +696,12 @@ impl Pile {
     /// # Errors
     ///
     /// Various sorts of I/O errors as the different [`enum@Error`] variants.
-    pub fn restore(&self, prefix: &Path, global: &GlobalConfig) -> Result<(), Error> {
+    pub fn restore(
+        &self,
+        prefix: &Path,
+        global: &GlobalConfig,
+        progress: &mut Progress,
+    ) -> Result<Report, Error> {
         // // let plain = context.decrypt_file(&path.join("aaa.txt")).expect("err
         // // decrypting"); fs::write(&path.join("aaa.txt"),
         // // plain.unsecure_ref()).expect("error writing");
@@ -490,12 +713,12 @@ impl Pile {
             )
             .entered();
 
-            Self::copy(self, prefix, path, global, true)?;
+            let (root, entries) = split_pile_path(path)?;
+            Self::copy(self, prefix, root, entries, global, true, progress)
         } else {
             tracing::warn!("pile has no associated path -- perhaps no environment matched");
+            Ok(Report::default())
         }
-
-        Ok(())
     }
 }
 
@@ -512,7 +735,13 @@ impl MultipleEntries {
     /// # Errors
     ///
     /// See [`Pile::backup`].
-    pub fn backup(&self, prefix: &Path, global: &GlobalConfig) -> Result<(), Error> {
+    pub fn backup(
+        &self,
+        prefix: &Path,
+        global: &GlobalConfig,
+        progress: &mut Progress,
+    ) -> Result<Report, Error> {
+        let mut report = Report::default();
         for (name, entry) in &self.piles {
             let _span = tracing::info_span!(
                 "backup_multi_pile",
@@ -521,10 +750,12 @@ impl MultipleEntries {
             .entered();
 
             let sub_prefix = prefix.join(name);
-            entry.backup(&sub_prefix, global)?;
+            progress.set_pile(Some(name));
+            report += entry.backup(&sub_prefix, global, progress)?;
         }
 
-        Ok(())
+        progress.set_pile(None);
+        Ok(report)
     }
 
     /// Restore all of the contained [`Pile`]s.
@@ -532,7 +763,13 @@ impl MultipleEntries {
     /// # Errors
     ///
     /// See [`Pile::restore`].
-    pub fn restore(&self, prefix: &Path, global: &GlobalConfig) -> Result<(), Error> {
+    pub fn restore(
+        &self,
+        prefix: &Path,
+        global: &GlobalConfig,
+        progress: &mut Progress,
+    ) -> Result<Report, Error> {
+        let mut report = Report::default();
         for (name, entry) in &self.piles {
             let _span = tracing::info_span!(
                 "restore_multi_pile",
@@ -541,10 +778,12 @@ impl MultipleEntries {
             .entered();
 
             let sub_prefix = prefix.join(name);
-            entry.restore(&sub_prefix, global)?;
+            progress.set_pile(Some(name));
+            report += entry.restore(&sub_prefix, global, progress)?;
         }
 
-        Ok(())
+        progress.set_pile(None);
+        Ok(report)
     }
 }
 
@@ -559,49 +798,88 @@ pub enum Hoard {
 }
 
 impl Hoard {
-    /// Back up this [`Hoard`].
+    /// Back up this [`Hoard`], returning a [`Report`] of all of its piles.
     ///
     /// # Errors
     ///
     /// See [`Pile::backup`].
-    pub fn backup(&self, prefix: &Path, global: &GlobalConfig) -> Result<(), Error> {
+    pub fn backup(
+        &self,
+        prefix: &Path,
+        global: &GlobalConfig,
+        progress: &mut Progress,
+    ) -> Result<Report, Error> {
         let _span =
             tracing::trace_span!("backup_hoard", prefix = prefix.to_string_lossy().as_ref())
                 .entered();
 
         match self {
-            Hoard::Anonymous(single) => single.backup(prefix, global),
-            Hoard::Named(multiple) => multiple.backup(prefix, global),
+            Hoard::Anonymous(single) => single.backup(prefix, global, progress),
+            Hoard::Named(multiple) => multiple.backup(prefix, global, progress),
         }
     }
 
-    /// Restore this [`Hoard`].
+    /// Restore this [`Hoard`], returning a [`Report`] of all of its piles.
     ///
     /// # Errors
     ///
     /// See [`Pile::restore`].
-    pub fn restore(&self, prefix: &Path, global: &GlobalConfig) -> Result<(), Error> {
+    pub fn restore(
+        &self,
+        prefix: &Path,
+        global: &GlobalConfig,
+        progress: &mut Progress,
+    ) -> Result<Report, Error> {
         let _span =
             tracing::trace_span!("restore_hoard", prefix = prefix.to_string_lossy().as_ref(),)
                 .entered();
 
         match self {
-            Hoard::Anonymous(single) => single.restore(prefix, global),
-            Hoard::Named(multiple) => multiple.restore(prefix, global),
+            Hoard::Anonymous(single) => single.restore(prefix, global, progress),
+            Hoard::Named(multiple) => multiple.restore(prefix, global, progress),
         }
     }
 
     /// Returns a [`HoardPaths`] based on this `Hoard`.
     #[must_use]
     pub fn get_paths(&self) -> HoardPaths {
+        self.into()
+    }
+
+    /// This `Hoard` with only the named piles `piles`. An anonymous hoard is
+    /// kept as is.
+    #[must_use]
+    pub fn with_piles(&self, piles: &BTreeSet<&str>) -> Self {
         match self {
-            Hoard::Anonymous(pile) => pile.path.clone().into(),
-            Hoard::Named(piles) => piles
-                .piles
-                .iter()
-                .filter_map(|(key, val)| val.path.clone().map(|path| (key.clone(), path)))
-                .collect::<HashMap<_, _>>()
-                .into(),
+            Hoard::Anonymous(_) => self.clone(),
+            Hoard::Named(named) => Hoard::Named(MultipleEntries {
+                piles: named
+                    .piles
+                    .iter()
+                    .filter(|(name, _)| piles.contains(name.as_str()))
+                    .map(|(name, pile)| (name.clone(), pile.clone()))
+                    .collect(),
+            }),
+        }
+    }
+
+    /// This `Hoard` with the paths used by a backup (`is_backup`) or a
+    /// restore. Backups use the first candidate path that exists, restores
+    /// the one chosen by each pile's [`RestoreCandidate`] setting.
+    #[must_use]
+    pub fn for_operation(&self, is_backup: bool) -> Self {
+        if is_backup {
+            return self.clone();
+        }
+        match self {
+            Hoard::Anonymous(pile) => Hoard::Anonymous(pile.for_restore()),
+            Hoard::Named(named) => Hoard::Named(MultipleEntries {
+                piles: named
+                    .piles
+                    .iter()
+                    .map(|(name, pile)| (name.clone(), pile.for_restore()))
+                    .collect(),
+            }),
         }
     }
 }
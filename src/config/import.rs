@@ -0,0 +1,1044 @@
+//! Import the dotfiles managed by another tool.
+//!
+//! `hoard import-config --from <tool> <path>` reads the layout or
+//! configuration of a GNU Stow directory, a dotbot configuration, a chezmoi
+//! source directory or a yadm repository, adds equivalent hoards, piles and
+//! environments to the configuration file and backs the new hoards up like
+//! `hoard backup` does.
+//!
+//! Files are grouped into a hoard per application directory, like
+//! `~/.config/nvim` or `~/.zshrc`, unless the tool groups them itself (stow
+//! packages). Directories that hold secrets next to the managed files, like
+//! `~/.ssh`, are imported file by file.
+//!
+//! See [`run_import`].
+
+use super::{
+    adopt,
+    builder::glob,
+    catalog,
+    directories::PROJECT_DIRS,
+    edit::{self, ConfigDocument},
+    filetypes::infer_format,
+    output::print_document,
+    Config,
+};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env::consts::OS,
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use thiserror::Error;
+
+/// Directories that hold the directories of applications rather than being
+/// one, e.g. `~/.config`.
+const CONTAINERS: &[&str] = &[
+    ".config",
+    ".local",
+    ".local/bin",
+    ".local/share",
+    ".local/state",
+    ".cache",
+    "bin",
+    "Library",
+    "Library/Application Support",
+    "Library/Preferences",
+    "AppData",
+    "AppData/Local",
+    "AppData/Roaming",
+];
+
+/// Application directories whose unmanaged files must not be backed up.
+const PRIVATE_DIRS: &[&str] = &[".ssh", ".gnupg"];
+
+/// The names of dotbot configuration files, in order of preference.
+const DOTBOT_CONFIGS: &[&str] = &["install.conf.yaml", "install.conf.yml", "install.conf.json"];
+
+/// The environment variable that selects the yadm class.
+const CLASS_VAR: &str = "DOTFILES_CLASS";
+
+/// Errors that can occur while importing from another tool.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The path to import from does not exist.
+    #[error("cannot import from {0}: no such file or directory")]
+    NotFound(PathBuf),
+    /// No dotbot configuration file was found in the directory.
+    #[error("no dotbot configuration ({}) found in {0}", DOTBOT_CONFIGS.join(", "))]
+    NoDotbotConfig(PathBuf),
+    /// The dotbot configuration file could not be parsed.
+    #[error("failed to parse dotbot configuration {path}: {error}")]
+    Dotbot {
+        /// The configuration file.
+        path:  PathBuf,
+        /// The error that occurred while parsing it.
+        #[source]
+        error: serde_yaml::Error,
+    },
+    /// The files tracked by the yadm repository could not be listed.
+    #[error("failed to list the files in yadm repository {path}: {message}")]
+    Yadm {
+        /// The repository.
+        path:    PathBuf,
+        /// What went wrong.
+        message: String,
+    },
+    /// The tool does not manage any file that can be imported.
+    #[error("found nothing to import in {0}")]
+    NothingToImport(PathBuf),
+    /// A file or directory could not be read or copied.
+    #[error("failed to import {path}: {error}")]
+    IO {
+        /// The file or directory.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+    /// Error while editing the configuration file.
+    #[error("failed to update configuration file: {0}")]
+    Edit(#[from] edit::Error),
+    /// The generated configuration could not be printed.
+    #[error("failed to print configuration: {0}")]
+    Print(#[source] io::Error),
+}
+
+/// An environment: its name and its definition.
+type Env = (String, Value);
+
+/// A file or directory managed by the other tool.
+#[derive(Clone, Debug, PartialEq)]
+struct Item {
+    /// Where the tool keeps it.
+    source: PathBuf,
+    /// Where the tool installs it, relative to the target directory.
+    target: PathBuf,
+    /// The hoard to put it in, if the tool groups files itself.
+    hoard:  Option<String>,
+    /// Whether it is a pile of its own rather than a part of its application
+    /// directory.
+    single: bool,
+    /// The environments it is installed under. Empty if it is always
+    /// installed.
+    envs:   Vec<Env>,
+}
+
+impl Item {
+    fn new(source: PathBuf, target: PathBuf) -> Self {
+        Self {
+            source,
+            target,
+            hoard: None,
+            single: false,
+            envs: Vec::new(),
+        }
+    }
+}
+
+/// What was found in the other tool's layout or configuration.
+#[derive(Debug, Default)]
+struct Found {
+    items:   Vec<Item>,
+    /// Application directories to import file by file.
+    private: BTreeSet<PathBuf>,
+}
+
+/// A pile to add to the configuration.
+#[derive(Clone, Debug, PartialEq)]
+struct ImportedPile {
+    /// The name of the pile, if its hoard has several.
+    name:      String,
+    /// The path of the pile, relative to the target directory.
+    target:    PathBuf,
+    /// The environment condition it is used under.
+    condition: String,
+    /// Each file or directory of the pile and where it goes in the pile.
+    files:     Vec<(PathBuf, PathBuf)>,
+}
+
+/// `path` as an absolute path, relative to the current directory.
+fn absolute(path: &Path) -> Result<PathBuf, Error> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map_err(|error| Error::IO {
+                path: path.to_path_buf(),
+                error,
+            })?
+            .join(path)
+    };
+    let path = path.components().collect::<PathBuf>();
+    if path.exists() {
+        Ok(path)
+    } else {
+        Err(Error::NotFound(path))
+    }
+}
+
+/// The entries of `dir`, sorted by name.
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let io_error = |error| Error::IO {
+        path: dir.to_path_buf(),
+        error,
+    };
+    let mut entries = fs::read_dir(dir)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error)?;
+    entries.sort_unstable();
+    Ok(entries)
+}
+
+/// Every file below `dir`, except the contents of `.git` directories.
+fn files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut found = Vec::new();
+    for entry in read_dir(dir)? {
+        if entry.file_name() == Some(OsStr::new(".git")) {
+            continue;
+        }
+        if entry.is_dir() {
+            found.extend(files(&entry)?);
+        } else {
+            found.push(entry);
+        }
+    }
+    Ok(found)
+}
+
+/// `name` as a hoard, pile or environment name: without leading dots and
+/// with path separators replaced.
+fn name_of(name: &str) -> String {
+    name.trim_start_matches('.')
+        .replace(&['/', '\\', ':', '|'][..], "-")
+}
+
+/// The application directory (or file) `target` belongs to: its first
+/// component that is not a container like `.config`.
+fn app_root(target: &Path) -> PathBuf {
+    let mut root = PathBuf::new();
+    for component in target.components() {
+        root.push(component);
+        if !CONTAINERS.iter().any(|dir| Path::new(dir) == root) {
+            break;
+        }
+    }
+    root
+}
+
+/// `path` with a leading `~` replaced by `home`.
+fn expand_tilde(path: &str, home: &Path) -> PathBuf {
+    if path == "~" {
+        home.to_path_buf()
+    } else {
+        path.strip_prefix("~/")
+            .map_or_else(|| PathBuf::from(path), |rest| home.join(rest))
+    }
+}
+
+/// The packages of the stow directory `dir`, each as a hoard.
+///
+/// Top-level `README`, `LICENSE` and `COPYING` files, `.gitignore` and
+/// `.stow-local-ignore` are skipped, like stow does, and `dot-` prefixes are
+/// replaced with `.`, like `stow --dotfiles` does.
+fn read_stow(dir: &Path) -> Result<Found, Error> {
+    let mut found = Found::default();
+    for package in read_dir(dir)? {
+        let name = package.file_name().map(OsStr::to_string_lossy);
+        let name = match name {
+            Some(name) if package.is_dir() && !name.starts_with('.') => name_of(&name),
+            Some(_) | None => continue,
+        };
+        for source in files(&package)? {
+            let relative = source.strip_prefix(&package).unwrap_or(&source);
+            let file_name = relative
+                .file_name()
+                .map_or_else(Default::default, OsStr::to_string_lossy);
+            let ignored = matches!(file_name.as_ref(), ".gitignore" | ".stow-local-ignore")
+                || (relative.components().count() == 1
+                    && ["README", "LICENSE", "COPYING"]
+                        .iter()
+                        .any(|prefix| file_name.starts_with(prefix)));
+            if ignored {
+                continue;
+            }
+            let target = relative
+                .iter()
+                .map(|component| {
+                    let component = component.to_string_lossy();
+                    component.strip_prefix("dot-").map_or_else(
+                        || component.clone().into_owned(),
+                        |rest| [".", rest].concat(),
+                    )
+                })
+                .collect();
+            found.items.push(Item {
+                hoard: Some(name.clone()),
+                ..Item::new(source.clone(), target)
+            });
+        }
+    }
+    Ok(found)
+}
+
+/// The source of the dotbot link to `target` described by `spec`, relative to
+/// the directory of the configuration file, or why it cannot be imported.
+fn dotbot_source(target: &str, spec: &serde_yaml::Value) -> Result<String, &'static str> {
+    // Without a path, dotbot links the target's name without a leading dot
+    let default = || {
+        let name = target.rsplit('/').next().unwrap_or(target);
+        name.trim_start_matches('.').to_owned()
+    };
+    match spec {
+        serde_yaml::Value::Null => Ok(default()),
+        serde_yaml::Value::String(path) => Ok(path.clone()),
+        serde_yaml::Value::Mapping(options) => {
+            let option = |key: &str| options.get(&serde_yaml::Value::from(key));
+            if option("glob").and_then(serde_yaml::Value::as_bool) == Some(true) {
+                return Err("glob links are not supported");
+            }
+            if option("if").is_some() {
+                return Err("conditional links cannot be translated to environments");
+            }
+            match option("path") {
+                None | Some(serde_yaml::Value::Null) => Ok(default()),
+                Some(serde_yaml::Value::String(path)) => Ok(path.clone()),
+                Some(_) => Err("its path is not a string"),
+            }
+        },
+        serde_yaml::Value::Bool(_)
+        | serde_yaml::Value::Number(_)
+        | serde_yaml::Value::Sequence(_) => Err("it is not a path or a table"),
+    }
+}
+
+/// The links of the dotbot configuration at `path` (or in the directory
+/// `path`) that are in `target_dir`, each as a pile of its own.
+///
+/// Sources are relative to the directory of the configuration file, targets
+/// to `target_dir`, and `~` is expanded in both.
+fn read_dotbot(path: &Path, target_dir: &Path, home: &Path) -> Result<Found, Error> {
+    let path = if path.is_dir() {
+        DOTBOT_CONFIGS
+            .iter()
+            .map(|name| path.join(name))
+            .find(|file| file.exists())
+            .ok_or_else(|| Error::NoDotbotConfig(path.to_path_buf()))?
+    } else {
+        path.to_path_buf()
+    };
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let text = fs::read_to_string(&path).map_err(|error| Error::IO {
+        path: path.clone(),
+        error,
+    })?;
+    let directives: Vec<BTreeMap<String, serde_yaml::Value>> = serde_yaml::from_str(&text)
+        .map_err(|error| Error::Dotbot {
+            path: path.clone(),
+            error,
+        })?;
+
+    let mut found = Found::default();
+    let links = directives
+        .iter()
+        .filter_map(|directive| directive.get("link"))
+        .filter_map(serde_yaml::Value::as_mapping)
+        .flat_map(serde_yaml::Mapping::iter);
+    for (target, spec) in links {
+        let target = match target.as_str() {
+            Some(target) => target,
+            None => continue,
+        };
+        let source = match dotbot_source(target, spec) {
+            Ok(source) => base.join(expand_tilde(&source, home)),
+            Err(reason) => {
+                tracing::warn!("skipping link {}: {}", target, reason);
+                continue;
+            },
+        };
+        let target_path = target_dir.join(expand_tilde(target, home));
+        match target_path.strip_prefix(target_dir) {
+            Ok(relative) if source.exists() => found.items.push(Item {
+                single: true,
+                ..Item::new(source, relative.to_path_buf())
+            }),
+            Ok(_) => tracing::warn!(
+                "skipping link {}: {} does not exist",
+                target,
+                source.display()
+            ),
+            Err(_) => tracing::warn!(
+                "skipping link {}: it is outside of {}",
+                target,
+                target_dir.display()
+            ),
+        }
+    }
+    Ok(found)
+}
+
+/// A file or directory in a chezmoi source directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SourceEntry {
+    /// It is installed under this name. `private` is whether chezmoi makes it
+    /// private to the user.
+    Target {
+        /// The name of the installed file or directory.
+        name:    String,
+        /// Whether it has the `private_` attribute.
+        private: bool,
+    },
+    /// It cannot be imported, for this reason.
+    Skipped(&'static str),
+}
+
+/// The attributes chezmoi strips from source names, in the order they appear.
+const CHEZMOI_ATTRIBUTES: &[&str] = &[
+    "after_",
+    "before_",
+    "create_",
+    "empty_",
+    "encrypted_",
+    "exact_",
+    "executable_",
+    "external_",
+    "modify_",
+    "once_",
+    "onchange_",
+    "private_",
+    "readonly_",
+    "remove_",
+    "run_",
+    "symlink_",
+];
+
+/// What the chezmoi source entry `name` installs.
+fn chezmoi_entry(name: &str) -> SourceEntry {
+    if name.starts_with('.') {
+        return SourceEntry::Skipped("chezmoi ignores it");
+    }
+    if Path::new(name).extension() == Some(OsStr::new("tmpl")) {
+        return SourceEntry::Skipped("templates cannot be imported, apply them first");
+    }
+
+    let mut rest = name.strip_suffix(".literal").unwrap_or(name);
+    let mut private = false;
+    loop {
+        if let Some(literal) = rest.strip_prefix("literal_") {
+            return SourceEntry::Target {
+                name: literal.to_owned(),
+                private,
+            };
+        }
+        let attribute = CHEZMOI_ATTRIBUTES
+            .iter()
+            .find(|attribute| rest.starts_with(*attribute));
+        match attribute.copied() {
+            Some("encrypted_") =>
+                return SourceEntry::Skipped("encrypted files cannot be imported"),
+            Some("modify_" | "run_" | "remove_") =>
+                return SourceEntry::Skipped("scripts are not files to back up"),
+            Some("symlink_") => return SourceEntry::Skipped("symbolic links are not supported"),
+            Some("external_") => return SourceEntry::Skipped("externals are not supported"),
+            Some("private_") => private = true,
+            Some(_) | None => {},
+        }
+        match attribute {
+            Some(attribute) => rest = rest.strip_prefix(attribute).unwrap_or(rest),
+            None => break,
+        }
+    }
+    let name = rest
+        .strip_prefix("dot_")
+        .map_or_else(|| rest.to_owned(), |rest| [".", rest].concat());
+    SourceEntry::Target { name, private }
+}
+
+/// The files below `dir` in a chezmoi source state, installed into `target`
+/// (relative to the target directory).
+fn walk_chezmoi(dir: &Path, target: &Path, found: &mut Found) -> Result<(), Error> {
+    for source in read_dir(dir)? {
+        let file_name = source
+            .file_name()
+            .map_or_else(Default::default, OsStr::to_string_lossy);
+        let (name, private) = match chezmoi_entry(&file_name) {
+            SourceEntry::Target { name, private } => (name, private),
+            SourceEntry::Skipped(reason) => {
+                if !file_name.starts_with('.') {
+                    tracing::warn!("skipping {}: {}", source.display(), reason);
+                }
+                continue;
+            },
+        };
+        let target = target.join(name);
+        if source.is_dir() {
+            if private && app_root(&target) == target {
+                found.private.insert(target.clone());
+            }
+            walk_chezmoi(&source, &target, found)?;
+        } else {
+            found.items.push(Item::new(source, target));
+        }
+    }
+    Ok(())
+}
+
+/// The files of the chezmoi source directory `dir`, or of the directory
+/// named in its `.chezmoiroot`.
+///
+/// Application directories with the `private_` attribute are imported file
+/// by file.
+fn read_chezmoi(dir: &Path) -> Result<Found, Error> {
+    let root_file = dir.join(".chezmoiroot");
+    let dir = match fs::read_to_string(&root_file) {
+        Ok(root) => dir.join(root.trim()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => dir.to_path_buf(),
+        Err(error) =>
+            return Err(Error::IO {
+                path: root_file,
+                error,
+            }),
+    };
+    let mut found = Found::default();
+    walk_chezmoi(&dir, Path::new(""), &mut found)?;
+    Ok(found)
+}
+
+/// The environment for the yadm alternate attribute `attribute`, e.g.
+/// `os.Linux`, or why it is not supported.
+fn yadm_env(attribute: &str) -> Result<Env, String> {
+    let (key, value) = match attribute.split_once('.') {
+        Some((key, value)) => (key, value),
+        None => (attribute, ""),
+    };
+    match key {
+        "os" | "o" => {
+            // yadm uses the output of `uname -s`
+            let os = match value {
+                "Darwin" => "macos".to_owned(),
+                value => value.to_lowercase(),
+            };
+            Ok((os.clone(), catalog::os_environment(&os)))
+        },
+        "hostname" | "h" => Ok((
+            ["hostname-", &name_of(value)].concat(),
+            json!({ "hostname": [value] }),
+        )),
+        "class" | "c" => Ok((
+            ["class-", &name_of(value)].concat(),
+            json!({ "env": [{ "var": CLASS_VAR, "expected": value }] }),
+        )),
+        "user" | "u" => Ok((
+            ["user-", &name_of(value)].concat(),
+            json!({ "env": [{ "var": "USER", "expected": value }] }),
+        )),
+        key => Err(format!("unsupported condition {:?}", key)),
+    }
+}
+
+/// The file name and environments of the yadm alternate `name`, e.g.
+/// `.gitconfig##os.Darwin,class.work`. `None` for `##default` alternates.
+fn yadm_alternate(name: &str) -> Result<Option<(&str, Vec<Env>)>, String> {
+    let (base, attributes) = match name.split_once("##") {
+        Some(split) => split,
+        None => return Ok(Some((name, Vec::new()))),
+    };
+    let mut envs = Vec::new();
+    for attribute in attributes.split(',') {
+        match attribute {
+            "default" => return Ok(None),
+            "template" | "t" => return Err("templates cannot be imported".to_owned()),
+            attribute if attribute.starts_with("extension.") || attribute.starts_with("e.") => {},
+            attribute => envs.push(yadm_env(attribute)?),
+        }
+    }
+    envs.sort_by(|(left, _), (right, _)| left.cmp(right));
+    envs.dedup_by(|(left, _), (right, _)| left == right);
+    Ok(Some((base, envs)))
+}
+
+/// The files tracked by the yadm repository `repo`, with `target_dir` as its
+/// work tree.
+///
+/// Each alternate becomes a pile of its own, used under its environments. A
+/// `##default` alternate is only imported if there are no others, because
+/// hoard cannot express "none of the others apply".
+fn read_yadm(repo: &Path, target_dir: &Path) -> Result<Found, Error> {
+    let yadm_error = |message: String| Error::Yadm {
+        path: repo.to_path_buf(),
+        message,
+    };
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo)
+        .arg("--work-tree")
+        .arg(target_dir)
+        .args(["ls-files", "-z"])
+        .output()
+        .map_err(|error| yadm_error(error.to_string()))?;
+    if !output.status.success() {
+        return Err(yadm_error(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+
+    let tracked = String::from_utf8_lossy(&output.stdout).into_owned();
+    let mut found = Found::default();
+    let mut defaults = Vec::new();
+    for file in tracked.split('\0').filter(|file| !file.is_empty()) {
+        let source = target_dir.join(file);
+        let (dir, name) = file.rsplit_once('/').unwrap_or(("", file));
+        match yadm_alternate(name) {
+            Ok(_) if !source.exists() => {
+                tracing::warn!("skipping {}: it does not exist", source.display());
+            },
+            Ok(Some((name, envs))) => found.items.push(Item {
+                envs,
+                ..Item::new(source, Path::new(dir).join(name))
+            }),
+            Ok(None) => {
+                let name = name.split_once("##").map_or(name, |(name, _)| name);
+                defaults.push(Item::new(source, Path::new(dir).join(name)));
+            },
+            Err(reason) => tracing::warn!("skipping {}: {}", source.display(), reason),
+        }
+    }
+    for item in defaults {
+        if found.items.iter().any(|other| other.target == item.target) {
+            tracing::warn!(
+                "skipping {}: default alternates cannot be imported next to others",
+                item.source.display()
+            );
+        } else {
+            found.items.push(item);
+        }
+    }
+    Ok(found)
+}
+
+/// The name of the pile at `target` in `hoard`, used under `condition`.
+fn pile_name(hoard: &str, target: &Path, condition: &str, default_condition: &str) -> String {
+    let root = app_root(target);
+    let name = match target.strip_prefix(&root) {
+        Ok(rest) if !rest.as_os_str().is_empty() => rest
+            .iter()
+            .map(|component| name_of(&component.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join("-"),
+        Ok(_) | Err(_) => root
+            .file_name()
+            .map_or_else(String::new, |name| name_of(&name.to_string_lossy())),
+    };
+    let name = if condition == default_condition {
+        name
+    } else {
+        [&name, "-", &name_of(condition)].concat()
+    };
+    if adopt::is_valid_name(&name, true) {
+        name
+    } else {
+        [hoard, "-", &name].concat()
+    }
+}
+
+/// Group the `found` items into piles of hoards.
+///
+/// Items that are always installed use `default_condition`; the others use
+/// their environments.
+fn group(found: Found, default_condition: &str) -> BTreeMap<String, Vec<ImportedPile>> {
+    let mut piles: BTreeMap<(String, PathBuf, String), Vec<(PathBuf, PathBuf)>> = BTreeMap::new();
+    for item in found.items {
+        let root = app_root(&item.target);
+        let target = if item.single || !item.envs.is_empty() || found.private.contains(&root) {
+            item.target.clone()
+        } else {
+            root.clone()
+        };
+        let hoard = item.hoard.unwrap_or_else(|| {
+            root.file_name()
+                .map_or_else(String::new, |name| name_of(&name.to_string_lossy()))
+        });
+        let condition = if item.envs.is_empty() {
+            default_condition.to_owned()
+        } else {
+            item.envs
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join("|")
+        };
+        let relative = item
+            .target
+            .strip_prefix(&target)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let files = piles.entry((hoard, target, condition)).or_default();
+        if files.iter().any(|(_, other)| *other == relative) {
+            tracing::warn!(
+                "skipping {}: {} is already imported",
+                item.source.display(),
+                item.target.display()
+            );
+        } else {
+            files.push((item.source, relative));
+        }
+    }
+
+    let mut hoards: BTreeMap<String, Vec<ImportedPile>> = BTreeMap::new();
+    for ((hoard, target, condition), files) in piles {
+        let pile = hoards.entry(hoard.clone()).or_default();
+        let base = pile_name(&hoard, &target, &condition, default_condition);
+        let mut name = base.clone();
+        let mut count: usize = 1;
+        while pile.iter().any(|other| other.name == name) {
+            count += 1;
+            name = format!("{}-{}", base, count);
+        }
+        pile.push(ImportedPile {
+            name,
+            target,
+            condition,
+            files,
+        });
+    }
+    hoards
+}
+
+/// The path of `pile`, relative to the target directory: its target, or only
+/// the entries of it that the tool manages, so that files next to them are
+/// not backed up.
+fn pile_path(pile: &ImportedPile) -> PathBuf {
+    let names: BTreeSet<&OsStr> = pile
+        .files
+        .iter()
+        .filter_map(|(_, relative)| relative.iter().next())
+        .collect();
+    let mut names = names.into_iter();
+    match (names.next(), names.len()) {
+        (None, _) => pile.target.clone(),
+        (Some(name), 0) => pile.target.join(name),
+        (Some(first), _) => {
+            let alternatives = std::iter::once(first)
+                .chain(names)
+                .map(|name| glob::escape(&name.to_string_lossy()))
+                .collect::<Vec<_>>()
+                .join(",");
+            pile.target.join(["{", &alternatives, "}"].concat())
+        },
+    }
+}
+
+/// The configuration of a hoard holding `piles`, with paths in `target_dir`.
+///
+/// With `follow_links`, for tools that install symbolic links to their
+/// files, the hoard follows them to back up the files they point to.
+fn hoard_value(
+    piles: &[ImportedPile],
+    target_dir: &Path,
+    home: &Path,
+    follow_links: bool,
+) -> Value {
+    let conditions = |pile: &ImportedPile| {
+        let mut conditions = Map::new();
+        conditions.insert(
+            pile.condition.clone(),
+            Value::String(adopt::stored_path(&target_dir.join(pile_path(pile)), home)),
+        );
+        conditions
+    };
+    let mut hoard = match piles {
+        [pile] => conditions(pile),
+        piles => piles
+            .iter()
+            .map(|pile| (pile.name.clone(), Value::Object(conditions(pile))))
+            .collect(),
+    };
+    if follow_links {
+        hoard.insert("config".to_owned(), json!({ "follow_links": true }));
+    }
+    Value::Object(hoard)
+}
+
+/// Drop the hoards and piles of `imported` that are already configured in
+/// `doc` or `config`, or already stored in the hoards root.
+fn drop_existing(
+    imported: &mut BTreeMap<String, Vec<ImportedPile>>,
+    config: &Config,
+    doc: &mut ConfigDocument,
+    target_dir: &Path,
+) -> Result<(), Error> {
+    let hoards = doc.hoards_mut()?;
+    imported.retain(|name, piles| {
+        if !adopt::is_valid_name(name, false) {
+            tracing::warn!("skipping hoard {:?}: it is not a valid name", name);
+            return false;
+        }
+        if hoards.contains_key(name)
+            || config.hoards.contains_key(name)
+            || config.hoards_root.join(name).exists()
+        {
+            tracing::warn!("skipping hoard {}: it already exists", name);
+            return false;
+        }
+        piles.retain(|pile| {
+            let path = target_dir.join(&pile.target);
+            match adopt::check_not_covered(&path, &config.hoards) {
+                Ok(()) => true,
+                Err(error) => {
+                    tracing::warn!("skipping {}: {}", path.display(), error);
+                    false
+                },
+            }
+        });
+        !piles.is_empty()
+    });
+    Ok(())
+}
+
+/// Read what `tool` manages at `path`.
+fn read(tool: &str, path: &Path, target_dir: &Path, home: &Path) -> Result<Found, Error> {
+    let mut found = match tool {
+        "stow" => read_stow(path)?,
+        "dotbot" => read_dotbot(path, target_dir, home)?,
+        "chezmoi" => read_chezmoi(path)?,
+        "yadm" => read_yadm(path, target_dir)?,
+        tool => unreachable!("unsupported tool {}", tool),
+    };
+    found.private.extend(PRIVATE_DIRS.iter().map(PathBuf::from));
+    if found.items.is_empty() {
+        return Err(Error::NothingToImport(path.to_path_buf()));
+    }
+    Ok(found)
+}
+
+/// Import what `tool` (`stow`, `dotbot`, `chezmoi` or `yadm`) manages at
+/// `path` into the configuration file, and back up the new hoards the way
+/// `hoard backup` does.
+///
+/// `target` is the directory the tool installs into. It defaults to the parent
+/// of the stow directory for stow, and to the home directory otherwise. The
+/// files are used under an environment for this operating system, or under
+/// the environments of their yadm alternates.
+///
+/// Hoards that already exist are skipped. With `dry_run`, the new
+/// configuration is printed instead, and nothing is backed up.
+///
+/// # Errors
+///
+/// Any [`Error`] while reading the other tool's files, or while validating or
+/// writing the configuration, and any [`super::Error`] from the first
+/// backup.
+pub(super) fn run_import(
+    config: &Config,
+    tool: &str,
+    path: &Path,
+    target: Option<&PathBuf>,
+    dry_run: bool,
+) -> Result<(), super::Error> {
+    let _span = tracing::debug_span!("import", %tool, ?path).entered();
+    let home = PROJECT_DIRS.home_dir();
+    let path = absolute(path)?;
+    let target_dir = match (target, tool) {
+        (Some(target), _) => absolute(target)?,
+        (None, "stow") => path.parent().unwrap_or(&path).to_path_buf(),
+        (None, _) => home.to_path_buf(),
+    };
+
+    let found = read(tool, &path, &target_dir, home)?;
+    let environments: BTreeMap<String, Value> = found
+        .items
+        .iter()
+        .flat_map(|item| item.envs.iter().cloned())
+        .chain(std::iter::once((
+            OS.to_owned(),
+            catalog::os_environment(OS),
+        )))
+        .collect();
+    let mut imported = group(found, OS);
+
+    let mut doc = if config.config_file.exists() {
+        ConfigDocument::load(&config.config_file).map_err(Error::from)?
+    } else {
+        let format = infer_format(Some(&config.config_file), None)?;
+        ConfigDocument::new(config.config_file.clone(), format)
+    };
+    drop_existing(&mut imported, config, &mut doc, &target_dir)?;
+    if imported.is_empty() {
+        tracing::warn!("nothing left to import");
+        return Ok(());
+    }
+    let used: BTreeSet<&str> = imported
+        .values()
+        .flatten()
+        .flat_map(|pile| pile.condition.split('|'))
+        .collect();
+    let envs = doc.table_mut("envs").map_err(Error::from)?;
+    for (name, definition) in environments
+        .iter()
+        .filter(|(name, _)| used.contains(name.as_str()))
+    {
+        envs.entry(name.clone())
+            .or_insert_with(|| definition.clone());
+    }
+    // Stow and dotbot install symbolic links, the others copies
+    let follow_links = matches!(tool, "stow" | "dotbot");
+    let hoards = doc.hoards_mut().map_err(Error::from)?;
+    for (name, piles) in &imported {
+        hoards.insert(
+            name.clone(),
+            hoard_value(piles, &target_dir, home, follow_links),
+        );
+    }
+
+    let imported_config = config.rebuild(&doc)?;
+    if dry_run {
+        let text = doc.to_text().map_err(Error::from)?;
+        print_document(config.output, doc.format().name(), &text).map_err(Error::Print)?;
+        return Ok(());
+    }
+
+    if let Some(parent) = doc.path().parent() {
+        fs::create_dir_all(parent).map_err(|error| Error::IO {
+            path: parent.to_path_buf(),
+            error,
+        })?;
+    }
+    doc.save().map_err(Error::from)?;
+    tracing::info!(path = %doc.path().display(), "wrote configuration");
+    for (name, piles) in &imported {
+        tracing::info!("imported {} with {} pile(s)", name, piles.len());
+    }
+    imported_config.run_operation(&imported.keys().cloned().collect::<Vec<_>>(), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(source: &str, target: &str) -> Item {
+        Item::new(PathBuf::from(source), PathBuf::from(target))
+    }
+
+    #[test]
+    fn chezmoi_attributes_are_stripped() {
+        let target = |name: &str, private: bool| SourceEntry::Target {
+            name: name.to_owned(),
+            private,
+        };
+        assert_eq!(chezmoi_entry("dot_zshrc"), target(".zshrc", false));
+        assert_eq!(
+            chezmoi_entry("private_executable_dot_script"),
+            target(".script", true)
+        );
+        assert_eq!(chezmoi_entry("literal_dot_file"), target("dot_file", false));
+        assert_eq!(chezmoi_entry("exact_nvim"), target("nvim", false));
+        assert!(matches!(
+            chezmoi_entry("dot_gitconfig.tmpl"),
+            SourceEntry::Skipped(_)
+        ));
+        assert!(matches!(
+            chezmoi_entry("encrypted_private_dot_netrc"),
+            SourceEntry::Skipped(_)
+        ));
+        assert!(matches!(
+            chezmoi_entry("run_once_install.sh"),
+            SourceEntry::Skipped(_)
+        ));
+    }
+
+    #[test]
+    fn yadm_alternates_become_environments() {
+        let (name, envs) = yadm_alternate(".gitconfig##os.Darwin,class.work")
+            .unwrap()
+            .unwrap();
+        assert_eq!(name, ".gitconfig");
+        let names: Vec<&str> = envs.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["class-work", "macos"]);
+        assert_eq!(envs[1].1, json!({ "os": ["macos"] }));
+        assert_eq!(yadm_alternate(".bashrc##default").unwrap(), None);
+        assert_eq!(
+            yadm_alternate(".bashrc").unwrap(),
+            Some((".bashrc", Vec::new()))
+        );
+        yadm_alternate(".vimrc##distro.Ubuntu").unwrap_err();
+    }
+
+    #[test]
+    fn items_are_grouped_by_application_directory() {
+        let mut found = Found::default();
+        found.private.insert(PathBuf::from(".ssh"));
+        found.items = vec![
+            item("/src/nvim/init.lua", ".config/nvim/init.lua"),
+            item("/src/nvim/lua/plugins.lua", ".config/nvim/lua/plugins.lua"),
+            item("/src/ssh/config", ".ssh/config"),
+            item("/src/ssh/known_hosts", ".ssh/known_hosts"),
+            Item {
+                envs: vec![("macos".to_owned(), json!({}))],
+                ..item("/src/gitconfig##os.Darwin", ".gitconfig")
+            },
+            item("/src/gitconfig", ".gitconfig"),
+        ];
+        let hoards = group(found, "linux");
+
+        let nvim = &hoards["nvim"];
+        assert_eq!(nvim.len(), 1);
+        assert_eq!(nvim[0].target, PathBuf::from(".config/nvim"));
+        assert_eq!(nvim[0].files, vec![
+            (
+                PathBuf::from("/src/nvim/init.lua"),
+                PathBuf::from("init.lua")
+            ),
+            (
+                PathBuf::from("/src/nvim/lua/plugins.lua"),
+                PathBuf::from("lua/plugins.lua")
+            ),
+        ]);
+
+        let ssh: Vec<(&str, &Path)> = hoards["ssh"]
+            .iter()
+            .map(|pile| (pile.name.as_str(), pile.target.as_path()))
+            .collect();
+        assert_eq!(ssh, vec![
+            ("ssh-config", Path::new(".ssh/config")),
+            ("known_hosts", Path::new(".ssh/known_hosts")),
+        ]);
+
+        let git: Vec<(&str, &str)> = hoards["gitconfig"]
+            .iter()
+            .map(|pile| (pile.name.as_str(), pile.condition.as_str()))
+            .collect();
+        assert_eq!(git, vec![
+            ("gitconfig", "linux"),
+            ("gitconfig-macos", "macos")
+        ]);
+        assert_eq!(
+            hoard_value(
+                &hoards["nvim"],
+                Path::new("/home/user"),
+                Path::new("/home/user"),
+                true
+            ),
+            json!({
+                "linux": "${HOME}/.config/nvim/{init.lua,lua}",
+                "config": { "follow_links": true },
+            })
+        );
+        assert_eq!(
+            hoard_value(
+                &hoards["ssh"],
+                Path::new("/home/user"),
+                Path::new("/home/user"),
+                false
+            ),
+            json!({
+                "ssh-config": { "linux": "${HOME}/.ssh/config" },
+                "known_hosts": { "linux": "${HOME}/.ssh/known_hosts" },
+            })
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Generate a starter configuration file.
+//!
+//! See [`run_init`].
+
+use super::{
+    catalog::{self, DetectedPile},
+    edit::ConfigDocument,
+    filetypes::{format::ConfigFormat, infer_format},
+    output::{emit, print_document, Event},
+    Config,
+};
+use std::{collections::BTreeMap, env::consts::OS, fs, io, path::PathBuf};
+use thiserror::Error;
+
+/// Errors that can occur while generating a configuration file.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The embedded application catalog could not be parsed.
+    #[error("failed to parse the application catalog: {0}")]
+    Catalog(#[from] ::toml::de::Error),
+    /// The configuration file exists and `--force` was not given.
+    #[error("{0} already exists, use --force to overwrite it")]
+    AlreadyExists(PathBuf),
+    /// The directory of the configuration file could not be created.
+    #[error("failed to create directory {path}: {error}")]
+    CreateDir {
+        /// The directory that could not be created.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+    /// The generated configuration could not be printed.
+    #[error("failed to print configuration: {0}")]
+    Print(#[source] io::Error),
+}
+
+/// The path and format to write the generated configuration in.
+///
+/// Without `output_file`, the configured configuration file is used, with its
+/// extension replaced if a different `format` is given.
+fn destination(
+    config: &Config,
+    format: Option<&String>,
+    output_file: Option<&PathBuf>,
+) -> Result<(PathBuf, ConfigFormat), super::Error> {
+    let path = match (output_file, format) {
+        (Some(path), _) => path.clone(),
+        (None, Some(format)) => {
+            let format = infer_format(None, Some(format))?;
+            config
+                .config_file
+                .with_extension(format.preferred_extension())
+        },
+        (None, None) => config.config_file.clone(),
+    };
+    let format = infer_format(Some(&path), format)?;
+    Ok((path, format))
+}
+
+/// Generate a starter configuration with an environment for this operating
+/// system and, with `detect`, a hoard and environment for each application
+/// in the catalog found on this system.
+///
+/// The configuration is validated, then written to `output_file` (or the
+/// configuration file), or printed with `dry_run`.
+///
+/// # Errors
+///
+/// [`Error::AlreadyExists`] if the file exists and `--force` was not given,
+/// or any error while generating, validating or writing the configuration.
+pub(super) fn run_init(
+    config: &Config,
+    detect: bool,
+    format: Option<&String>,
+    output_file: Option<&PathBuf>,
+    dry_run: bool,
+) -> Result<(), super::Error> {
+    config.emit_started("init");
+    let (path, format) = destination(config, format, output_file)?;
+    if !dry_run && !config.force && path.exists() {
+        return Err(Error::AlreadyExists(path).into());
+    }
+
+    let catalog = if detect {
+        catalog::load().map_err(Error::from)?
+    } else {
+        BTreeMap::default()
+    };
+    let detected: Vec<(&str, &catalog::App, Vec<DetectedPile<'_>>)> = catalog
+        .iter()
+        .map(|(name, app)| (name.as_str(), app, app.detect(OS)))
+        .filter(|(_, _, piles)| !piles.is_empty())
+        .collect();
+
+    let mut doc = ConfigDocument::new(path, format);
+    let envs = doc.table_mut("envs")?;
+    envs.insert(OS.to_owned(), catalog::os_environment(OS));
+    for (name, app, piles) in &detected {
+        envs.insert((*name).to_owned(), app.environment());
+        for os in piles.iter().flat_map(DetectedPile::operating_systems) {
+            envs.entry(os.to_owned())
+                .or_insert_with(|| catalog::os_environment(os));
+        }
+    }
+    let hoards = doc.hoards_mut()?;
+    for (name, _, piles) in &detected {
+        hoards.insert((*name).to_owned(), catalog::hoard_config(name, piles));
+    }
+
+    for (name, _, piles) in &detected {
+        let paths: Vec<PathBuf> = piles
+            .iter()
+            .map(|pile| pile.found().to_path_buf())
+            .collect();
+        tracing::info!(app = %name, "detected {}", name);
+        if config.output.is_json() {
+            emit(&Event::AppDetected {
+                app:   name,
+                paths: &paths,
+            });
+        }
+    }
+    if detect && detected.is_empty() {
+        tracing::warn!("no known applications were found");
+    }
+
+    config.rebuild(&doc)?;
+    if dry_run {
+        let text = doc.to_text()?;
+        print_document(config.output, doc.format().name(), &text).map_err(Error::Print)?;
+    } else {
+        if let Some(parent) = doc.path().parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::CreateDir {
+                path: parent.to_path_buf(),
+                error,
+            })?;
+        }
+        doc.save()?;
+        tracing::info!(path = %doc.path().display(), "wrote configuration");
+    }
+    config.emit_finished("init");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::builder::Builder;
+
+    #[test]
+    fn format_changes_extension_of_config_file() {
+        let config = Builder::new()
+            .set_config_file(PathBuf::from("/home/user/.config/hoard/config.toml"))
+            .build()
+            .unwrap();
+        let yaml = "yaml".to_owned();
+        assert_eq!(
+            destination(&config, Some(&yaml), None).unwrap(),
+            (
+                PathBuf::from("/home/user/.config/hoard/config.yaml"),
+                ConfigFormat::Yaml
+            )
+        );
+
+        let output = PathBuf::from("starter.json");
+        assert_eq!(
+            destination(&config, None, Some(&output)).unwrap(),
+            (output, ConfigFormat::Json)
+        );
+        assert_eq!(
+            destination(&config, None, None).unwrap(),
+            (
+                PathBuf::from("/home/user/.config/hoard/config.toml"),
+                ConfigFormat::Toml
+            )
+        );
+    }
+}
@@ -0,0 +1,568 @@
+//! Lint checks on the configuration, run by `hoard validate`.
+//!
+//! A configuration can build and still contain mistakes, e.g. an environment
+//! that no pile uses or two hoards backing up the same files. See
+//! [`run_validate`].
+
+use super::{
+    adopt::is_valid_name,
+    builder::{
+        environment::Environment,
+        glob,
+        hoard::{ConditionPaths, EncryptionKind, Hoard as HoardSource, Pile as PileSource},
+        vars::PathVars,
+        GlobalConfig,
+    },
+    migrate::display_name,
+    output::{emit, Event},
+    Config,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    io::{self, Write},
+    path::PathBuf,
+};
+use thiserror::Error;
+
+/// Errors that can occur while linting the configuration.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The configuration has findings that are not allowed.
+    #[error("configuration has {errors} error(s) and {warnings} warning(s)")]
+    Findings {
+        /// The number of findings with [`Severity::Error`].
+        errors:   usize,
+        /// The number of findings with [`Severity::Warning`].
+        warnings: usize,
+    },
+    /// The findings could not be printed.
+    #[error("failed to print lint findings: {0}")]
+    Print(#[source] io::Error),
+}
+
+/// How serious a [`Finding`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Probably a mistake. Only fails `hoard validate` with `--deny warnings`.
+    Warning,
+    /// Always fails `hoard validate`.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in the configuration.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Finding {
+    /// A stable name of the check that found the problem, e.g. `unused-env`.
+    pub code:     &'static str,
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// What is wrong.
+    pub message:  String,
+}
+
+impl Finding {
+    fn warning(code: &'static str, message: String) -> Self {
+        Self {
+            code,
+            severity: Severity::Warning,
+            message,
+        }
+    }
+
+    fn error(code: &'static str, message: String) -> Self {
+        Self {
+            code,
+            severity: Severity::Error,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+/// The configuration to lint.
+struct Lint<'a> {
+    envs:        &'a HashMap<String, Environment>,
+    hoards:      &'a HashMap<String, HoardSource>,
+    exclusivity: &'a [Vec<String>],
+    global:      &'a GlobalConfig,
+    vars:        &'a PathVars,
+}
+
+/// A condition of a pile and the path used with it.
+struct PilePath<'a> {
+    name:      String,
+    condition: &'a str,
+    path:      PathBuf,
+}
+
+/// A finding if the paths of `left` and `right` are the same or one is inside
+/// the other.
+fn overlap(left: &PilePath<'_>, right: &PilePath<'_>) -> Option<Finding> {
+    if left.path == right.path {
+        return Some(Finding::error(
+            "duplicate-path",
+            format!(
+                "{} and {} both use {}",
+                left.name,
+                right.name,
+                left.path.display()
+            ),
+        ));
+    }
+    let (outer, inner) = if right.path.starts_with(&left.path) {
+        (left, right)
+    } else if left.path.starts_with(&right.path) {
+        (right, left)
+    } else {
+        return None;
+    };
+    Some(Finding::warning(
+        "nested-path",
+        format!(
+            "{} ({}) is inside {} ({}), so its files are stored twice",
+            inner.name,
+            inner.path.display(),
+            outer.name,
+            outer.path.display()
+        ),
+    ))
+}
+
+/// A pile with the name and definition of its hoard.
+type PileEntry<'a> = (&'a str, &'a HoardSource, Option<&'a str>, &'a PileSource);
+
+impl<'a> Lint<'a> {
+    /// Every pile, with the name of its hoard, sorted by name.
+    fn piles(&self) -> Vec<PileEntry<'a>> {
+        let mut piles: Vec<_> = self
+            .hoards
+            .iter()
+            .flat_map(|(name, hoard)| {
+                hoard
+                    .piles()
+                    .into_iter()
+                    .map(move |(pile, source)| (name.as_str(), hoard, pile, source))
+            })
+            .collect();
+        piles.sort_unstable_by_key(|(hoard, _, pile, _)| (*hoard, *pile));
+        piles
+    }
+
+    /// Environments that are defined but neither used by any pile nor set
+    /// variables.
+    fn unused_envs(&self) -> Vec<Finding> {
+        let used: BTreeSet<&str> = self
+            .hoards
+            .values()
+            .flat_map(HoardSource::piles)
+            .flat_map(|(_, pile)| pile.conditions().keys())
+            .flat_map(|condition| condition.split('|'))
+            .collect();
+        self.envs
+            .iter()
+            .filter(|(name, env)| env.vars().is_none() && !used.contains(name.as_str()))
+            .map(|(name, _)| {
+                Finding::warning(
+                    "unused-env",
+                    format!("environment `{}` is not used by any pile", name),
+                )
+            })
+            .collect()
+    }
+
+    /// Why `condition` can never apply, if it cannot.
+    fn impossibility(&self, condition: &str) -> Option<String> {
+        let envs: Vec<&str> = condition.split('|').collect();
+        envs.iter().enumerate().find_map(|(index, left)| {
+            envs.iter().skip(index + 1).find_map(|right| {
+                let reason = self.envs.get(*left)?.conflict(self.envs.get(*right)?)?;
+                Some(format!(
+                    "`{}` and `{}` require different {}",
+                    left, right, reason
+                ))
+            })
+        })
+    }
+
+    /// Pile conditions with environments that can never apply together.
+    fn impossible_conditions(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (hoard, _, pile, source) in self.piles() {
+            let mut conditions: Vec<&String> = source.conditions().keys().collect();
+            conditions.sort_unstable();
+            for condition in conditions {
+                if let Some(reason) = self.impossibility(condition) {
+                    findings.push(Finding::warning(
+                        "impossible-condition",
+                        format!(
+                            "condition `{}` of {} can never apply: {}",
+                            condition,
+                            display_name(hoard, pile),
+                            reason
+                        ),
+                    ));
+                }
+            }
+        }
+        findings
+    }
+
+    /// Piles of different hoards, or of the same hoard, whose paths are the
+    /// same or inside each other under conditions that can apply together.
+    fn overlapping_paths(&self) -> Vec<Finding> {
+        let paths: Vec<PilePath<'_>> = self
+            .piles()
+            .into_iter()
+            .flat_map(|(hoard, _, pile, source)| {
+                let mut conditions: Vec<(&String, &ConditionPaths)> =
+                    source.conditions().iter().collect();
+                conditions.sort_unstable_by_key(|(condition, _)| *condition);
+                conditions.into_iter().flat_map(move |(condition, paths)| {
+                    paths.candidates().iter().filter_map(move |path| {
+                        Some(PilePath {
+                            name: display_name(hoard, pile),
+                            condition,
+                            path: self.vars.expand(path).ok()?,
+                        })
+                    })
+                })
+            })
+            .collect();
+
+        let mut reported = BTreeSet::new();
+        let mut findings = Vec::new();
+        for (index, left) in paths.iter().enumerate() {
+            for right in &paths[index + 1..] {
+                if left.name == right.name || reported.contains(&(&left.name, &right.name)) {
+                    continue;
+                }
+                let both = [left.condition, "|", right.condition].concat();
+                if self.impossibility(&both).is_some() {
+                    continue;
+                }
+                if let Some(finding) = overlap(left, right) {
+                    reported.insert((&left.name, &right.name));
+                    findings.push(finding);
+                }
+            }
+        }
+        findings
+    }
+
+    /// Exclusivity lists naming environments that are not defined.
+    fn unknown_exclusive_envs(&self) -> Vec<Finding> {
+        self.exclusivity
+            .iter()
+            .flat_map(|list| {
+                list.iter()
+                    .filter(|name| !self.envs.contains_key(*name))
+                    .map(move |name| {
+                        Finding::warning(
+                            "unknown-env",
+                            format!(
+                                "exclusivity list [{}] names `{}`, which is not an environment",
+                                list.join(", "),
+                                name
+                            ),
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Hoard and pile names that cannot be used as directory names in the
+    /// hoards root.
+    fn unsafe_names(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let mut names: Vec<&String> = self.hoards.keys().collect();
+        names.sort_unstable();
+        for name in names {
+            if !is_valid_name(name, false) {
+                findings.push(Finding::error(
+                    "unsafe-name",
+                    format!("hoard name `{}` cannot be used as a directory name", name),
+                ));
+            }
+        }
+        for (hoard, _, pile, _) in self.piles() {
+            if let Some(pile) = pile.filter(|pile| !is_valid_name(pile, true)) {
+                findings.push(Finding::error(
+                    "unsafe-name",
+                    format!(
+                        "pile name `{}` of hoard {} cannot be used as a directory name",
+                        pile, hoard
+                    ),
+                ));
+            }
+        }
+        findings
+    }
+
+    /// Pile paths whose glob patterns match several directories on this
+    /// system, so that the pile is skipped.
+    fn ambiguous_paths(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (hoard, _, pile, source) in self.piles() {
+            let mut conditions: Vec<(&String, &ConditionPaths)> =
+                source.conditions().iter().collect();
+            conditions.sort_unstable_by_key(|(condition, _)| *condition);
+            for (condition, paths) in conditions {
+                for path in paths.candidates() {
+                    let error = match self.vars.expand(path).map(|path| glob::resolve(&path)) {
+                        Ok(Err(error @ glob::Error::Ambiguous { .. })) => error,
+                        Ok(_) | Err(_) => continue,
+                    };
+                    findings.push(Finding::error(
+                        "ambiguous-path",
+                        format!(
+                            "condition `{}` of {} is skipped: {}",
+                            condition,
+                            display_name(hoard, pile),
+                            error
+                        ),
+                    ));
+                }
+            }
+        }
+        findings
+    }
+
+    /// Piles encrypted asymmetrically without a public key at any level.
+    fn missing_encryption_keys(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (hoard, source, pile, pile_source) in self.piles() {
+            let mut layer = self.global.config.clone();
+            if pile.is_some() {
+                layer = layer.layer(source.config().cloned().unwrap_or_default());
+            }
+            let encryption = layer
+                .layer(pile_source.config().cloned().unwrap_or_default())
+                .encryption
+                .unwrap_or_default();
+            if encryption.kind == Some(EncryptionKind::Asymmetric)
+                && encryption.public_key.is_none()
+                && self.global.public_key.is_none()
+            {
+                findings.push(Finding::warning(
+                    "missing-encryption-key",
+                    format!(
+                        "{} is encrypted asymmetrically but no `encrypt_pub_key` or \
+                         `global_config.public_key` is set, so gpg will ask for one",
+                        display_name(hoard, pile)
+                    ),
+                ));
+            }
+        }
+        findings
+    }
+
+    /// Every finding, sorted by code.
+    fn run(&self) -> Vec<Finding> {
+        let mut findings: Vec<Finding> = [
+            self.unused_envs(),
+            self.impossible_conditions(),
+            self.overlapping_paths(),
+            self.unknown_exclusive_envs(),
+            self.unsafe_names(),
+            self.missing_encryption_keys(),
+            self.ambiguous_paths(),
+        ]
+        .concat();
+        findings.sort();
+        findings
+    }
+}
+
+/// Lint the configuration and print what was found. Errors always fail,
+/// warnings only with `deny_warnings`.
+///
+/// # Errors
+///
+/// [`Error::Findings`] if the configuration has findings that are not allowed,
+/// or [`Error::Print`].
+pub(super) fn run_validate(config: &Config, deny_warnings: bool) -> Result<(), Error> {
+    let findings = Lint {
+        envs:        &config.env_sources,
+        hoards:      &config.sources,
+        exclusivity: &config.exclusivity,
+        global:      &config.global_config,
+        vars:        &config.vars,
+    }
+    .run();
+
+    if config.output.is_json() {
+        for finding in &findings {
+            emit(&Event::LintFinding {
+                code:     finding.code,
+                severity: finding.severity,
+                message:  &finding.message,
+            });
+        }
+    } else {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for finding in &findings {
+            writeln!(out, "{}", finding).map_err(Error::Print)?;
+        }
+    }
+
+    let count = |severity| {
+        findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    };
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+    if errors > 0 || (deny_warnings && warnings > 0) {
+        return Err(Error::Findings { errors, warnings });
+    }
+    tracing::info!(warnings, "configuration is valid");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(envs: &str, hoards: &str, exclusivity: &[&[&str]]) -> Vec<Finding> {
+        let envs: HashMap<String, Environment> = toml::from_str(envs).unwrap();
+        let hoards: HashMap<String, HoardSource> = toml::from_str(hoards).unwrap();
+        let exclusivity: Vec<Vec<String>> = exclusivity
+            .iter()
+            .map(|list| list.iter().map(|name| (*name).to_owned()).collect())
+            .collect();
+        Lint {
+            envs:        &envs,
+            hoards:      &hoards,
+            exclusivity: &exclusivity,
+            global:      &GlobalConfig::default(),
+            vars:        &PathVars::default(),
+        }
+        .run()
+    }
+
+    fn codes(findings: &[Finding]) -> Vec<&'static str> {
+        findings.iter().map(|finding| finding.code).collect()
+    }
+
+    const ENVS: &str =
+        "[linux]\nos = [\"linux\"]\n[macos]\nos = [\"macos\"]\n[laptop]\nhostname = [\"laptop\"]\n";
+
+    #[test]
+    fn clean_configuration_has_no_findings() {
+        let findings = lint(
+            ENVS,
+            "[nvim]\nlinux = \"/home/user/.config/nvim\"\nmacos = \
+             \"/Users/user/.config/nvim\"\n[fish]\n\"linux|laptop\" = \
+             \"/home/user/.config/fish\"\n",
+            &[&["linux", "macos"]],
+        );
+        assert_eq!(findings, Vec::new());
+    }
+
+    #[test]
+    fn unused_and_unknown_environments_are_warnings() {
+        let findings = lint(ENVS, "[nvim]\nlinux = \"/home/user/.config/nvim\"\n", &[&[
+            "linux", "windows",
+        ]]);
+        assert_eq!(codes(&findings), [
+            "unknown-env",
+            "unused-env",
+            "unused-env"
+        ]);
+        assert!(findings
+            .iter()
+            .all(|finding| finding.severity == Severity::Warning));
+        assert!(findings[0].message.contains("`windows`"));
+    }
+
+    #[test]
+    fn impossible_conditions_are_found() {
+        let findings = lint(
+            ENVS,
+            "[nvim]\n\"linux|macos\" = \"/nvim\"\n\"laptop|linux\" = \"/nvim2\"\n",
+            &[],
+        );
+        assert_eq!(codes(&findings), ["impossible-condition"]);
+        assert!(findings[0]
+            .message
+            .contains("`linux` and `macos` require different operating systems"));
+    }
+
+    #[test]
+    fn overlapping_paths_are_found() {
+        let findings = lint(
+            ENVS,
+            "[config]\nlinux = \"/home/user/.config\"\n[nvim]\nlinux = \
+             \"/home/user/.config/nvim\"\nmacos = \"/Users/user/.config/nvim\"\n[vim]\nlinux = \
+             \"/home/user/.config/nvim\"\n[mac]\nmacos = \"/home/user/.config\"\n",
+            &[],
+        );
+        let findings: Vec<(&str, Severity)> = findings
+            .iter()
+            .map(|finding| (finding.code, finding.severity))
+            .collect();
+        assert_eq!(findings, [
+            ("duplicate-path", Severity::Error),
+            ("nested-path", Severity::Warning),
+            ("nested-path", Severity::Warning),
+            ("unused-env", Severity::Warning),
+        ]);
+    }
+
+    #[test]
+    fn ambiguous_paths_are_found() {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        for profile in &["a.default", "b.default"] {
+            std::fs::create_dir(dir.path().join(profile)).unwrap();
+        }
+        let findings = lint(
+            ENVS,
+            &format!(
+                "[firefox]\nlinux = \"{}/*.default/user.js\"\nmacos = \"{}/a.*/user.js\"\n",
+                dir.path().display(),
+                dir.path().display()
+            ),
+            &[],
+        );
+        assert_eq!(codes(&findings), ["ambiguous-path", "unused-env"]);
+        assert!(findings[0]
+            .message
+            .starts_with("condition `linux` of firefox"));
+    }
+
+    #[test]
+    fn unsafe_names_and_missing_keys_are_found() {
+        let findings = lint(
+            ENVS,
+            "[\"..\"]\nlinux = \"/a\"\n[nvim.\"a/b\"]\nlinux = \
+             \"/b\"\n[gpg.config.encryption]\nencrypt = \"asymmetric\"\n[gpg.keys]\nlinux = \
+             \"/c\"\n",
+            &[],
+        );
+        assert_eq!(codes(&findings), [
+            "missing-encryption-key",
+            "unsafe-name",
+            "unsafe-name",
+            "unused-env",
+            "unused-env",
+        ]);
+    }
+}
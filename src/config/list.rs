@@ -0,0 +1,401 @@
+//! List what the configured hoards resolve to on this system.
+//!
+//! See [`run_list`].
+
+use super::{
+    builder::{
+        glob,
+        hoard::{
+            ConditionPaths, ConfigLayer, Encryption, Error as HoardError, Hoard as HoardSource,
+            Pile as PileSource, SymmetricEncryption, Walker,
+        },
+    },
+    hoard::{Hoard, Pile},
+    output::{emit, Event},
+    Config,
+};
+use indicatif::HumanBytes;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Errors that can occur while listing hoards.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The environment conditions of a pile could not be evaluated.
+    #[error("{0}")]
+    Hoard(#[from] HoardError),
+    /// The stored files of a pile could not be read.
+    #[error("failed to read {path}: {error}")]
+    IO {
+        /// The path that could not be read.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+    /// The list could not be printed.
+    #[error("failed to print hoards: {0}")]
+    Print(#[source] io::Error),
+}
+
+/// Everything listed about a single pile.
+struct Listing<'a> {
+    hoard:      &'a str,
+    pile:       Option<&'a str>,
+    /// The environment condition whose path is used, if any matches.
+    condition:  Option<&'a str>,
+    /// Every configured condition, sorted, with its unexpanded path.
+    conditions: Vec<(&'a str, &'a ConditionPaths)>,
+    /// The expanded path, if any condition matches.
+    path:       Option<&'a Path>,
+    /// The expanded candidate paths of the matching condition, if it has
+    /// several.
+    candidates: &'a [PathBuf],
+    encryption: Option<Encryption>,
+    walker:     Walker,
+    /// The level of the configuration (`global`, `hoard` or `pile`) each
+    /// setting that is not a default comes from.
+    settings:   BTreeMap<&'static str, &'static str>,
+    /// Number of files and total size in bytes stored in the hoards root.
+    stored:     (usize, u64),
+}
+
+/// Number of files and their total size in bytes at `path`, which may be a
+/// file or a directory. A missing path has no files.
+fn stored_size(path: &Path) -> Result<(usize, u64), Error> {
+    let io_error = |error| Error::IO {
+        path: path.to_path_buf(),
+        error,
+    };
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(err) => return Err(io_error(err)),
+    };
+    if !metadata.is_dir() {
+        return Ok((1, metadata.len()));
+    }
+
+    let mut total = (0, 0);
+    for entry in fs::read_dir(path).map_err(io_error)? {
+        let (files, bytes) = stored_size(&entry.map_err(io_error)?.path())?;
+        total.0 += files;
+        total.1 += bytes;
+    }
+    Ok(total)
+}
+
+/// A short description of `encryption`, without any password.
+fn describe_encryption(encryption: &Encryption) -> String {
+    match encryption {
+        Encryption::Symmetric(SymmetricEncryption::Password(_)) =>
+            "symmetric (password)".to_owned(),
+        Encryption::Symmetric(SymmetricEncryption::PasswordCmd(cmd)) =>
+            format!("symmetric (password from `{}`)", cmd.join(" ")),
+        Encryption::Asymmetric(asymmetric) => format!(
+            "asymmetric ({}{})",
+            asymmetric.public_key.as_ref().map_or_else(
+                || "key chosen when used".to_owned(),
+                |key| format!("key {}", key)
+            ),
+            if asymmetric.armor { ", armored" } else { "" }
+        ),
+    }
+}
+
+/// The level each setting is taken from, given the settings of each level
+/// from the top down.
+fn setting_levels(
+    levels: &[(&'static str, Option<&ConfigLayer>)],
+) -> BTreeMap<&'static str, &'static str> {
+    let mut settings = BTreeMap::new();
+    for &(level, layer) in levels {
+        for field in layer.iter().flat_map(|layer| layer.set_fields()) {
+            settings.insert(field, level);
+        }
+    }
+    settings
+}
+
+/// A short description of the settings of `walker`.
+fn describe_walker(walker: &Walker) -> String {
+    let mut parts = vec![format!(
+        "{} {:?}{}",
+        if walker.regex { "regex" } else { "glob" },
+        walker.pattern,
+        if walker.case_sensitive {
+            " (case sensitive)"
+        } else {
+            ""
+        }
+    )];
+    if walker.hidden {
+        parts.push("hidden files".to_owned());
+    }
+    if walker.follow_links {
+        parts.push("follows links".to_owned());
+    }
+    if let Some(depth) = walker.max_depth {
+        parts.push(format!("max depth {}", depth));
+    }
+    if !walker.exclude.is_empty() {
+        parts.push(format!("excluding {}", walker.exclude.join(", ")));
+    }
+    parts.join(", ")
+}
+
+impl Listing<'_> {
+    /// Write the listing as human-readable text.
+    fn write_to(&self, out: &mut impl Write, all: bool) -> io::Result<()> {
+        match self.pile {
+            Some(pile) => writeln!(out, "{}:{}", self.hoard, pile)?,
+            None => writeln!(out, "{}", self.hoard)?,
+        }
+        match (self.condition, self.path) {
+            (Some(condition), Some(path)) => {
+                writeln!(out, "  condition:  {}", condition)?;
+                let exists = if glob::exists(path) { "" } else { " (missing)" };
+                writeln!(out, "  path:       {}{}", path.display(), exists)?;
+                for (index, candidate) in self.candidates.iter().enumerate() {
+                    let marker = if candidate == path { '*' } else { ' ' };
+                    let missing = if glob::exists(candidate) {
+                        ""
+                    } else {
+                        " (missing)"
+                    };
+                    writeln!(
+                        out,
+                        "    {} {}. {}{}",
+                        marker,
+                        index + 1,
+                        candidate.display(),
+                        missing
+                    )?;
+                }
+            },
+            (Some(condition), None) => {
+                writeln!(out, "  condition:  {}", condition)?;
+                writeln!(
+                    out,
+                    "  path:       NONE -- no directory matches, this pile is skipped"
+                )?;
+            },
+            (None, _) => writeln!(
+                out,
+                "  condition:  NONE -- no environment matches, this pile is skipped"
+            )?,
+        }
+        writeln!(
+            out,
+            "  encryption: {}",
+            self.encryption
+                .as_ref()
+                .map_or_else(|| "none".to_owned(), describe_encryption)
+        )?;
+        writeln!(out, "  walker:     {}", describe_walker(&self.walker))?;
+        if self.settings.is_empty() {
+            writeln!(out, "  settings:   defaults")?;
+        } else {
+            let settings: Vec<String> = self
+                .settings
+                .iter()
+                .map(|(setting, level)| format!("{} ({})", setting, level))
+                .collect();
+            writeln!(out, "  settings:   {}", settings.join(", "))?;
+        }
+        writeln!(
+            out,
+            "  stored:     {} file(s), {}",
+            self.stored.0,
+            HumanBytes(self.stored.1)
+        )?;
+        if all {
+            writeln!(out, "  conditions:")?;
+            for &(condition, path) in &self.conditions {
+                let marker = if Some(condition) == self.condition {
+                    '*'
+                } else {
+                    ' '
+                };
+                writeln!(out, "    {} {} = {}", marker, condition, path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit the listing as an event.
+    fn emit(&self, all: bool) {
+        let exists = self.path.map_or(false, glob::exists);
+        emit(&Event::PileListed {
+            hoard: self.hoard,
+            pile: self.pile,
+            condition: self.condition,
+            path: self.path,
+            exists,
+            candidates: self.candidates,
+            encryption: self.encryption.as_ref().map(Encryption::name),
+            walker: &self.walker,
+            settings: &self.settings,
+            stored_files: self.stored.0,
+            stored_bytes: self.stored.1,
+            conditions: if all {
+                self.conditions.iter().copied().collect()
+            } else {
+                BTreeMap::default()
+            },
+        });
+    }
+}
+
+/// Collect the listing of `pile` of `hoard`.
+fn list_pile<'a>(
+    config: &'a Config,
+    hoard: &'a str,
+    pile_name: Option<&'a str>,
+    (hoard_source, source): (&'a HoardSource, &'a PileSource),
+    pile: &'a Pile,
+) -> Result<Listing<'a>, Error> {
+    let condition = source.matching_condition(&config.environments, &config.exclusivity)?;
+    let mut conditions: Vec<(&str, &ConditionPaths)> = source
+        .conditions()
+        .iter()
+        .map(|(condition, path)| (condition.as_str(), path))
+        .collect();
+    conditions.sort_unstable_by_key(|(condition, _)| *condition);
+
+    let stored = config.hoards_root.join(hoard);
+    let stored = match pile_name {
+        Some(name) => stored.join(name),
+        None => stored,
+    };
+    let pile_config = pile.config.clone().unwrap_or_default();
+    let global = &config.global_config;
+    let mut settings = setting_levels(&[
+        ("global", Some(&global.config)),
+        ("hoard", hoard_source.config()),
+        ("pile", pile_name.and(source.config())),
+    ]);
+    if let (Some(Encryption::Asymmetric(_)), Some(_)) =
+        (&pile_config.encryption, &global.public_key)
+    {
+        settings.entry("encrypt_pub_key").or_insert("global");
+    }
+
+    Ok(Listing {
+        hoard,
+        pile: pile_name,
+        condition,
+        conditions,
+        path: pile.path.as_deref(),
+        candidates: if pile.candidates.len() > 1 {
+            &pile.candidates
+        } else {
+            &[]
+        },
+        encryption: pile_config.encryption,
+        walker: pile_config.walker,
+        settings,
+        stored: stored_size(&stored)?,
+    })
+}
+
+/// Print every hoard and pile with the path it resolves to on this system.
+///
+/// Piles without a matching environment are flagged, as they are skipped by
+/// backups and restores. With `all`, every configured condition is printed
+/// as well.
+///
+/// # Errors
+///
+/// Any [`enum@Error`] while evaluating the conditions or reading the stored
+/// files.
+pub(super) fn run_list(config: &Config, all: bool) -> Result<(), Error> {
+    config.emit_started("list");
+    let mut names: Vec<&String> = config.hoards.keys().collect();
+    names.sort_unstable();
+
+    let mut listings = Vec::new();
+    for name in names {
+        let (hoard, source) = match (config.hoards.get(name), config.sources.get(name)) {
+            (Some(hoard), Some(source)) => (hoard, source),
+            (None, _) | (_, None) => continue,
+        };
+        let mut sources = source.piles();
+        sources.sort_unstable_by_key(|(pile, _)| *pile);
+        for (pile_name, pile_source) in sources {
+            let pile = match (hoard, pile_name) {
+                (Hoard::Anonymous(pile), None) => Some(pile),
+                (Hoard::Named(multiple), Some(pile_name)) => multiple.piles.get(pile_name),
+                (Hoard::Anonymous(_), Some(_)) | (Hoard::Named(_), None) => None,
+            };
+            if let Some(pile) = pile {
+                listings.push(list_pile(
+                    config,
+                    name,
+                    pile_name,
+                    (source, pile_source),
+                    pile,
+                )?);
+            }
+        }
+    }
+
+    if config.output.is_json() {
+        for listing in &listings {
+            listing.emit(all);
+        }
+        config.emit_finished("list");
+        return Ok(());
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, listing) in listings.iter().enumerate() {
+        if i > 0 {
+            writeln!(out).map_err(Error::Print)?;
+        }
+        listing.write_to(&mut out, all).map_err(Error::Print)?;
+    }
+    config.emit_finished("list");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_size_counts_files_recursively() {
+        let dir = tempfile::TempDir::new().expect("failed to create temp dir");
+        fs::create_dir_all(dir.path().join("nvim/lua")).unwrap();
+        fs::write(dir.path().join("nvim/init.vim"), b"set nu").unwrap();
+        fs::write(dir.path().join("nvim/lua/plugins.lua"), b"return {}").unwrap();
+
+        assert_eq!(stored_size(&dir.path().join("nvim")).unwrap(), (2, 15));
+        assert_eq!(
+            stored_size(&dir.path().join("nvim/init.vim")).unwrap(),
+            (1, 6)
+        );
+        assert_eq!(stored_size(&dir.path().join("missing")).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn walker_description_lists_non_default_settings() {
+        assert_eq!(describe_walker(&Walker::default()), "glob \"*\"");
+        let walker = Walker {
+            hidden: true,
+            max_depth: Some(2),
+            exclude: vec!["*.log".into()],
+            ..Walker::default()
+        };
+        assert_eq!(
+            describe_walker(&walker),
+            "glob \"*\", hidden files, max depth 2, excluding *.log"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Advisory locking of the directories `hoard` writes to.
+//!
+//! Two `hoard` processes (e.g. a scheduled backup and a manual restore) must
+//! not write into the same hoards root or history directory at the same time.
+//! A [`DirLock`] holds an exclusive `flock(2)` on each directory for as long as
+//! it is alive. The locks are released when it is dropped, or by the kernel if
+//! the process dies.
+
+use fs2::FileExt;
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Errors that can occur while locking directories.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Another process holds the lock.
+    #[error("another hoard process is using {0}: wait for it to finish or run again with --wait")]
+    Locked(PathBuf),
+    /// Error while creating, opening or locking a directory.
+    #[error("failed to lock {path}: {error}")]
+    IO {
+        /// The directory that was being locked.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+}
+
+/// Exclusive locks on a set of directories, released when dropped.
+#[derive(Debug)]
+pub(crate) struct DirLock {
+    /// Open handles to the locked directories.
+    dirs: Vec<File>,
+}
+
+impl DirLock {
+    /// Lock all of `paths`, in order, creating them if they do not exist.
+    ///
+    /// If `wait` is `true`, blocks until other processes release their locks.
+    /// Otherwise, fails with [`Error::Locked`] if any directory is already
+    /// locked.
+    pub(crate) fn acquire(paths: &[&Path], wait: bool) -> Result<Self, Error> {
+        let mut dirs = Vec::with_capacity(paths.len());
+        for path in paths {
+            let _span = tracing::debug_span!("lock_dir", path = %path.display()).entered();
+            let io_error = |error| Error::IO {
+                path: path.to_path_buf(),
+                error,
+            };
+
+            fs::create_dir_all(path).map_err(io_error)?;
+            let dir = File::open(path).map_err(io_error)?;
+            match dir.try_lock_exclusive() {
+                Ok(()) => {},
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock =>
+                    if wait {
+                        tracing::info!("waiting for another hoard process to finish");
+                        dir.lock_exclusive().map_err(io_error)?;
+                    } else {
+                        return Err(Error::Locked(path.to_path_buf()));
+                    },
+                Err(err) => return Err(io_error(err)),
+            }
+
+            tracing::trace!("acquired lock");
+            dirs.push(dir);
+        }
+
+        Ok(Self { dirs })
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        for dir in &self.dirs {
+            if let Err(err) = dir.unlock() {
+                tracing::warn!(error = %err, "failed to release directory lock");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn second_lock_fails_until_first_is_dropped() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let root = dir.path().join("root");
+
+        let first = DirLock::acquire(&[&root], false).expect("first lock should succeed");
+        assert!(root.is_dir(), "locking should create the directory");
+        assert!(matches!(
+            DirLock::acquire(&[&root], false),
+            Err(Error::Locked(path)) if path == root
+        ));
+
+        drop(first);
+        DirLock::acquire(&[&root], false).expect("lock should succeed after release");
+    }
+}
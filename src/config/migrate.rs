@@ -0,0 +1,491 @@
+//! Rename and forget hoards and piles.
+//!
+//! Besides the configuration file, a hoard's name is used for its directory in
+//! the hoards root, its entry in `last_paths.json` and the directory of its
+//! operation logs. `hoard rename` and `hoard forget` change all of them
+//! together, so that the checkers do not report mismatches afterwards.
+//!
+//! See [`run_rename`] and [`run_forget`].
+
+use super::{adopt::is_valid_name, edit, git, hoard::Hoard, Config};
+use crate::checkers::history::{get_all_history_dirs, last_paths, operation, Migration};
+use serde_json::{Map, Value};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Errors that can occur while renaming or forgetting hoards and piles.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A name is not of the form `hoard` or `hoard:pile`.
+    #[error("expected `hoard` or `hoard:pile`, got {0:?}")]
+    InvalidTarget(String),
+    /// A hoard or pile name cannot be used as a directory name.
+    #[error("invalid hoard or pile name: {0:?}")]
+    InvalidName(String),
+    /// The hoard does not have the named pile.
+    #[error("no such pile is configured: {0}")]
+    NoSuchPile(String),
+    /// A pile was named for a hoard with a single anonymous pile.
+    #[error("hoard {0} has a single anonymous pile, it has no named piles")]
+    AnonymousHoard(String),
+    /// A pile cannot be renamed into a different hoard.
+    #[error("cannot move pile {from} to another hoard ({to})")]
+    OtherHoard {
+        /// The pile being renamed.
+        from: String,
+        /// The new name.
+        to:   String,
+    },
+    /// The new name is already configured.
+    #[error("{0} is already configured")]
+    AlreadyConfigured(String),
+    /// Files are already stored under the new name.
+    #[error("files are already stored in {0}, move or delete them first")]
+    AlreadyStored(PathBuf),
+    /// The only pile of a hoard cannot be forgotten on its own.
+    #[error("{0} is the only pile of its hoard, forget the whole hoard instead")]
+    LastPile(String),
+    /// An I/O error while moving or deleting stored files.
+    #[error("failed to update stored files in {path}: {error}")]
+    IO {
+        /// The path that could not be moved or deleted.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+}
+
+/// Split `target` into a hoard and an optional pile name.
+pub(super) fn parse_target(target: &str) -> Result<(&str, Option<&str>), Error> {
+    let (hoard, pile) = match target.split_once(':') {
+        Some((hoard, pile)) => (hoard, Some(pile)),
+        None => (target, None),
+    };
+    if hoard.is_empty() || pile.map_or(false, |pile| pile.is_empty() || pile.contains(':')) {
+        Err(Error::InvalidTarget(target.to_owned()))
+    } else {
+        Ok((hoard, pile))
+    }
+}
+
+/// `hoard` and, if given, `pile` as they are shown to the user.
+pub(super) fn display_name(hoard: &str, pile: Option<&str>) -> String {
+    pile.map_or_else(|| hoard.to_owned(), |pile| [hoard, ":", pile].concat())
+}
+
+/// Fail unless `hoard` has a named pile called `pile`, and return the number
+/// of piles it has.
+pub(super) fn check_pile(config: &Config, hoard: &str, pile: &str) -> Result<usize, super::Error> {
+    match config.get_hoard(hoard)? {
+        Hoard::Anonymous(_) => Err(Error::AnonymousHoard(hoard.to_owned()).into()),
+        Hoard::Named(named) if named.piles.contains_key(pile) => Ok(named.piles.len()),
+        Hoard::Named(_) => Err(Error::NoSuchPile(display_name(hoard, Some(pile))).into()),
+    }
+}
+
+/// The migration for renaming `old` to `new`, after checking that it is
+/// possible.
+fn rename_migration<'a>(
+    config: &Config,
+    old: &'a str,
+    new: &'a str,
+) -> Result<Migration<'a>, super::Error> {
+    let (old_hoard, old_pile) = parse_target(old)?;
+    let (new_hoard, new_pile) = parse_target(new)?;
+
+    let migration = match (old_pile, new_pile) {
+        (None, Some(_)) => return Err(Error::InvalidTarget(new.to_owned()).into()),
+        (None, None) => {
+            config.get_hoard(old_hoard)?;
+            if !is_valid_name(new_hoard, false) {
+                return Err(Error::InvalidName(new_hoard.to_owned()).into());
+            }
+            if config.hoards.contains_key(new_hoard) {
+                return Err(Error::AlreadyConfigured(new_hoard.to_owned()).into());
+            }
+            Migration::RenameHoard {
+                from: old_hoard,
+                to:   new_hoard,
+            }
+        },
+        (Some(_), Some(_)) if new_hoard != old_hoard => {
+            return Err(Error::OtherHoard {
+                from: old.to_owned(),
+                to:   new.to_owned(),
+            }
+            .into());
+        },
+        // `hoard rename nvim:init nvim:lua` or `hoard rename nvim:init lua`
+        (Some(from), new_pile) => {
+            let to = new_pile.unwrap_or(new_hoard);
+            check_pile(config, old_hoard, from)?;
+            if !is_valid_name(to, true) {
+                return Err(Error::InvalidName(display_name(old_hoard, Some(to))).into());
+            }
+            if check_pile(config, old_hoard, to).is_ok() {
+                return Err(Error::AlreadyConfigured(display_name(old_hoard, Some(to))).into());
+            }
+            Migration::RenamePile {
+                hoard: old_hoard,
+                from,
+                to,
+            }
+        },
+    };
+
+    Ok(migration)
+}
+
+/// The migration for forgetting `target`, after checking that it is possible.
+fn forget_migration<'a>(config: &Config, target: &'a str) -> Result<Migration<'a>, super::Error> {
+    match parse_target(target)? {
+        (hoard, None) => {
+            config.get_hoard(hoard)?;
+            Ok(Migration::ForgetHoard(hoard))
+        },
+        (hoard, Some(pile)) => {
+            if check_pile(config, hoard, pile)? == 1 {
+                return Err(Error::LastPile(target.to_owned()).into());
+            }
+            Ok(Migration::ForgetPile { hoard, pile })
+        },
+    }
+}
+
+/// The table of `hoard` in the `hoards` table of a configuration file.
+fn hoard_table<'a>(
+    hoards: &'a mut Map<String, Value>,
+    hoard: &str,
+) -> Result<&'a mut Map<String, Value>, edit::Error> {
+    hoards
+        .get_mut(hoard)
+        .and_then(Value::as_object_mut)
+        .ok_or_else(|| edit::Error::NotATable(["hoards.", hoard].concat()))
+}
+
+/// Apply `migration` to the `hoards` table of a configuration file.
+fn migrate_hoards(
+    hoards: &mut Map<String, Value>,
+    migration: &Migration,
+) -> Result<(), edit::Error> {
+    match *migration {
+        Migration::RenameHoard { from, to } => {
+            let hoard = hoards
+                .remove(from)
+                .ok_or_else(|| edit::Error::NotATable(["hoards.", from].concat()))?;
+            hoards.insert(to.to_owned(), hoard);
+        },
+        Migration::ForgetHoard(hoard) => {
+            hoards.remove(hoard);
+        },
+        Migration::RenamePile { hoard, from, to } => {
+            let table = hoard_table(hoards, hoard)?;
+            if let Some(pile) = table.remove(from) {
+                table.insert(to.to_owned(), pile);
+            }
+        },
+        Migration::ForgetPile { hoard, pile } => {
+            hoard_table(hoards, hoard)?.remove(pile);
+        },
+    }
+    Ok(())
+}
+
+/// Where the files of `migration`'s hoard or pile are stored, and where they
+/// are moved to (or `None` if they are deleted).
+fn stored_paths(root: &Path, migration: &Migration) -> (PathBuf, Option<PathBuf>) {
+    match *migration {
+        Migration::RenameHoard { from, to } => (root.join(from), Some(root.join(to))),
+        Migration::RenamePile { hoard, from, to } =>
+            (root.join(hoard).join(from), Some(root.join(hoard).join(to))),
+        Migration::ForgetHoard(hoard) => (root.join(hoard), None),
+        Migration::ForgetPile { hoard, pile } => (root.join(hoard).join(pile), None),
+    }
+}
+
+/// Move or delete the stored files of `migration`'s hoard or pile.
+fn migrate_files(root: &Path, migration: &Migration, keep_files: bool) -> Result<(), Error> {
+    let (src, dest) = stored_paths(root, migration);
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| Error::IO { path, error }
+    };
+    let metadata = match fs::symlink_metadata(&src) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(io_error(&src)(err)),
+    };
+
+    match dest {
+        Some(dest) => {
+            tracing::debug!(src = %src.display(), dest = %dest.display(), "moving stored files");
+            fs::rename(&src, &dest).map_err(io_error(&dest))
+        },
+        None if keep_files => Ok(()),
+        None if metadata.is_dir() => {
+            tracing::debug!(path = %src.display(), "deleting stored files");
+            fs::remove_dir_all(&src).map_err(io_error(&src))
+        },
+        None => fs::remove_file(&src).map_err(io_error(&src)),
+    }
+}
+
+/// The hoards whose stored files are changed by `migration`.
+fn affected_hoards<'a>(migration: &Migration<'a>) -> Vec<&'a str> {
+    match *migration {
+        Migration::RenameHoard { from, to } => vec![from, to],
+        Migration::ForgetHoard(hoard)
+        | Migration::RenamePile { hoard, .. }
+        | Migration::ForgetPile { hoard, .. } => vec![hoard],
+    }
+}
+
+/// Move or delete the stored files of `migration` around `update`, which
+/// updates the history and the configuration file.
+///
+/// Stored files are moved before `update` and moved back if it fails. They
+/// are only deleted once `update` succeeded, since that cannot be undone.
+fn apply_migration<F>(
+    root: &Path,
+    migration: &Migration,
+    keep_files: bool,
+    update: F,
+) -> Result<(), super::Error>
+where
+    F: FnOnce() -> Result<(), super::Error>,
+{
+    let (src, dest) = stored_paths(root, migration);
+    if let Some(dest) = dest {
+        migrate_files(root, migration, keep_files)?;
+        return update().map_err(|err| {
+            if dest.exists() {
+                tracing::debug!(src = %dest.display(), dest = %src.display(), "moving stored files back");
+                if let Err(error) = fs::rename(&dest, &src) {
+                    tracing::error!(
+                        %error,
+                        path = %dest.display(),
+                        "failed to move stored files back, move them to {} manually",
+                        src.display()
+                    );
+                }
+            }
+            err
+        });
+    }
+
+    update()?;
+    migrate_files(root, migration, keep_files).map_err(Into::into)
+}
+
+/// Apply `migration` to the configuration file, stored files and history.
+///
+/// Everything that can be checked is checked before changing anything, and
+/// the configuration file is saved last.
+fn run_migration(
+    config: &Config,
+    migration: &Migration,
+    keep_files: bool,
+    summary: &str,
+) -> Result<(), super::Error> {
+    let _span = tracing::debug_span!("migrate", ?migration).entered();
+    for hoard in affected_hoards(migration) {
+        config.check_hoard_file(hoard)?;
+    }
+    let mut doc = edit::ConfigDocument::load(&config.config_file)?;
+    migrate_hoards(doc.hoards_mut()?, migration)?;
+    // Make sure the new configuration builds before changing anything
+    config.rebuild(&doc)?;
+
+    let _lock = config.lock()?;
+    let hoards = affected_hoards(migration);
+    config.pull_remote(&hoards)?;
+    if let (_, Some(dest)) = stored_paths(&config.hoards_root, migration) {
+        if dest.exists() {
+            return Err(Error::AlreadyStored(dest).into());
+        }
+    }
+
+    apply_migration(&config.hoards_root, migration, keep_files, || {
+        for dir in get_all_history_dirs().map_err(super::HoardOperationError::from)? {
+            last_paths::migrate(&dir, migration)?;
+            operation::migrate(&dir, migration)?;
+        }
+        doc.save().map_err(super::Error::from)
+    })?;
+
+    if let Some(git) = &config.git {
+        git::commit_changes(&config.hoards_root, git, &hoards, summary)?;
+    }
+    config.push_remote(&hoards, true)?;
+
+    if !config.output.is_json() {
+        let stdout = io::stdout();
+        writeln!(stdout.lock(), "{}", summary).map_err(super::Error::Summary)?;
+    }
+    Ok(())
+}
+
+/// Rename the hoard or pile `old` to `new`.
+///
+/// # Errors
+///
+/// Any [`Error`] if the rename is not possible, or any [`super::Error`] while
+/// updating the configuration file, stored files or history.
+pub(super) fn run_rename(config: &Config, old: &str, new: &str) -> Result<(), super::Error> {
+    config.emit_started("rename");
+    let migration = rename_migration(config, old, new)?;
+    let summary = format!("Rename {} to {}", old, new);
+    run_migration(config, &migration, false, &summary)?;
+    config.emit_finished("rename");
+    Ok(())
+}
+
+/// Remove the hoard or pile `target` from the configuration, along with its
+/// history and, unless `keep_files` is `true`, its stored files.
+///
+/// # Errors
+///
+/// Any [`Error`] if `target` is not configured, or any [`super::Error`] while
+/// updating the configuration file, stored files or history.
+pub(super) fn run_forget(
+    config: &Config,
+    target: &str,
+    keep_files: bool,
+) -> Result<(), super::Error> {
+    config.emit_started("forget");
+    let migration = forget_migration(config, target)?;
+    let summary = format!("Forget {}", target);
+    run_migration(config, &migration, keep_files, &summary)?;
+    config.emit_finished("forget");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn targets_are_hoards_or_piles() {
+        assert_eq!(parse_target("nvim").unwrap(), ("nvim", None));
+        assert_eq!(parse_target("nvim:init").unwrap(), ("nvim", Some("init")));
+        parse_target(":init").expect_err("hoard name is required");
+        parse_target("nvim:").expect_err("pile name cannot be empty");
+        parse_target("a:b:c").expect_err("only one pile can be named");
+    }
+
+    #[test]
+    fn migrations_update_the_hoards_table() {
+        let mut hoards = json!({
+            "nvim": { "init": { "linux": "~/.config/nvim/init.vim" }, "lua": { "linux": "~/.config/nvim/lua" } },
+            "zsh": { "linux": "~/.zshrc" },
+        });
+        let table = hoards.as_object_mut().unwrap();
+
+        migrate_hoards(table, &Migration::RenameHoard {
+            from: "nvim",
+            to:   "neovim",
+        })
+        .unwrap();
+        migrate_hoards(table, &Migration::RenamePile {
+            hoard: "neovim",
+            from:  "init",
+            to:    "vimrc",
+        })
+        .unwrap();
+        migrate_hoards(table, &Migration::ForgetPile {
+            hoard: "neovim",
+            pile:  "lua",
+        })
+        .unwrap();
+        migrate_hoards(table, &Migration::ForgetHoard("zsh")).unwrap();
+
+        assert_eq!(
+            hoards,
+            json!({ "neovim": { "vimrc": { "linux": "~/.config/nvim/init.vim" } } })
+        );
+    }
+
+    #[test]
+    fn stored_files_are_moved_or_deleted() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let root = dir.path();
+        fs::create_dir_all(root.join("nvim").join("init")).unwrap();
+        fs::write(root.join("nvim").join("init").join("init.vim"), "set nu").unwrap();
+        fs::write(root.join("zsh"), "export EDITOR=nvim").unwrap();
+
+        let rename = Migration::RenamePile {
+            hoard: "nvim",
+            from:  "init",
+            to:    "vimrc",
+        };
+        migrate_files(root, &rename, false).unwrap();
+        assert!(root.join("nvim").join("vimrc").join("init.vim").exists());
+
+        migrate_files(root, &Migration::ForgetHoard("zsh"), true).unwrap();
+        assert!(root.join("zsh").exists());
+        migrate_files(root, &Migration::ForgetHoard("zsh"), false).unwrap();
+        assert!(!root.join("zsh").exists());
+        migrate_files(root, &Migration::ForgetHoard("nvim"), false).unwrap();
+        assert!(!root.join("nvim").exists());
+    }
+
+    #[test]
+    fn hoards_of_included_files_are_not_edited() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let main = dir.path().join("config.toml");
+        let text = "[envs.linux]\nos = [\"linux\"]\n";
+        fs::write(&main, text).unwrap();
+        let included = dir.path().join("config.d").join("zsh.toml");
+        fs::create_dir_all(included.parent().unwrap()).unwrap();
+        fs::write(&included, "[hoards.zsh]\n\"linux\" = \"/zshrc\"\n").unwrap();
+        let config = crate::config::Builder::from_file(&main)
+            .and_then(|builder| builder.with_includes(&main))
+            .unwrap()
+            .set_config_file(main.clone())
+            .set_hoards_root(dir.path().join("hoards"))
+            .build()
+            .unwrap();
+
+        match run_forget(&config, "zsh", false).expect_err("zsh is defined in config.d") {
+            crate::config::Error::Edit(edit::Error::DefinedElsewhere { hoard, path }) => {
+                assert_eq!(hoard, "zsh");
+                assert_eq!(path, included);
+            },
+            err => panic!("unexpected error: {}", err),
+        }
+        assert_eq!(fs::read_to_string(&main).unwrap(), text);
+    }
+
+    #[test]
+    fn failed_updates_leave_stored_files_in_place() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let root = dir.path();
+        fs::create_dir_all(root.join("nvim").join("init")).unwrap();
+        fs::write(root.join("zsh"), "export EDITOR=nvim").unwrap();
+        let failed_update = || Err(crate::config::Error::Summary(io::ErrorKind::Other.into()));
+
+        let rename = Migration::RenamePile {
+            hoard: "nvim",
+            from:  "init",
+            to:    "vimrc",
+        };
+        apply_migration(root, &rename, false, failed_update)
+            .expect_err("failed update should fail the rename");
+        assert!(root.join("nvim").join("init").is_dir());
+        assert!(!root.join("nvim").join("vimrc").exists());
+
+        apply_migration(root, &Migration::ForgetHoard("zsh"), false, failed_update)
+            .expect_err("failed update should fail the forget");
+        assert!(root.join("zsh").exists());
+
+        apply_migration(root, &rename, false, || Ok(())).unwrap();
+        assert!(root.join("nvim").join("vimrc").is_dir());
+    }
+}
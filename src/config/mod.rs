@@ -6,30 +6,69 @@
 // - [`Checkers`]: check path UUID
 
 pub use self::builder::Builder;
-use self::hoard::Hoard;
+use self::{
+    builder::{
+        environment::Environment,
+        hoard::{Hoard as HoardSource, Schedule},
+        vars::PathVars,
+    },
+    hoard::Hoard,
+};
 use crate::{
     checkers::{
         history::{
+            get_history_root_dir, get_or_generate_uuid,
             last_paths::{Error as LastPathsError, LastPaths},
             operation::{Error as HoardOperationError, HoardOperation},
         },
         Checker,
     },
-    command::Command,
+    command::{Command, ConfigCommand},
     config::{
         builder::GlobalConfig,
+        edit::ConfigDocument,
         filetypes::{assets::run_cache, ConfigConversion, Error as ConversionError},
+        git::GitConfig,
+        lock::DirLock,
+        output::{emit, print_document, Event, OutputFormat},
+        report::{print_summary, Progress},
+        storage::{self as store, LocalStorage, Location},
     },
 };
 
-use std::{collections::HashMap, path::PathBuf};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
+use structopt::StructOpt;
 use thiserror::Error;
 
+mod adopt;
 pub mod builder;
+mod catalog;
+mod convert;
 pub mod directories;
+pub mod edit;
+mod editor;
+mod effective;
 pub mod encrypt;
+mod explain;
 pub mod filetypes;
+pub mod git;
 pub mod hoard;
+mod import;
+mod init;
+pub mod lint;
+mod list;
+pub mod lock;
+mod migrate;
+pub mod output;
+pub mod report;
+mod schedule;
+pub mod storage;
+mod watch;
 
 /// Errors that can occur while working with a [`Config`].
 #[derive(Debug, Error)]
@@ -77,6 +116,250 @@ pub enum Error {
          must be done (`-x`)."
     )]
     MissingConfigCommand,
+    /// Error while watching pile paths for changes.
+    #[error("failed to watch files for changes: {0}")]
+    Watch(#[from] notify::Error),
+    /// Error while installing the signal handler.
+    #[error("failed to set up signal handler: {0}")]
+    Signal(#[from] ctrlc::Error),
+    /// Error while printing the summary of a backup or restore.
+    #[error("failed to print summary: {0}")]
+    Summary(#[source] std::io::Error),
+    /// Another `hoard` process is using the hoards root or history.
+    #[error("{0}")]
+    Lock(#[from] lock::Error),
+    /// Error while installing or inspecting scheduled backups.
+    #[error("failed to manage scheduled backups: {0}")]
+    Schedule(#[from] schedule::Error),
+    /// Error while editing the configuration file.
+    #[error("failed to update configuration file: {0}")]
+    Edit(#[from] edit::Error),
+    /// Error while adding a path to the configuration file.
+    #[error("{0}")]
+    Adopt(#[from] adopt::Error),
+    /// Error while renaming or forgetting a hoard or pile.
+    #[error("{0}")]
+    Migrate(#[from] migrate::Error),
+    /// Error while listing hoards.
+    #[error("{0}")]
+    List(#[from] list::Error),
+    /// Error while explaining environments and piles.
+    #[error("{0}")]
+    Explain(#[from] explain::Error),
+    /// Error while editing the configuration file in an editor.
+    #[error("{0}")]
+    Editor(#[from] editor::Error),
+    /// Error while converting the configuration file in place.
+    #[error("{0}")]
+    Convert(#[from] convert::Error),
+    /// Error while generating a configuration file.
+    #[error("{0}")]
+    Init(#[from] init::Error),
+    /// Error while importing the dotfiles managed by another tool.
+    #[error("{0}")]
+    Import(#[from] import::Error),
+    /// Error while printing the effective configuration.
+    #[error("{0}")]
+    Effective(#[from] effective::Error),
+    /// The configuration has lint findings that are not allowed.
+    #[error("{0}")]
+    Lint(#[from] lint::Error),
+    /// Error while using git in the hoards root.
+    #[error("{0}")]
+    Git(#[from] git::Error),
+    /// Error while syncing with a remote hoards root.
+    #[error("failed to sync with remote storage: {0}")]
+    Storage(#[from] store::Error),
+}
+
+impl Error {
+    /// Exit code for operations a checker refused to run.
+    pub const EXIT_CHECKER: i32 = 3;
+    /// Exit code for configuration errors, e.g. an invalid configuration file.
+    pub const EXIT_CONFIG: i32 = 2;
+    /// Exit code for I/O failures while running a command.
+    pub const EXIT_IO: i32 = 4;
+    /// Exit code when another `hoard` process holds the lock.
+    pub const EXIT_LOCKED: i32 = 5;
+
+    /// A stable, machine-readable name of the kind of error.
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Backup { .. } => "backup",
+            Self::Builder(_) => "builder",
+            Self::NoSuchHoard(_) => "no_such_hoard",
+            Self::Restore { .. } => "restore",
+            Self::LastPaths(LastPathsError::HoardPathsMismatch) => "last_paths_mismatch",
+            Self::LastPaths(_) => "last_paths",
+            Self::Operation(HoardOperationError::RestoreRequired) => "restore_required",
+            Self::Operation(_) => "operation",
+            Self::ConversionError(_) => "conversion",
+            Self::InvalidDirectory(_) => "invalid_directory",
+            Self::MissingConfigCommand => "missing_config_command",
+            Self::Watch(_) => "watch",
+            Self::Signal(_) => "signal",
+            Self::Summary(_) => "summary",
+            Self::Lock(lock::Error::Locked(_)) => "locked",
+            Self::Lock(_) => "lock",
+            Self::Schedule(_) => "schedule",
+            Self::Storage(_) => "storage",
+            Self::Git(git::Error::DirtyTree(_)) => "git_dirty_tree",
+            Self::Git(_) => "git",
+            Self::Adopt(adopt::Error::AlreadyCovered { .. }) => "already_covered",
+            Self::Adopt(_) => "adopt",
+            Self::Migrate(_) => "migrate",
+            Self::List(_) => "list",
+            Self::Explain(_) => "explain",
+            Self::Editor(editor::Error::Aborted) => "edit_aborted",
+            Self::Editor(_) => "editor",
+            Self::Init(_) => "init",
+            Self::Import(_) => "import",
+            Self::Effective(_) => "effective",
+            Self::Lint(_) => "lint",
+            Self::Convert(convert::Error::Cancelled) => "convert_cancelled",
+            Self::Convert(_) => "convert",
+            Self::Edit(_) => "edit",
+        }
+    }
+
+    /// The code `hoard` should exit with because of this error.
+    ///
+    /// Configuration errors exit with [`Error::EXIT_CONFIG`], checkers refusing
+    /// an operation with [`Error::EXIT_CHECKER`], I/O failures with
+    /// [`Error::EXIT_IO`] and lock contention with [`Error::EXIT_LOCKED`].
+    /// Anything else exits with `1`.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Builder(_)
+            | Self::NoSuchHoard(_)
+            | Self::ConversionError(_)
+            | Self::InvalidDirectory(_)
+            | Self::MissingConfigCommand
+            | Self::Adopt(
+                adopt::Error::NotFound(_)
+                | adopt::Error::AlreadyCovered { .. }
+                | adopt::Error::InvalidName(_)
+                | adopt::Error::UnknownEnv(_)
+                | adopt::Error::ConditionDoesNotApply(_)
+                | adopt::Error::AmbiguousEnv(_)
+                | adopt::Error::PileRequired(_)
+                | adopt::Error::AnonymousHoard(_)
+                | adopt::Error::ConditionExists { .. },
+            )
+            | Self::Edit(
+                edit::Error::Format(_)
+                | edit::Error::NotATable(_)
+                | edit::Error::DefinedElsewhere { .. }
+                | edit::Error::NotAnArray(_)
+                | edit::Error::Invalid(_),
+            )
+            | Self::Migrate(
+                migrate::Error::InvalidTarget(_)
+                | migrate::Error::InvalidName(_)
+                | migrate::Error::NoSuchPile(_)
+                | migrate::Error::AnonymousHoard(_)
+                | migrate::Error::OtherHoard { .. }
+                | migrate::Error::AlreadyConfigured(_)
+                | migrate::Error::AlreadyStored(_)
+                | migrate::Error::LastPile(_),
+            )
+            | Self::List(list::Error::Hoard(_))
+            | Self::Explain(explain::Error::Hoard(_))
+            | Self::Editor(editor::Error::Aborted)
+            | Self::Init(init::Error::AlreadyExists(_))
+            | Self::Import(
+                import::Error::NotFound(_)
+                | import::Error::NoDotbotConfig(_)
+                | import::Error::Dotbot { .. }
+                | import::Error::NothingToImport(_),
+            )
+            | Self::Lint(lint::Error::Findings { .. })
+            | Self::Convert(convert::Error::AlreadyExists(_) | convert::Error::Cancelled)
+            | Self::Storage(
+                store::Error::InvalidUrl { .. } | store::Error::MissingCredentials(_),
+            ) => Self::EXIT_CONFIG,
+            Self::LastPaths(LastPathsError::HoardPathsMismatch)
+            | Self::Operation(HoardOperationError::RestoreRequired)
+            | Self::Git(git::Error::DirtyTree(_)) => Self::EXIT_CHECKER,
+            Self::Git(git::Error::Exited(code)) => *code,
+            Self::Lock(lock::Error::Locked(_)) => Self::EXIT_LOCKED,
+            Self::Backup { .. }
+            | Self::Restore { .. }
+            | Self::LastPaths(_)
+            | Self::Operation(_)
+            | Self::Watch(_)
+            | Self::Summary(_)
+            | Self::Lock(_)
+            | Self::Schedule(_)
+            | Self::Storage(_)
+            | Self::Git(_)
+            | Self::Adopt(_)
+            | Self::Edit(_)
+            | Self::Migrate(_)
+            | Self::List(_)
+            | Self::Explain(_)
+            | Self::Editor(_)
+            | Self::Init(_)
+            | Self::Import(_)
+            | Self::Effective(_)
+            | Self::Lint(_)
+            | Self::Convert(_) => Self::EXIT_IO,
+            Self::Signal(_) => 1,
+        }
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            Self::Backup { name, error } | Self::Restore { name, error } => {
+                map.serialize_entry("hoard", name)?;
+                map.serialize_entry("cause", error)?;
+            },
+            Self::NoSuchHoard(name) => map.serialize_entry("hoard", name)?,
+            Self::Builder(builder::Error::Parse {
+                path, line, column, ..
+            }) => {
+                map.serialize_entry("file", path)?;
+                map.serialize_entry("line", line)?;
+                map.serialize_entry("column", column)?;
+            },
+            Self::Lint(lint::Error::Findings { errors, warnings }) => {
+                map.serialize_entry("errors", errors)?;
+                map.serialize_entry("warnings", warnings)?;
+            },
+            Self::Builder(_)
+            | Self::Lint(lint::Error::Print(_))
+            | Self::LastPaths(_)
+            | Self::Operation(_)
+            | Self::ConversionError(_)
+            | Self::InvalidDirectory(_)
+            | Self::MissingConfigCommand
+            | Self::Watch(_)
+            | Self::Signal(_)
+            | Self::Summary(_)
+            | Self::Lock(_)
+            | Self::Schedule(_)
+            | Self::Storage(_)
+            | Self::Git(_)
+            | Self::Adopt(_)
+            | Self::Edit(_)
+            | Self::Migrate(_)
+            | Self::List(_)
+            | Self::Explain(_)
+            | Self::Editor(_)
+            | Self::Init(_)
+            | Self::Import(_)
+            | Self::Effective(_)
+            | Self::Convert(_) => {},
+        }
+        map.end()
+    }
 }
 
 /// A (processed) configuration.
@@ -87,16 +370,40 @@ pub struct Config {
     /// The command to run.
     pub command:   Command,
     /// The root directory to backup/restore hoards from.
+    ///
+    /// If the hoards are stored remotely, this is a local mirror.
     hoards_root:   PathBuf,
+    /// Where the hoards are stored.
+    storage:       Location,
     /// Path to a configuration file.
     config_file:   PathBuf,
     /// Global configuration options
     global_config: GlobalConfig,
     /// All of the configured hoards.
     hoards:        HashMap<String, Hoard>,
+    /// The hoards as configured, before choosing paths for this system.
+    sources:       HashMap<String, HoardSource>,
+    /// The environments as configured.
+    env_sources:   HashMap<String, Environment>,
+    /// Whether each configured environment applies to this system.
+    environments:  HashMap<String, bool>,
+    /// Sets of mutually exclusive environments.
+    exclusivity:   Vec<Vec<String>>,
+    /// The variables for pile paths on this system.
+    vars:          PathVars,
+    /// Schedules of the hoards that should be backed up unattended.
+    schedules:     HashMap<String, Schedule>,
     /// Whether to force the operation to continue despite possible
     /// inconsistencies.
     force:         bool,
+    /// Whether to wait for other `hoard` processes instead of failing.
+    wait:          bool,
+    /// The format to report progress and results in.
+    output:        OutputFormat,
+    /// How to use git in the hoards root, if at all.
+    git:           Option<GitConfig>,
+    /// The configuration file each hoard is defined in, if known.
+    hoard_files:   HashMap<String, PathBuf>,
 }
 
 impl Default for Config {
@@ -129,9 +436,22 @@ impl Config {
     /// [`Error::Builder`].
     pub fn load() -> Result<Self, Error> {
         tracing::info!("loading configuration...");
-        let config = Builder::from_args_then_file()
-            .map(Builder::build)?
-            .map_err(Error::Builder)?;
+        let config = match Builder::from_args_then_file().and_then(Builder::build) {
+            Ok(config) => config,
+            Err(err) => {
+                // `hoard edit` and `hoard init` are how a missing or invalid
+                // configuration file gets fixed
+                let from_args = Builder::from_args();
+                if from_args
+                    .command()
+                    .map_or(true, Command::requires_valid_config)
+                {
+                    return Err(Error::Builder(err));
+                }
+                tracing::info!("continuing without a valid configuration: {}", err);
+                from_args.build()?
+            },
+        };
         // println!("CONIFIG: {:#?}",  config.global_config.ignores);
         tracing::info!("loaded configuration.");
         Ok(config)
@@ -149,6 +469,12 @@ impl Config {
         self.hoards_root.clone()
     }
 
+    /// The format to report progress and results in.
+    #[must_use]
+    pub fn get_output_format(&self) -> OutputFormat {
+        self.output
+    }
+
     fn get_hoards<'a>(
         &'a self,
         hoards: &'a [String],
@@ -181,6 +507,287 @@ impl Config {
             .ok_or_else(|| Error::NoSuchHoard(name.to_owned()))
     }
 
+    /// Fail if `hoard` is defined in another file than the configuration
+    /// file, e.g. an included one, since only the configuration file is
+    /// edited.
+    fn check_hoard_file(&self, hoard: &str) -> Result<(), edit::Error> {
+        match self.hoard_files.get(hoard) {
+            Some(path) if *path != self.config_file => Err(edit::Error::DefinedElsewhere {
+                hoard: hoard.to_owned(),
+                path:  path.clone(),
+            }),
+            Some(_) | None => Ok(()),
+        }
+    }
+
+    /// Build the configuration in `doc` with the same command-line options as
+    /// this one, e.g. to validate an edited configuration file before saving
+    /// it.
+    fn rebuild(&self, doc: &ConfigDocument) -> Result<Self, Error> {
+        let config = doc
+            .to_builder()?
+            .with_includes(doc.path())?
+            .with_layers(doc.path())?
+            .with_env_overrides()?
+            .set_hoards_root(self.storage.root())
+            .set_config_file(self.config_file.clone())
+            .set_force(self.force)
+            .set_wait(self.wait)
+            .set_output(self.output)
+            .build()?;
+        Ok(config)
+    }
+
+    /// Lock the hoards root and the history directory against other `hoard`
+    /// processes until the returned lock is dropped.
+    fn lock(&self) -> Result<DirLock, Error> {
+        let history_root = get_history_root_dir();
+        Ok(DirLock::acquire(
+            &[&self.hoards_root, &history_root],
+            self.wait,
+        )?)
+    }
+
+    /// Bring the local mirror of a remote hoards root up to date for `hoards`,
+    /// along with the operation logs of other machines if they are stored
+    /// remotely. Does nothing for a local hoards root.
+    fn pull_remote(&self, hoards: &[&str]) -> Result<(), Error> {
+        if let Some(remote) = self.storage.open_remote()? {
+            let _span = tracing::info_span!("pull_remote").entered();
+            let local = LocalStorage::new(self.hoards_root.clone());
+            for hoard in hoards {
+                store::sync_dir(remote.as_ref(), &local, hoard, true)?;
+            }
+        }
+
+        if let Some(remote) = self.storage.open_remote_history()? {
+            let _span = tracing::info_span!("pull_remote_history").entered();
+            let local = LocalStorage::new(get_history_root_dir());
+            // This machine's own logs are only ever pushed, never pulled
+            let own = get_or_generate_uuid().map_err(HoardOperationError::from)?;
+            let mut machines = remote
+                .list("")?
+                .into_keys()
+                .filter_map(|key| key.split('/').next().map(str::to_owned))
+                .collect::<Vec<_>>();
+            machines.dedup();
+            for machine in machines.iter().filter(|id| **id != own.to_string()) {
+                store::sync(remote.as_ref(), &local, &format!("{}/", machine), false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Upload `hoards` to a remote hoards root after a backup, and this
+    /// machine's operation logs if they are stored remotely. Does nothing for a
+    /// local hoards root.
+    fn push_remote(&self, hoards: &[&str], is_backup: bool) -> Result<(), Error> {
+        if is_backup {
+            if let Some(remote) = self.storage.open_remote()? {
+                let _span = tracing::info_span!("push_remote").entered();
+                let local = LocalStorage::new(self.hoards_root.clone());
+                for hoard in hoards {
+                    store::sync_dir(&local, remote.as_ref(), hoard, true)?;
+                }
+            }
+        }
+
+        if let Some(remote) = self.storage.open_remote_history()? {
+            let _span = tracing::info_span!("push_remote_history").entered();
+            let local = LocalStorage::new(get_history_root_dir());
+            let own = get_or_generate_uuid().map_err(HoardOperationError::from)?;
+            store::sync(&local, remote.as_ref(), &format!("{}/", own), false)?;
+        }
+        Ok(())
+    }
+
+    /// Back up or restore the given hoards and print a summary of each.
+    fn run_operation(&self, hoards: &[String], is_backup: bool) -> Result<(), Error> {
+        let hoards = self
+            .get_hoards(hoards)?
+            .into_iter()
+            .map(|(name, hoard)| (name, hoard.for_operation(is_backup)))
+            .collect();
+        self.run_on_hoards(hoards, is_backup, false)
+    }
+
+    /// Back up only the named piles `piles` of `hoard` and print a summary.
+    ///
+    /// The history of the hoard is updated for these piles only. A hoard
+    /// without any operation on this system is backed up as a whole, so that
+    /// its history starts out complete.
+    fn backup_piles(&self, hoard: &str, piles: &BTreeSet<&str>) -> Result<(), Error> {
+        let names = [hoard.to_owned()];
+        if HoardOperation::latest_local(hoard)?.is_none() {
+            return self.run_operation(&names, true);
+        }
+        let hoards = self
+            .get_hoards(&names)?
+            .into_iter()
+            .map(|(name, hoard)| (name, hoard.with_piles(piles)))
+            .collect();
+        self.run_on_hoards(hoards, true, true)
+    }
+
+    /// Back up or restore `hoards`. With `only_piles`, the named hoards contain
+    /// only the piles to back up, and the history of their other piles is kept.
+    fn run_on_hoards(
+        &self,
+        hoards: HashMap<&str, Hoard>,
+        is_backup: bool,
+        only_piles: bool,
+    ) -> Result<(), Error> {
+        let operation = if is_backup { "backup" } else { "restore" };
+        if self.output.is_json() {
+            let mut names = hoards.keys().copied().collect::<Vec<_>>();
+            names.sort_unstable();
+            emit(&Event::OperationStarted {
+                operation,
+                hoards: names,
+            });
+        }
+
+        let _lock = self.lock()?;
+        let names = hoards.keys().copied().collect::<Vec<_>>();
+        self.pull_remote(&names)?;
+        if let (false, Some(git)) = (is_backup, &self.git) {
+            git::check_clean(&self.hoards_root, git, &names)?;
+        }
+        let mut checkers = if only_piles {
+            Checkers::for_piles(&hoards)?
+        } else {
+            Checkers::new(&hoards, is_backup)?
+        };
+        if !self.force {
+            checkers.check(self.output)?;
+        }
+
+        let mut progress = Progress::for_output(self.output);
+        let mut reports = Vec::new();
+        for (name, hoard) in hoards {
+            let prefix = self.get_prefix(name);
+            progress.set_hoard(name);
+
+            let report = if is_backup {
+                tracing::info!(hoard = %name, "backing up hoard");
+                let _span = tracing::info_span!("backup", hoard = %name).entered();
+                hoard
+                    .backup(&prefix, &self.global_config, &mut progress)
+                    .map_err(|error| Error::Backup {
+                        name: name.to_owned(),
+                        error,
+                    })
+            } else {
+                tracing::info!(hoard = %name, "restoring hoard");
+                let _span = tracing::info_span!("restore", hoard = %name).entered();
+                hoard
+                    .restore(&prefix, &self.global_config, &mut progress)
+                    .map_err(|error| Error::Restore {
+                        name: name.to_owned(),
+                        error,
+                    })
+            };
+
+            match report {
+                Ok(report) => reports.push((name, report)),
+                Err(err) => {
+                    progress.finish();
+                    return Err(err);
+                },
+            }
+        }
+        progress.finish();
+
+        checkers.commit_to_disk()?;
+        if let (true, Some(git)) = (is_backup, &self.git) {
+            git::commit_backup(&self.hoards_root, git, &names)?;
+        }
+        self.push_remote(&names, is_backup)?;
+        if self.output.is_json() {
+            emit(&Event::Summary {
+                operation,
+                hoards: reports
+                    .iter()
+                    .map(|(name, report)| (*name, report))
+                    .collect(),
+            });
+            Ok(())
+        } else {
+            print_summary(&reports).map_err(Error::Summary)
+        }
+    }
+
+    /// Emit the start of a command other than a backup or restore.
+    fn emit_started(&self, operation: &str) {
+        if self.output.is_json() {
+            emit(&Event::OperationStarted {
+                operation,
+                hoards: Vec::new(),
+            });
+        }
+    }
+
+    /// Emit the end of a command other than a backup or restore.
+    fn emit_finished(&self, operation: &str) {
+        if self.output.is_json() {
+            emit(&Event::OperationFinished { operation });
+        }
+    }
+
+    /// Run `hoard config` with the stored [`Command`].
+    fn run_config(&self) -> Result<(), Error> {
+        if let Command::Config {
+            convert,
+            input_format,
+            output_format,
+            output_file,
+            in_place,
+            show_effective,
+            theme,
+            color,
+            cache_build,
+            cache_clear,
+            source,
+            dest,
+            action,
+        } = &self.command
+        {
+            self.emit_started("config");
+            if let Some(ConfigCommand::Schema) = action {
+                effective::run_schema(self.output)?;
+            } else if let (true, Some(format)) = (*in_place, output_format) {
+                convert::run_convert_in_place(self, format)?;
+            } else if *show_effective {
+                effective::run_show_effective(self, output_format.as_ref())?;
+            } else if *convert {
+                tracing::info!("configuration is being edited");
+                let conversion = ConfigConversion::new(
+                    &self.config_file,
+                    input_format,
+                    output_file,
+                    output_format,
+                    theme.clone(),
+                    *color && atty::is(atty::Stream::Stdout),
+                )?;
+
+                if self.output.is_json() && output_file.is_none() {
+                    // Printing the document would interleave with the events
+                    let converted = conversion.convert()?;
+                    print_document(self.output, converted.format.name(), &converted.text)
+                        .map_err(effective::Error::Print)?;
+                } else {
+                    conversion.run().map_err(Error::from)?;
+                }
+            } else if *cache_build || *cache_clear {
+                run_cache(*cache_build, *cache_clear, source, dest)?;
+            } else {
+                return Err(Error::MissingConfigCommand);
+            }
+            self.emit_finished("config");
+        }
+        Ok(())
+    }
+
     /// Run the stored [`Command`] using this [`Config`].
     ///
     /// # Errors
@@ -189,102 +796,75 @@ impl Config {
     pub fn run(&mut self) -> Result<(), Error> {
         tracing::trace!(command = ?self.command, "running command");
         match &self.command {
-            Command::Config {
-                convert,
-                input_format,
-                output_format,
-                output_file,
-                theme,
-                color,
-                cache_build,
-                cache_clear,
-                source,
-                dest,
-            } =>
-                if *convert {
-                    tracing::info!("configuration is being edited");
-                    let conversion = ConfigConversion::new(
-                        &self.config_file,
-                        input_format,
-                        output_file,
-                        output_format,
-                        theme.clone(),
-                        *color && atty::is(atty::Stream::Stdout),
-                    )?;
-
-                    conversion.run().map_err(Error::from)?;
-                } else if *cache_build || *cache_clear {
-                    run_cache(*cache_build, *cache_clear, source, dest)?;
-                } else {
-                    return Err(Error::MissingConfigCommand);
-                },
-            Command::Validate => {
-                tracing::info!("configuration is valid");
+            Command::Config { .. } => self.run_config()?,
+            Command::Validate { deny } => {
+                self.emit_started("validate");
+                lint::run_validate(self, deny.is_some())?;
+                self.emit_finished("validate");
             },
-            Command::Backup { hoards } => {
-                let hoards = self.get_hoards(hoards)?;
-                let mut checkers = Checkers::new(&hoards, true)?;
-                if !self.force {
-                    checkers.check()?;
-                }
-
-                for (name, hoard) in hoards {
-                    let prefix = self.get_prefix(name);
-
-                    tracing::info!(hoard = %name, "backing up hoard");
-                    let _span = tracing::info_span!("backup", hoard = %name).entered();
-                    hoard
-                        .backup(&prefix, &self.global_config)
-                        .map_err(|error| Error::Backup {
-                            name: name.to_owned(),
-                            error,
-                        })?;
-                }
-
-                checkers.commit_to_disk()?;
+            Command::Backup { hoards } => self.run_operation(hoards, true)?,
+            Command::Restore { hoards } => self.run_operation(hoards, false)?,
+            Command::Watch { hoards, delay } => {
+                watch::run_watch(self, hoards, Duration::from_secs(*delay))?;
             },
-            Command::Restore { hoards } => {
-                let hoards = self.get_hoards(hoards)?;
-                let mut checkers = Checkers::new(&hoards, false)?;
-                if !self.force {
-                    checkers.check()?;
-                }
-
-                for (name, hoard) in hoards {
-                    let prefix = self.get_prefix(name);
-
-                    tracing::info!(hoard = %name, "restoring hoard");
-                    let _span = tracing::info_span!("restore", hoard = %name).entered();
-                    hoard
-                        .restore(&prefix, &self.global_config)
-                        .map_err(|error| Error::Restore {
-                            name: name.to_owned(),
-                            error,
-                        })?;
-                }
-
-                checkers.commit_to_disk()?;
+            Command::Schedule(action) => schedule::run_schedule(self, action)?,
+            Command::Git { args } => git::passthrough(&self.hoards_root, args)?,
+            Command::Adopt {
+                path,
+                hoard,
+                pile,
+                env,
+                backup,
+            } => {
+                self.emit_started("adopt");
+                adopt::run_adopt(self, path, hoard, pile.as_deref(), env.as_deref(), *backup)?;
+                self.emit_finished("adopt");
             },
-            // TODO: finish this command
-            Command::Add { ignores, .. } => {
-                if let Some(patt) = ignores {
-                    self.global_config.ignores = self
-                        .global_config
-                        .ignores
-                        .clone()
-                        .map(|mut ignores| {
-                            ignores.push(patt.clone());
-                            ignores
-                        })
-                        .or_else(|| Some(vec![patt.to_string()]));
-                }
-
-                // WIP
-                let conversion = ConfigConversion::overwrite(&self.config_file)?;
-                conversion.run().map_err(Error::from)?;
-
-                // println!("IGNORES: {:#?}", self);
+            Command::ImportConfig {
+                from,
+                path,
+                target,
+                dry_run,
+            } => {
+                self.emit_started("import-config");
+                import::run_import(self, from, path, target.as_ref(), *dry_run)?;
+                self.emit_finished("import-config");
+            },
+            Command::Rename { old, new } => migrate::run_rename(self, old, new)?,
+            Command::Forget { target, keep_files } => {
+                migrate::run_forget(self, target, *keep_files)?;
             },
+            Command::List { all } => list::run_list(self, *all)?,
+            Command::Explain { target, format } => {
+                explain::run_explain(self, target.as_deref(), format)?;
+            },
+            Command::Edit => editor::run_edit(self)?,
+            Command::Init {
+                detect,
+                format,
+                output_file,
+                dry_run,
+            } => init::run_init(
+                self,
+                *detect,
+                format.as_ref(),
+                output_file.as_ref(),
+                *dry_run,
+            )?,
+            // TODO: finish this command
+            Command::Add { ignores, .. } =>
+                if let Some(pattern) = ignores {
+                    let mut doc = ConfigDocument::load(&self.config_file)?;
+                    let ignores = doc.array_mut("global_config", "ignores")?;
+                    if !ignores
+                        .iter()
+                        .any(|ignore| ignore.as_str() == Some(pattern))
+                    {
+                        ignores.push(serde_json::Value::String(pattern.clone()));
+                    }
+                    self.rebuild(&doc)?;
+                    doc.save()?;
+                },
         }
 
         Ok(())
@@ -297,7 +877,7 @@ struct Checkers {
 }
 
 impl Checkers {
-    fn new(hoard_map: &HashMap<&str, &Hoard>, is_backup: bool) -> Result<Self, Error> {
+    fn new(hoard_map: &HashMap<&str, Hoard>, is_backup: bool) -> Result<Self, Error> {
         let mut last_paths = HashMap::new();
         let mut operations = HashMap::new();
 
@@ -314,17 +894,64 @@ impl Checkers {
         })
     }
 
-    fn check(&mut self) -> Result<(), Error> {
+    /// Like [`Checkers::new`], for backups of only the piles that each of the
+    /// named hoards in `hoard_map` contains.
+    fn for_piles(hoard_map: &HashMap<&str, Hoard>) -> Result<Self, Error> {
+        let mut last_paths = HashMap::new();
+        let mut operations = HashMap::new();
+
+        for (name, hoard) in hoard_map {
+            let lp = LastPaths::for_piles(name, hoard)?;
+            let op = HoardOperation::for_piles(name, hoard)?;
+            last_paths.insert((*name).to_owned(), lp);
+            operations.insert((*name).to_owned(), op);
+        }
+
+        Ok(Self {
+            last_paths,
+            operations,
+        })
+    }
+
+    fn check(&mut self, output: OutputFormat) -> Result<(), Error> {
         let _span = tracing::info_span!("running_checks").entered();
-        for last_path in &mut self.last_paths.values_mut() {
-            last_path.check()?;
+        for (hoard, last_path) in &mut self.last_paths {
+            Self::report(
+                "last_paths",
+                hoard,
+                last_path.check().map_err(Error::from),
+                output,
+            )?;
         }
-        for operation in self.operations.values_mut() {
-            operation.check()?;
+        for (hoard, operation) in &mut self.operations {
+            Self::report(
+                "operation",
+                hoard,
+                operation.check().map_err(Error::from),
+                output,
+            )?;
         }
         Ok(())
     }
 
+    /// Emit the result of running `checker` against `hoard`.
+    fn report(
+        checker: &str,
+        hoard: &str,
+        result: Result<(), Error>,
+        output: OutputFormat,
+    ) -> Result<(), Error> {
+        if output.is_json() {
+            emit(&Event::CheckerResult {
+                checker,
+                hoard,
+                passed: result.is_ok(),
+                error: result.as_ref().err(),
+            });
+        }
+        result
+    }
+
     fn commit_to_disk(self) -> Result<(), Error> {
         let Self {
             last_paths,
@@ -0,0 +1,357 @@
+//! Machine-readable output of `hoard` commands.
+//!
+//! With `--output json`, `hoard` writes one JSON object per line (NDJSON) to
+//! stdout for every [`Event`] of a command, instead of drawing progress bars
+//! and printing a summary. Every object has an `event` field naming the kind of
+//! event. Log messages are written to stderr so they do not interleave with the
+//! events.
+
+use super::{
+    builder::{
+        environment::ConditionResult,
+        hoard::{Candidate, ConditionPaths, Walker},
+    },
+    lint::Severity,
+    report::Report,
+    Error,
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The format `hoard` reports the progress and outcome of commands in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// Progress bars, summaries and log messages meant for humans.
+    Text,
+    /// One JSON event per line.
+    Json,
+}
+
+impl OutputFormat {
+    /// Helper function to list possible variants of [`OutputFormat`].
+    #[allow(clippy::must_use_candidate)]
+    pub fn variants() -> [&'static str; 2] {
+        ["text", "json"]
+    }
+
+    /// Whether events should be emitted as JSON.
+    #[must_use]
+    pub fn is_json(self) -> bool {
+        self == Self::Json
+    }
+
+    /// Look for `--output json` in the process's arguments.
+    ///
+    /// This is used to report errors that happen before the arguments have
+    /// been fully parsed, e.g. while reading the configuration file.
+    #[must_use]
+    pub fn from_env_args() -> Self {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let value = match arg.strip_prefix("--output") {
+                Some("") => args.next(),
+                Some(rest) => rest.strip_prefix('=').map(str::to_owned),
+                None => None,
+            };
+            if let Some(format) = value.and_then(|value| value.parse().ok()) {
+                return format;
+            }
+        }
+        Self::default()
+    }
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("invalid output format: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// A single event of a command, written as one line of JSON.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A command started.
+    OperationStarted {
+        /// The command, e.g. `backup`.
+        operation: &'a str,
+        /// The hoards the command acts on, if any.
+        #[serde(skip_serializing_if = "<[_]>::is_empty")]
+        hoards:    Vec<&'a str>,
+    },
+    /// A checker ran against a hoard before a backup or restore.
+    CheckerResult {
+        /// The checker that ran, e.g. `last_paths`.
+        checker: &'a str,
+        /// The hoard that was checked.
+        hoard:   &'a str,
+        /// Whether it is safe to operate on the hoard.
+        passed:  bool,
+        /// The reason the check did not pass.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error:   Option<&'a Error>,
+    },
+    /// A file was copied, encrypted or decrypted.
+    FileCopied {
+        /// The hoard the file belongs to.
+        hoard:  &'a str,
+        /// The named pile the file belongs to, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pile:   Option<&'a str>,
+        /// The file that was read.
+        src:    &'a Path,
+        /// The file that was written.
+        dest:   &'a Path,
+        /// Size of the source file.
+        bytes:  u64,
+        /// Whether the file was encrypted or decrypted on the way.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        crypto: Option<&'a str>,
+    },
+    /// A file was not copied.
+    FileSkipped {
+        /// The hoard the file belongs to.
+        hoard:  &'a str,
+        /// The named pile the file belongs to, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pile:   Option<&'a str>,
+        /// The file that was skipped.
+        path:   &'a Path,
+        /// Why the file was skipped.
+        reason: &'a str,
+    },
+    /// A file could not be read or copied.
+    FileFailed {
+        /// The hoard the file belongs to.
+        hoard:  &'a str,
+        /// The named pile the file belongs to, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pile:   Option<&'a str>,
+        /// The file that failed, if known.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path:   Option<&'a Path>,
+        /// A stable identifier of the kind of failure.
+        kind:   &'a str,
+        /// Why the file failed.
+        reason: String,
+    },
+    /// A pile listed by `hoard list`.
+    PileListed {
+        /// The hoard the pile belongs to.
+        hoard:        &'a str,
+        /// The name of the pile, if it is named.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pile:         Option<&'a str>,
+        /// The environment condition whose path is used, or `null` if no
+        /// environment matches.
+        condition:    Option<&'a str>,
+        /// The expanded path, or `null` if no environment matches.
+        path:         Option<&'a Path>,
+        /// Whether the path exists.
+        exists:       bool,
+        /// The expanded candidate paths of the matching condition, if it has
+        /// several.
+        #[serde(skip_serializing_if = "<[_]>::is_empty")]
+        candidates:   &'a [PathBuf],
+        /// The kind of encryption used, if any.
+        encryption:   Option<&'a str>,
+        /// How files in the pile are found.
+        walker:       &'a Walker,
+        /// The level of the configuration (`global`, `hoard` or `pile`)
+        /// each setting that is not a default comes from.
+        settings:     &'a BTreeMap<&'static str, &'static str>,
+        /// Number of files stored in the hoards root.
+        stored_files: usize,
+        /// Total size of the files stored in the hoards root.
+        stored_bytes: u64,
+        /// Every configured condition and its path, with `hoard list --all`.
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        conditions:   BTreeMap<&'a str, &'a ConditionPaths>,
+    },
+    /// An environment evaluated by `hoard explain`.
+    EnvironmentExplained {
+        /// The name of the environment.
+        name:       &'a str,
+        /// Whether the environment applies to this system.
+        applies:    bool,
+        /// The result of each kind of condition of the environment.
+        conditions: &'a [ConditionResult],
+    },
+    /// A pile evaluated by `hoard explain`.
+    PileExplained {
+        /// The hoard the pile belongs to.
+        hoard:      &'a str,
+        /// The name of the pile, if it is named.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pile:       Option<&'a str>,
+        /// Every environment condition of the pile.
+        candidates: &'a [Candidate],
+        /// The condition whose path is used, or `null` if none applies.
+        chosen:     Option<&'a str>,
+    },
+    /// A problem found in the configuration by `hoard validate`.
+    LintFinding {
+        /// A stable name of the check that found the problem.
+        code:     &'a str,
+        /// How serious the problem is.
+        severity: Severity,
+        /// What is wrong.
+        message:  &'a str,
+    },
+    /// A known application found by `hoard init --detect`.
+    AppDetected {
+        /// The name of the application, used as the name of its hoard.
+        app:   &'a str,
+        /// The configuration paths of the application found on this system.
+        paths: &'a [PathBuf],
+    },
+    /// A document printed by a command, e.g. `hoard config --show-effective`
+    /// or a dry run of `hoard init`.
+    Document {
+        /// The format of the document: `json`, `toml` or `yaml`.
+        format:  &'a str,
+        /// The text of the document.
+        content: &'a str,
+    },
+    /// A backup or restore finished.
+    Summary {
+        /// The command, e.g. `backup`.
+        operation: &'a str,
+        /// The report of each hoard.
+        hoards:    BTreeMap<&'a str, &'a Report>,
+    },
+    /// A command other than a backup or restore finished.
+    OperationFinished {
+        /// The command, e.g. `validate`.
+        operation: &'a str,
+    },
+    /// A command failed.
+    Error {
+        /// The error that ended the command.
+        error:     &'a Error,
+        /// The code `hoard` exits with.
+        exit_code: i32,
+    },
+}
+
+/// Write `event` as a line of JSON to stdout.
+///
+/// Failing to write an event is logged instead of failing the command.
+pub fn emit(event: &Event<'_>) {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = serde_json::to_writer(&mut out, event)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(out));
+    if let Err(err) = result {
+        tracing::warn!(error = %err, "failed to write event");
+    }
+}
+
+/// Print `content`, a document in `format`, to stdout, or emit it as an
+/// [`Event::Document`] with `--output json`.
+///
+/// # Errors
+///
+/// Any I/O error while printing the document as text.
+pub fn print_document(output: OutputFormat, format: &str, content: &str) -> io::Result<()> {
+    if output.is_json() {
+        emit(&Event::Document { format, content });
+        Ok(())
+    } else {
+        io::stdout().lock().write_all(content.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_format_round_trips() {
+        for name in OutputFormat::variants() {
+            let format: OutputFormat = name.parse().expect("variant should parse");
+            assert_eq!(format.to_string(), name);
+        }
+        "xml"
+            .parse::<OutputFormat>()
+            .expect_err("unknown formats should not parse");
+    }
+
+    #[test]
+    fn events_are_tagged() {
+        let event = Event::FileSkipped {
+            hoard:  "nvim",
+            pile:   None,
+            path:   Path::new("/tmp/init.vim"),
+            reason: "pattern",
+        };
+        let json = serde_json::to_value(&event).expect("event should serialize");
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "event": "file_skipped",
+                "hoard": "nvim",
+                "path": "/tmp/init.vim",
+                "reason": "pattern",
+            })
+        );
+    }
+
+    #[test]
+    fn documents_are_wrapped_in_events() {
+        let json = serde_json::to_value(&Event::Document {
+            format:  "toml",
+            content: "[hoards]\n",
+        })
+        .expect("event should serialize");
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "event": "document",
+                "format": "toml",
+                "content": "[hoards]\n",
+            })
+        );
+    }
+
+    #[test]
+    fn errors_serialize_with_kind() {
+        let error = Error::NoSuchHoard("nvim".into());
+        let json = serde_json::to_value(&Event::Error {
+            error:     &error,
+            exit_code: error.exit_code(),
+        })
+        .expect("event should serialize");
+        assert_eq!(json["event"], "error");
+        assert_eq!(json["error"]["kind"], "no_such_hoard");
+        assert_eq!(json["exit_code"], error.exit_code());
+    }
+}
@@ -0,0 +1,332 @@
+//! Statistics and progress display for backups and restores.
+//!
+//! [`Hoard::backup`](super::hoard::Hoard::backup) and
+//! [`Hoard::restore`](super::hoard::Hoard::restore) return a [`Report`] with
+//! counts of what happened to each file, and update a [`Progress`] while they
+//! run. With `--output json`, [`Progress`] emits an [`Event`] for each file
+//! instead of drawing a progress bar.
+
+use super::output::{emit, Event, OutputFormat};
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::{Serialize, Serializer};
+use std::{
+    fmt,
+    io::{self, Write},
+    ops::AddAssign,
+    path::Path,
+    time::Duration,
+};
+
+/// Template of the progress bar shown for each pile.
+const PROGRESS_TEMPLATE: &str = "{msg} [{wide_bar}] {bytes}/{total_bytes} ({eta} left)";
+
+/// What happened to a single file during a backup or restore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileOutcome {
+    /// The file was copied as-is.
+    Copied,
+    /// The file was encrypted into the hoard.
+    Encrypted,
+    /// The file was decrypted out of the hoard.
+    Decrypted,
+    /// The file was not copied, for the given reason.
+    Skipped(&'static str),
+}
+
+/// Counts of what happened while backing up or restoring a hoard or pile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// Files written to the destination, including encrypted and decrypted
+    /// files.
+    pub files_copied:    usize,
+    /// Files that were not copied, e.g. because they did not match the
+    /// walker's pattern.
+    pub files_skipped:   usize,
+    /// Files that were encrypted while backing up.
+    pub files_encrypted: usize,
+    /// Files that were decrypted while restoring.
+    pub files_decrypted: usize,
+    /// Errors that did not stop the operation, e.g. unreadable directories.
+    pub errors:          usize,
+    /// Total size of the copied source files.
+    pub bytes:           u64,
+    /// Time spent on the operation.
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    pub elapsed:         Duration,
+}
+
+/// Serialize a [`Duration`] as fractional seconds.
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+impl Report {
+    /// Records the outcome of a single file of size `bytes`.
+    pub(crate) fn record(&mut self, outcome: FileOutcome, bytes: u64) {
+        match outcome {
+            FileOutcome::Skipped(_) => {
+                self.files_skipped += 1;
+                return;
+            },
+            FileOutcome::Encrypted => self.files_encrypted += 1,
+            FileOutcome::Decrypted => self.files_decrypted += 1,
+            FileOutcome::Copied => {},
+        }
+        self.files_copied += 1;
+        self.bytes += bytes;
+    }
+}
+
+impl AddAssign for Report {
+    fn add_assign(&mut self, rhs: Self) {
+        self.files_copied += rhs.files_copied;
+        self.files_skipped += rhs.files_skipped;
+        self.files_encrypted += rhs.files_encrypted;
+        self.files_decrypted += rhs.files_decrypted;
+        self.errors += rhs.errors;
+        self.bytes += rhs.bytes;
+        self.elapsed += rhs.elapsed;
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files copied ({} encrypted, {} decrypted), {} skipped, {} errors, {} in {:.2}s",
+            self.files_copied,
+            self.files_encrypted,
+            self.files_decrypted,
+            self.files_skipped,
+            self.errors,
+            HumanBytes(self.bytes),
+            self.elapsed.as_secs_f64(),
+        )
+    }
+}
+
+/// Progress bar showing the files and bytes processed in the current pile.
+///
+/// Nothing is drawn unless stdout is a terminal.
+#[derive(Debug)]
+pub struct Progress {
+    bar:         ProgressBar,
+    /// Whether to emit an [`Event`] for each file.
+    events:      bool,
+    /// Name of the hoard being processed.
+    hoard:       String,
+    /// Name of the pile being processed, if it is a named pile.
+    pile:        Option<String>,
+    /// Number of files in the current pile.
+    total_files: usize,
+    /// Number of files in the current pile that have been processed.
+    done_files:  usize,
+}
+
+impl Progress {
+    /// Create a new `Progress` that draws to stdout if it is a terminal.
+    #[must_use]
+    pub fn new() -> Self {
+        if atty::is(atty::Stream::Stdout) {
+            Self::with_bar(ProgressBar::with_draw_target(
+                Some(0),
+                ProgressDrawTarget::stdout(),
+            ))
+        } else {
+            Self::hidden()
+        }
+    }
+
+    /// Create a new `Progress` that never draws anything.
+    #[must_use]
+    pub fn hidden() -> Self {
+        Self::with_bar(ProgressBar::hidden())
+    }
+
+    /// Create a new `Progress` suitable for the given output format.
+    ///
+    /// [`OutputFormat::Json`] never draws anything and emits an [`Event`] for
+    /// each file instead.
+    #[must_use]
+    pub fn for_output(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Text => Self::new(),
+            OutputFormat::Json => Self {
+                events: true,
+                ..Self::hidden()
+            },
+        }
+    }
+
+    fn with_bar(bar: ProgressBar) -> Self {
+        match ProgressStyle::with_template(PROGRESS_TEMPLATE) {
+            Ok(style) => bar.set_style(style.progress_chars("=> ")),
+            Err(err) => tracing::warn!(error = %err, "invalid progress bar template"),
+        }
+        Self {
+            bar,
+            events: false,
+            hoard: String::new(),
+            pile: None,
+            total_files: 0,
+            done_files: 0,
+        }
+    }
+
+    /// Set the name of the hoard being processed.
+    pub fn set_hoard(&mut self, hoard: &str) {
+        hoard.clone_into(&mut self.hoard);
+        self.pile = None;
+    }
+
+    /// Set the name of the named pile being processed.
+    pub fn set_pile(&mut self, pile: Option<&str>) {
+        self.pile = pile.map(str::to_owned);
+    }
+
+    /// Start showing progress for a pile with the given number of files and
+    /// bytes.
+    pub(crate) fn start_pile(&mut self, files: usize, bytes: u64) {
+        self.total_files = files;
+        self.done_files = 0;
+        self.bar.set_length(bytes);
+        self.bar.set_position(0);
+        self.bar.reset_eta();
+        self.update_message();
+    }
+
+    /// Mark the file `src` of size `bytes` as processed.
+    pub(crate) fn file_done(&mut self, src: &Path, dest: &Path, outcome: FileOutcome, bytes: u64) {
+        self.done_files += 1;
+        self.bar.inc(bytes);
+        self.update_message();
+
+        if !self.events {
+            return;
+        }
+        let crypto = match outcome {
+            FileOutcome::Skipped(reason) => return self.file_skipped(src, reason),
+            FileOutcome::Copied => None,
+            FileOutcome::Encrypted => Some("encrypted"),
+            FileOutcome::Decrypted => Some("decrypted"),
+        };
+        emit(&Event::FileCopied {
+            hoard: &self.hoard,
+            pile: self.pile.as_deref(),
+            src,
+            dest,
+            bytes,
+            crypto,
+        });
+    }
+
+    /// Report that `path` was not copied.
+    pub(crate) fn file_skipped(&self, path: &Path, reason: &str) {
+        if self.events {
+            emit(&Event::FileSkipped {
+                hoard: &self.hoard,
+                pile: self.pile.as_deref(),
+                path,
+                reason,
+            });
+        }
+    }
+
+    /// Report that `path` could not be read or copied.
+    pub(crate) fn file_failed(&self, path: Option<&Path>, kind: &str, reason: String) {
+        if self.events {
+            emit(&Event::FileFailed {
+                hoard: &self.hoard,
+                pile: self.pile.as_deref(),
+                path,
+                kind,
+                reason,
+            });
+        }
+    }
+
+    /// Remove the progress bar from the terminal.
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+
+    fn update_message(&self) {
+        let name = match &self.pile {
+            Some(pile) => format!("{}/{}", self.hoard, pile),
+            None => self.hoard.clone(),
+        };
+        self.bar.set_message(format!(
+            "{} ({}/{} files)",
+            name, self.done_files, self.total_files
+        ));
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Print the end-of-run summary of each hoard to stdout, sorted by hoard name.
+///
+/// # Errors
+///
+/// Any I/O error that occurs while writing to stdout.
+pub fn print_summary(reports: &[(&str, Report)]) -> Result<(), io::Error> {
+    let mut reports = reports.to_vec();
+    reports.sort_by_key(|(hoard, _)| *hoard);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (hoard, report) in reports {
+        writeln!(out, "{}: {}", hoard, report)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_counts_outcomes() {
+        let mut report = Report::default();
+        report.record(FileOutcome::Copied, 10);
+        report.record(FileOutcome::Encrypted, 20);
+        report.record(FileOutcome::Decrypted, 30);
+        report.record(FileOutcome::Skipped("test"), 40);
+
+        assert_eq!(report, Report {
+            files_copied:    3,
+            files_skipped:   1,
+            files_encrypted: 1,
+            files_decrypted: 1,
+            errors:          0,
+            bytes:           60,
+            elapsed:         Duration::default(),
+        });
+    }
+
+    #[test]
+    fn reports_add_up() {
+        let mut total = Report {
+            files_copied: 1,
+            bytes: 5,
+            elapsed: Duration::from_secs(1),
+            ..Report::default()
+        };
+        total += Report {
+            files_copied: 2,
+            errors: 1,
+            bytes: 10,
+            elapsed: Duration::from_secs(2),
+            ..Report::default()
+        };
+
+        assert_eq!(total.files_copied, 3);
+        assert_eq!(total.errors, 1);
+        assert_eq!(total.bytes, 15);
+        assert_eq!(total.elapsed, Duration::from_secs(3));
+    }
+}
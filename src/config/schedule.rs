@@ -0,0 +1,645 @@
+//! Install and inspect unattended backups of hoards with a [`Schedule`].
+//!
+//! Every scheduled hoard gets its own pair of systemd user units
+//! (`hoard-backup-<name>.service` and `.timer`), or its own line in a marked
+//! block of the user's crontab. Both invoke `hoard backup <name>` with the
+//! configuration file and hoards root of the installing invocation.
+//!
+//! See [`run_schedule`].
+
+use super::{builder::hoard::Schedule, Config};
+use crate::{
+    checkers::history::operation::{Error as OperationError, HoardOperation},
+    command::ScheduleCommand,
+};
+use chrono::{Duration as ChronoDuration, Utc};
+use directories::BaseDirs;
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+use thiserror::Error;
+
+/// Prefix of the names of all systemd units installed by `hoard`.
+const UNIT_PREFIX: &str = "hoard-backup-";
+/// Comment at the top of every generated unit file.
+const UNIT_HEADER: &str = "# Generated by `hoard schedule install`. Changes will be overwritten.";
+/// First line of the block that `hoard` manages in the user's crontab.
+const CRON_BEGIN: &str = "# BEGIN hoard schedule (managed by `hoard schedule`)";
+/// Last line of the block that `hoard` manages in the user's crontab.
+const CRON_END: &str = "# END hoard schedule";
+
+/// Errors that can occur while managing scheduled backups.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Any I/O error.
+    #[error("an I/O error occurred: {0}")]
+    IO(#[from] io::Error),
+    /// The directory for systemd user units could not be determined.
+    #[error("could not determine the systemd user unit directory")]
+    NoUnitDirectory,
+    /// An external command (`systemctl`, `crontab`) failed.
+    #[error("`{command}` failed: {stderr}")]
+    CommandFailed {
+        /// The command that failed.
+        command: String,
+        /// What the command printed to stderr.
+        stderr:  String,
+    },
+    /// A schedule cannot be expressed as a cron expression.
+    #[error("schedule of hoard {hoard} cannot be used with cron: {reason}")]
+    Cron {
+        /// The name of the hoard with the schedule.
+        hoard:  String,
+        /// Why the schedule cannot be converted.
+        reason: String,
+    },
+    /// A schedule interval is not a valid duration.
+    #[error("invalid schedule interval: {0}")]
+    Interval(#[from] humantime::DurationError),
+    /// Error while reading the operation logs.
+    #[error("failed to read operation logs: {0}")]
+    Operation(#[from] OperationError),
+}
+
+/// Run a `hoard schedule` action.
+///
+/// # Errors
+///
+/// Any [`enum@Error`] that occurs while running the action.
+pub(super) fn run_schedule(config: &Config, action: &ScheduleCommand) -> Result<(), Error> {
+    let _span = tracing::debug_span!("schedule", ?action).entered();
+    let schedules: BTreeMap<&str, &Schedule> = config
+        .schedules
+        .iter()
+        .map(|(name, schedule)| (name.as_str(), schedule))
+        .collect();
+
+    match action {
+        ScheduleCommand::Install { cron, dry_run } => {
+            let invocation = Invocation::new(config)?;
+            if *cron {
+                install_cron(&schedules, &invocation, *dry_run)
+            } else {
+                install_systemd(&schedules, &invocation, *dry_run)
+            }
+        },
+        ScheduleCommand::Uninstall { cron } =>
+            if *cron {
+                write_crontab_block(&[])
+            } else {
+                uninstall_systemd()
+            },
+        ScheduleCommand::Status => status(&schedules),
+    }
+}
+
+/// The `hoard` command line that scheduled backups run.
+#[derive(Debug)]
+struct Invocation {
+    exe:         PathBuf,
+    config_file: PathBuf,
+    hoards_root: PathBuf,
+}
+
+impl Invocation {
+    fn new(config: &Config) -> Result<Self, Error> {
+        Ok(Self {
+            exe:         std::env::current_exe()?,
+            config_file: config.config_file.clone(),
+            hoards_root: config.storage.root(),
+        })
+    }
+
+    /// The arguments (including the executable) to back up the given hoard.
+    fn args<'a>(&'a self, hoard: &'a str) -> Vec<&'a OsStr> {
+        vec![
+            self.exe.as_os_str(),
+            OsStr::new("--config-file"),
+            self.config_file.as_os_str(),
+            OsStr::new("--hoards-root"),
+            self.hoards_root.as_os_str(),
+            OsStr::new("backup"),
+            OsStr::new(hoard),
+        ]
+    }
+}
+
+/// Escapes a hoard name for use in a systemd unit name.
+fn unit_name(hoard: &str) -> String {
+    let escaped: String = hoard
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b':' {
+                char::from(byte).to_string()
+            } else {
+                format!("\\x{:02x}", byte)
+            }
+        })
+        .collect();
+    format!("{}{}", UNIT_PREFIX, escaped)
+}
+
+/// Quotes an argument for an `ExecStart=` line.
+fn systemd_quote(arg: &OsStr) -> String {
+    let escaped = arg
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{}\"", escaped)
+}
+
+/// Quotes an argument for a POSIX shell, as used by cron. A `%` is escaped
+/// even in quotes, since cron turns it into a newline otherwise.
+fn shell_quote(arg: &OsStr) -> String {
+    let escaped = arg
+        .to_string_lossy()
+        .replace('\'', "'\\''")
+        .replace('%', "\\%");
+    format!("'{}'", escaped)
+}
+
+/// Generates the `.service` unit backing up the given hoard.
+fn service_unit(hoard: &str, invocation: &Invocation) -> String {
+    let exec = invocation
+        .args(hoard)
+        .into_iter()
+        .map(systemd_quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    [
+        UNIT_HEADER,
+        "[Unit]",
+        &format!("Description=Back up hoard {}", hoard),
+        "",
+        "[Service]",
+        "Type=oneshot",
+        &format!("ExecStart={}", exec),
+        "",
+    ]
+    .join("\n")
+}
+
+/// Generates the `.timer` unit triggering the backup of the given hoard.
+fn timer_unit(hoard: &str, schedule: &Schedule) -> Result<String, Error> {
+    let timing = match schedule {
+        Schedule::Interval(interval) => {
+            let secs = humantime::parse_duration(interval)?.as_secs();
+            vec![
+                format!("OnBootSec={}s", secs),
+                format!("OnUnitActiveSec={}s", secs),
+            ]
+        },
+        Schedule::Calendar(calendar) => vec![
+            format!("OnCalendar={}", calendar),
+            "Persistent=true".to_owned(),
+        ],
+    };
+
+    let mut lines = vec![
+        UNIT_HEADER.to_owned(),
+        "[Unit]".to_owned(),
+        format!("Description=Scheduled backup of hoard {}", hoard),
+        String::new(),
+        "[Timer]".to_owned(),
+    ];
+    lines.extend(timing);
+    lines.extend(vec![
+        String::new(),
+        "[Install]".to_owned(),
+        "WantedBy=timers.target".to_owned(),
+        String::new(),
+    ]);
+    Ok(lines.join("\n"))
+}
+
+/// The directory systemd reads user units from.
+fn unit_dir() -> Result<PathBuf, Error> {
+    BaseDirs::new()
+        .map(|dirs| dirs.config_dir().join("systemd").join("user"))
+        .ok_or(Error::NoUnitDirectory)
+}
+
+/// All unit files in `dir` that were installed by `hoard`.
+fn installed_units(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut units = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_hoard_unit = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .map_or(false, |name| name.starts_with(UNIT_PREFIX));
+        if is_hoard_unit {
+            units.push(path);
+        }
+    }
+    units.sort();
+    Ok(units)
+}
+
+fn is_timer(unit: &Path) -> bool {
+    unit.extension() == Some(OsStr::new("timer"))
+}
+
+/// Runs an external command, returning its stdout.
+fn run_command(program: &str, args: &[&str], stdin: Option<&str>) -> Result<String, Error> {
+    let command = format!("{} {}", program, args.join(" "));
+    tracing::debug!(%command, "running command");
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(Error::CommandFailed {
+            command,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        })
+    }
+}
+
+fn systemctl(args: &[&str]) -> Result<String, Error> {
+    let mut full_args = vec!["--user"];
+    full_args.extend_from_slice(args);
+    run_command("systemctl", &full_args, None)
+}
+
+/// Disables the timers among `units` and removes all of `units`.
+fn remove_units(units: &[PathBuf]) -> Result<(), Error> {
+    for unit in units {
+        let name = unit.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        if is_timer(unit) {
+            tracing::info!(timer = %name, "disabling timer");
+            if let Err(err) = systemctl(&["disable", "--now", name]) {
+                tracing::warn!(error = %err, timer = %name, "failed to disable timer");
+            }
+        }
+        tracing::debug!(unit = %unit.display(), "removing unit file");
+        fs::remove_file(unit)?;
+    }
+    Ok(())
+}
+
+fn install_systemd(
+    schedules: &BTreeMap<&str, &Schedule>,
+    invocation: &Invocation,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let dir = unit_dir()?;
+    let mut units = Vec::new();
+    for (hoard, schedule) in schedules {
+        let name = unit_name(hoard);
+        units.push((
+            dir.join(format!("{}.service", name)),
+            service_unit(hoard, invocation),
+        ));
+        units.push((
+            dir.join(format!("{}.timer", name)),
+            timer_unit(hoard, schedule)?,
+        ));
+    }
+
+    if dry_run {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for (path, content) in &units {
+            writeln!(out, "# {}\n{}", path.display(), content)?;
+        }
+        return Ok(());
+    }
+
+    let stale: Vec<PathBuf> = installed_units(&dir)?
+        .into_iter()
+        .filter(|path| !units.iter().any(|(unit, _)| unit == path))
+        .collect();
+    remove_units(&stale)?;
+
+    fs::create_dir_all(&dir)?;
+    for (path, content) in &units {
+        tracing::debug!(unit = %path.display(), "writing unit file");
+        fs::write(path, content)?;
+    }
+
+    systemctl(&["daemon-reload"])?;
+    for (path, _) in &units {
+        let name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        if is_timer(path) {
+            systemctl(&["enable", "--now", name])?;
+            tracing::info!(timer = %name, "enabled timer");
+        }
+    }
+
+    if schedules.is_empty() {
+        tracing::warn!("no hoards have a schedule configured");
+    }
+    Ok(())
+}
+
+fn uninstall_systemd() -> Result<(), Error> {
+    let units = installed_units(&unit_dir()?)?;
+    remove_units(&units)?;
+    systemctl(&["daemon-reload"])?;
+    tracing::info!(count = units.len(), "removed scheduled backup units");
+    Ok(())
+}
+
+/// Converts a [`Schedule`] into the time fields of a crontab line.
+fn cron_expression(hoard: &str, schedule: &Schedule) -> Result<String, Error> {
+    let cron_error = |reason: &str| Error::Cron {
+        hoard:  hoard.to_owned(),
+        reason: reason.to_owned(),
+    };
+
+    match schedule {
+        Schedule::Calendar(calendar) => match calendar.trim() {
+            shortcut @ ("hourly" | "daily" | "weekly" | "monthly" | "yearly" | "annually") =>
+                Ok(format!("@{}", shortcut)),
+            expr if expr.split_whitespace().count() == 5 => Ok(expr.to_owned()),
+            _ => Err(cron_error(
+                "calendar must be a five-field cron expression or hourly, daily, weekly, monthly \
+                 or yearly",
+            )),
+        },
+        Schedule::Interval(interval) => {
+            let secs = humantime::parse_duration(interval)?.as_secs();
+            let mins = secs / 60;
+            let hours = mins / 60;
+            if secs == 0 || secs % 60 != 0 {
+                Err(cron_error("interval must be a whole number of minutes"))
+            } else if mins < 60 && 60 % mins == 0 {
+                Ok(format!("*/{} * * * *", mins))
+            } else if mins % 60 == 0 && hours < 24 && 24 % hours == 0 {
+                Ok(format!("0 */{} * * *", hours))
+            } else if hours % 24 == 0 && hours / 24 == 1 {
+                Ok("0 0 * * *".to_owned())
+            } else {
+                Err(cron_error(
+                    "interval must divide an hour or a day evenly, or be exactly one day",
+                ))
+            }
+        },
+    }
+}
+
+/// Replaces the `hoard` block in `crontab` with `lines`, removing the block if
+/// `lines` is empty.
+fn replace_crontab_block(crontab: &str, lines: &[String]) -> String {
+    let mut result = Vec::new();
+    let mut in_block = false;
+    for line in crontab.lines() {
+        if line == CRON_BEGIN {
+            in_block = true;
+        } else if line == CRON_END {
+            in_block = false;
+        } else if !in_block {
+            result.push(line.to_owned());
+        }
+    }
+
+    if !lines.is_empty() {
+        result.push(CRON_BEGIN.to_owned());
+        result.extend(lines.iter().cloned());
+        result.push(CRON_END.to_owned());
+    }
+
+    let mut result = result.join("\n");
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    result
+}
+
+fn write_crontab_block(lines: &[String]) -> Result<(), Error> {
+    let current = match run_command("crontab", &["-l"], None) {
+        Ok(current) => current,
+        Err(Error::CommandFailed { stderr, .. }) if stderr.contains("no crontab") => String::new(),
+        Err(err) => return Err(err),
+    };
+
+    run_command(
+        "crontab",
+        &["-"],
+        Some(&replace_crontab_block(&current, lines)),
+    )?;
+    tracing::info!(count = lines.len(), "updated crontab");
+    Ok(())
+}
+
+fn install_cron(
+    schedules: &BTreeMap<&str, &Schedule>,
+    invocation: &Invocation,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let lines = schedules
+        .iter()
+        .map(|(hoard, schedule)| {
+            let command = invocation
+                .args(hoard)
+                .into_iter()
+                .map(shell_quote)
+                .collect::<Vec<_>>()
+                .join(" ");
+            Ok(format!("{} {}", cron_expression(hoard, schedule)?, command))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    if dry_run {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        writeln!(out, "{}", replace_crontab_block("", &lines))?;
+        return Ok(());
+    }
+
+    if schedules.is_empty() {
+        tracing::warn!("no hoards have a schedule configured");
+    }
+    write_crontab_block(&lines)
+}
+
+/// Asks systemd when a calendar expression elapses next.
+fn next_calendar_elapse(calendar: &str) -> Option<String> {
+    run_command("systemd-analyze", &["calendar", calendar], None)
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            line.trim()
+                .strip_prefix("Next elapse:")
+                .map(|next| next.trim().to_owned())
+        })
+}
+
+fn status(schedules: &BTreeMap<&str, &Schedule>) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if schedules.is_empty() {
+        writeln!(out, "No hoards have a schedule configured.")?;
+    }
+
+    for (hoard, schedule) in schedules {
+        let last = HoardOperation::latest_local_backup(hoard)?.map(|op| op.timestamp());
+        let (description, next) = match schedule {
+            Schedule::Interval(interval) => {
+                let next = match last {
+                    Some(last) => ChronoDuration::from_std(humantime::parse_duration(interval)?)
+                        .ok()
+                        .map(|interval| last + interval)
+                        .map(|next| {
+                            if next < Utc::now() {
+                                format!("{} (overdue)", next)
+                            } else {
+                                next.to_string()
+                            }
+                        }),
+                    _ => Some("on the next scheduled run".to_owned()),
+                };
+                (format!("every {}", interval), next)
+            },
+            Schedule::Calendar(calendar) =>
+                (format!("on {}", calendar), next_calendar_elapse(calendar)),
+        };
+
+        writeln!(out, "{}: {}", hoard, description)?;
+        writeln!(
+            out,
+            "  last backup: {}",
+            last.map_or_else(|| "never".to_owned(), |last| last.to_string())
+        )?;
+        writeln!(
+            out,
+            "  next backup: {}",
+            next.unwrap_or_else(|| "unknown".to_owned())
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invocation() -> Invocation {
+        Invocation {
+            exe:         PathBuf::from("/usr/bin/hoard"),
+            config_file: PathBuf::from("/home/user/.config/hoard/config.toml"),
+            hoards_root: PathBuf::from("/home/user/my hoards"),
+        }
+    }
+
+    #[test]
+    fn unit_names_are_escaped() {
+        assert_eq!(unit_name("nvim"), "hoard-backup-nvim");
+        assert_eq!(unit_name("game saves"), "hoard-backup-game\\x20saves");
+        assert_eq!(unit_name("a-b/c"), "hoard-backup-a\\x2db\\x2fc");
+    }
+
+    #[test]
+    fn service_runs_backup_of_hoard() {
+        let unit = service_unit("nvim", &invocation());
+        assert!(unit.contains(
+            "ExecStart=\"/usr/bin/hoard\" \"--config-file\" \
+             \"/home/user/.config/hoard/config.toml\" \"--hoards-root\" \"/home/user/my hoards\" \
+             \"backup\" \"nvim\"\n"
+        ));
+    }
+
+    #[test]
+    fn timer_uses_interval_or_calendar() {
+        let interval = timer_unit("nvim", &Schedule::Interval("1h 30m".into()))
+            .expect("interval should be valid");
+        assert!(interval.contains("OnBootSec=5400s\nOnUnitActiveSec=5400s\n"));
+
+        let calendar = timer_unit("nvim", &Schedule::Calendar("Mon *-*-* 09:00".into()))
+            .expect("calendar should be valid");
+        assert!(calendar.contains("OnCalendar=Mon *-*-* 09:00\nPersistent=true\n"));
+    }
+
+    #[test]
+    fn schedules_convert_to_cron() {
+        let cron = |schedule: Schedule| cron_expression("test", &schedule).ok();
+        assert_eq!(
+            cron(Schedule::Interval("15m".into())).as_deref(),
+            Some("*/15 * * * *")
+        );
+        assert_eq!(
+            cron(Schedule::Interval("6h".into())).as_deref(),
+            Some("0 */6 * * *")
+        );
+        assert_eq!(
+            cron(Schedule::Interval("1day".into())).as_deref(),
+            Some("0 0 * * *")
+        );
+        assert_eq!(
+            cron(Schedule::Calendar("daily".into())).as_deref(),
+            Some("@daily")
+        );
+        assert_eq!(
+            cron(Schedule::Calendar("0 9 * * 1".into())).as_deref(),
+            Some("0 9 * * 1")
+        );
+        assert_eq!(cron(Schedule::Interval("7m".into())), None);
+        assert_eq!(cron(Schedule::Interval("90s".into())), None);
+        assert_eq!(cron(Schedule::Calendar("Mon *-*-* 09:00".into())), None);
+    }
+
+    #[test]
+    fn crontab_block_is_replaced() {
+        let existing = format!(
+            "MAILTO=me\n{}\nold line\n{}\n0 * * * * other-job\n",
+            CRON_BEGIN, CRON_END
+        );
+        let replaced = replace_crontab_block(&existing, &["new line".to_owned()]);
+        assert_eq!(
+            replaced,
+            format!(
+                "MAILTO=me\n0 * * * * other-job\n{}\nnew line\n{}\n",
+                CRON_BEGIN, CRON_END
+            )
+        );
+        assert_eq!(
+            replace_crontab_block(&replaced, &[]),
+            "MAILTO=me\n0 * * * * other-job\n"
+        );
+    }
+
+    #[test]
+    fn cron_lines_are_shell_quoted() {
+        let args = invocation()
+            .args("it's")
+            .into_iter()
+            .map(shell_quote)
+            .collect::<Vec<_>>();
+        assert_eq!(args[4], "'/home/user/my hoards'");
+        assert_eq!(args[6], "'it'\\''s'");
+        let args = invocation()
+            .args("100%")
+            .into_iter()
+            .map(shell_quote)
+            .collect::<Vec<_>>();
+        assert_eq!(args[6], "'100\\%'");
+    }
+}
@@ -0,0 +1,168 @@
+//! A [`Storage`] in a local directory.
+
+use super::{key_to_path, Error, Metadata, Storage};
+use crate::utils::write_atomic;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A [`Storage`] that keeps files in a local directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalStorage {
+    /// The directory the keys are relative to.
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Create a storage rooted at `root`. The directory is created when the
+    /// first file is written.
+    #[must_use]
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// The directory the keys are relative to.
+    #[must_use]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Add all files below `dir`, whose keys start with `key`, to `found`.
+    fn walk(
+        &self,
+        dir: &Path,
+        key: &str,
+        found: &mut BTreeMap<String, Metadata>,
+    ) -> Result<(), Error> {
+        let io_error = |error| Error::IO {
+            path: dir.to_path_buf(),
+            error,
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(io_error(err)),
+        };
+
+        for entry in entries {
+            let entry = entry.map_err(io_error)?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let child = if key.is_empty() {
+                name.into_owned()
+            } else {
+                format!("{}/{}", key, name)
+            };
+
+            if entry.file_type().map_err(io_error)?.is_dir() {
+                self.walk(&entry.path(), &child, found)?;
+            } else if let Some(metadata) = self.metadata(&child)? {
+                found.insert(child, metadata);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the empty directories between `path` and the root.
+    fn remove_empty_parents(&self, path: &Path) {
+        for dir in path.ancestors().skip(1) {
+            if dir == self.root || !dir.starts_with(&self.root) || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl Storage for LocalStorage {
+    fn list(&self, prefix: &str) -> Result<BTreeMap<String, Metadata>, Error> {
+        // Only walk the deepest directory that all matching keys are in
+        let dir_key = prefix.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let dir = key_to_path(&self.root, dir_key)?;
+        let mut found = BTreeMap::new();
+        // A file where the directory would be has nothing below it
+        if dir.is_file() {
+            return Ok(found);
+        }
+        self.walk(&dir, dir_key, &mut found)?;
+        found.retain(|key, _| key.starts_with(prefix));
+        Ok(found)
+    }
+
+    fn read(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let path = key_to_path(&self.root, key)?;
+        fs::read(&path).map_err(|error| Error::IO { path, error })
+    }
+
+    fn write(&self, key: &str, contents: &[u8]) -> Result<(), Error> {
+        let path = key_to_path(&self.root, key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|error| Error::IO {
+                path: parent.to_path_buf(),
+                error,
+            })?;
+        }
+        write_atomic(&path, contents).map_err(|error| Error::IO { path, error })
+    }
+
+    fn delete(&self, key: &str) -> Result<(), Error> {
+        let path = key_to_path(&self.root, key)?;
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                self.remove_empty_parents(&path);
+                Ok(())
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(Error::IO { path, error }),
+        }
+    }
+
+    fn metadata(&self, key: &str) -> Result<Option<Metadata>, Error> {
+        let path = key_to_path(&self.root, key)?;
+        // A directory is not a file, only the files below it are
+        if path.is_dir() {
+            return Ok(None);
+        }
+        match fs::read(&path) {
+            Ok(contents) => Ok(Some(Metadata::of(&contents))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(Error::IO { path, error }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn list_only_returns_keys_with_prefix() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let storage = LocalStorage::new(dir.path().to_path_buf());
+        storage.write("nvim/init.vim", b"set nu").unwrap();
+        storage.write("nvim/lua/plugins.lua", b"return {}").unwrap();
+        storage.write("nvim2/init.vim", b"").unwrap();
+
+        let keys = storage
+            .list("nvim/")
+            .unwrap()
+            .into_keys()
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["nvim/init.vim", "nvim/lua/plugins.lua"]);
+        assert_eq!(storage.list("").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn delete_removes_empty_directories() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let storage = LocalStorage::new(dir.path().to_path_buf());
+        storage.write("nvim/lua/plugins.lua", b"return {}").unwrap();
+
+        storage.delete("nvim/lua/plugins.lua").unwrap();
+        storage.delete("nvim/lua/plugins.lua").unwrap();
+        assert!(!dir.path().join("nvim").exists());
+        assert!(dir.path().exists());
+    }
+}
@@ -0,0 +1,455 @@
+//! Storage backends for the hoards root.
+//!
+//! Backups and restores always operate on a local directory. If `hoards_root`
+//! is a URL of a remote [`Storage`] (e.g. `s3://bucket/prefix`), that directory
+//! is a local mirror of the remote storage: it is brought up to date with the
+//! remote before every operation, and the remote is brought up to date with it
+//! after every backup. See [`Location`] for the supported URLs.
+//!
+//! Operation logs can be kept in the remote storage as well, so that machines
+//! can check each other's operations without a separate sync tool.
+
+use crate::config::directories::PROJECT_DIRS;
+use md5::{Digest, Md5};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt::Debug,
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+pub use self::{
+    local::LocalStorage,
+    s3::{S3Config, S3Storage},
+};
+
+mod local;
+mod s3;
+
+/// The key prefix that operation logs are stored under in a remote storage.
+const HISTORY_PREFIX: &str = ".history";
+
+/// Errors that can occur while using a [`Storage`].
+#[derive(Debug, Error)]
+pub enum Error {
+    /// An I/O error on a local file.
+    #[error("I/O error on {path}: {error}")]
+    IO {
+        /// The path of the file.
+        path:  PathBuf,
+        /// The error that occurred.
+        #[source]
+        error: io::Error,
+    },
+    /// A key that does not name a file inside the storage, e.g. one containing
+    /// `..`.
+    #[error("invalid storage key: {0:?}")]
+    InvalidKey(String),
+    /// `hoards_root` is not a valid path or storage URL.
+    #[error("invalid storage URL {url}: {reason}")]
+    InvalidUrl {
+        /// The URL that could not be parsed.
+        url:    String,
+        /// Why it could not be parsed.
+        reason: String,
+    },
+    /// Credentials for a remote storage are not set.
+    #[error("missing credentials for remote storage: set {0}")]
+    MissingCredentials(&'static str),
+    /// A remote storage responded with an error.
+    #[error("{method} {key} failed with status {status}: {message}")]
+    Response {
+        /// The HTTP method of the request.
+        method:  &'static str,
+        /// The key the request was about.
+        key:     String,
+        /// The HTTP status code of the response.
+        status:  u16,
+        /// The body of the response.
+        message: String,
+    },
+    /// A remote storage could not be reached.
+    #[error("failed to reach remote storage: {0}")]
+    Transport(String),
+    /// The response of a remote storage could not be understood.
+    #[error("invalid response from remote storage: {0}")]
+    InvalidResponse(String),
+}
+
+/// What a [`Storage`] knows about a stored file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Hex-encoded MD5 checksum of the contents, if known.
+    pub md5:  Option<String>,
+}
+
+impl Metadata {
+    /// Metadata of a file with the given contents.
+    #[must_use]
+    pub fn of(contents: &[u8]) -> Self {
+        Self {
+            size: u64::try_from(contents.len()).unwrap_or(u64::MAX),
+            md5:  Some(hex::encode(Md5::digest(contents))),
+        }
+    }
+
+    /// Whether two files have the same contents, as far as can be told.
+    #[must_use]
+    pub fn same_contents(&self, other: &Self) -> bool {
+        match (&self.md5, &other.md5) {
+            (Some(md5), Some(other_md5)) => md5 == other_md5,
+            _ => self.size == other.size,
+        }
+    }
+}
+
+/// A place hoards are stored in.
+///
+/// Files are addressed by keys: `/`-separated paths relative to the root of
+/// the storage, e.g. `nvim/init.vim`.
+pub trait Storage: Debug {
+    /// All keys starting with `prefix`, with their metadata.
+    ///
+    /// # Errors
+    ///
+    /// Any error while listing the storage.
+    fn list(&self, prefix: &str) -> Result<BTreeMap<String, Metadata>, Error>;
+    /// The contents of the file at `key`.
+    ///
+    /// # Errors
+    ///
+    /// Any error while reading, including when `key` does not exist.
+    fn read(&self, key: &str) -> Result<Vec<u8>, Error>;
+    /// Replace the contents of the file at `key`, creating it if necessary.
+    ///
+    /// # Errors
+    ///
+    /// Any error while writing.
+    fn write(&self, key: &str, contents: &[u8]) -> Result<(), Error>;
+    /// Delete the file at `key`. Deleting a missing file is not an error.
+    ///
+    /// # Errors
+    ///
+    /// Any error while deleting.
+    fn delete(&self, key: &str) -> Result<(), Error>;
+    /// The metadata of the file at `key`, or `None` if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Any error while reading the metadata.
+    fn metadata(&self, key: &str) -> Result<Option<Metadata>, Error>;
+}
+
+/// Counts of what [`sync`] did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncStats {
+    /// Files copied because they were missing or different.
+    pub copied:  usize,
+    /// Files deleted because they no longer exist in the source.
+    pub deleted: usize,
+}
+
+/// Make the files under `prefix` in `to` the same as those in `from`.
+///
+/// Files are only copied if their contents differ. If `delete` is `true`, files
+/// in `to` that do not exist in `from` are deleted.
+///
+/// # Errors
+///
+/// Any [`Error`] while listing, reading, writing or deleting files.
+pub fn sync(
+    from: &dyn Storage,
+    to: &dyn Storage,
+    prefix: &str,
+    delete: bool,
+) -> Result<SyncStats, Error> {
+    let _span = tracing::debug_span!("sync", %prefix, ?from, ?to).entered();
+    sync_listed(from, to, &from.list(prefix)?, &to.list(prefix)?, delete)
+}
+
+/// The files below `dir/` in `storage`, or the file at `dir` itself.
+fn list_dir(storage: &dyn Storage, dir: &str) -> Result<BTreeMap<String, Metadata>, Error> {
+    let mut found = storage.list(&format!("{}/", dir))?;
+    if let Some(metadata) = storage.metadata(dir)? {
+        found.insert(dir.to_owned(), metadata);
+    }
+    Ok(found)
+}
+
+/// Make the files below `dir/` in `to`, or the file at `dir` itself, the
+/// same as those in `from`, e.g. for a hoard stored as a directory or, with a
+/// single file as its only pile, as a file.
+///
+/// See [`sync`] for how files are copied and deleted.
+///
+/// # Errors
+///
+/// Any [`Error`] while listing, reading, writing or deleting files.
+pub fn sync_dir(
+    from: &dyn Storage,
+    to: &dyn Storage,
+    dir: &str,
+    delete: bool,
+) -> Result<SyncStats, Error> {
+    let _span = tracing::debug_span!("sync_dir", %dir, ?from, ?to).entered();
+    sync_listed(from, to, &list_dir(from, dir)?, &list_dir(to, dir)?, delete)
+}
+
+/// Delete the files in `dest`, listed from `to`, that are not in `source`
+/// if `delete` is `true`, then copy the files in `source`, listed from `from`,
+/// to `to` unless `dest` has them already.
+///
+/// Deleting first lets a file replace a directory of the same name, and the
+/// other way around.
+fn sync_listed(
+    from: &dyn Storage,
+    to: &dyn Storage,
+    source: &BTreeMap<String, Metadata>,
+    dest: &BTreeMap<String, Metadata>,
+    delete: bool,
+) -> Result<SyncStats, Error> {
+    let mut stats = SyncStats::default();
+
+    if delete {
+        for key in dest.keys().filter(|key| !source.contains_key(*key)) {
+            tracing::trace!(%key, "deleting");
+            to.delete(key)?;
+            stats.deleted += 1;
+        }
+    }
+
+    for (key, metadata) in source {
+        let up_to_date = dest
+            .get(key)
+            .map_or(false, |existing| existing.same_contents(metadata));
+        if !up_to_date {
+            tracing::trace!(%key, "copying");
+            to.write(key, &from.read(key)?)?;
+            stats.copied += 1;
+        }
+    }
+
+    tracing::debug!(copied = stats.copied, deleted = stats.deleted, "synced");
+    Ok(stats)
+}
+
+/// Where the hoards root is, parsed from the configured `hoards_root`.
+///
+/// - A plain path or a `file://` URL is a local directory.
+/// - `s3://bucket/prefix` is an S3-compatible object storage. The query string can
+///   set `endpoint` (e.g. `http://localhost:9000` for `MinIO`), `region`,
+///   `path_style` and `history`. Credentials are read from `AWS_ACCESS_KEY_ID`,
+///   `AWS_SECRET_ACCESS_KEY` and, optionally, `AWS_SESSION_TOKEN`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    /// A local directory.
+    Local(PathBuf),
+    /// An S3-compatible object storage.
+    S3(S3Config),
+}
+
+impl Location {
+    /// Parse the configured `hoards_root`.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidUrl`] if it looks like a URL but is not a supported
+    /// one.
+    pub fn parse(root: &Path) -> Result<Self, Error> {
+        let url = match root.to_str() {
+            Some(url) if url.contains("://") => url,
+            _ => return Ok(Self::Local(root.to_path_buf())),
+        };
+
+        if let Some(path) = url.strip_prefix("file://") {
+            Ok(Self::Local(PathBuf::from(path)))
+        } else if url.starts_with("s3://") {
+            S3Config::parse(url).map(Self::S3)
+        } else {
+            Err(Error::InvalidUrl {
+                url:    url.to_owned(),
+                reason: "unsupported scheme, expected a path, file:// or s3://".to_owned(),
+            })
+        }
+    }
+
+    /// The location as it would be configured in `hoards_root`.
+    #[must_use]
+    pub fn root(&self) -> PathBuf {
+        match self {
+            Self::Local(path) => path.clone(),
+            Self::S3(config) => PathBuf::from(config.to_string()),
+        }
+    }
+
+    /// Whether files are stored somewhere other than the local directory.
+    #[must_use]
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, Self::Local(_))
+    }
+
+    /// Whether operation logs are stored in this location as well.
+    #[must_use]
+    pub fn stores_history(&self) -> bool {
+        match self {
+            Self::Local(_) => false,
+            Self::S3(config) => config.history,
+        }
+    }
+
+    /// The local directory that backups and restores operate on.
+    ///
+    /// For remote locations, this is a mirror in the cache directory.
+    #[must_use]
+    pub fn local_dir(&self) -> PathBuf {
+        match self {
+            Self::Local(path) => path.clone(),
+            Self::S3(config) => PROJECT_DIRS
+                .cache_dir()
+                .join("remote")
+                .join(config.mirror_name()),
+        }
+    }
+
+    /// Open the remote storage, or `None` if this is a local directory.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::MissingCredentials`] if the credentials are not set.
+    pub fn open_remote(&self) -> Result<Option<Box<dyn Storage>>, Error> {
+        match self {
+            Self::Local(_) => Ok(None),
+            Self::S3(config) => Ok(Some(Box::new(S3Storage::new(config.clone())?))),
+        }
+    }
+
+    /// Open the part of the remote storage that holds operation logs, or `None`
+    /// if operation logs are not stored remotely.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::MissingCredentials`] if the credentials are not set.
+    pub fn open_remote_history(&self) -> Result<Option<Box<dyn Storage>>, Error> {
+        match self {
+            Self::S3(config) if config.history => Ok(Some(Box::new(S3Storage::new(
+                config.child(HISTORY_PREFIX),
+            )?))),
+            Self::Local(_) | Self::S3(_) => Ok(None),
+        }
+    }
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self::Local(PathBuf::new())
+    }
+}
+
+/// Turn `key` into a path below `root`, rejecting keys that would escape it.
+///
+/// # Errors
+///
+/// [`Error::InvalidKey`] if `key` is absolute or contains `..`.
+fn key_to_path(root: &Path, key: &str) -> Result<PathBuf, Error> {
+    let mut path = root.to_path_buf();
+    for part in key
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+    {
+        if part == ".." || part.contains('\\') {
+            return Err(Error::InvalidKey(key.to_owned()));
+        }
+        path.push(part);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn paths_and_file_urls_are_local() {
+        assert_eq!(
+            Location::parse(Path::new("/data/hoards")).unwrap(),
+            Location::Local(PathBuf::from("/data/hoards"))
+        );
+        assert_eq!(
+            Location::parse(Path::new("file:///data/hoards")).unwrap(),
+            Location::Local(PathBuf::from("/data/hoards"))
+        );
+        assert!(matches!(
+            Location::parse(Path::new("ftp://example.com/hoards")),
+            Err(Error::InvalidUrl { .. })
+        ));
+    }
+
+    #[test]
+    fn keys_cannot_escape_the_root() {
+        let root = Path::new("/root");
+        assert_eq!(
+            key_to_path(root, "nvim/init.vim").unwrap(),
+            root.join("nvim").join("init.vim")
+        );
+        key_to_path(root, "../etc/passwd").expect_err("keys cannot contain ..");
+    }
+
+    #[test]
+    fn sync_copies_changed_files_and_deletes_removed_ones() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let from = LocalStorage::new(dir.path().join("from"));
+        let to = LocalStorage::new(dir.path().join("to"));
+
+        from.write("hoard/same", b"same").unwrap();
+        from.write("hoard/changed", b"new").unwrap();
+        from.write("other/untouched", b"other").unwrap();
+        to.write("hoard/same", b"same").unwrap();
+        to.write("hoard/changed", b"old").unwrap();
+        to.write("hoard/removed", b"removed").unwrap();
+
+        let stats = sync(&from, &to, "hoard/", true).unwrap();
+        assert_eq!(stats, SyncStats {
+            copied:  1,
+            deleted: 1,
+        });
+        assert_eq!(to.read("hoard/changed").unwrap(), b"new");
+        assert_eq!(to.metadata("hoard/removed").unwrap(), None);
+        assert_eq!(to.metadata("other/untouched").unwrap(), None);
+    }
+
+    #[test]
+    fn sync_dir_handles_hoards_stored_as_one_file() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let from = LocalStorage::new(dir.path().join("from"));
+        let to = LocalStorage::new(dir.path().join("to"));
+
+        from.write("zsh", b"export EDITOR=nvim").unwrap();
+        from.write("zsh2/zshrc", b"other").unwrap();
+        assert_eq!(from.list("zsh/").unwrap().len(), 0);
+
+        let stats = sync_dir(&from, &to, "zsh", true).unwrap();
+        assert_eq!(stats, SyncStats {
+            copied:  1,
+            deleted: 0,
+        });
+        assert_eq!(to.read("zsh").unwrap(), b"export EDITOR=nvim");
+        assert_eq!(to.metadata("zsh2/zshrc").unwrap(), None);
+
+        from.delete("zsh").unwrap();
+        from.write("zsh/zshrc", b"export EDITOR=nvim").unwrap();
+        to.write("zsh2/zshrc", b"untouched").unwrap();
+        let stats = sync_dir(&from, &to, "zsh", true).unwrap();
+        assert_eq!(stats, SyncStats {
+            copied:  1,
+            deleted: 1,
+        });
+        assert_eq!(to.metadata("zsh").unwrap(), None);
+        assert_eq!(to.read("zsh/zshrc").unwrap(), b"export EDITOR=nvim");
+        assert_eq!(to.read("zsh2/zshrc").unwrap(), b"untouched");
+    }
+}
@@ -0,0 +1,428 @@
+//! Watch the paths of configured piles and back them up when they change.
+//!
+//! See [`run_watch`].
+
+use super::{hoard::Hoard, Config, Error};
+use crate::config::{
+    builder::{glob, hoard::Config as PileConfig, GlobalConfig},
+    hoard::{walker_excludes, walker_pattern, Error as HoardError, Pile},
+};
+use globset::GlobMatcher;
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    overrides::Override,
+};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use regex::bytes::Regex;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+
+/// How often the event loop wakes up to check for shutdown and pending piles.
+const TICK: Duration = Duration::from_millis(250);
+
+/// A pile that is being watched for changes.
+#[derive(Debug)]
+struct WatchedPile<'a> {
+    /// The name of the hoard the pile belongs to.
+    hoard:  &'a str,
+    /// The name of the pile, if it is part of a named hoard.
+    pile:   Option<&'a str>,
+    /// The path of the pile on the filesystem.
+    path:   &'a Path,
+    /// Decides which changed paths should trigger a backup.
+    filter: PileFilter,
+}
+
+/// Filters filesystem events the same way the walker used by
+/// [`Pile::backup`] filters files.
+#[derive(Debug)]
+struct PileFilter {
+    root:      PathBuf,
+    /// The names of the entries of `root` that belong to the pile, if its
+    /// path ends in a glob pattern.
+    entries:   Option<GlobMatcher>,
+    pattern:   Regex,
+    excludes:  Override,
+    ignores:   Gitignore,
+    hidden:    bool,
+    max_depth: Option<usize>,
+}
+
+impl PileFilter {
+    fn new(
+        root: &Path,
+        entries: Option<GlobMatcher>,
+        config: &PileConfig,
+        global: &GlobalConfig,
+    ) -> Result<Self, HoardError> {
+        let mut ignores = GitignoreBuilder::new(root);
+        for line in global.ignores.iter().flatten() {
+            ignores
+                .add_line(None, line)
+                .map_err(|err| HoardError::IgnorePattern(err.to_string()))?;
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            entries,
+            pattern: walker_pattern(&config.walker)?,
+            excludes: walker_excludes(root, &config.walker)?,
+            ignores: ignores
+                .build()
+                .map_err(|err| HoardError::IgnorePattern(err.to_string()))?,
+            hidden: config.walker.hidden,
+            max_depth: config.walker.max_depth,
+        })
+    }
+
+    /// Whether a change to `path` should trigger a backup of the pile.
+    fn matches(&self, path: &Path) -> bool {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+
+        let is_dir = path.is_dir();
+        let depth = relative.components().count();
+
+        if let Some(entries) = &self.entries {
+            if !relative
+                .iter()
+                .next()
+                .map_or(false, |name| entries.is_match(name))
+            {
+                return false;
+            }
+        }
+
+        if self.max_depth.map_or(false, |max| depth > max) {
+            return false;
+        }
+
+        if self.hidden
+            && relative
+                .components()
+                .any(|comp| comp.as_os_str().to_string_lossy().starts_with('.'))
+        {
+            return false;
+        }
+
+        if self.excludes.matched(path, is_dir).is_ignore()
+            || self
+                .ignores
+                .matched_path_or_any_parents(path, is_dir)
+                .is_ignore()
+        {
+            return false;
+        }
+
+        // Directories only matter through the files inside of them.
+        is_dir
+            || path.file_name().map_or(true, |name| {
+                self.pattern.is_match(name.to_string_lossy().as_bytes())
+            })
+    }
+}
+
+/// Returns the paths touched by a debounced event, if any.
+fn event_paths(event: DebouncedEvent) -> Vec<PathBuf> {
+    match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path)
+        | DebouncedEvent::Remove(path) => vec![path],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => Vec::new(),
+        DebouncedEvent::Rescan => {
+            tracing::warn!("filesystem watcher requested a rescan");
+            Vec::new()
+        },
+        DebouncedEvent::Error(err, path) => {
+            tracing::error!(?path, error = %err, "error while watching files");
+            Vec::new()
+        },
+    }
+}
+
+/// Collects every pile of the given hoards that has an existing path.
+fn watched_piles<'a>(
+    config: &Config,
+    hoards: &HashMap<&'a str, &'a Hoard>,
+) -> Result<Vec<WatchedPile<'a>>, Error> {
+    let mut piles = Vec::new();
+    for (name, hoard) in hoards {
+        let named: Vec<(Option<&str>, &Pile)> = match hoard {
+            Hoard::Anonymous(pile) => vec![(None, pile)],
+            Hoard::Named(named) => named
+                .piles
+                .iter()
+                .map(|(pile_name, pile)| (Some(pile_name.as_str()), pile))
+                .collect(),
+        };
+
+        for (pile_name, pile) in named {
+            let _span =
+                tracing::debug_span!("watch_pile", hoard = %name, pile = ?pile_name).entered();
+            let path = match &pile.path {
+                Some(path) if glob::exists(path) => path,
+                Some(path) => {
+                    tracing::warn!(?path, "pile path does not exist -- not watching it");
+                    continue;
+                },
+                None => {
+                    tracing::warn!("pile has no associated path -- not watching it");
+                    continue;
+                },
+            };
+
+            let pile_config = pile.config.clone().unwrap_or_default();
+            let backup_error = |error| Error::Backup {
+                name: (*name).to_owned(),
+                error,
+            };
+            let (path, entries) = glob::split(path)
+                .map_err(|err| backup_error(HoardError::GlobError(err.to_string())))?;
+            let filter = PileFilter::new(path, entries, &pile_config, &config.global_config)
+                .map_err(backup_error)?;
+
+            piles.push(WatchedPile {
+                hoard: name,
+                pile: pile_name,
+                path,
+                filter,
+            });
+        }
+    }
+
+    Ok(piles)
+}
+
+/// Watch the piles of the given hoards (or all hoards, if empty) and back up
+/// each changed pile `delay` after its files stop changing.
+///
+/// Runs until `SIGINT` or `SIGTERM` is received.
+///
+/// # Errors
+///
+/// Any [`enum@Error`] that occurs while setting up the watches. Errors while
+/// backing up a single hoard are logged and do not stop the watch.
+pub(super) fn run_watch(config: &Config, hoards: &[String], delay: Duration) -> Result<(), Error> {
+    let hoards = config.get_hoards(hoards)?;
+    let piles = watched_piles(config, &hoards)?;
+
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = Arc::clone(&running);
+        ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))?;
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut fs_watcher = notify::watcher(tx, delay)?;
+    for pile in &piles {
+        // Watch the parent of single files so that editors replacing the file
+        // (instead of writing to it) do not break the watch.
+        match pile.path.parent() {
+            Some(parent) if pile.path.is_file() =>
+                fs_watcher.watch(parent, RecursiveMode::NonRecursive)?,
+            _ => fs_watcher.watch(pile.path, RecursiveMode::Recursive)?,
+        }
+        tracing::info!(
+            hoard = %pile.hoard,
+            pile = ?pile.pile,
+            path = %pile.path.display(),
+            "watching pile"
+        );
+    }
+
+    // The watcher reports a change once its path has not changed for `delay`,
+    // so pending piles are backed up as soon as no more changes are reported.
+    let mut pending: BTreeSet<usize> = BTreeSet::new();
+
+    while running.load(Ordering::SeqCst) {
+        match rx.recv_timeout(TICK) {
+            Ok(event) =>
+                for path in event_paths(event) {
+                    for (idx, pile) in piles.iter().enumerate() {
+                        if pile.filter.matches(&path) {
+                            tracing::debug!(
+                                path = %path.display(),
+                                hoard = %pile.hoard,
+                                pile = ?pile.pile,
+                                "detected change"
+                            );
+                            pending.insert(idx);
+                        }
+                    }
+                },
+            Err(mpsc::RecvTimeoutError::Timeout) =>
+                for (hoard, names) in changed_piles(&piles, &std::mem::take(&mut pending)) {
+                    if let Err(err) = backup_piles(config, hoard, &names) {
+                        tracing::error!(%hoard, error = %err, "failed to back up piles");
+                    }
+                },
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                tracing::error!("filesystem watcher stopped unexpectedly");
+                break;
+            },
+        }
+    }
+
+    tracing::info!("stopping watch");
+    Ok(())
+}
+
+/// The names of the changed piles, by hoard. The anonymous pile of a hoard
+/// has no name.
+fn changed_piles<'a>(
+    piles: &[WatchedPile<'a>],
+    pending: &BTreeSet<usize>,
+) -> BTreeMap<&'a str, BTreeSet<&'a str>> {
+    let mut changed: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for pile in pending.iter().filter_map(|idx| piles.get(*idx)) {
+        changed.entry(pile.hoard).or_default().extend(pile.pile);
+    }
+    changed
+}
+
+/// Backs up the changed piles of a hoard the way `hoard backup` does, leaving
+/// the other piles and their history as they are.
+fn backup_piles(config: &Config, hoard: &str, piles: &BTreeSet<&str>) -> Result<(), Error> {
+    let _span = tracing::info_span!("watch_backup", %hoard, ?piles).entered();
+    tracing::info!("change detected, backing up piles");
+    config.backup_piles(hoard, piles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{builder::hoard::Walker, hoard::MultipleEntries};
+    use tempfile::TempDir;
+
+    fn filter_for(root: &Path, walker: Walker, ignores: Option<Vec<String>>) -> PileFilter {
+        let config = PileConfig {
+            encryption: None,
+            walker,
+            ..PileConfig::default()
+        };
+        let global = GlobalConfig {
+            ignores,
+            ..GlobalConfig::default()
+        };
+        PileFilter::new(root, None, &config, &global).expect("failed to create filter")
+    }
+
+    #[test]
+    fn changed_piles_are_backed_up_alone() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let pile = |name: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, name).unwrap();
+            Pile {
+                config:     None,
+                path:       Some(path.clone()),
+                candidates: vec![path],
+            }
+        };
+        let dotfiles = Hoard::Named(MultipleEntries {
+            piles: vec![
+                ("vim".to_owned(), pile("vimrc")),
+                ("zsh".to_owned(), pile("zshrc")),
+            ]
+            .into_iter()
+            .collect(),
+        });
+        let other = Hoard::Anonymous(pile("gitconfig"));
+        let hoards: HashMap<&str, &Hoard> = vec![("dotfiles", &dotfiles), ("git", &other)]
+            .into_iter()
+            .collect();
+        let piles = watched_piles(&Config::default(), &hoards).unwrap();
+        assert_eq!(piles.len(), 3);
+
+        let index = |hoard: &str, name: Option<&str>| {
+            piles
+                .iter()
+                .position(|pile| pile.hoard == hoard && pile.pile == name)
+                .unwrap()
+        };
+        let pending: BTreeSet<usize> = vec![index("dotfiles", Some("vim"))].into_iter().collect();
+        assert_eq!(
+            changed_piles(&piles, &pending),
+            vec![("dotfiles", vec!["vim"].into_iter().collect())]
+                .into_iter()
+                .collect(),
+            "a change to one pile backs up only that pile"
+        );
+        let pending: BTreeSet<usize> = vec![
+            index("dotfiles", Some("vim")),
+            index("dotfiles", Some("zsh")),
+            index("git", None),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            changed_piles(&piles, &pending),
+            vec![
+                ("dotfiles", vec!["vim", "zsh"].into_iter().collect()),
+                ("git", BTreeSet::new()),
+            ]
+            .into_iter()
+            .collect(),
+            "each hoard is backed up once"
+        );
+
+        let dotfiles = dotfiles.with_piles(&changed_piles(&piles, &pending)["dotfiles"]);
+        let vim_only = dotfiles.with_piles(&vec!["vim"].into_iter().collect());
+        assert!(
+            matches!(&vim_only, Hoard::Named(named) if named.piles.keys().eq(["vim"].iter())),
+            "only the changed pile is backed up"
+        );
+    }
+
+    #[test]
+    fn filter_rejects_paths_outside_of_pile() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let filter = filter_for(dir.path(), Walker::default(), None);
+        assert!(!filter.matches(Path::new("/definitely/not/in/the/pile")));
+        assert!(filter.matches(&dir.path().join("file.txt")));
+    }
+
+    #[test]
+    fn filter_honors_global_ignores() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let filter = filter_for(dir.path(), Walker::default(), Some(vec!["*.swp".into()]));
+        assert!(!filter.matches(&dir.path().join("file.txt.swp")));
+        assert!(filter.matches(&dir.path().join("file.txt")));
+    }
+
+    #[test]
+    fn filter_honors_walker_config() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let walker = Walker {
+            hidden: true,
+            max_depth: Some(2),
+            exclude: vec!["*.log".into()],
+            pattern: "*.txt".into(),
+            ..Walker::default()
+        };
+        let filter = filter_for(dir.path(), walker, None);
+
+        assert!(filter.matches(&dir.path().join("sub/file.txt")));
+        assert!(!filter.matches(&dir.path().join("file.md")), "pattern");
+        assert!(!filter.matches(&dir.path().join("debug.log")), "exclude");
+        assert!(
+            !filter.matches(&dir.path().join(".hidden/file.txt")),
+            "hidden"
+        );
+        assert!(
+            !filter.matches(&dir.path().join("a/b/file.txt")),
+            "max depth"
+        );
+    }
+}
@@ -1,10 +1,13 @@
 //! Expand environment variables inside of a path.
 //!
-//! The only function exported from this module is [`expand_env_in_path`].
+//! The functions exported from this module are [`expand_env_in_path`] and
+//! [`expand_vars_in_path`].
 
 use crate::config::directories::PROJECT_DIRS;
 use std::{
+    collections::HashMap,
     env, fmt,
+    hash::BuildHasher,
     path::{Path, PathBuf},
 };
 
@@ -29,6 +32,10 @@ pub struct Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.error {
+            // Variables from the configuration have a dot in their name, e.g. `vars.dotfiles`
+            env::VarError::NotPresent if self.var.contains('.') => {
+                write!(f, "variable is not defined: {}", self.var)
+            },
             env::VarError::NotPresent => write!(f, "{}: {}", self.error, self.var),
             env::VarError::NotUnicode(_) => self.error.fmt(f),
         }
@@ -60,9 +67,35 @@ impl std::error::Error for Error {
 ///
 /// - Any [`VarError`](env::VarError) from looking up the environment variable's
 ///   value.
+pub fn expand_env_in_path(path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+    expand_vars_in_path(path, &HashMap::new())
+}
+
+/// Like [`expand_env_in_path`], but variables in `vars` take precedence over
+/// environment variables of the same name.
+///
+/// # Example
+///
+/// ```
+/// use hoard::env_vars::expand_vars_in_path;
+/// use std::{collections::HashMap, path::PathBuf};
+///
+/// let mut vars = HashMap::new();
+/// vars.insert("vars.dotfiles".to_owned(), "/home/user/dotfiles".to_owned());
+/// let path = expand_vars_in_path("${vars.dotfiles}/vimrc", &vars).expect("failed to expand path");
+/// assert_eq!(path, PathBuf::from("/home/user/dotfiles/vimrc"));
+/// ```
+///
+/// # Errors
+///
+/// - Any [`VarError`](env::VarError) from looking up the environment variable's
+///   value.
 #[allow(clippy::missing_panics_doc)]
 #[allow(clippy::too_many_lines)]
-pub fn expand_env_in_path(path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+pub fn expand_vars_in_path<P: AsRef<Path>, S: BuildHasher>(
+    path: P,
+    vars: &HashMap<String, String, S>,
+) -> Result<PathBuf, Error> {
     let new_path = path.as_ref().to_path_buf().display().to_string();
     let _span = tracing::debug_span!("expand_env_in_path", %new_path).entered();
 
@@ -97,7 +130,20 @@ pub fn expand_env_in_path(path: impl AsRef<Path>) -> Result<PathBuf, Error> {
             c.is_alphanumeric() || c == '_'
         }
 
-        fn context(s: &str) -> Result<Option<String>, Error> {
+        fn context<S: BuildHasher>(
+            vars: &HashMap<String, String, S>,
+            s: &str,
+        ) -> Result<Option<String>, Error> {
+            if let Some(value) = vars.get(s) {
+                return Ok(Some(value.clone()));
+            }
+            // Unlike environment variables, variables from the configuration must exist
+            if s.contains('.') {
+                return Err(Error {
+                    error: env::VarError::NotPresent,
+                    var:   s.to_owned(),
+                });
+            }
             // std::env::var(s).map(Some)
             match env::var(s) {
                 Ok(value) => Ok(Some(value)),
@@ -145,7 +191,7 @@ pub fn expand_env_in_path(path: impl AsRef<Path>) -> Result<PathBuf, Error> {
                             dv
                         };
 
-                        match context(dv) {
+                        match context(vars, dv) {
                             Ok(Some(default)) => {
                                 tracing::trace!("expanded nested default value: {}", default);
                                 Some(default)
@@ -161,7 +207,7 @@ pub fn expand_env_in_path(path: impl AsRef<Path>) -> Result<PathBuf, Error> {
 
                     let var_name = &new_path[2..var_name_end_idx];
                     tracing::trace!(?default_value, %var_name);
-                    match context(var_name) {
+                    match context(vars, var_name) {
                         // if we have the variable set to some value
                         Ok(Some(var_value)) => {
                             res.push_str(var_value.as_ref());
@@ -199,7 +245,7 @@ pub fn expand_env_in_path(path: impl AsRef<Path>) -> Result<PathBuf, Error> {
 
                 let var_name = &new_path[1..end_idx];
 
-                match context(var_name) {
+                match context(vars, var_name) {
                     Ok(var_value) =>
                         if let Some(var_value) = var_value {
                             tracing::trace!(
@@ -129,7 +129,16 @@ pub mod macros;
 pub mod utils;
 
 /// The default file name of the configuration file.
-pub const CONFIG_FILE_NAME: &str = "config.yml";
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// The file names looked for in the configuration directory when no
+/// configuration file is given, by precedence.
+pub const CONFIG_FILE_NAMES: [&str; 4] =
+    [CONFIG_FILE_NAME, "config.yaml", "config.yml", "config.json"];
+
+/// The file name of a project configuration file, found in the current
+/// directory or one of its parents.
+pub const PROJECT_CONFIG_FILE_NAME: &str = ".hoard.toml";
 
 /// The name of the directory containing the backed up hoards.
 pub const HOARDS_DIR_SLUG: &str = "hoards";
@@ -1,10 +1,16 @@
-use hoard::Config;
-use std::io::Stdout;
+use hoard::{
+    config::{
+        output::{emit, Event, OutputFormat},
+        Error,
+    },
+    Config,
+};
+use std::io::{self, Stderr, Stdout};
 use tracing::{level_filters::LevelFilter, Level};
 use tracing_subscriber::{
     fmt::{
         format::{Format, Pretty},
-        SubscriberBuilder,
+        MakeWriter, SubscriberBuilder,
     },
     EnvFilter, FmtSubscriber,
 };
@@ -20,6 +26,20 @@ fn error_and_exit<E: std::error::Error>(err: E) -> ! {
     std::process::exit(1);
 }
 
+/// Report a [`Config`] error in the requested format and exit with its code.
+fn config_error_and_exit(err: &Error, output: OutputFormat) -> ! {
+    let exit_code = err.exit_code();
+    if output.is_json() {
+        emit(&Event::Error {
+            error: err,
+            exit_code,
+        });
+    } else {
+        tracing::error!("{}", err);
+    }
+    std::process::exit(exit_code);
+}
+
 type Subscriber = SubscriberBuilder<Pretty, Format<Pretty, ()>, LevelFilter, fn() -> Stdout>;
 fn get_subscriber() -> Subscriber {
     FmtSubscriber::builder()
@@ -35,11 +55,13 @@ fn get_subscriber() -> Subscriber {
         })
 }
 
-fn main() {
-    // Set up default logging
+/// Install `subscriber` as the global default, filtered by [`LOG_ENV`] if set.
+fn init_subscriber<W>(subscriber: SubscriberBuilder<Pretty, Format<Pretty, ()>, LevelFilter, W>)
+where
+    W: MakeWriter + Send + Sync + 'static,
+{
     // There is no obvious way to set up a default logging level in case the env
     // isn't set, so use this match thing instead.
-    let subscriber = get_subscriber();
     match std::env::var_os(LOG_ENV) {
         Some(_) => match EnvFilter::try_from_env(LOG_ENV) {
             Err(err) => error_and_exit(err),
@@ -50,15 +72,30 @@ fn main() {
         },
         None => subscriber.init(),
     };
+}
+
+fn main() {
+    // Set up default logging
+    // JSON events are written to stdout, so logs go to stderr instead.
+    let output = OutputFormat::from_env_args();
+    if output.is_json() {
+        init_subscriber(
+            get_subscriber()
+                .with_ansi(false)
+                .with_writer(io::stderr as fn() -> Stderr),
+        );
+    } else {
+        init_subscriber(get_subscriber());
+    }
 
     // Get configuration
     let mut config = match Config::load() {
         Ok(config) => config,
-        Err(err) => error_and_exit(err),
+        Err(err) => config_error_and_exit(&err, output),
     };
 
     // Run command with config
     if let Err(err) = config.run() {
-        error_and_exit(err);
+        config_error_and_exit(&err, config.get_output_format());
     }
 }
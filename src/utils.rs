@@ -207,6 +207,45 @@ pub fn create_temp_path() -> String {
     tmp_path.display().to_string()
 }
 
+/// Atomically replace the contents of the file at `path`.
+///
+/// The contents are written to a temporary file in the same directory, synced
+/// to disk and then renamed over `path`, so readers see either the old or the
+/// new contents but never a partially written file.
+///
+/// # Errors
+///
+/// Any I/O error that occurs while writing or renaming the temporary file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), io::Error> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .map_or_else(|| "file".into(), OsStr::to_string_lossy);
+    let tmp = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name,
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect::<String>()
+    ));
+
+    let result = File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+
+    if result.is_err() {
+        if let Err(err) = fs::remove_file(&tmp) {
+            tracing::warn!(error = %err, path = %tmp.display(), "failed to remove temporary file");
+        }
+    }
+    result
+}
+
 /// Write to the tempoary ignore file
 pub fn modify_temp_ignore<P: AsRef<Path>>(
     path: P,